@@ -0,0 +1,89 @@
+#!/usr/bin/env bash
+# ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+# Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+#
+# Stub external trainer speaking the JSON lines protocol of src/external.rs, it trains nothing.
+#
+# set the external trainer command of a project in the Training tab to
+#
+#     bash /path/to/scripts/stub-trainer.sh
+#
+# it reports `epochs` epochs (a hyperparameter, default 5) with made up metrics, writes a
+# checkpoint file every second epoch and a model file at the end. It follows pause, resume
+# and stop requests. The socket transport needs socat, an nc with -U or python3.
+#
+# environment variables besides the ones set by AI Lab:
+#     STUB_DELAY    seconds per epoch (default 0.5)
+#     STUB_FAIL     fail after this epoch, with an error event and exit status 1
+
+set -eu
+
+: "${AI_LAB_JOB:?AI_LAB_JOB is not set, the stub is started by AI Lab}"
+: "${AI_LAB_RUN_DIR:?AI_LAB_RUN_DIR is not set, the stub is started by AI Lab}"
+delay=${STUB_DELAY:-0.5}
+fail=${STUB_FAIL:-0}
+
+# one event per line, on stdout or on the socket
+emit() {
+    if [ -z "${AI_LAB_SOCKET:-}" ]; then
+        printf '%s\n' "$1"
+    elif command -v socat > /dev/null; then
+        printf '%s\n' "$1" | socat -u - "UNIX-CONNECT:$AI_LAB_SOCKET"
+    elif command -v nc > /dev/null; then
+        printf '%s\n' "$1" | nc -N -U "$AI_LAB_SOCKET"
+    else
+        printf '%s\n' "$1" | python3 -c '
+import socket, sys
+connection = socket.socket(socket.AF_UNIX)
+connection.connect(sys.argv[1])
+connection.sendall(sys.stdin.buffer.read())' "$AI_LAB_SOCKET"
+    fi
+}
+
+# the job is JSON, grep is enough to read the little the stub needs
+items=$(grep -c '"split":' "$AI_LAB_JOB" || true)
+epochs=$(grep -o '"epochs": *[0-9]*' "$AI_LAB_JOB" | head -n 1 | grep -o '[0-9]*$' || true)
+epochs=${epochs:-5}
+
+echo "stub trainer for $items items and $epochs epochs"
+echo "the stub only pretends to train" >&2
+emit "{\"event\": \"status\", \"message\": \"loading $items items\"}"
+
+paused=0
+epoch=0
+while [ "$epoch" -lt "$epochs" ]; do
+    # requests of AI Lab arrive on stdin
+    while read -r -t 0.01 request; do
+        case $request in
+            *'"stop"'*)
+                emit "{\"event\": \"status\", \"message\": \"stopped after epoch $epoch\"}"
+                exit 0
+                ;;
+            *'"pause"'*) paused=1 ;;
+            *'"resume"'*) paused=0 ;;
+        esac
+    done
+    if [ "$paused" -eq 1 ]; then
+        sleep 0.1
+        continue
+    fi
+
+    sleep "$delay"
+    epoch=$((epoch + 1))
+    if [ "$epoch" -eq "$fail" ]; then
+        emit "{\"event\": \"error\", \"message\": \"the stub failed after epoch $epoch as asked\"}"
+        exit 1
+    fi
+    metrics=$(awk -v e="$epoch" 'BEGIN {
+        printf "\"loss\": %.4f, \"accuracy\": %.4f, \"val_loss\": %.4f, \"val_accuracy\": %.4f",
+            1 / (e + 1), 1 - 0.5 / e, 1.2 / (e + 1), 0.95 - 0.5 / e
+    }')
+    emit "{\"event\": \"epoch\", \"epoch\": $epoch, \"epochs\": $epochs, \"metrics\": {$metrics}, \"learning_rate\": 0.001, \"samples\": $items}"
+    if [ $((epoch % 2)) -eq 0 ]; then
+        echo "epoch $epoch" > "$AI_LAB_RUN_DIR/stub-checkpoint-$epoch.txt"
+        emit "{\"event\": \"checkpoint\", \"epoch\": $epoch, \"path\": \"stub-checkpoint-$epoch.txt\"}"
+    fi
+done
+
+echo "stub model after $epochs epochs" > "$AI_LAB_RUN_DIR/stub-model.txt"
+emit "{\"event\": \"status\", \"message\": \"saved stub-model.txt\"}"
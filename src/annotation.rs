@@ -2,9 +2,24 @@
 // Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
 
 use gtk::prelude::*;
-use gtk::Box as GtkBox;
+use gtk::{Box as GtkBox, Button, Label};
 
-pub fn annotation_ui() -> GtkBox {
+use crate::debug_println;
+use crate::helper::show_error_message;
+use crate::project::SharedProject;
+use crate::store::Modality;
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// item currently shown in the annotator, shared with the tabs that link into it
+pub(crate) type CurrentItem = Rc<Cell<Option<u64>>>;
+
+/// shows an item in the annotator (switches to the annotation tab)
+pub(crate) type JumpToItem = Rc<dyn Fn(u64)>;
+
+pub fn annotation_ui(project: &SharedProject, current_item: &CurrentItem) -> GtkBox {
     let main_box = gtk::Box::builder()
         .spacing(1)
         .orientation(gtk::Orientation::Vertical)
@@ -13,5 +28,156 @@ pub fn annotation_ui() -> GtkBox {
 
     main_box.append(&gtk::Label::new(Some("annotator")));
 
+    // summary of the annotation store of the current project
+    // ---------------------------------------------------------------------------------------------
+    let summary_label = Label::new(None);
+    main_box.append(&summary_label);
+
+    let refresh_summary = {
+        let project = project.clone();
+        let summary_label = summary_label.clone();
+        move || {
+            let text = match project.borrow().as_ref().map(|p| &p.store) {
+                Some(store) => format!(
+                    "{} items, {} unlabelled, {} annotations",
+                    store.item_count(),
+                    store.unlabelled_items().len(),
+                    store.annotations().count()
+                ),
+                None => "no project opened".to_string(),
+            };
+            summary_label.set_text(&text);
+        }
+    };
+
+    // the current item (e.g. opened from the lint page) and its annotations
+    // ---------------------------------------------------------------------------------------------
+    let item_label = Label::builder().wrap(true).build();
+    let picture = gtk::Picture::builder()
+        .can_shrink(true)
+        .height_request(250)
+        .visible(false)
+        .build();
+    let annotations_label = Label::builder().wrap(true).build();
+
+    main_box.append(&item_label);
+    main_box.append(&picture);
+    main_box.append(&annotations_label);
+
+    let refresh_item = gtk::glib::clone!(@strong project, @strong current_item => move || {
+        let project_ref = project.borrow();
+        let item = project_ref
+            .as_ref()
+            .zip(current_item.get())
+            .and_then(|(project, id)| Some((&project.store, project.store.item(id)?)));
+
+        let Some((store, item)) = item else {
+            item_label.set_text("");
+            annotations_label.set_text("");
+            picture.set_visible(false);
+            return;
+        };
+
+        item_label.set_text(&format!("#{} {} ({})", item.id, item.path, item.modality.name()));
+        if item.modality == Modality::Image {
+            picture.set_filename(Some(&item.path));
+            picture.set_visible(true);
+        } else {
+            picture.set_visible(false);
+        }
+
+        let lines: Vec<String> = store
+            .annotations_of_item(item.id)
+            .iter()
+            .map(|a| format!("#{} {}: {:?}", a.id, a.class, a.shape))
+            .collect();
+        annotations_label.set_text(&if lines.is_empty() {
+            "no annotations".to_string()
+        } else {
+            lines.join("\n")
+        });
+    });
+
+    refresh_summary();
+    {
+        let refresh_summary = refresh_summary.clone();
+        main_box.connect_map(move |_| {
+            refresh_summary();
+            refresh_item();
+        });
+    }
+
+    // import files into the store
+    // ---------------------------------------------------------------------------------------------
+    let import_btn = Button::with_label("import files");
+
+    import_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
+        if project.borrow().is_none() {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("ANNOTATION ERROR"),
+                Some("Unable to import files, since no project is opened."),
+            );
+            return;
+        }
+
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Select files to import")
+            .action(gtk::FileChooserAction::Open)
+            .select_multiple(true)
+            .build();
+
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Import", gtk::ResponseType::Accept),
+        ]);
+
+        let refresh_summary = refresh_summary.clone();
+        dialog.connect_response(gtk::glib::clone!(@strong project => move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                let files = dialog.files();
+                let mut project_ref = project.borrow_mut();
+                let Some(store) = project_ref.as_mut().map(|p| &mut p.store) else {
+                    dialog.close();
+                    return;
+                };
+
+                for i in 0..files.n_items() {
+                    let Some(path) = files
+                        .item(i)
+                        .and_downcast::<gtk::gio::File>()
+                        .and_then(|file| file.path())
+                    else {
+                        continue;
+                    };
+
+                    match Modality::from_path(&path) {
+                        Some(modality) => {
+                            if let Err(e) = store.add_item(
+                                &path.display().to_string(),
+                                modality,
+                                BTreeMap::new(),
+                            ) {
+                                debug_println!("[ERROR: IMPORT] {}: {}", path.display(), e);
+                            }
+                        }
+                        None => {
+                            debug_println!(
+                                "[WARNING: IMPORT] unsupported file type, skipped: {}",
+                                path.display()
+                            );
+                        }
+                    }
+                }
+            }
+            dialog.close();
+            refresh_summary();
+        }));
+
+        dialog.show();
+    }));
+
+    main_box.append(&import_btn);
+
     main_box
 }
@@ -0,0 +1,587 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Audio feature extraction
+//!
+//! Decoding of `.wav` files and the usual front end of sound / speech models: mono mixdown,
+//! resampling, pre-emphasis, STFT spectrograms, mel filterbanks and MFCCs. The features of a
+//! file are cached inside the project, keyed by the content of the file and the settings.
+
+use crate::engine::{cache_file, cache_key};
+use crate::media::wav_info;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// sub directory of the project cache holding the features
+pub(crate) const AUDIO_CACHE: &str = "audio";
+
+/// number of (min, max) columns of the waveform overview
+const ENVELOPE_COLUMNS: usize = 1000;
+
+/// smallest power (energy) before taking logarithms
+const LOG_FLOOR: f32 = 1e-10;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Settings of the audio front end, window and hop sizes are in samples (after resampling)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AudioConfig {
+    /// target sample rate, 0 keeps the rate of every file
+    pub(crate) sample_rate: u32,
+    /// mix all channels down to one (otherwise only the first channel is used)
+    pub(crate) mono: bool,
+    /// coefficient of `y[n] = x[n] - a x[n - 1]`, 0 disables the filter
+    pub(crate) pre_emphasis: f64,
+    pub(crate) window: usize,
+    pub(crate) hop: usize,
+    pub(crate) mel_bands: usize,
+    pub(crate) mfcc: usize,
+    pub(crate) f_min: f64,
+    /// upper edge of the mel filterbank, 0 is half the sample rate
+    pub(crate) f_max: f64,
+}
+
+/// Decoded samples of a sound file, values between -1 and 1
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Audio {
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: Vec<Vec<f32>>,
+}
+
+/// Features of one file, every matrix is indexed `[frame][bin]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AudioFeatures {
+    /// sample rate after resampling
+    pub(crate) sample_rate: u32,
+    pub(crate) duration: f64,
+    /// (min, max) of the processed samples per column, for drawing the waveform
+    pub(crate) envelope: Vec<(f32, f32)>,
+    /// power spectrogram in dB
+    pub(crate) spectrogram: Vec<Vec<f32>>,
+    /// log mel energies
+    pub(crate) mel: Vec<Vec<f32>>,
+    pub(crate) mfcc: Vec<Vec<f32>>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for AudioConfig {
+    /// 16 kHz mono with 25 ms windows every 10 ms, 40 mel bands and 13 MFCCs
+    fn default() -> Self {
+        AudioConfig {
+            sample_rate: 16000,
+            mono: true,
+            pre_emphasis: 0.97,
+            window: 400,
+            hop: 160,
+            mel_bands: 40,
+            mfcc: 13,
+            f_min: 0.0,
+            f_max: 0.0,
+        }
+    }
+}
+
+impl AudioConfig {
+    /// named numeric parameters for building a form (like [`crate::pipeline::ImageOp::params`])
+    pub(crate) fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("sample rate [Hz] (0 = keep)", self.sample_rate as f64),
+            ("pre-emphasis", self.pre_emphasis),
+            ("window [samples]", self.window as f64),
+            ("hop [samples]", self.hop as f64),
+            ("mel bands", self.mel_bands as f64),
+            ("MFCCs", self.mfcc as f64),
+            ("min frequency [Hz]", self.f_min),
+            ("max frequency [Hz] (0 = nyquist)", self.f_max),
+        ]
+    }
+
+    /// set the parameter at `index` of [`AudioConfig::params`], out of range indices are ignored
+    pub(crate) fn set_param(&mut self, index: usize, value: f64) {
+        let value = value.max(0.0);
+        let count = value.round() as usize;
+        match index {
+            0 => self.sample_rate = value.round() as u32,
+            1 => self.pre_emphasis = value.min(1.0),
+            2 => self.window = count.max(2),
+            3 => self.hop = count.max(1),
+            4 => self.mel_bands = count.max(1),
+            5 => self.mfcc = count,
+            6 => self.f_min = value,
+            7 => self.f_max = value,
+            _ => {}
+        }
+    }
+}
+
+/// # decode a `.wav` file
+///
+/// supports 8, 16, 24 and 32 bit integer PCM and 32 / 64 bit float samples.
+pub(crate) fn read_wav(path: &Path) -> Result<Audio, Box<dyn Error>> {
+    let is_wav = path
+        .extension()
+        .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("wav"));
+    if !is_wav {
+        return Err(format!("{}: only .wav files can be decoded", path.display()).into());
+    }
+
+    let info = wav_info(path)?;
+    let bytes_per_sample = info.bits_per_sample as usize / 8;
+    let channels = info.channels.max(1) as usize;
+
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(info.data_offset))?;
+    let mut data = Vec::new();
+    reader
+        .take(info.frames * (channels * bytes_per_sample) as u64)
+        .read_to_end(&mut data)?;
+
+    let decode: fn(&[u8]) -> f32 = match (info.format, info.bits_per_sample) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8388608.0,
+        (1, 32) => |b| (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2147483648.0) as f32,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (3, 64) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        (format, bits) => {
+            return Err(format!(
+                "{}: unsupported sample format {} with {} bits",
+                path.display(),
+                format,
+                bits
+            )
+            .into())
+        }
+    };
+
+    let mut decoded = vec![Vec::with_capacity(info.frames as usize); channels];
+    for frame in data.chunks_exact(channels * bytes_per_sample) {
+        for (channel, sample) in decoded.iter_mut().zip(frame.chunks_exact(bytes_per_sample)) {
+            channel.push(decode(sample));
+        }
+    }
+    Ok(Audio {
+        sample_rate: info.sample_rate,
+        channels: decoded,
+    })
+}
+
+/// average of all channels
+pub(crate) fn mixdown(audio: &Audio) -> Vec<f32> {
+    let len = audio.channels.iter().map(Vec::len).min().unwrap_or(0);
+    let count = audio.channels.len().max(1) as f32;
+    (0..len)
+        .map(|i| audio.channels.iter().map(|c| c[i]).sum::<f32>() / count)
+        .collect()
+}
+
+/// # resample with linear interpolation
+///
+/// when downsampling, every output sample is the mean of the input samples it covers, which
+/// suppresses most of the aliasing.
+pub(crate) fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || from == 0 || to == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (samples.len() as f64 / ratio).floor().max(1.0) as usize;
+    let last = samples.len() - 1;
+
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            if ratio > 1.0 {
+                let start = (position.floor() as usize).min(last);
+                let end = ((position + ratio).ceil() as usize).clamp(start + 1, samples.len());
+                let window = &samples[start..end];
+                window.iter().sum::<f32>() / window.len().max(1) as f32
+            } else {
+                let i0 = (position.floor() as usize).min(last);
+                let i1 = (i0 + 1).min(last);
+                let t = (position - i0 as f64) as f32;
+                samples[i0] * (1.0 - t) + samples[i1] * t
+            }
+        })
+        .collect()
+}
+
+/// `y[n] = x[n] - coefficient x[n - 1]`, boosts high frequencies
+pub(crate) fn pre_emphasis(samples: &[f32], coefficient: f64) -> Vec<f32> {
+    let coefficient = coefficient as f32;
+    let mut previous = 0.0;
+    samples
+        .iter()
+        .map(|x| {
+            let y = x - coefficient * previous;
+            previous = *x;
+            y
+        })
+        .collect()
+}
+
+/// # in-place radix-2 FFT
+///
+/// the length of both slices must be the same power of two.
+pub(crate) fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    if n < 2 {
+        return;
+    }
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// FFT size of a window: the next power of two
+pub(crate) fn fft_size(window: usize) -> usize {
+    window.max(2).next_power_of_two()
+}
+
+/// # power spectrogram
+///
+/// hann windowed frames of `window` samples every `hop` samples, zero padded to
+/// [`fft_size`]. Every frame holds `fft_size / 2 + 1` bins from 0 Hz to half the sample rate.
+pub(crate) fn power_spectrogram(samples: &[f32], window: usize, hop: usize) -> Vec<Vec<f32>> {
+    let window = window.max(2);
+    let n_fft = fft_size(window);
+    let hann: Vec<f64> = (0..window)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (window - 1) as f64).cos())
+        .collect();
+
+    let frames = if samples.len() < window {
+        usize::from(!samples.is_empty())
+    } else {
+        (samples.len() - window) / hop.max(1) + 1
+    };
+
+    (0..frames)
+        .map(|frame| {
+            let start = frame * hop.max(1);
+            let mut re = vec![0.0; n_fft];
+            let mut im = vec![0.0; n_fft];
+            for (i, w) in hann.iter().enumerate() {
+                re[i] = samples.get(start + i).copied().unwrap_or(0.0) as f64 * w;
+            }
+            fft(&mut re, &mut im);
+            (0..=n_fft / 2)
+                .map(|k| (re[k] * re[k] + im[k] * im[k]) as f32)
+                .collect()
+        })
+        .collect()
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    700.0 * (10f64.powf(mel / 2595.0) - 1.0)
+}
+
+/// # triangular mel filterbank
+///
+/// `bands` filters evenly spaced on the mel scale between `f_min` and `f_max`, each a vector
+/// of weights for the `n_fft / 2 + 1` bins of a power spectrum.
+pub(crate) fn mel_filterbank(
+    bands: usize,
+    n_fft: usize,
+    sample_rate: u32,
+    f_min: f64,
+    f_max: f64,
+) -> Vec<Vec<f32>> {
+    let nyquist = sample_rate as f64 / 2.0;
+    let f_max = if f_max <= 0.0 {
+        nyquist
+    } else {
+        f_max.min(nyquist)
+    };
+    let f_min = f_min.min(f_max);
+    let (mel_min, mel_max) = (hz_to_mel(f_min), hz_to_mel(f_max));
+    let edges: Vec<f64> = (0..bands + 2)
+        .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f64 / (bands + 1) as f64))
+        .collect();
+
+    (0..bands)
+        .map(|band| {
+            let (left, centre, right) = (edges[band], edges[band + 1], edges[band + 2]);
+            (0..=n_fft / 2)
+                .map(|bin| {
+                    let f = bin as f64 * sample_rate as f64 / n_fft as f64;
+                    let weight = if f <= left || f >= right {
+                        0.0
+                    } else if f <= centre {
+                        (f - left) / (centre - left)
+                    } else {
+                        (right - f) / (right - centre)
+                    };
+                    weight as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// natural logarithm of the filterbank energies of every frame
+pub(crate) fn log_mel(power: &[Vec<f32>], filterbank: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    power
+        .iter()
+        .map(|frame| {
+            filterbank
+                .iter()
+                .map(|filter| {
+                    let energy: f32 = filter.iter().zip(frame).map(|(w, p)| w * p).sum();
+                    energy.max(LOG_FLOOR).ln()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// the first `count` coefficients of the orthonormal DCT-II of every log mel frame
+pub(crate) fn mfcc(log_mel: &[Vec<f32>], count: usize) -> Vec<Vec<f32>> {
+    log_mel
+        .iter()
+        .map(|frame| {
+            let n = frame.len();
+            (0..count.min(n))
+                .map(|k| {
+                    let sum: f64 = frame
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| {
+                            *v as f64 * (PI * k as f64 * (i as f64 + 0.5) / n as f64).cos()
+                        })
+                        .sum();
+                    let scale = if k == 0 {
+                        1.0 / n as f64
+                    } else {
+                        2.0 / n as f64
+                    };
+                    (sum * scale.sqrt()) as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// (min, max) of the samples in `columns` equally long pieces
+fn envelope(samples: &[f32], columns: usize) -> Vec<(f32, f32)> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let columns = columns.min(samples.len());
+    (0..columns)
+        .map(|c| {
+            let piece = &samples[c * samples.len() / columns..(c + 1) * samples.len() / columns];
+            piece
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)))
+        })
+        .collect()
+}
+
+/// run the whole front end on decoded audio
+pub(crate) fn extract_features(audio: &Audio, config: &AudioConfig) -> AudioFeatures {
+    let samples = if config.mono {
+        mixdown(audio)
+    } else {
+        audio.channels.first().cloned().unwrap_or_default()
+    };
+    let sample_rate = if config.sample_rate == 0 {
+        audio.sample_rate
+    } else {
+        config.sample_rate
+    };
+    let samples = resample(&samples, audio.sample_rate, sample_rate);
+    let samples = if config.pre_emphasis > 0.0 {
+        pre_emphasis(&samples, config.pre_emphasis)
+    } else {
+        samples
+    };
+
+    let power = power_spectrogram(&samples, config.window, config.hop);
+    let filterbank = mel_filterbank(
+        config.mel_bands,
+        fft_size(config.window),
+        sample_rate,
+        config.f_min,
+        config.f_max,
+    );
+    let mel = log_mel(&power, &filterbank);
+    let spectrogram = power
+        .iter()
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|p| 10.0 * p.max(LOG_FLOOR).log10())
+                .collect()
+        })
+        .collect();
+
+    AudioFeatures {
+        sample_rate,
+        duration: samples.len() as f64 / sample_rate.max(1) as f64,
+        envelope: envelope(&samples, ENVELOPE_COLUMNS),
+        spectrogram,
+        mfcc: mfcc(&mel, config.mfcc),
+        mel,
+    }
+}
+
+/// cache file of a sound file with the given settings (changes with the content and settings)
+fn cache_path(
+    project_dir: &Path,
+    path: &Path,
+    config: &AudioConfig,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let key = cache_key(path, AUDIO_CACHE, config)?;
+    Ok(cache_file(project_dir, AUDIO_CACHE, &key, "json"))
+}
+
+/// # features of a sound file, computed once per content and settings
+///
+/// returns:
+///     Result with the features and whether they came from the cache
+pub(crate) fn cached_features(
+    project_dir: &Path,
+    path: &Path,
+    config: &AudioConfig,
+) -> Result<(AudioFeatures, bool), Box<dyn Error>> {
+    let cache = cache_path(project_dir, path, config)?;
+    if let Ok(contents) = fs::read_to_string(&cache) {
+        if let Ok(features) = serde_json::from_str(&contents) {
+            return Ok((features, true));
+        }
+    }
+
+    let features = extract_features(&read_wav(path)?, config);
+    if let Some(dir) = cache.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&cache, serde_json::to_string(&features)?)?;
+    Ok((features, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn fft_matches_the_dft() {
+        let n = 16;
+        let signal: Vec<f64> = (0..n).map(|i| ((i * 7) % 5) as f64 - 1.5).collect();
+        let (mut re, mut im) = (signal.clone(), vec![0.0; n]);
+        fft(&mut re, &mut im);
+
+        for k in 0..n {
+            let (mut dft_re, mut dft_im) = (0.0, 0.0);
+            for (t, x) in signal.iter().enumerate() {
+                let angle = -2.0 * PI * (k * t) as f64 / n as f64;
+                dft_re += x * angle.cos();
+                dft_im += x * angle.sin();
+            }
+            assert_close(re[k], dft_re);
+            assert_close(im[k], dft_im);
+        }
+    }
+
+    #[test]
+    fn fft_of_a_cosine_has_two_peaks() {
+        let n = 32;
+        let mut re: Vec<f64> = (0..n)
+            .map(|t| (2.0 * PI * 3.0 * t as f64 / n as f64).cos())
+            .collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+
+        for k in 0..n {
+            let magnitude = re[k].hypot(im[k]);
+            let expected = if k == 3 || k == n - 3 {
+                n as f64 / 2.0
+            } else {
+                0.0
+            };
+            assert_close(magnitude, expected);
+        }
+        assert_eq!(fft_size(400), 512);
+        assert_eq!(fft_size(512), 512);
+    }
+
+    #[test]
+    fn mel_scale_round_trips() {
+        assert!((hz_to_mel(1000.0) - 1000.0).abs() < 0.5);
+        for hz in [0.0, 440.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn mel_filters_are_ordered_triangles() {
+        let (bands, n_fft, sample_rate) = (10, 512, 16000);
+        let filters = mel_filterbank(bands, n_fft, sample_rate, 0.0, 0.0);
+        assert_eq!(filters.len(), bands);
+
+        let mut peaks = Vec::new();
+        for filter in &filters {
+            assert_eq!(filter.len(), n_fft / 2 + 1);
+            assert!(filter.iter().all(|w| (0.0..=1.0).contains(w)));
+            let peak = (0..filter.len())
+                .max_by(|a, b| filter[*a].total_cmp(&filter[*b]))
+                .unwrap();
+            assert!(peaks.last().is_none_or(|last| peak > *last));
+            peaks.push(peak);
+        }
+
+        // between the first and the last centre neighbouring filters add up to one
+        for bin in peaks[0] + 1..peaks[bands - 1] {
+            let sum: f32 = filters.iter().map(|f| f[bin]).sum();
+            assert!((sum - 1.0).abs() < 1e-4, "bin {}: {}", bin, sum);
+        }
+
+        // f_max is clamped to the nyquist frequency
+        assert_eq!(
+            mel_filterbank(bands, n_fft, sample_rate, 0.0, 20000.0),
+            filters
+        );
+    }
+}
@@ -0,0 +1,713 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Data augmentation
+//!
+//! Random transformations of a training sample. Geometric transformations (flips, rotation,
+//! scale / shift) are applied to the image, the optional mask and all annotation shapes
+//! alike, so boxes, polygons and keypoints keep matching the augmented image. All randomness
+//! comes from a seeded [`Rng`], the same seed always gives the same augmented samples.
+
+use crate::imagebuf::ImageBuf;
+use crate::imageops::gaussian_blur;
+use crate::rng::Rng;
+use crate::store::{Annotation, Shape};
+
+use serde::{Deserialize, Serialize};
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A random transformation, applied with probability `p`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum Augment {
+    HorizontalFlip {
+        p: f64,
+    },
+    VerticalFlip {
+        p: f64,
+    },
+    /// rotation about the centre by up to `max_degrees` in both directions
+    Rotate {
+        p: f64,
+        max_degrees: f64,
+    },
+    /// scale by a factor in `[min_scale, max_scale]`, shift by up to `max_shift` times the size
+    ScaleShift {
+        p: f64,
+        min_scale: f64,
+        max_scale: f64,
+        max_shift: f64,
+    },
+    /// brightness, contrast and saturation factors in `[1 - x, 1 + x]`
+    ColourJitter {
+        p: f64,
+        brightness: f64,
+        contrast: f64,
+        saturation: f64,
+    },
+    Blur {
+        p: f64,
+        min_sigma: f64,
+        max_sigma: f64,
+    },
+    /// additive gaussian noise
+    Noise {
+        p: f64,
+        std: f64,
+    },
+    /// gray rectangles with a side length of `size` times the smaller image side
+    Cutout {
+        p: f64,
+        holes: usize,
+        size: f64,
+    },
+    /// blend with another sample, the weight is drawn from Beta(alpha, alpha); the shapes of
+    /// both samples are kept with their weights, the mask stays the one of the sample
+    Mixup {
+        p: f64,
+        alpha: f64,
+    },
+}
+
+/// Augmentation settings of a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AugmentationConfig {
+    pub(crate) seed: u64,
+    #[serde(default)]
+    pub(crate) steps: Vec<Augment>,
+}
+
+/// An annotation shape of a sample
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SampleShape {
+    pub(crate) class: String,
+    pub(crate) shape: Shape,
+    /// 1 for own shapes, the mixup weight for shapes of a blended sample
+    pub(crate) weight: f32,
+}
+
+/// An image with everything that has to be transformed with it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sample {
+    pub(crate) image: ImageBuf,
+    /// segmentation mask, transformed geometrically only (nearest neighbour)
+    pub(crate) mask: Option<ImageBuf>,
+    pub(crate) shapes: Vec<SampleShape>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for AugmentationConfig {
+    fn default() -> Self {
+        AugmentationConfig {
+            seed: 42,
+            steps: Vec::new(),
+        }
+    }
+}
+
+/// 2x3 affine matrix `[a, b, c, d, e, f]`: `x' = a x + b y + c`, `y' = d x + e y + f`
+type Affine = [f64; 6];
+
+fn apply_affine(m: &Affine, (x, y): (f32, f32)) -> (f32, f32) {
+    let (x, y) = (x as f64, y as f64);
+    (
+        (m[0] * x + m[1] * y + m[2]) as f32,
+        (m[3] * x + m[4] * y + m[5]) as f32,
+    )
+}
+
+fn invert_affine(m: &Affine) -> Affine {
+    let det = m[0] * m[4] - m[1] * m[3];
+    let det = if det == 0.0 { f64::EPSILON } else { det };
+    let (a, b, d, e) = (m[4] / det, -m[1] / det, -m[3] / det, m[0] / det);
+    [a, b, -(a * m[2] + b * m[5]), d, e, -(d * m[2] + e * m[5])]
+}
+
+/// # warp an image with an affine transformation
+///
+/// every output pixel is sampled from the input at the inversely transformed position,
+/// bilinearly or (for masks) with the nearest neighbour. Pixels from outside the input
+/// are 0 (transparent for images with alpha).
+fn warp(image: &ImageBuf, m: &Affine, nearest: bool) -> ImageBuf {
+    let inverse = invert_affine(m);
+    let mut out = ImageBuf::new(image.width, image.height, image.channels);
+    let (w, h) = (image.width as f32, image.height as f32);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (sx, sy) = apply_affine(&inverse, (x as f32 + 0.5, y as f32 + 0.5));
+            let (sx, sy) = (sx - 0.5, sy - 0.5);
+            if sx < -0.5 || sy < -0.5 || sx > w - 0.5 || sy > h - 0.5 {
+                continue;
+            }
+            if nearest {
+                let xx = (sx.round().max(0.0) as usize).min(image.width - 1);
+                let yy = (sy.round().max(0.0) as usize).min(image.height - 1);
+                for c in 0..image.channels {
+                    out.set(x, y, c, image.get(xx, yy, c));
+                }
+                continue;
+            }
+            let (sx, sy) = (sx.clamp(0.0, w - 1.0), sy.clamp(0.0, h - 1.0));
+            let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+            let (x1, y1) = (
+                (x0 + 1).min(image.width - 1),
+                (y0 + 1).min(image.height - 1),
+            );
+            let (wx, wy) = (sx - x0 as f32, sy - y0 as f32);
+            for c in 0..image.channels {
+                let top = image.get(x0, y0, c) * (1.0 - wx) + image.get(x1, y0, c) * wx;
+                let bottom = image.get(x0, y1, c) * (1.0 - wx) + image.get(x1, y1, c) * wx;
+                out.set(x, y, c, top * (1.0 - wy) + bottom * wy);
+            }
+        }
+    }
+    out
+}
+
+/// Sutherland-Hodgman clipping of a polygon to the rectangle `(0, 0, w, h)`
+fn clip_polygon(points: &[(f32, f32)], w: f32, h: f32) -> Vec<(f32, f32)> {
+    type Edge = fn((f32, f32), f32) -> f32;
+    // signed distance to the inside of each of the four borders
+    let edges: [(Edge, f32); 4] = [
+        (|p, _| p.0, 0.0),
+        (|p, w| w - p.0, w),
+        (|p, _| p.1, 0.0),
+        (|p, h| h - p.1, h),
+    ];
+
+    let mut output = points.to_vec();
+    for (inside, limit) in edges {
+        let input = std::mem::take(&mut output);
+        for (i, current) in input.iter().enumerate() {
+            let previous = input[(i + input.len() - 1) % input.len()];
+            let (d_current, d_previous) = (inside(*current, limit), inside(previous, limit));
+            if (d_current >= 0.0) != (d_previous >= 0.0) {
+                let t = d_previous / (d_previous - d_current);
+                output.push((
+                    previous.0 + t * (current.0 - previous.0),
+                    previous.1 + t * (current.1 - previous.1),
+                ));
+            }
+            if d_current >= 0.0 {
+                output.push(*current);
+            }
+        }
+    }
+    output
+}
+
+/// # transform a shape with the same matrix as the image
+///
+/// boxes become the bounding box of their transformed corners, everything is clipped to the
+/// image. Shapes that end up outside of the image are dropped (`None`).
+fn transform_shape(shape: &Shape, m: &Affine, w: f32, h: f32) -> Option<Shape> {
+    match shape {
+        Shape::Label | Shape::Segment { .. } => Some(shape.clone()),
+        Shape::BoundingBox { x, y, w: bw, h: bh } => {
+            let corners = [(*x, *y), (x + bw, *y), (*x, y + bh), (x + bw, y + bh)]
+                .map(|p| apply_affine(m, p));
+            let x0 = corners
+                .iter()
+                .map(|p| p.0)
+                .fold(f32::MAX, f32::min)
+                .max(0.0);
+            let y0 = corners
+                .iter()
+                .map(|p| p.1)
+                .fold(f32::MAX, f32::min)
+                .max(0.0);
+            let x1 = corners.iter().map(|p| p.0).fold(f32::MIN, f32::max).min(w);
+            let y1 = corners.iter().map(|p| p.1).fold(f32::MIN, f32::max).min(h);
+            (x1 > x0 && y1 > y0).then_some(Shape::BoundingBox {
+                x: x0,
+                y: y0,
+                w: x1 - x0,
+                h: y1 - y0,
+            })
+        }
+        Shape::Polygon { points } => {
+            let moved: Vec<(f32, f32)> = points.iter().map(|p| apply_affine(m, *p)).collect();
+            let clipped = clip_polygon(&moved, w, h);
+            (clipped.len() >= 3).then_some(Shape::Polygon { points: clipped })
+        }
+        Shape::Keypoint { x, y } => {
+            let (x, y) = apply_affine(m, (*x, *y));
+            (x >= 0.0 && y >= 0.0 && x <= w && y <= h).then_some(Shape::Keypoint { x, y })
+        }
+    }
+}
+
+fn transform_sample(sample: &Sample, m: &Affine) -> Sample {
+    let (w, h) = (sample.image.width as f32, sample.image.height as f32);
+    Sample {
+        image: warp(&sample.image, m, false),
+        mask: sample.mask.as_ref().map(|mask| warp(mask, m, true)),
+        shapes: sample
+            .shapes
+            .iter()
+            .filter_map(|s| {
+                Some(SampleShape {
+                    shape: transform_shape(&s.shape, m, w, h)?,
+                    ..s.clone()
+                })
+            })
+            .collect(),
+    }
+}
+
+fn inside_polygon(points: &[(f32, f32)], (x, y): (f32, f32)) -> bool {
+    let mut inside = false;
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + points.len() - 1) % points.len()];
+        if (a.1 > y) != (b.1 > y) && x < a.0 + (y - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// # build a sample from an image and the annotations of its item
+///
+/// `scale` maps annotation coordinates to pixels of `image` (for scaled down previews). The
+/// polygons are rasterised into a mask holding `index + 1` of their class in `classes`
+/// (0 is background), there is no mask without polygons.
+pub(crate) fn sample_from_annotations(
+    image: ImageBuf,
+    annotations: &[&Annotation],
+    scale: f32,
+    classes: &[String],
+) -> Sample {
+    let (w, h) = (image.width as f32, image.height as f32);
+    let m = [scale as f64, 0.0, 0.0, 0.0, scale as f64, 0.0];
+    let shapes: Vec<SampleShape> = annotations
+        .iter()
+        .filter_map(|a| {
+            Some(SampleShape {
+                class: a.class.clone(),
+                shape: transform_shape(&a.shape, &m, w, h)?,
+                weight: 1.0,
+            })
+        })
+        .collect();
+
+    let mut mask: Option<ImageBuf> = None;
+    for shape in &shapes {
+        let Shape::Polygon { points } = &shape.shape else {
+            continue;
+        };
+        let value = classes
+            .iter()
+            .position(|c| *c == shape.class)
+            .map_or(classes.len() + 1, |i| i + 1) as f32;
+        let mask = mask.get_or_insert_with(|| ImageBuf::new(image.width, image.height, 1));
+        for y in 0..image.height {
+            for x in 0..image.width {
+                if inside_polygon(points, (x as f32 + 0.5, y as f32 + 0.5)) {
+                    mask.set(x, y, 0, value);
+                }
+            }
+        }
+    }
+
+    Sample {
+        image,
+        mask,
+        shapes,
+    }
+}
+
+/// `centre + transform * (p - centre) + shift`
+fn about_centre(image: &ImageBuf, linear: [f64; 4], shift: (f64, f64)) -> Affine {
+    let (cx, cy) = (image.width as f64 / 2.0, image.height as f64 / 2.0);
+    let [a, b, d, e] = linear;
+    [
+        a,
+        b,
+        cx - a * cx - b * cy + shift.0,
+        d,
+        e,
+        cy - d * cx - e * cy + shift.1,
+    ]
+}
+
+/// Gamma(shape, 1) distributed sample (Marsaglia and Tsang)
+fn gamma(rng: &mut Rng, shape: f64) -> f64 {
+    if shape < 1.0 {
+        let u = 1.0 - rng.next_f64();
+        return gamma(rng, shape + 1.0) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = rng.normal();
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = 1.0 - rng.next_f64();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+fn beta(rng: &mut Rng, alpha: f64) -> f64 {
+    let x = gamma(rng, alpha.max(1e-3));
+    let y = gamma(rng, alpha.max(1e-3));
+    if x + y == 0.0 {
+        0.5
+    } else {
+        x / (x + y)
+    }
+}
+
+fn colour_jitter(image: &ImageBuf, brightness: f32, contrast: f32, saturation: f32) -> ImageBuf {
+    let colour = match image.channels {
+        2 | 4 => image.channels - 1,
+        channels => channels,
+    };
+    let gray = image.to_gray();
+    let mean = gray.data.iter().sum::<f32>() / gray.data.len().max(1) as f32;
+
+    let mut out = image.clone();
+    for (pixel, luminance) in out.data.chunks_exact_mut(image.channels).zip(&gray.data) {
+        for v in &mut pixel[..colour] {
+            let saturated = luminance + (*v - luminance) * saturation;
+            let contrasted = mean + (saturated - mean) * contrast;
+            *v = (contrasted * brightness).clamp(0.0, 1.0);
+        }
+    }
+    out
+}
+
+impl Augment {
+    /// one augmentation of every kind with default parameters
+    pub(crate) fn defaults() -> Vec<Augment> {
+        vec![
+            Augment::HorizontalFlip { p: 0.5 },
+            Augment::VerticalFlip { p: 0.5 },
+            Augment::Rotate {
+                p: 0.5,
+                max_degrees: 15.0,
+            },
+            Augment::ScaleShift {
+                p: 0.5,
+                min_scale: 0.9,
+                max_scale: 1.1,
+                max_shift: 0.1,
+            },
+            Augment::ColourJitter {
+                p: 0.5,
+                brightness: 0.2,
+                contrast: 0.2,
+                saturation: 0.2,
+            },
+            Augment::Blur {
+                p: 0.2,
+                min_sigma: 0.5,
+                max_sigma: 1.5,
+            },
+            Augment::Noise { p: 0.2, std: 0.03 },
+            Augment::Cutout {
+                p: 0.3,
+                holes: 1,
+                size: 0.2,
+            },
+            Augment::Mixup { p: 0.2, alpha: 0.4 },
+        ]
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Augment::HorizontalFlip { .. } => "horizontal flip",
+            Augment::VerticalFlip { .. } => "vertical flip",
+            Augment::Rotate { .. } => "rotate",
+            Augment::ScaleShift { .. } => "scale / shift",
+            Augment::ColourJitter { .. } => "colour jitter",
+            Augment::Blur { .. } => "blur",
+            Augment::Noise { .. } => "noise",
+            Augment::Cutout { .. } => "cutout",
+            Augment::Mixup { .. } => "mixup",
+        }
+    }
+
+    /// named numeric parameters, the probability comes first
+    pub(crate) fn params(&self) -> Vec<(&'static str, f64)> {
+        match self {
+            Augment::HorizontalFlip { p } | Augment::VerticalFlip { p } => vec![("p", *p)],
+            Augment::Rotate { p, max_degrees } => vec![("p", *p), ("max degrees", *max_degrees)],
+            Augment::ScaleShift {
+                p,
+                min_scale,
+                max_scale,
+                max_shift,
+            } => vec![
+                ("p", *p),
+                ("min scale", *min_scale),
+                ("max scale", *max_scale),
+                ("max shift", *max_shift),
+            ],
+            Augment::ColourJitter {
+                p,
+                brightness,
+                contrast,
+                saturation,
+            } => vec![
+                ("p", *p),
+                ("brightness", *brightness),
+                ("contrast", *contrast),
+                ("saturation", *saturation),
+            ],
+            Augment::Blur {
+                p,
+                min_sigma,
+                max_sigma,
+            } => vec![
+                ("p", *p),
+                ("min sigma", *min_sigma),
+                ("max sigma", *max_sigma),
+            ],
+            Augment::Noise { p, std } => vec![("p", *p), ("std", *std)],
+            Augment::Cutout { p, holes, size } => {
+                vec![("p", *p), ("holes", *holes as f64), ("size", *size)]
+            }
+            Augment::Mixup { p, alpha } => vec![("p", *p), ("alpha", *alpha)],
+        }
+    }
+
+    /// set the parameter at `index` of [`Augment::params`], out of range indices are ignored
+    pub(crate) fn set_param(&mut self, index: usize, value: f64) {
+        let value = value.max(0.0);
+        match self {
+            Augment::HorizontalFlip { p } | Augment::VerticalFlip { p } => {
+                if index == 0 {
+                    *p = value.min(1.0);
+                }
+            }
+            Augment::Rotate { p, max_degrees } => match index {
+                0 => *p = value.min(1.0),
+                1 => *max_degrees = value.min(180.0),
+                _ => {}
+            },
+            Augment::ScaleShift {
+                p,
+                min_scale,
+                max_scale,
+                max_shift,
+            } => match index {
+                0 => *p = value.min(1.0),
+                1 => *min_scale = value.max(0.01),
+                2 => *max_scale = value.max(0.01),
+                3 => *max_shift = value.min(1.0),
+                _ => {}
+            },
+            Augment::ColourJitter {
+                p,
+                brightness,
+                contrast,
+                saturation,
+            } => match index {
+                0 => *p = value.min(1.0),
+                1 => *brightness = value,
+                2 => *contrast = value,
+                3 => *saturation = value,
+                _ => {}
+            },
+            Augment::Blur {
+                p,
+                min_sigma,
+                max_sigma,
+            } => match index {
+                0 => *p = value.min(1.0),
+                1 => *min_sigma = value,
+                2 => *max_sigma = value,
+                _ => {}
+            },
+            Augment::Noise { p, std } => match index {
+                0 => *p = value.min(1.0),
+                1 => *std = value,
+                _ => {}
+            },
+            Augment::Cutout { p, holes, size } => match index {
+                0 => *p = value.min(1.0),
+                1 => *holes = value.round() as usize,
+                2 => *size = value.min(1.0),
+                _ => {}
+            },
+            Augment::Mixup { p, alpha } => match index {
+                0 => *p = value.min(1.0),
+                1 => *alpha = value.max(0.01),
+                _ => {}
+            },
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let params: Vec<String> = self
+            .params()
+            .iter()
+            .map(|(name, value)| format!("{} {}", name, value))
+            .collect();
+        format!("{} ({})", self.name(), params.join(", "))
+    }
+
+    fn probability(&self) -> f64 {
+        self.params()[0].1
+    }
+
+    /// # apply the augmentation (with its probability)
+    ///
+    /// `partner` is the sample blended in by mixup, mixup does nothing without one.
+    pub(crate) fn apply(&self, sample: &Sample, partner: Option<&Sample>, rng: &mut Rng) -> Sample {
+        if !rng.chance(self.probability()) {
+            return sample.clone();
+        }
+        let image = &sample.image;
+        let (w, h) = (image.width as f64, image.height as f64);
+
+        match self {
+            Augment::HorizontalFlip { .. } => {
+                transform_sample(sample, &[-1.0, 0.0, w, 0.0, 1.0, 0.0])
+            }
+            Augment::VerticalFlip { .. } => {
+                transform_sample(sample, &[1.0, 0.0, 0.0, 0.0, -1.0, h])
+            }
+            Augment::Rotate { max_degrees, .. } => {
+                let angle = rng.uniform(-max_degrees, *max_degrees).to_radians();
+                let (sin, cos) = angle.sin_cos();
+                transform_sample(
+                    sample,
+                    &about_centre(image, [cos, -sin, sin, cos], (0.0, 0.0)),
+                )
+            }
+            Augment::ScaleShift {
+                min_scale,
+                max_scale,
+                max_shift,
+                ..
+            } => {
+                let scale = rng.uniform(*min_scale, max_scale.max(*min_scale));
+                let shift = (
+                    rng.uniform(-max_shift, *max_shift) * w,
+                    rng.uniform(-max_shift, *max_shift) * h,
+                );
+                transform_sample(
+                    sample,
+                    &about_centre(image, [scale, 0.0, 0.0, scale], shift),
+                )
+            }
+            Augment::ColourJitter {
+                brightness,
+                contrast,
+                saturation,
+                ..
+            } => {
+                let mut factor = |range: f64| rng.uniform(1.0 - range, 1.0 + range).max(0.0) as f32;
+                let (b, c, s) = (factor(*brightness), factor(*contrast), factor(*saturation));
+                Sample {
+                    image: colour_jitter(image, b, c, s),
+                    ..sample.clone()
+                }
+            }
+            Augment::Blur {
+                min_sigma,
+                max_sigma,
+                ..
+            } => Sample {
+                image: gaussian_blur(image, rng.uniform(*min_sigma, max_sigma.max(*min_sigma))),
+                ..sample.clone()
+            },
+            Augment::Noise { std, .. } => {
+                let mut out = sample.clone();
+                for v in out.image.data.iter_mut() {
+                    *v = (*v + (rng.normal() * std) as f32).clamp(0.0, 1.0);
+                }
+                out
+            }
+            Augment::Cutout { holes, size, .. } => {
+                let mut out = sample.clone();
+                let side = ((w.min(h) * size).round() as usize).max(1);
+                for _ in 0..*holes {
+                    let x0 = rng.below(image.width.max(1));
+                    let y0 = rng.below(image.height.max(1));
+                    for y in y0..(y0 + side).min(image.height) {
+                        for x in x0..(x0 + side).min(image.width) {
+                            for c in 0..image.channels.min(3) {
+                                out.image.set(x, y, c, 0.5);
+                            }
+                        }
+                    }
+                }
+                out
+            }
+            Augment::Mixup { alpha, .. } => {
+                let Some(partner) = partner else {
+                    return sample.clone();
+                };
+                let lambda = beta(rng, *alpha) as f32;
+                let other = partner.image.resize_bilinear(image.width, image.height);
+                let (sx, sy) = (
+                    w as f32 / partner.image.width.max(1) as f32,
+                    h as f32 / partner.image.height.max(1) as f32,
+                );
+                let scale = [sx as f64, 0.0, 0.0, 0.0, sy as f64, 0.0];
+
+                let mut out = sample.clone();
+                for (v, o) in out.image.data.iter_mut().zip(&other.data) {
+                    *v = lambda * *v + (1.0 - lambda) * o;
+                }
+                for shape in out.shapes.iter_mut() {
+                    shape.weight *= lambda;
+                }
+                out.shapes.extend(partner.shapes.iter().filter_map(|s| {
+                    Some(SampleShape {
+                        shape: transform_shape(&s.shape, &scale, w as f32, h as f32)?,
+                        weight: s.weight * (1.0 - lambda),
+                        class: s.class.clone(),
+                    })
+                }));
+                out
+            }
+        }
+    }
+}
+
+impl AugmentationConfig {
+    /// apply all steps in order
+    pub(crate) fn augment(
+        &self,
+        sample: &Sample,
+        partner: Option<&Sample>,
+        rng: &mut Rng,
+    ) -> Sample {
+        self.steps.iter().fold(sample.clone(), |sample, step| {
+            step.apply(&sample, partner, rng)
+        })
+    }
+
+    /// # `count` augmented versions of a sample (e.g. for the preview grid)
+    ///
+    /// the versions only depend on the seed of the config, the sample and the partners;
+    /// mixup partners are picked from `partners` at random.
+    pub(crate) fn preview(
+        &self,
+        sample: &Sample,
+        partners: &[Sample],
+        count: usize,
+    ) -> Vec<Sample> {
+        let mut rng = Rng::new(self.seed);
+        (0..count)
+            .map(|_| {
+                let partner = if partners.is_empty() {
+                    None
+                } else {
+                    Some(&partners[rng.below(partners.len())])
+                };
+                self.augment(sample, partner, &mut rng)
+            })
+            .collect()
+    }
+}
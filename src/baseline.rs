@@ -0,0 +1,168 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Baseline classifiers
+//!
+//! Trivial models that train instantly. They show whether the data and the training setup
+//! work at all and give the score every real model has to beat.
+
+use crate::dataset::Dataset;
+use crate::metrics::softmax;
+use crate::model::{
+    bool_param, evaluate, load_json, save_json, FitContext, Hyperparameter, Model, Params,
+    Standardiser,
+};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Predicts the (weighted) class frequencies of the training data
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct MajorityClass {
+    probabilities: Vec<f64>,
+}
+
+/// Assigns the class with the closest mean of the standardised features
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct NearestCentroid {
+    standardise: bool,
+    standardiser: Option<Standardiser>,
+    centroids: Vec<Vec<f32>>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Model for MajorityClass {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        Vec::new()
+    }
+
+    fn new(_params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(MajorityClass::default())
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let mut totals = vec![0.0; data.classes.len()];
+        for i in &context.train {
+            if let Some(label) = data.samples[*i].label {
+                totals[label] += context.weight(data, *i);
+            }
+        }
+        let sum: f64 = totals.iter().sum();
+        if sum <= 0.0 {
+            return Err("no labelled training samples".into());
+        }
+        self.probabilities = totals.iter().map(|t| t / sum).collect();
+
+        let metrics = evaluate(self, data, &context.train);
+        context.end_epoch(data, self, 1, 1, metrics, None, context.train.len());
+        Ok(())
+    }
+
+    fn predict(&self, _x: &[f32]) -> Vec<f64> {
+        self.probabilities.clone()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl Model for NearestCentroid {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![Hyperparameter::bool(
+            "standardise",
+            "scale every feature to zero mean and unit variance first",
+            true,
+        )]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(NearestCentroid {
+            standardise: bool_param(params, "standardise"),
+            ..NearestCentroid::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        self.standardiser = self
+            .standardise
+            .then(|| Standardiser::fit(data, &context.train));
+        let size = data.input_size();
+        let mut sums = vec![vec![0.0f64; size]; data.classes.len()];
+        let mut counts = vec![0usize; data.classes.len()];
+        for i in &context.train {
+            let sample = &data.samples[*i];
+            let Some(label) = sample.label else {
+                continue;
+            };
+            for (s, v) in sums[label].iter_mut().zip(self.transform(&sample.x)) {
+                *s += v as f64;
+            }
+            counts[label] += 1;
+        }
+        if counts.iter().all(|c| *c == 0) {
+            return Err("no labelled training samples".into());
+        }
+        self.centroids = sums
+            .into_iter()
+            .zip(&counts)
+            .map(|(sum, count)| {
+                if *count == 0 {
+                    // classes without training samples are never predicted
+                    Vec::new()
+                } else {
+                    sum.iter().map(|s| (s / *count as f64) as f32).collect()
+                }
+            })
+            .collect();
+
+        let metrics = evaluate(self, data, &context.train);
+        context.end_epoch(data, self, 1, 1, metrics, None, context.train.len());
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let x = self.transform(x);
+        // softmax over negative squared distances
+        let scores: Vec<f64> = self
+            .centroids
+            .iter()
+            .map(|c| {
+                if c.is_empty() {
+                    f64::NEG_INFINITY
+                } else {
+                    -c.iter()
+                        .zip(&x)
+                        .map(|(a, b)| ((a - b) as f64).powi(2))
+                        .sum::<f64>()
+                }
+            })
+            .collect();
+        softmax(&scores)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl NearestCentroid {
+    fn transform(&self, x: &[f32]) -> Vec<f32> {
+        match &self.standardiser {
+            Some(standardiser) => standardiser.apply(x),
+            None => x.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Gaussian naive Bayes classifier
+//!
+//! Every feature is modelled as an independent normal distribution per class. Training is a
+//! single pass over the data, which makes it a quick sanity check for new feature sets.
+
+use crate::dataset::Dataset;
+use crate::metrics::{softmax, Metrics};
+use crate::model::{
+    evaluate, float_param, load_json, save_json, FitContext, Hyperparameter, Model, Params,
+};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Class priors and per class means and variances of every feature
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct GaussianNaiveBayes {
+    /// added to all variances, as a fraction of the largest feature variance
+    var_smoothing: f64,
+    /// `ln P(class)`, `-inf` for classes without training samples
+    log_priors: Vec<f64>,
+    means: Vec<Vec<f64>>,
+    variances: Vec<Vec<f64>>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Model for GaussianNaiveBayes {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![Hyperparameter::float(
+            "var_smoothing",
+            "portion of the largest feature variance added to all variances",
+            1e-9,
+            1e-12,
+            1.0,
+            true,
+        )]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(GaussianNaiveBayes {
+            var_smoothing: float_param(params, "var_smoothing"),
+            ..GaussianNaiveBayes::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let (classes, features) = (data.classes.len(), data.input_size());
+        // weighted sums, so class weights shift the priors
+        let mut weights = vec![0.0; classes];
+        let mut sums = vec![vec![0.0; features]; classes];
+        let mut squares = vec![vec![0.0; features]; classes];
+        for i in &context.train {
+            let sample = &data.samples[*i];
+            let Some(label) = sample.label else {
+                continue;
+            };
+            let weight = context.weight(data, *i);
+            weights[label] += weight;
+            for (f, v) in sample.x.iter().enumerate() {
+                sums[label][f] += weight * *v as f64;
+                squares[label][f] += weight * (*v as f64) * (*v as f64);
+            }
+        }
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return Err("no labelled training samples".into());
+        }
+
+        self.means = (0..classes)
+            .map(|c| {
+                sums[c]
+                    .iter()
+                    .map(|s| s / weights[c].max(f64::MIN_POSITIVE))
+                    .collect()
+            })
+            .collect();
+        self.variances = (0..classes)
+            .map(|c| {
+                (0..features)
+                    .map(|f| {
+                        (squares[c][f] / weights[c].max(f64::MIN_POSITIVE)
+                            - self.means[c][f].powi(2))
+                        .max(0.0)
+                    })
+                    .collect()
+            })
+            .collect();
+        let largest = self.variances.iter().flatten().copied().fold(0.0, f64::max);
+        let epsilon = (self.var_smoothing * largest).max(1e-12);
+        for variance in self.variances.iter_mut().flatten() {
+            *variance += epsilon;
+        }
+        self.log_priors = weights
+            .iter()
+            .map(|w| {
+                if *w > 0.0 {
+                    (w / total).ln()
+                } else {
+                    f64::NEG_INFINITY
+                }
+            })
+            .collect();
+
+        let metrics: Metrics = evaluate(self, data, &context.train);
+        let samples = context.train.len();
+        context.end_epoch(data, self, 1, 1, metrics, None, samples);
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let scores: Vec<f64> = self
+            .log_priors
+            .iter()
+            .enumerate()
+            .map(|(c, prior)| {
+                if !prior.is_finite() {
+                    return f64::NEG_INFINITY;
+                }
+                let likelihood: f64 = x
+                    .iter()
+                    .zip(self.means[c].iter().zip(&self.variances[c]))
+                    .map(|(v, (mean, variance))| {
+                        -0.5 * ((2.0 * std::f64::consts::PI * variance).ln()
+                            + (*v as f64 - mean).powi(2) / variance)
+                    })
+                    .sum();
+                prior + likelihood
+            })
+            .collect();
+        softmax(&scores)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
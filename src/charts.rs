@@ -0,0 +1,615 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Simple charts drawn with cairo
+//!
+//! The same drawing code renders into a `gtk::DrawingArea` and into PNG / SVG files, so an
+//! exported chart looks exactly like the one on screen.
+
+use gtk::cairo;
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+/// size of a single chart in exported files
+pub(crate) const EXPORT_CHART_WIDTH: f64 = 420.0;
+pub(crate) const EXPORT_CHART_HEIGHT: f64 = 260.0;
+/// number of charts per row in exported files
+const EXPORT_COLUMNS: usize = 2;
+
+/// rgb colour with components between 0 and 1
+pub(crate) type Rgb = (f64, f64, f64);
+
+/// One bar of a [`BarChart`]
+#[derive(Debug, Clone)]
+pub(crate) struct Bar {
+    pub(crate) label: String,
+    pub(crate) value: f64,
+    pub(crate) colour: Rgb,
+}
+
+/// A titled bar chart (also used for histograms, one bar per bin)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BarChart {
+    pub(crate) title: String,
+    pub(crate) bars: Vec<Bar>,
+}
+
+/// A named line of a [`LineChart`]
+#[derive(Debug, Clone)]
+pub(crate) struct Series {
+    pub(crate) label: String,
+    /// `(x, y)` points, drawn in the given order
+    pub(crate) points: Vec<(f64, f64)>,
+    pub(crate) colour: Rgb,
+    /// dashed lines, e.g. to tell validation from training curves
+    pub(crate) dashed: bool,
+}
+
+/// A titled line chart with a shared value range for all series
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LineChart {
+    pub(crate) title: String,
+    pub(crate) x_label: String,
+    pub(crate) series: Vec<Series>,
+}
+
+/// A vertical axis of a [`ParallelChart`]
+#[derive(Debug, Clone)]
+pub(crate) struct ParallelAxis {
+    pub(crate) label: String,
+    /// labels spread evenly from the bottom to the top of the axis
+    pub(crate) ticks: Vec<String>,
+}
+
+/// A line of a [`ParallelChart`], crossing every axis
+#[derive(Debug, Clone)]
+pub(crate) struct ParallelLine {
+    /// height on every axis, 0 at the bottom and 1 at the top
+    pub(crate) values: Vec<f64>,
+    pub(crate) colour: Rgb,
+    /// drawn thicker and on top of the others
+    pub(crate) highlighted: bool,
+}
+
+/// A titled parallel coordinates plot, e.g. hyperparameters and the score of many runs
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParallelChart {
+    pub(crate) title: String,
+    pub(crate) axes: Vec<ParallelAxis>,
+    pub(crate) lines: Vec<ParallelLine>,
+}
+
+/// distinct colours for charts without a given colour per bar
+pub(crate) fn palette_colour(index: usize) -> Rgb {
+    const PALETTE: [Rgb; 8] = [
+        (0.12, 0.47, 0.71),
+        (1.00, 0.50, 0.05),
+        (0.17, 0.63, 0.17),
+        (0.84, 0.15, 0.16),
+        (0.58, 0.40, 0.74),
+        (0.55, 0.34, 0.29),
+        (0.89, 0.47, 0.76),
+        (0.50, 0.50, 0.50),
+    ];
+    PALETTE[index % PALETTE.len()]
+}
+
+/// # draw a bar chart
+///
+/// draws title, bars, value labels and (if there is enough room) bar labels into the
+/// rectangle `(0, 0, width, height)` of the given context.
+pub(crate) fn draw_bar_chart(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    chart: &BarChart,
+) -> Result<(), cairo::Error> {
+    let margin = 10.0;
+    let title_height = 20.0;
+    let label_height = 16.0;
+
+    cr.set_source_rgb(1.0, 1.0, 1.0);
+    cr.rectangle(0.0, 0.0, width, height);
+    cr.fill()?;
+
+    cr.set_source_rgb(0.0, 0.0, 0.0);
+    cr.set_font_size(13.0);
+    cr.move_to(margin, margin + 12.0);
+    cr.show_text(&chart.title)?;
+
+    let plot_top = margin + title_height + label_height;
+    let plot_bottom = height - margin - label_height;
+    let plot_height = (plot_bottom - plot_top).max(1.0);
+    let plot_width = (width - 2.0 * margin).max(1.0);
+
+    if chart.bars.is_empty() {
+        cr.set_font_size(11.0);
+        cr.move_to(margin, plot_top + plot_height / 2.0);
+        cr.show_text("no data")?;
+        return Ok(());
+    }
+
+    let max_value = chart
+        .bars
+        .iter()
+        .map(|bar| bar.value)
+        .fold(0.0, f64::max)
+        .max(f64::EPSILON);
+    let slot = plot_width / chart.bars.len() as f64;
+    let bar_width = (slot * 0.8).max(1.0);
+
+    cr.set_font_size(10.0);
+    for (i, bar) in chart.bars.iter().enumerate() {
+        let bar_height = plot_height * bar.value.max(0.0) / max_value;
+        let x = margin + slot * i as f64 + (slot - bar_width) / 2.0;
+
+        cr.set_source_rgb(bar.colour.0, bar.colour.1, bar.colour.2);
+        cr.rectangle(x, plot_bottom - bar_height, bar_width, bar_height);
+        cr.fill()?;
+
+        // value above the bar and label below it, labels are skipped if the bars are too small
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        let value = if bar.value.fract() == 0.0 {
+            format!("{:.0}", bar.value)
+        } else {
+            format!("{:.2}", bar.value)
+        };
+        let extents = cr.text_extents(&value)?;
+        if extents.width() <= slot {
+            cr.move_to(
+                x + (bar_width - extents.width()) / 2.0,
+                plot_bottom - bar_height - 3.0,
+            );
+            cr.show_text(&value)?;
+        }
+
+        let extents = cr.text_extents(&bar.label)?;
+        if extents.width() <= slot {
+            cr.move_to(
+                x + (bar_width - extents.width()) / 2.0,
+                plot_bottom + label_height - 4.0,
+            );
+            cr.show_text(&bar.label)?;
+        }
+    }
+
+    // x axis
+    cr.set_source_rgb(0.3, 0.3, 0.3);
+    cr.set_line_width(1.0);
+    cr.move_to(margin, plot_bottom + 0.5);
+    cr.line_to(width - margin, plot_bottom + 0.5);
+    cr.stroke()?;
+
+    Ok(())
+}
+
+/// short axis label of a value, e.g. `0.25`, `1200`, `1.5e-5`
+fn axis_value(value: f64) -> String {
+    if value != 0.0 && (value.abs() < 1e-2 || value.abs() >= 1e5) {
+        format!("{:.1e}", value)
+    } else if value.fract() == 0.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.3}", value)
+    }
+}
+
+/// # draw a line chart
+///
+/// draws title, axes with their ranges, every series as a line with dots and a legend into
+/// the rectangle `(0, 0, width, height)` of the given context.
+pub(crate) fn draw_line_chart(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    chart: &LineChart,
+) -> Result<(), cairo::Error> {
+    let margin = 10.0;
+    let title_height = 20.0;
+    let axis_width = 48.0;
+    let label_height = 16.0;
+
+    cr.set_source_rgb(1.0, 1.0, 1.0);
+    cr.rectangle(0.0, 0.0, width, height);
+    cr.fill()?;
+
+    cr.set_source_rgb(0.0, 0.0, 0.0);
+    cr.set_font_size(13.0);
+    cr.move_to(margin, margin + 12.0);
+    cr.show_text(&chart.title)?;
+
+    let left = margin + axis_width;
+    let top = margin + title_height;
+    let bottom = height - margin - label_height;
+    let plot_width = (width - left - margin).max(1.0);
+    let plot_height = (bottom - top).max(1.0);
+
+    let points: Vec<(f64, f64)> = chart
+        .series
+        .iter()
+        .flat_map(|series| series.points.iter().copied())
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .collect();
+    if points.is_empty() {
+        cr.set_font_size(11.0);
+        cr.move_to(margin, top + plot_height / 2.0);
+        cr.show_text("no data")?;
+        return Ok(());
+    }
+    let (x_min, x_max, y_min, y_max) = points.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(x0, x1, y0, y1), (x, y)| (x0.min(*x), x1.max(*x), y0.min(*y), y1.max(*y)),
+    );
+    let x_range = (x_max - x_min).max(f64::EPSILON);
+    let y_range = (y_max - y_min).max(f64::EPSILON);
+    let to_screen = |x: f64, y: f64| {
+        (
+            left + (x - x_min) / x_range * plot_width,
+            bottom - (y - y_min) / y_range * plot_height,
+        )
+    };
+
+    // axes and their ranges
+    cr.set_source_rgb(0.3, 0.3, 0.3);
+    cr.set_line_width(1.0);
+    cr.move_to(left - 0.5, top);
+    cr.line_to(left - 0.5, bottom + 0.5);
+    cr.line_to(width - margin, bottom + 0.5);
+    cr.stroke()?;
+    cr.set_font_size(10.0);
+    for (value, y) in [(y_max, top + 8.0), (y_min, bottom)] {
+        let text = axis_value(value);
+        let extents = cr.text_extents(&text)?;
+        cr.move_to(left - extents.width() - 4.0, y);
+        cr.show_text(&text)?;
+    }
+    let x_text = format!(
+        "{} {} .. {}",
+        chart.x_label,
+        axis_value(x_min),
+        axis_value(x_max)
+    );
+    cr.move_to(left, bottom + label_height - 3.0);
+    cr.show_text(&x_text)?;
+
+    for series in &chart.series {
+        let (r, g, b) = series.colour;
+        cr.set_source_rgb(r, g, b);
+        cr.set_line_width(1.5);
+        cr.set_dash(if series.dashed { &[5.0, 3.0] } else { &[] }, 0.0);
+        for (x, y) in &series.points {
+            let (sx, sy) = to_screen(*x, *y);
+            cr.line_to(sx, sy);
+        }
+        cr.stroke()?;
+        cr.set_dash(&[], 0.0);
+        if series.points.len() <= plot_width as usize / 6 {
+            for (x, y) in &series.points {
+                let (sx, sy) = to_screen(*x, *y);
+                cr.arc(sx, sy, 2.0, 0.0, 2.0 * std::f64::consts::PI);
+                cr.fill()?;
+            }
+        }
+    }
+
+    // legend in the top right corner
+    let mut y = top + 10.0;
+    for series in &chart.series {
+        let extents = cr.text_extents(&series.label)?;
+        let x = width - margin - extents.width();
+        let (r, g, b) = series.colour;
+        cr.set_source_rgb(r, g, b);
+        cr.rectangle(x - 12.0, y - 7.0, 8.0, 8.0);
+        cr.fill()?;
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        cr.move_to(x, y);
+        cr.show_text(&series.label)?;
+        y += 13.0;
+    }
+    Ok(())
+}
+
+/// # draw a parallel coordinates plot
+///
+/// draws title, the axes side by side with their labels and ticks and every line across them
+/// into the rectangle `(0, 0, width, height)` of the given context.
+pub(crate) fn draw_parallel_chart(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    chart: &ParallelChart,
+) -> Result<(), cairo::Error> {
+    let margin = 10.0;
+    let title_height = 20.0;
+    let label_height = 16.0;
+    let tick_width = 60.0;
+
+    draw_title(cr, width, height, &chart.title)?;
+    let top = margin + title_height;
+    let bottom = height - margin - label_height;
+    let plot_height = (bottom - top).max(1.0);
+    if chart.axes.is_empty() || chart.lines.is_empty() {
+        cr.set_font_size(11.0);
+        cr.move_to(margin, top + plot_height / 2.0);
+        cr.show_text("no data")?;
+        return Ok(());
+    }
+    // the ticks of the last axis go to its right
+    let left = margin + 4.0;
+    let right = width - margin - tick_width;
+    let gap = if chart.axes.len() > 1 {
+        (right - left).max(1.0) / (chart.axes.len() - 1) as f64
+    } else {
+        0.0
+    };
+    let x_of = |axis: usize| left + gap * axis as f64;
+    let y_of = |value: f64| bottom - value.clamp(0.0, 1.0) * plot_height;
+
+    let mut lines: Vec<&ParallelLine> = chart.lines.iter().collect();
+    lines.sort_by_key(|line| line.highlighted);
+    for line in lines {
+        let (r, g, b) = line.colour;
+        cr.set_source_rgb(r, g, b);
+        cr.set_line_width(if line.highlighted { 3.0 } else { 1.0 });
+        for (axis, value) in line.values.iter().enumerate() {
+            cr.line_to(x_of(axis), y_of(*value));
+        }
+        cr.stroke()?;
+    }
+
+    cr.set_font_size(10.0);
+    for (i, axis) in chart.axes.iter().enumerate() {
+        let x = x_of(i).round() + 0.5;
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.set_line_width(1.0);
+        cr.move_to(x, top);
+        cr.line_to(x, bottom);
+        cr.stroke()?;
+
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        let extents = cr.text_extents(&axis.label)?;
+        let label_x = (x - extents.width() / 2.0).clamp(0.0, (width - extents.width()).max(0.0));
+        cr.move_to(label_x, bottom + label_height - 3.0);
+        cr.show_text(&axis.label)?;
+        for (t, tick) in axis.ticks.iter().enumerate() {
+            let position = if axis.ticks.len() > 1 {
+                t as f64 / (axis.ticks.len() - 1) as f64
+            } else {
+                0.5
+            };
+            cr.move_to(x + 3.0, y_of(position) + 3.0);
+            cr.show_text(tick)?;
+        }
+    }
+    Ok(())
+}
+
+/// # colour of a heatmap cell
+///
+/// `t` between 0 and 1 runs from dark blue over magenta and orange to light yellow.
+pub(crate) fn heatmap_colour(t: f64) -> Rgb {
+    const STOPS: [Rgb; 5] = [
+        (0.05, 0.03, 0.20),
+        (0.35, 0.05, 0.50),
+        (0.75, 0.20, 0.45),
+        (0.98, 0.55, 0.20),
+        (0.99, 0.95, 0.65),
+    ];
+    let position = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (position.floor() as usize).min(STOPS.len() - 2);
+    let f = position - i as f64;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    (
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
+fn draw_title(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    title: &str,
+) -> Result<(), cairo::Error> {
+    cr.set_source_rgb(1.0, 1.0, 1.0);
+    cr.rectangle(0.0, 0.0, width, height);
+    cr.fill()?;
+    cr.set_source_rgb(0.0, 0.0, 0.0);
+    cr.set_font_size(12.0);
+    cr.move_to(4.0, 13.0);
+    cr.show_text(title)
+}
+
+/// # draw a waveform overview
+///
+/// `envelope` holds the (min, max) sample value of every column, values are between -1 and 1.
+pub(crate) fn draw_waveform(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    title: &str,
+    envelope: &[(f32, f32)],
+) -> Result<(), cairo::Error> {
+    draw_title(cr, width, height, title)?;
+    let top = 18.0;
+    let middle = top + (height - top) / 2.0;
+    let amplitude = (height - top) / 2.0;
+
+    cr.set_source_rgb(0.12, 0.47, 0.71);
+    cr.set_line_width(1.0);
+    let column = width / envelope.len().max(1) as f64;
+    for (i, (min, max)) in envelope.iter().enumerate() {
+        let x = (i as f64 + 0.5) * column;
+        cr.move_to(x, middle - max.clamp(-1.0, 1.0) as f64 * amplitude);
+        cr.line_to(x, middle - min.clamp(-1.0, 1.0) as f64 * amplitude + 1.0);
+    }
+    cr.stroke()
+}
+
+/// # draw a matrix as heatmap
+///
+/// `matrix` is indexed `[column][row]` (e.g. `[frame][frequency bin]`), row 0 is drawn at
+/// the bottom. The colours span the value range of the whole matrix.
+pub(crate) fn draw_heatmap(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    title: &str,
+    matrix: &[Vec<f32>],
+) -> Result<(), cairo::Error> {
+    draw_title(cr, width, height, title)?;
+    let columns = matrix.len();
+    let rows = matrix.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 || rows == 0 {
+        return Ok(());
+    }
+
+    let (min, max) = matrix
+        .iter()
+        .flatten()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    let range = (max - min).max(f32::EPSILON);
+
+    // one pixel per cell, scaled up to the drawing area
+    let stride = cairo::Format::Rgb24.stride_for_width(columns as u32)?;
+    let mut data = vec![0u8; stride as usize * rows];
+    for (x, column) in matrix.iter().enumerate() {
+        for (y, v) in column.iter().enumerate() {
+            let (r, g, b) = heatmap_colour(((v - min) / range) as f64);
+            let offset = (rows - 1 - y) * stride as usize + x * 4;
+            // native endian 0x00RRGGBB
+            let pixel =
+                u32::from_be_bytes([0, (r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]);
+            data[offset..offset + 4].copy_from_slice(&pixel.to_ne_bytes());
+        }
+    }
+    let surface = cairo::ImageSurface::create_for_data(
+        data,
+        cairo::Format::Rgb24,
+        columns as i32,
+        rows as i32,
+        stride,
+    )?;
+
+    let top = 18.0;
+    cr.save()?;
+    cr.translate(0.0, top);
+    cr.scale(
+        width / columns as f64,
+        (height - top).max(1.0) / rows as f64,
+    );
+    cr.set_source_surface(&surface, 0.0, 0.0)?;
+    // sharp cells instead of a blurry interpolation
+    cr.source().set_filter(cairo::Filter::Nearest);
+    cr.paint()?;
+    cr.restore()
+}
+
+/// # draw signals as lines over a common time axis
+///
+/// every signal is scaled to its own value range. `spans` are shaded intervals and `marks`
+/// vertical lines, both given as fractions of the time axis.
+pub(crate) fn draw_signals(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    title: &str,
+    signals: &[Vec<f64>],
+    spans: &[(f64, f64, Rgb)],
+    marks: &[f64],
+) -> Result<(), cairo::Error> {
+    draw_title(cr, width, height, title)?;
+    let top = 18.0;
+    let plot_height = (height - top).max(1.0);
+
+    for (start, end, (r, g, b)) in spans {
+        cr.set_source_rgba(*r, *g, *b, 0.25);
+        cr.rectangle(start * width, top, (end - start) * width, plot_height);
+        cr.fill()?;
+    }
+
+    cr.set_source_rgba(0.3, 0.3, 0.3, 0.5);
+    cr.set_line_width(1.0);
+    for mark in marks {
+        cr.move_to((mark * width).round() + 0.5, top);
+        cr.line_to((mark * width).round() + 0.5, height);
+    }
+    cr.stroke()?;
+
+    // one band per signal
+    let band = plot_height / signals.len().max(1) as f64;
+    for (i, values) in signals.iter().enumerate() {
+        if values.is_empty() {
+            continue;
+        }
+        let (min, max) = values
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+        let range = (max - min).max(f64::EPSILON);
+        let (r, g, b) = palette_colour(i);
+        cr.set_source_rgb(r, g, b);
+        // at most one point per horizontal pixel
+        let step = (values.len() as f64 / width.max(1.0)).max(1.0);
+        let mut position = 0.0;
+        while (position as usize) < values.len() {
+            let v = values[position as usize];
+            let x = position / values.len() as f64 * width;
+            let y = top + band * (i as f64 + 0.95) - (v - min) / range * band * 0.9;
+            cr.line_to(x, y);
+            position += step;
+        }
+        cr.stroke()?;
+    }
+    Ok(())
+}
+
+/// draw several charts in a grid, as used for the exported files
+fn draw_chart_grid(cr: &cairo::Context, charts: &[BarChart]) -> Result<(), cairo::Error> {
+    for (i, chart) in charts.iter().enumerate() {
+        cr.save()?;
+        cr.translate(
+            (i % EXPORT_COLUMNS) as f64 * EXPORT_CHART_WIDTH,
+            (i / EXPORT_COLUMNS) as f64 * EXPORT_CHART_HEIGHT,
+        );
+        draw_bar_chart(cr, EXPORT_CHART_WIDTH, EXPORT_CHART_HEIGHT, chart)?;
+        cr.restore()?;
+    }
+    Ok(())
+}
+
+fn export_size(charts: &[BarChart]) -> (f64, f64) {
+    let rows = charts.len().div_ceil(EXPORT_COLUMNS).max(1);
+    let columns = charts.len().clamp(1, EXPORT_COLUMNS);
+    (
+        columns as f64 * EXPORT_CHART_WIDTH,
+        rows as f64 * EXPORT_CHART_HEIGHT,
+    )
+}
+
+/// write all charts into one PNG file
+pub(crate) fn export_png(charts: &[BarChart], path: &Path) -> Result<(), Box<dyn Error>> {
+    let (width, height) = export_size(charts);
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)?;
+    {
+        let cr = cairo::Context::new(&surface)?;
+        draw_chart_grid(&cr, charts)?;
+    }
+    let mut file = File::create(path)?;
+    surface.write_to_png(&mut file)?;
+    Ok(())
+}
+
+/// write all charts into one SVG file
+pub(crate) fn export_svg(charts: &[BarChart], path: &Path) -> Result<(), Box<dyn Error>> {
+    let (width, height) = export_size(charts);
+    let surface = cairo::SvgSurface::new(width, height, Some(path))?;
+    {
+        let cr = cairo::Context::new(&surface)?;
+        draw_chart_grid(&cr, charts)?;
+    }
+    surface.finish();
+    Ok(())
+}
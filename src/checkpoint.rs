@@ -0,0 +1,249 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Checkpoints of training runs
+//!
+//! Iterative models write a checkpoint into their run directory after every few epochs: the
+//! model itself (weights and optimiser state, see [`Model::save`]) and `checkpoint.json` with
+//! the epoch, the reports so far and the state of the training loop (RNG, sample order, early
+//! stopping). A stopped or crashed run continues from there as if it had never been
+//! interrupted.
+//!
+//! Independent of the checkpoints, the model of the epoch with the best validation metric is
+//! kept as `best_model.json`.
+
+use crate::debug_println;
+use crate::metrics::higher_is_better;
+use crate::model::{load_json, save_json, EpochReport, Model};
+use crate::store::unix_now;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// file name of the latest checkpoint inside a run directory
+pub(crate) const CHECKPOINT_FILE_NAME: &str = "checkpoint.json";
+
+/// file name of the model of the best epoch inside a run directory
+pub(crate) const BEST_MODEL_FILE_NAME: &str = "best_model.json";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// When to write checkpoints and how to pick the best epoch
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct CheckpointConfig {
+    /// write a checkpoint after every `every` epochs, 0 for never
+    pub(crate) every: usize,
+    /// metric deciding the best epoch, e.g. `val_loss` or `val_accuracy`
+    pub(crate) best_metric: String,
+}
+
+/// The best epoch of a run so far
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct BestEpoch {
+    pub(crate) epoch: usize,
+    pub(crate) metric: String,
+    pub(crate) value: f64,
+}
+
+/// State of a run after a finished epoch, enough to continue training exactly from there
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Checkpoint {
+    /// last finished epoch (1 based)
+    pub(crate) epoch: usize,
+    /// unix timestamp (seconds) of writing the checkpoint
+    pub(crate) written: u64,
+    /// file of the saved model, relative to the run directory
+    pub(crate) model_file: String,
+    pub(crate) best: Option<BestEpoch>,
+    /// reports of all epochs up to `epoch`, oldest first
+    pub(crate) reports: Vec<EpochReport>,
+    /// state of the training loop, specific to the model
+    pub(crate) state: serde_json::Value,
+}
+
+/// Writes the checkpoints of a run and keeps track of its best epoch
+pub(crate) struct Checkpointer {
+    run_dir: PathBuf,
+    config: CheckpointConfig,
+    pub(crate) best: Option<BestEpoch>,
+    reports: Vec<EpochReport>,
+    /// checkpoint the run continues from, until the model takes it
+    resume: Option<Checkpoint>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        CheckpointConfig {
+            every: 1,
+            best_metric: "val_loss".to_string(),
+        }
+    }
+}
+
+impl BestEpoch {
+    /// whether `value` of `metric` beats this epoch
+    pub(crate) fn beaten_by(&self, metric: &str, value: f64) -> bool {
+        if higher_is_better(metric) {
+            value > self.value
+        } else {
+            value < self.value
+        }
+    }
+}
+
+impl Checkpointer {
+    /// checkpoints of a new run
+    pub(crate) fn new(run_dir: &Path, config: CheckpointConfig) -> Checkpointer {
+        Checkpointer {
+            run_dir: run_dir.to_path_buf(),
+            config,
+            best: None,
+            reports: Vec::new(),
+            resume: None,
+        }
+    }
+
+    /// # continue a run from its latest checkpoint
+    ///
+    /// returns:
+    ///     Result with the checkpointer and the path of the checkpointed model
+    pub(crate) fn resume(
+        run_dir: &Path,
+        config: CheckpointConfig,
+    ) -> Result<(Checkpointer, PathBuf), Box<dyn Error>> {
+        let checkpoint = load_checkpoint(run_dir)?;
+        let model_path = run_dir.join(&checkpoint.model_file);
+        let checkpointer = Checkpointer {
+            run_dir: run_dir.to_path_buf(),
+            config,
+            best: checkpoint.best.clone(),
+            reports: checkpoint.reports.clone(),
+            resume: Some(checkpoint),
+        };
+        Ok((checkpointer, model_path))
+    }
+
+    /// whether the model still has to take the checkpoint it continues from
+    pub(crate) fn is_resuming(&self) -> bool {
+        self.resume.is_some()
+    }
+
+    /// reports of the epochs so far (including the ones before resuming)
+    pub(crate) fn reports(&self) -> &[EpochReport] {
+        &self.reports
+    }
+
+    /// # take the state the training loop of the model left in the checkpoint
+    ///
+    /// returns:
+    ///     Result with the last finished epoch and the state, `None` for a fresh run
+    pub(crate) fn take_resume<S: DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<(usize, S)>, Box<dyn Error>> {
+        match self.resume.take() {
+            Some(checkpoint) => Ok(Some((
+                checkpoint.epoch,
+                serde_json::from_value(checkpoint.state)?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// # remember the report of an epoch
+    ///
+    /// saves the model as the best one if the epoch beats the best epoch so far (epochs
+    /// without the metric are ignored).
+    pub(crate) fn observe(&mut self, model: &dyn Model, report: &EpochReport) {
+        self.reports.push(report.clone());
+        let metric = &self.config.best_metric;
+        let Some(value) = report.metrics.get(metric).copied() else {
+            return;
+        };
+        if !value.is_finite()
+            || self
+                .best
+                .as_ref()
+                .is_some_and(|b| !b.beaten_by(metric, value))
+        {
+            return;
+        }
+        match model.save(&self.run_dir.join(BEST_MODEL_FILE_NAME)) {
+            Ok(()) => {
+                self.best = Some(BestEpoch {
+                    epoch: report.epoch,
+                    metric: metric.clone(),
+                    value,
+                })
+            }
+            Err(e) => debug_println!("[ERROR: CHECKPOINT] unable to save the best model: {}", e),
+        }
+    }
+
+    /// whether the checkpoint of `epoch` is due
+    pub(crate) fn due(&self, epoch: usize) -> bool {
+        self.config.every > 0 && epoch.is_multiple_of(self.config.every)
+    }
+
+    /// # write a checkpoint after a finished epoch
+    ///
+    /// the model goes into a file of its own first, so a crash while writing never leaves a
+    /// checkpoint pointing to a half written model; older model files are removed afterwards.
+    pub(crate) fn save<S: Serialize>(
+        &mut self,
+        model: &dyn Model,
+        epoch: usize,
+        state: &S,
+    ) -> Result<(), Box<dyn Error>> {
+        let model_file = format!("checkpoint-{}.model.json", epoch);
+        model.save(&self.run_dir.join(&model_file))?;
+
+        let checkpoint = Checkpoint {
+            epoch,
+            written: unix_now(),
+            model_file: model_file.clone(),
+            best: self.best.clone(),
+            reports: self
+                .reports
+                .iter()
+                .filter(|r| r.epoch <= epoch)
+                .cloned()
+                .collect(),
+            state: serde_json::to_value(state)?,
+        };
+        let path = self.run_dir.join(CHECKPOINT_FILE_NAME);
+        let temporary = path.with_extension("json.tmp");
+        save_json(&temporary, &checkpoint)?;
+        fs::rename(&temporary, &path)?;
+
+        for entry in fs::read_dir(&self.run_dir)?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("checkpoint-")
+                && name.ends_with(".model.json")
+                && name != model_file
+            {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        debug_println!(
+            "[INFO: CHECKPOINT] epoch {} of {}",
+            epoch,
+            self.run_dir.display()
+        );
+        Ok(())
+    }
+}
+
+pub(crate) fn load_checkpoint(run_dir: &Path) -> Result<Checkpoint, Box<dyn Error>> {
+    load_json(&run_dir.join(CHECKPOINT_FILE_NAME))
+}
+
+/// whether a run directory holds a checkpoint to continue from
+pub(crate) fn has_checkpoint(run_dir: &Path) -> bool {
+    run_dir.join(CHECKPOINT_FILE_NAME).is_file()
+}
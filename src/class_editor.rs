@@ -0,0 +1,426 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Class management page of the Projects tab
+
+use gtk::prelude::*;
+use gtk::{Button, Entry, Label};
+
+use crate::classes::{
+    class_colour, hex_colour, hierarchy, merge_classes, move_class, remove_class, rename_class,
+    set_hotkey, set_parent, split_class, validate_class_name, LabelClass,
+};
+use crate::debug_println;
+use crate::helper::show_error_message;
+use crate::project::{Project, SharedProject};
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+/// entry of the parent / remap drop downs that stands for "no class"
+const NO_CLASS: &str = "(none)";
+
+/// # apply a change to the opened project
+///
+/// runs `change`, saves the config on success and shows an error dialog otherwise.
+///
+/// returns:
+///     `true` if the change was applied
+fn modify_project(
+    project: &SharedProject,
+    change: impl FnOnce(&mut Project) -> Result<String, Box<dyn Error>>,
+) -> bool {
+    let mut project_ref = project.borrow_mut();
+    let Some(project) = project_ref.as_mut() else {
+        show_error_message(
+            None::<&gtk::Widget>,
+            Some("CLASSES ERROR"),
+            Some("Unable to edit classes, since no project is opened."),
+        );
+        return false;
+    };
+
+    let result = change(project).and_then(|message| {
+        project.save_config()?;
+        Ok(message)
+    });
+
+    match result {
+        Ok(message) => {
+            debug_println!("[INFO: CLASSES] {}", message);
+            true
+        }
+        Err(e) => {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("CLASSES ERROR"),
+                Some(&e.to_string()),
+            );
+            false
+        }
+    }
+}
+
+/// small coloured square in front of a class name
+fn colour_swatch(colour: (f64, f64, f64)) -> gtk::DrawingArea {
+    let swatch = gtk::DrawingArea::builder()
+        .content_width(16)
+        .content_height(16)
+        .valign(gtk::Align::Center)
+        .build();
+    swatch.set_draw_func(move |_, cr, width, height| {
+        cr.set_source_rgb(colour.0, colour.1, colour.2);
+        cr.rectangle(0.0, 0.0, width as f64, height as f64);
+        let _ = cr.fill();
+    });
+    swatch
+}
+
+/// Class page: rename, merge, split, remove, reorder and describe the project's classes
+pub(crate) fn classes_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    // class list in hierarchy order, the row index maps to a class name via `row_classes`
+    // ---------------------------------------------------------------------------------------------
+    let list_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Multiple)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(180)
+        .vexpand(true)
+        .child(&list_box)
+        .build();
+    let row_classes: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    // drop downs listing all classes (with `NO_CLASS` first)
+    let parent_names = gtk::StringList::new(&[]);
+    let remap_names = gtk::StringList::new(&[]);
+
+    let refresh = gtk::glib::clone!(@strong project, @strong list_box, @strong row_classes, @strong parent_names, @strong remap_names => move || {
+        while let Some(child) = list_box.first_child() {
+            list_box.remove(&child);
+        }
+        row_classes.borrow_mut().clear();
+
+        let project_ref = project.borrow();
+        let classes: &[LabelClass] = project_ref
+            .as_ref()
+            .map(|p| p.config.classes.as_slice())
+            .unwrap_or_default();
+
+        for (depth, index) in hierarchy(classes) {
+            let class = &classes[index];
+            let row = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(8)
+                .margin_start(8 + 20 * depth as i32)
+                .build();
+
+            row.append(&colour_swatch(class_colour(classes, index)));
+
+            let mut text = format!("{}: {}", index, class.name);
+            if let Some(key) = class.hotkey {
+                text.push_str(&format!("  [{}]", key));
+            }
+            if !class.description.is_empty() {
+                text.push_str(&format!("  - {}", class.description));
+            }
+            row.append(&Label::new(Some(&text)));
+
+            list_box.append(&row);
+            row_classes.borrow_mut().push(class.name.clone());
+        }
+
+        let mut names = vec![NO_CLASS.to_string()];
+        names.extend(classes.iter().map(|c| c.name.clone()));
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        parent_names.splice(0, parent_names.n_items(), &names);
+        remap_names.splice(0, remap_names.n_items(), &names);
+    });
+
+    // name of the first selected class / all selected classes
+    let selected_classes = gtk::glib::clone!(@strong list_box, @strong row_classes => move || {
+        let row_classes = row_classes.borrow();
+        list_box
+            .selected_rows()
+            .iter()
+            .filter_map(|row| row_classes.get(row.index() as usize).cloned())
+            .collect::<Vec<_>>()
+    });
+
+    // properties of the selected class
+    // ---------------------------------------------------------------------------------------------
+    let name_entry = Entry::builder().placeholder_text("name").build();
+    let description_entry = Entry::builder()
+        .placeholder_text("description")
+        .hexpand(true)
+        .build();
+    let hotkey_entry = Entry::builder()
+        .placeholder_text("key")
+        .max_length(1)
+        .width_chars(3)
+        .build();
+    let colour_button = gtk::ColorButton::new();
+    let parent_dd = gtk::DropDown::builder().model(&parent_names).build();
+
+    let properties_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    properties_box.append(&name_entry);
+    properties_box.append(&colour_button);
+    properties_box.append(&Label::new(Some("hotkey:")));
+    properties_box.append(&hotkey_entry);
+    properties_box.append(&Label::new(Some("parent:")));
+    properties_box.append(&parent_dd);
+    properties_box.append(&description_entry);
+
+    // show the properties of a newly selected class
+    list_box.connect_selected_rows_changed(gtk::glib::clone!(@strong project, @strong selected_classes, @strong name_entry, @strong description_entry, @strong hotkey_entry, @strong colour_button, @strong parent_dd => move |_| {
+        let Some(name) = selected_classes().into_iter().next() else {
+            return;
+        };
+        let project_ref = project.borrow();
+        let Some(classes) = project_ref.as_ref().map(|p| &p.config.classes) else {
+            return;
+        };
+        let Some(index) = classes.iter().position(|c| c.name == name) else {
+            return;
+        };
+        let class = &classes[index];
+
+        name_entry.set_text(&class.name);
+        description_entry.set_text(&class.description);
+        hotkey_entry.set_text(&class.hotkey.map(String::from).unwrap_or_default());
+        let colour = class_colour(classes, index);
+        colour_button.set_rgba(&gtk::gdk::RGBA::new(
+            colour.0 as f32,
+            colour.1 as f32,
+            colour.2 as f32,
+            1.0,
+        ));
+        let parent_position = class
+            .parent
+            .as_ref()
+            .and_then(|parent| classes.iter().position(|c| &c.name == parent))
+            .map_or(0, |i| i + 1);
+        parent_dd.set_selected(parent_position as u32);
+    }));
+
+    let apply_btn = Button::with_label("apply");
+    let rename_btn = Button::with_label("rename");
+    let add_btn = Button::with_label("add class");
+    let up_btn = Button::with_label("move up");
+    let down_btn = Button::with_label("move down");
+
+    add_btn.connect_clicked(
+        gtk::glib::clone!(@strong project, @strong refresh, @strong name_entry => move |_| {
+            let name = name_entry.text().trim().to_string();
+            let added = modify_project(&project, |project| {
+                let classes = &mut project.config.classes;
+                let names: Vec<&str> = classes.iter().map(|c| c.name.as_str()).collect();
+                validate_class_name(&names, &name)?;
+                classes.push(LabelClass::new(&name));
+                Ok(format!("added class '{}'", name))
+            });
+            if added {
+                refresh();
+            }
+        }),
+    );
+
+    apply_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes, @strong description_entry, @strong hotkey_entry, @strong colour_button, @strong parent_dd, @strong parent_names => move |_| {
+        let Some(name) = selected_classes().into_iter().next() else {
+            return;
+        };
+        let rgba = colour_button.rgba();
+        let colour = hex_colour((rgba.red() as f64, rgba.green() as f64, rgba.blue() as f64));
+        let description = description_entry.text().trim().to_string();
+        let hotkey = hotkey_entry.text().chars().next();
+        let parent = parent_names
+            .string(parent_dd.selected())
+            .map(|s| s.to_string())
+            .filter(|s| s != NO_CLASS);
+
+        let applied = modify_project(&project, |project| {
+            let classes = &mut project.config.classes;
+            set_hotkey(classes, &name, hotkey)?;
+            set_parent(classes, &name, parent.as_deref())?;
+            if let Some(class) = classes.iter_mut().find(|c| c.name == name) {
+                class.colour = Some(colour);
+                class.description = description;
+            }
+            Ok(format!("updated class '{}'", name))
+        });
+        if applied {
+            refresh();
+        }
+    }));
+
+    rename_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes, @strong name_entry => move |_| {
+        let Some(old) = selected_classes().into_iter().next() else {
+            return;
+        };
+        let new = name_entry.text().trim().to_string();
+        let renamed = modify_project(&project, |project| {
+            let count = rename_class(&mut project.config.classes, &mut project.store, &old, &new)?;
+            Ok(format!("renamed '{}' to '{}' ({} annotations)", old, new, count))
+        });
+        if renamed {
+            refresh();
+        }
+    }));
+
+    for (button, offset) in [(&up_btn, -1), (&down_btn, 1)] {
+        button.connect_clicked(gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes => move |_| {
+            let Some(name) = selected_classes().into_iter().next() else {
+                return;
+            };
+            let moved = modify_project(&project, |project| {
+                let classes = &mut project.config.classes;
+                let index = classes.iter().position(|c| c.name == name).unwrap_or(0);
+                move_class(classes, &name, index.saturating_add_signed(offset))?;
+                Ok(format!("moved class '{}'", name))
+            });
+            if moved {
+                refresh();
+            }
+        }));
+    }
+
+    let edit_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    edit_box.append(&add_btn);
+    edit_box.append(&rename_btn);
+    edit_box.append(&apply_btn);
+    edit_box.append(&up_btn);
+    edit_box.append(&down_btn);
+
+    // operations rewriting annotations
+    // ---------------------------------------------------------------------------------------------
+    let merge_btn = Button::with_label("merge selected into first selected");
+
+    merge_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes => move |_| {
+        let selected = selected_classes();
+        let Some(target) = selected.first().cloned() else {
+            return;
+        };
+        let sources: Vec<&str> = selected.iter().map(String::as_str).collect();
+        let merged = modify_project(&project, |project| {
+            let count = merge_classes(&mut project.config.classes, &mut project.store, &sources, &target)?;
+            Ok(format!("merged {:?} into '{}' ({} annotations)", sources, target, count))
+        });
+        if merged {
+            refresh();
+        }
+    }));
+
+    let remap_dd = gtk::DropDown::builder().model(&remap_names).build();
+    let remove_btn = Button::with_label("remove selected, remap annotations to:");
+
+    remove_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes, @strong remap_names => move |_| {
+        let remap_to = remap_names
+            .string(remap_dd.selected())
+            .map(|s| s.to_string())
+            .filter(|s| s != NO_CLASS);
+        let removed = modify_project(&project, |project| {
+            let mut messages = Vec::new();
+            for name in selected_classes() {
+                let count = remove_class(
+                    &mut project.config.classes,
+                    &mut project.store,
+                    &name,
+                    remap_to.as_deref(),
+                )?;
+                messages.push(match &remap_to {
+                    Some(target) => format!("removed '{}', {} annotations remapped to '{}'", name, count, target),
+                    None => format!("removed '{}' and its {} annotations", name, count),
+                });
+            }
+            Ok(messages.join(", "))
+        });
+        if removed {
+            refresh();
+        }
+    }));
+
+    let split_name_entry = Entry::builder().placeholder_text("new class").build();
+    let split_key_entry = Entry::builder().placeholder_text("meta key").build();
+    let split_value_entry = Entry::builder().placeholder_text("value").build();
+    let split_btn = Button::with_label("split");
+
+    // annotations of items with the given meta value are moved into the new (child) class
+    split_btn.connect_clicked(
+        gtk::glib::clone!(@strong project, @strong refresh, @strong selected_classes => move |_| {
+            let Some(class) = selected_classes().into_iter().next() else {
+                return;
+            };
+            let new = split_name_entry.text().trim().to_string();
+            let key = split_key_entry.text().trim().to_string();
+            let value = split_value_entry.text().trim().to_string();
+
+            let split = modify_project(&project, |project| {
+                let store = &project.store;
+                let annotation_ids: Vec<u64> = store
+                    .annotations_of_class(&class)
+                    .iter()
+                    .filter(|a| {
+                        store
+                            .item(a.item_id)
+                            .and_then(|item| item.meta.get(&key))
+                            .is_some_and(|v| *v == value)
+                    })
+                    .map(|a| a.id)
+                    .collect();
+                let count = split_class(
+                    &mut project.config.classes,
+                    &mut project.store,
+                    &class,
+                    &new,
+                    &annotation_ids,
+                )?;
+                Ok(format!("split {} annotations of '{}' into '{}'", count, class, new))
+            });
+            if split {
+                refresh();
+            }
+        }),
+    );
+
+    let merge_remove_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    merge_remove_box.append(&merge_btn);
+    merge_remove_box.append(&remove_btn);
+    merge_remove_box.append(&remap_dd);
+
+    let split_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    split_box.append(&Label::new(Some("split off items with")));
+    split_box.append(&split_key_entry);
+    split_box.append(&Label::new(Some("=")));
+    split_box.append(&split_value_entry);
+    split_box.append(&Label::new(Some("into")));
+    split_box.append(&split_name_entry);
+    split_box.append(&split_btn);
+
+    refresh();
+    vbox.connect_map(move |_| refresh());
+
+    vbox.append(&scrolled_window);
+    vbox.append(&properties_box);
+    vbox.append(&edit_box);
+    vbox.append(&merge_remove_box);
+    vbox.append(&split_box);
+
+    vbox
+}
@@ -0,0 +1,453 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Label class management
+//!
+//! The classes of a project are stored in its config. Their position in the list is the class
+//! index used for training, a class may name a parent class to build a hierarchy. Operations
+//! that change class names (rename, merge, split, remove) also rewrite the affected
+//! annotations in the store, so config and annotations never disagree.
+
+use crate::charts::{palette_colour, Rgb};
+use crate::helper::BACKGROUND_CLASS;
+use crate::store::{Annotation, AnnotationStore};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A label class of a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct LabelClass {
+    pub(crate) name: String,
+    /// `#rrggbb`, used for drawing shapes and in charts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) colour: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) description: String,
+    /// key selecting the class in the annotator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hotkey: Option<char>,
+    /// name of the parent class, `None` for top level classes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) parent: Option<String>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl LabelClass {
+    pub(crate) fn new(name: &str) -> LabelClass {
+        LabelClass {
+            name: name.to_string(),
+            colour: None,
+            description: String::new(),
+            hotkey: None,
+            parent: None,
+        }
+    }
+}
+
+/// parse a `#rrggbb` colour
+pub(crate) fn parse_hex_colour(hex: &str) -> Option<Rgb> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let component = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|v| v as f64 / 255.0)
+    };
+    Some((component(0)?, component(2)?, component(4)?))
+}
+
+pub(crate) fn hex_colour(colour: Rgb) -> String {
+    let to_byte = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        to_byte(colour.0),
+        to_byte(colour.1),
+        to_byte(colour.2)
+    )
+}
+
+/// colour of the class at `index`, classes without (valid) colour get a palette colour
+pub(crate) fn class_colour(classes: &[LabelClass], index: usize) -> Rgb {
+    classes
+        .get(index)
+        .and_then(|c| c.colour.as_deref())
+        .and_then(parse_hex_colour)
+        .unwrap_or_else(|| palette_colour(index))
+}
+
+pub(crate) fn class_names(classes: &[LabelClass]) -> Vec<String> {
+    classes.iter().map(|c| c.name.clone()).collect()
+}
+
+pub(crate) fn class_index(classes: &[LabelClass], name: &str) -> Option<usize> {
+    classes.iter().position(|c| c.name == name)
+}
+
+fn require_class(classes: &[LabelClass], name: &str) -> Result<usize, Box<dyn Error>> {
+    class_index(classes, name).ok_or_else(|| format!("unknown class '{}'", name).into())
+}
+
+/// # check the name of a new class
+///
+/// names must not be empty, must not start or end with whitespace and must differ from all
+/// `existing` names ignoring case ("Dog" and "dog" can not be told apart in a menu).
+pub(crate) fn validate_class_name(existing: &[&str], name: &str) -> Result<(), Box<dyn Error>> {
+    if name.trim().is_empty() {
+        return Err("class names must not be empty".into());
+    }
+    if name.trim() != name {
+        return Err(format!("class name '{}' starts or ends with whitespace", name).into());
+    }
+    let lowercase = name.to_lowercase();
+    if let Some(other) = existing.iter().find(|e| e.to_lowercase() == lowercase) {
+        return Err(format!("class '{}' already exists", other).into());
+    }
+    Ok(())
+}
+
+/// linear sRGB component
+fn linearise(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// sRGB to CIELAB (D65 white point)
+fn to_lab(colour: Rgb) -> (f64, f64, f64) {
+    let (r, g, b) = (
+        linearise(colour.0),
+        linearise(colour.1),
+        linearise(colour.2),
+    );
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// perceptual distance of two colours (CIE76 delta E, about 2.3 is just noticeable)
+pub(crate) fn colour_distance(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (to_lab(a), to_lab(b));
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+/// colours closer than this are hard to tell apart when drawn as thin box outlines
+pub(crate) const MIN_COLOUR_DISTANCE: f64 = 15.0;
+
+/// the existing colour closest to `colour` if it is closer than [`MIN_COLOUR_DISTANCE`]
+pub(crate) fn too_close_colour<'a>(
+    existing: &[(&'a str, Rgb)],
+    colour: Rgb,
+) -> Option<(&'a str, f64)> {
+    existing
+        .iter()
+        .map(|(name, other)| (*name, colour_distance(*other, colour)))
+        .filter(|(_, distance)| *distance < MIN_COLOUR_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// is `ancestor` the class itself or one of its (transitive) parents?
+pub(crate) fn is_descendant(classes: &[LabelClass], name: &str, ancestor: &str) -> bool {
+    let mut current = Some(name);
+    for _ in 0..=classes.len() {
+        match current {
+            Some(class) if class == ancestor => return true,
+            Some(class) => {
+                current = class_index(classes, class).and_then(|i| classes[i].parent.as_deref())
+            }
+            None => return false,
+        }
+    }
+    false
+}
+
+/// # classes in hierarchy order
+///
+/// depth first: every class is followed by its children, siblings keep their relative order
+/// of the class list.
+///
+/// returns:
+///     (depth, index into `classes`) pairs
+pub(crate) fn hierarchy(classes: &[LabelClass]) -> Vec<(usize, usize)> {
+    fn visit(
+        classes: &[LabelClass],
+        parent: Option<&str>,
+        depth: usize,
+        out: &mut Vec<(usize, usize)>,
+    ) {
+        for (i, class) in classes.iter().enumerate() {
+            if class.parent.as_deref() == parent && !out.iter().any(|(_, j)| *j == i) {
+                out.push((depth, i));
+                visit(classes, Some(&class.name), depth + 1, out);
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(classes.len());
+    // classes with an unknown parent are shown as top level classes
+    for (i, class) in classes.iter().enumerate() {
+        let orphan = class
+            .parent
+            .as_deref()
+            .is_none_or(|parent| class_index(classes, parent).is_none());
+        if orphan && !out.iter().any(|(_, j)| *j == i) {
+            out.push((0, i));
+            visit(classes, Some(&class.name), 1, &mut out);
+        }
+    }
+    // a broken (cyclic) config must not hide classes
+    for i in 0..classes.len() {
+        if !out.iter().any(|(_, j)| *j == i) {
+            out.push((0, i));
+        }
+    }
+    out
+}
+
+/// write all annotations of the store whose class is in `from` with the class `to`
+fn relabel(store: &mut AnnotationStore, from: &[&str], to: &str) -> Result<usize, Box<dyn Error>> {
+    let changed: Vec<Annotation> = from
+        .iter()
+        .flat_map(|class| store.annotations_of_class(class))
+        .map(|annotation| Annotation {
+            class: to.to_string(),
+            ..annotation.clone()
+        })
+        .collect();
+    let count = changed.len();
+    if count > 0 {
+        store.update_annotations(changed)?;
+    }
+    Ok(count)
+}
+
+/// # rename a class
+///
+/// the new name is written to every annotation of the class and to the children of the class.
+///
+/// returns:
+///     Result with the number of rewritten annotations
+pub(crate) fn rename_class(
+    classes: &mut [LabelClass],
+    store: &mut AnnotationStore,
+    old: &str,
+    new: &str,
+) -> Result<usize, Box<dyn Error>> {
+    let index = require_class(classes, old)?;
+    if old == BACKGROUND_CLASS {
+        return Err("the background class can not be renamed".into());
+    }
+    // renaming "Dog" to "dog" is fine
+    let others: Vec<&str> = classes
+        .iter()
+        .filter(|c| c.name != old)
+        .map(|c| c.name.as_str())
+        .collect();
+    validate_class_name(&others, new)?;
+
+    let count = relabel(store, &[old], new)?;
+    classes[index].name = new.to_string();
+    for class in classes.iter_mut() {
+        if class.parent.as_deref() == Some(old) {
+            class.parent = Some(new.to_string());
+        }
+    }
+    Ok(count)
+}
+
+/// # merge classes into one
+///
+/// annotations and children of the `sources` move to `target`, the sources are removed from
+/// the class list. `target` may be one of the sources.
+///
+/// returns:
+///     Result with the number of rewritten annotations
+pub(crate) fn merge_classes(
+    classes: &mut Vec<LabelClass>,
+    store: &mut AnnotationStore,
+    sources: &[&str],
+    target: &str,
+) -> Result<usize, Box<dyn Error>> {
+    require_class(classes, target)?;
+    let sources: Vec<&str> = sources.iter().copied().filter(|s| *s != target).collect();
+    for source in &sources {
+        require_class(classes, source)?;
+        if *source == BACKGROUND_CLASS {
+            return Err("the background class can not be merged into another class".into());
+        }
+    }
+
+    let count = relabel(store, &sources, target)?;
+    classes.retain(|c| !sources.contains(&c.name.as_str()));
+    for class in classes.iter_mut() {
+        if class
+            .parent
+            .as_deref()
+            .is_some_and(|parent| sources.contains(&parent))
+        {
+            class.parent = Some(target.to_string());
+        }
+    }
+    // merging a parent into its own child would otherwise leave a cycle
+    if let Some(index) = class_index(classes, target) {
+        if classes[index].parent.as_deref() == Some(target) {
+            classes[index].parent = None;
+        }
+    }
+    Ok(count)
+}
+
+/// # split annotations off into a new class
+///
+/// creates `new` as a child of `class` (right behind it in the list) and moves the given
+/// annotations of `class` to it.
+///
+/// returns:
+///     Result with the number of moved annotations
+pub(crate) fn split_class(
+    classes: &mut Vec<LabelClass>,
+    store: &mut AnnotationStore,
+    class: &str,
+    new: &str,
+    annotation_ids: &[u64],
+) -> Result<usize, Box<dyn Error>> {
+    let index = require_class(classes, class)?;
+    let names: Vec<&str> = classes.iter().map(|c| c.name.as_str()).collect();
+    validate_class_name(&names, new)?;
+
+    let moved: Vec<Annotation> = annotation_ids
+        .iter()
+        .filter_map(|id| store.annotation(*id))
+        .filter(|annotation| annotation.class == class)
+        .map(|annotation| Annotation {
+            class: new.to_string(),
+            ..annotation.clone()
+        })
+        .collect();
+    let count = moved.len();
+    if count > 0 {
+        store.update_annotations(moved)?;
+    }
+
+    classes.insert(
+        index + 1,
+        LabelClass {
+            parent: Some(class.to_string()),
+            ..LabelClass::new(new)
+        },
+    );
+    Ok(count)
+}
+
+/// # remove a class
+///
+/// annotations of the class are moved to `remap_to` or deleted if it is `None`, children of
+/// the class move up to its parent.
+///
+/// returns:
+///     Result with the number of remapped or deleted annotations
+pub(crate) fn remove_class(
+    classes: &mut Vec<LabelClass>,
+    store: &mut AnnotationStore,
+    name: &str,
+    remap_to: Option<&str>,
+) -> Result<usize, Box<dyn Error>> {
+    let index = require_class(classes, name)?;
+    if name == BACKGROUND_CLASS {
+        return Err("the background class can not be removed".into());
+    }
+
+    let count = match remap_to {
+        Some(target) if target == name => {
+            return Err("can not remap a class to itself".into());
+        }
+        Some(target) => {
+            require_class(classes, target)?;
+            relabel(store, &[name], target)?
+        }
+        None => {
+            let ids: Vec<u64> = store
+                .annotations_of_class(name)
+                .iter()
+                .map(|a| a.id)
+                .collect();
+            for id in &ids {
+                store.delete_annotation(*id)?;
+            }
+            ids.len()
+        }
+    };
+
+    let removed = classes.remove(index);
+    for class in classes.iter_mut() {
+        if class.parent.as_deref() == Some(name) {
+            class.parent = removed.parent.clone();
+        }
+    }
+    Ok(count)
+}
+
+/// move a class to a new position (= class index), clamped to the end of the list
+pub(crate) fn move_class(
+    classes: &mut Vec<LabelClass>,
+    name: &str,
+    new_index: usize,
+) -> Result<(), Box<dyn Error>> {
+    let index = require_class(classes, name)?;
+    let class = classes.remove(index);
+    classes.insert(new_index.min(classes.len()), class);
+    Ok(())
+}
+
+/// make `parent` the parent of `name`, `None` makes it a top level class
+pub(crate) fn set_parent(
+    classes: &mut [LabelClass],
+    name: &str,
+    parent: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let index = require_class(classes, name)?;
+    if let Some(parent) = parent {
+        require_class(classes, parent)?;
+        if is_descendant(classes, parent, name) {
+            return Err(format!("'{}' can not be a child of itself or its children", name).into());
+        }
+    }
+    classes[index].parent = parent.map(str::to_string);
+    Ok(())
+}
+
+/// set a hotkey, `None` removes it; a key can only select one class
+pub(crate) fn set_hotkey(
+    classes: &mut [LabelClass],
+    name: &str,
+    hotkey: Option<char>,
+) -> Result<(), Box<dyn Error>> {
+    let index = require_class(classes, name)?;
+    if let Some(key) = hotkey {
+        if let Some(other) = classes
+            .iter()
+            .find(|c| c.name != name && c.hotkey == Some(key))
+        {
+            return Err(format!("hotkey '{}' is already used by '{}'", key, other.name).into());
+        }
+    }
+    classes[index].hotkey = hotkey;
+    Ok(())
+}
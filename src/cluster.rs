@@ -0,0 +1,995 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Clustering models
+//!
+//! k-means (k-means++ initialisation), mini-batch k-means, DBSCAN, agglomerative clustering
+//! and Gaussian mixtures on the (optionally standardised) feature vectors of a project.
+//!
+//! Clustering models implement the same [`Model`] trait as classifiers: `predict` returns
+//! the cluster memberships (one-hot, soft for mixtures, all zero for DBSCAN noise) and every
+//! epoch reports the inertia and the silhouette score of the current clustering.
+
+use crate::dataset::Dataset;
+use crate::metrics::{softmax, Metrics};
+use crate::model::{
+    bool_param, float_param, int_param, load_json, save_json, text_param, FitContext,
+    Hyperparameter, Model, Params, Standardiser,
+};
+use crate::rng::Rng;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// the silhouette score is estimated on at most this many random samples
+const SILHOUETTE_SAMPLES: usize = 500;
+/// agglomerative clustering keeps all pairwise distances, so it is limited to this many samples
+const MAX_AGGLOMERATIVE_SAMPLES: usize = 4000;
+/// k-means++ starts per `k` of the elbow sweep, the one with the lowest inertia is kept
+const ELBOW_STARTS: usize = 3;
+/// linkages of agglomerative clustering
+const LINKAGES: &[&str] = &["ward", "average", "complete", "single"];
+/// added to the variances of mixture components, keeps them from collapsing onto a point
+const MIN_VARIANCE: f64 = 1e-6;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Lloyd's algorithm from several k-means++ starts, the start with the lowest inertia wins
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct KMeans {
+    k: usize,
+    n_init: usize,
+    max_iter: usize,
+    standardiser: Option<Standardiser>,
+    centres: Vec<Vec<f64>>,
+}
+
+/// k-means updated from random mini-batches, for large datasets
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct MiniBatchKMeans {
+    k: usize,
+    batch_size: usize,
+    epochs: usize,
+    standardiser: Option<Standardiser>,
+    centres: Vec<Vec<f64>>,
+}
+
+/// Density based clustering, samples in sparse regions are noise
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Dbscan {
+    eps: f64,
+    min_samples: usize,
+    standardiser: Option<Standardiser>,
+    /// core samples and their clusters, new samples join the cluster of a core sample within `eps`
+    core_points: Vec<Vec<f64>>,
+    core_clusters: Vec<usize>,
+    clusters: usize,
+}
+
+/// Bottom-up merging of the closest clusters until `n_clusters` are left
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Agglomerative {
+    n_clusters: usize,
+    linkage: String,
+    standardiser: Option<Standardiser>,
+    /// new samples are assigned to the cluster with the closest mean
+    centroids: Vec<Vec<f64>>,
+}
+
+/// Mixture of normal distributions with diagonal covariances, fitted with EM
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct GaussianMixture {
+    components: usize,
+    max_iter: usize,
+    tolerance: f64,
+    standardiser: Option<Standardiser>,
+    weights: Vec<f64>,
+    means: Vec<Vec<f64>>,
+    variances: Vec<Vec<f64>>,
+}
+
+/// Inertia and silhouette of k-means for one `k`, see [`elbow`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ElbowPoint {
+    pub(crate) k: usize,
+    pub(crate) inertia: f64,
+    pub(crate) silhouette: f64,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// index of and squared distance to the closest centre
+fn nearest(centres: &[Vec<f64>], x: &[f64]) -> (usize, f64) {
+    centres
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, squared_distance(c, x)))
+        .fold(
+            (0, f64::INFINITY),
+            |best, d| if d.1 < best.1 { d } else { best },
+        )
+}
+
+fn one_hot(index: usize, size: usize) -> Vec<f64> {
+    (0..size)
+        .map(|i| if i == index { 1.0 } else { 0.0 })
+        .collect()
+}
+
+/// cluster of a membership vector, `None` for noise (no positive membership)
+pub(crate) fn cluster_of(memberships: &[f64]) -> Option<usize> {
+    memberships
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| **m > 0.0)
+        .fold(None, |best: Option<(usize, f64)>, (i, m)| match best {
+            Some((_, b)) if b >= *m => best,
+            _ => Some((i, *m)),
+        })
+        .map(|(i, _)| i)
+}
+
+/// # the training samples as points
+///
+/// returns:
+///     the fitted standardiser (if enabled) and the (standardised) inputs of `indices`
+fn fit_points(
+    data: &Dataset,
+    indices: &[usize],
+    standardise: bool,
+) -> (Option<Standardiser>, Vec<Vec<f64>>) {
+    let standardiser = standardise.then(|| Standardiser::fit(data, indices));
+    let points = indices
+        .iter()
+        .map(|i| transform(&standardiser, &data.samples[*i].x))
+        .collect();
+    (standardiser, points)
+}
+
+fn transform(standardiser: &Option<Standardiser>, x: &[f32]) -> Vec<f64> {
+    match standardiser {
+        Some(standardiser) => standardiser.apply(x).iter().map(|v| *v as f64).collect(),
+        None => x.iter().map(|v| *v as f64).collect(),
+    }
+}
+
+/// means of the clusters (empty for clusters without members)
+fn cluster_means(
+    points: &[Vec<f64>],
+    assignments: &[Option<usize>],
+    clusters: usize,
+) -> Vec<Vec<f64>> {
+    let size = points.first().map_or(0, Vec::len);
+    let mut sums = vec![vec![0.0; size]; clusters];
+    let mut counts = vec![0usize; clusters];
+    for (point, cluster) in points.iter().zip(assignments) {
+        if let Some(c) = cluster {
+            for (s, v) in sums[*c].iter_mut().zip(point) {
+                *s += v;
+            }
+            counts[*c] += 1;
+        }
+    }
+    sums.into_iter()
+        .zip(counts)
+        .map(|(sum, count)| {
+            if count == 0 {
+                Vec::new()
+            } else {
+                sum.iter().map(|s| s / count as f64).collect()
+            }
+        })
+        .collect()
+}
+
+/// sum of the squared distances of all (non noise) points to the mean of their cluster
+pub(crate) fn inertia(points: &[Vec<f64>], assignments: &[Option<usize>]) -> f64 {
+    let clusters = assignments.iter().flatten().max().map_or(0, |c| c + 1);
+    let means = cluster_means(points, assignments, clusters);
+    points
+        .iter()
+        .zip(assignments)
+        .filter_map(|(point, cluster)| Some(squared_distance(point, &means[(*cluster)?])))
+        .sum()
+}
+
+/// # mean silhouette coefficient
+///
+/// estimated on at most [`SILHOUETTE_SAMPLES`] random points, noise is ignored.
+///
+/// returns:
+///     a value between -1 and 1 (higher is better), 0 for fewer than two clusters
+pub(crate) fn silhouette(points: &[Vec<f64>], assignments: &[Option<usize>], seed: u64) -> f64 {
+    let clusters = assignments.iter().flatten().max().map_or(0, |c| c + 1);
+    let mut members: Vec<usize> = (0..points.len())
+        .filter(|i| assignments[*i].is_some())
+        .collect();
+    if clusters < 2 || members.len() < 2 {
+        return 0.0;
+    }
+    let mut sizes = vec![0usize; clusters];
+    for c in assignments.iter().flatten() {
+        sizes[*c] += 1;
+    }
+    let all = members.clone();
+    Rng::new(seed).shuffle(&mut members);
+    members.truncate(SILHOUETTE_SAMPLES);
+
+    let mut total = 0.0;
+    for i in &members {
+        let own = assignments[*i].unwrap_or(0);
+        let mut sums = vec![0.0; clusters];
+        for j in &all {
+            if i != j {
+                sums[assignments[*j].unwrap_or(0)] +=
+                    squared_distance(&points[*i], &points[*j]).sqrt();
+            }
+        }
+        if sizes[own] <= 1 {
+            // the silhouette of singletons is defined as 0
+            continue;
+        }
+        let a = sums[own] / (sizes[own] - 1) as f64;
+        let b = (0..clusters)
+            .filter(|c| *c != own && sizes[*c] > 0)
+            .map(|c| sums[c] / sizes[c] as f64)
+            .fold(f64::INFINITY, f64::min);
+        if b.is_finite() && a.max(b) > 0.0 {
+            total += (b - a) / a.max(b);
+        }
+    }
+    total / members.len() as f64
+}
+
+/// # inertia, silhouette, number of clusters and fraction of noise of a clustering
+pub(crate) fn clustering_metrics(
+    points: &[Vec<f64>],
+    assignments: &[Option<usize>],
+    seed: u64,
+) -> Metrics {
+    let mut clusters: Vec<usize> = assignments.iter().flatten().copied().collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    let noise = assignments.iter().filter(|a| a.is_none()).count();
+    Metrics::from([
+        ("inertia".to_string(), inertia(points, assignments)),
+        (
+            "silhouette".to_string(),
+            silhouette(points, assignments, seed),
+        ),
+        ("clusters".to_string(), clusters.len() as f64),
+        (
+            "noise".to_string(),
+            noise as f64 / points.len().max(1) as f64,
+        ),
+    ])
+}
+
+/// # k-means++ initialisation
+///
+/// the first centre is a random point, every further centre is drawn with a probability
+/// proportional to its squared distance to the closest centre chosen so far.
+pub(crate) fn kmeans_plus_plus(points: &[Vec<f64>], k: usize, rng: &mut Rng) -> Vec<Vec<f64>> {
+    let mut centres = vec![points[rng.below(points.len())].clone()];
+    let mut distances: Vec<f64> = points
+        .iter()
+        .map(|p| squared_distance(p, &centres[0]))
+        .collect();
+    while centres.len() < k {
+        let total: f64 = distances.iter().sum();
+        let next = if total <= 0.0 {
+            // all points coincide with a centre
+            rng.below(points.len())
+        } else {
+            let mut target = rng.uniform(0.0, total);
+            distances
+                .iter()
+                .position(|d| {
+                    target -= d;
+                    target < 0.0
+                })
+                .unwrap_or(points.len() - 1)
+        };
+        centres.push(points[next].clone());
+        for (d, p) in distances.iter_mut().zip(points) {
+            *d = d.min(squared_distance(p, &centres[centres.len() - 1]));
+        }
+    }
+    centres
+}
+
+/// # Lloyd's algorithm
+///
+/// returns:
+///     the final centres, the assignments and the inertia
+fn lloyd(
+    points: &[Vec<f64>],
+    mut centres: Vec<Vec<f64>>,
+    max_iter: usize,
+) -> (Vec<Vec<f64>>, Vec<usize>, f64) {
+    let mut assignments = vec![usize::MAX; points.len()];
+    for _ in 0..max_iter.max(1) {
+        let mut changed = false;
+        for (a, p) in assignments.iter_mut().zip(points) {
+            let (c, _) = nearest(&centres, p);
+            changed |= *a != c;
+            *a = c;
+        }
+        let hard: Vec<Option<usize>> = assignments.iter().map(|a| Some(*a)).collect();
+        let means = cluster_means(points, &hard, centres.len());
+        for (centre, mean) in centres.iter_mut().zip(means) {
+            // empty clusters keep their centre
+            if !mean.is_empty() {
+                *centre = mean;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let inertia = points.iter().map(|p| nearest(&centres, p).1).sum();
+    (centres, assignments, inertia)
+}
+
+fn check_cluster_count(points: usize, clusters: usize) -> Result<(), Box<dyn Error>> {
+    if points < clusters {
+        return Err(format!(
+            "{} clusters need at least as many samples, got {}",
+            clusters, points
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// # inertia and silhouette of k-means for a range of `k`
+///
+/// the "elbow" of the inertia curve and the maximum of the silhouette curve are good
+/// candidates for the number of clusters. `progress` is called after every `k`.
+///
+/// returns:
+///     one point per `k`, fewer if cancelled
+pub(crate) fn elbow(
+    points: &[Vec<f64>],
+    ks: &[usize],
+    seed: u64,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Vec<ElbowPoint> {
+    let mut result = Vec::new();
+    for (i, k) in ks.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) || *k == 0 || *k > points.len() {
+            break;
+        }
+        let mut rng = Rng::new(seed);
+        let (_, assignments, inertia) = (0..ELBOW_STARTS)
+            .map(|_| lloyd(points, kmeans_plus_plus(points, *k, &mut rng), 300))
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .unwrap_or_default();
+        let hard: Vec<Option<usize>> = assignments.into_iter().map(Some).collect();
+        result.push(ElbowPoint {
+            k: *k,
+            inertia,
+            silhouette: silhouette(points, &hard, seed),
+        });
+        progress(i + 1, ks.len());
+    }
+    result
+}
+
+/// standardised feature vectors of all samples of a dataset, the input of [`elbow`]
+pub(crate) fn standardised_points(data: &Dataset) -> Vec<Vec<f64>> {
+    let all: Vec<usize> = (0..data.samples.len()).collect();
+    fit_points(data, &all, true).1
+}
+
+fn standardise_hyperparameter() -> Hyperparameter {
+    Hyperparameter::bool(
+        "standardise",
+        "scale every feature to zero mean and unit variance first",
+        true,
+    )
+}
+
+impl Model for KMeans {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int("k", "number of clusters", 3, 1, 100),
+            Hyperparameter::int(
+                "n_init",
+                "number of k-means++ starts, the best one is kept",
+                4,
+                1,
+                50,
+            ),
+            Hyperparameter::int(
+                "max_iter",
+                "maximal number of iterations per start",
+                300,
+                1,
+                10000,
+            ),
+            standardise_hyperparameter(),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(KMeans {
+            k: int_param(params, "k") as usize,
+            n_init: int_param(params, "n_init") as usize,
+            max_iter: int_param(params, "max_iter") as usize,
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            centres: Vec::new(),
+        })
+    }
+
+    /// every k-means++ start is one epoch
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let (standardiser, points) = fit_points(data, &context.train, self.standardiser.is_some());
+        check_cluster_count(points.len(), self.k)?;
+        self.standardiser = standardiser;
+
+        let mut best: Option<(f64, Vec<usize>)> = None;
+        for start in 1..=self.n_init {
+            let mut rng = Rng::new(context.seed.wrapping_add(start as u64));
+            let initial = kmeans_plus_plus(&points, self.k, &mut rng);
+            let (centres, assignments, inertia) = lloyd(&points, initial, self.max_iter);
+            if best.as_ref().is_none_or(|(b, _)| inertia < *b) {
+                self.centres = centres;
+                best = Some((inertia, assignments));
+            }
+
+            let hard: Vec<Option<usize>> = best
+                .iter()
+                .flat_map(|(_, a)| a.iter().map(|c| Some(*c)))
+                .collect();
+            let metrics = clustering_metrics(&points, &hard, context.seed);
+            if !context.end_epoch(data, self, start, self.n_init, metrics, None, points.len()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let (c, _) = nearest(&self.centres, &transform(&self.standardiser, x));
+        one_hot(c, self.centres.len())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl Model for MiniBatchKMeans {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int("k", "number of clusters", 3, 1, 100),
+            Hyperparameter::int("batch_size", "samples per update", 256, 1, 65536),
+            Hyperparameter::int(
+                "epochs",
+                "passes over the data (in random batches)",
+                20,
+                1,
+                10000,
+            ),
+            standardise_hyperparameter(),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(MiniBatchKMeans {
+            k: int_param(params, "k") as usize,
+            batch_size: int_param(params, "batch_size") as usize,
+            epochs: int_param(params, "epochs") as usize,
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            centres: Vec::new(),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let (standardiser, points) = fit_points(data, &context.train, self.standardiser.is_some());
+        check_cluster_count(points.len(), self.k)?;
+        self.standardiser = standardiser;
+
+        let mut rng = Rng::new(context.seed);
+        // initialise on a random subset, k-means++ over all points would defeat the purpose
+        let mut subset: Vec<usize> = (0..points.len()).collect();
+        rng.shuffle(&mut subset);
+        subset.truncate((3 * self.batch_size).max(self.k));
+        let initial: Vec<Vec<f64>> = subset.iter().map(|i| points[*i].clone()).collect();
+        self.centres = kmeans_plus_plus(&initial, self.k, &mut rng);
+
+        let batch_size = self.batch_size.clamp(1, points.len());
+        let batches = points.len().div_ceil(batch_size);
+        let mut counts = vec![0usize; self.k];
+        for epoch in 1..=self.epochs {
+            for _ in 0..batches {
+                let batch: Vec<usize> = (0..batch_size).map(|_| rng.below(points.len())).collect();
+                let nearest_centres: Vec<usize> = batch
+                    .iter()
+                    .map(|i| nearest(&self.centres, &points[*i]).0)
+                    .collect();
+                for (i, c) in batch.iter().zip(nearest_centres) {
+                    // per centre learning rate 1 / (samples seen by the centre)
+                    counts[c] += 1;
+                    let rate = 1.0 / counts[c] as f64;
+                    for (centre, v) in self.centres[c].iter_mut().zip(&points[*i]) {
+                        *centre += rate * (v - *centre);
+                    }
+                }
+                if context.should_stop() {
+                    break;
+                }
+            }
+
+            let assignments: Vec<Option<usize>> = points
+                .iter()
+                .map(|p| Some(nearest(&self.centres, p).0))
+                .collect();
+            let metrics = clustering_metrics(&points, &assignments, context.seed);
+            if !context.end_epoch(
+                data,
+                self,
+                epoch,
+                self.epochs,
+                metrics,
+                None,
+                batches * batch_size,
+            ) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let (c, _) = nearest(&self.centres, &transform(&self.standardiser, x));
+        one_hot(c, self.centres.len())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl Model for Dbscan {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::float(
+                "eps",
+                "radius of the neighbourhood of a sample",
+                0.5,
+                1e-4,
+                1e4,
+                true,
+            ),
+            Hyperparameter::int(
+                "min_samples",
+                "samples (including itself) a neighbourhood needs for a core sample",
+                5,
+                1,
+                10000,
+            ),
+            standardise_hyperparameter(),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(Dbscan {
+            eps: float_param(params, "eps"),
+            min_samples: int_param(params, "min_samples") as usize,
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            ..Dbscan::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let (standardiser, points) = fit_points(data, &context.train, self.standardiser.is_some());
+        self.standardiser = standardiser;
+        let eps2 = self.eps * self.eps;
+        let neighbours = |i: usize| -> Vec<usize> {
+            (0..points.len())
+                .filter(|j| squared_distance(&points[i], &points[*j]) <= eps2)
+                .collect()
+        };
+
+        let mut assignments: Vec<Option<usize>> = vec![None; points.len()];
+        let mut visited = vec![false; points.len()];
+        let mut core = vec![false; points.len()];
+        let mut clusters = 0;
+        for start in 0..points.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let region = neighbours(start);
+            if region.len() < self.min_samples {
+                // noise, unless a later cluster reaches it as a border sample
+                continue;
+            }
+            if context.should_stop() {
+                break;
+            }
+            core[start] = true;
+            assignments[start] = Some(clusters);
+            let mut queue: VecDeque<usize> = region.into_iter().collect();
+            while let Some(j) = queue.pop_front() {
+                if assignments[j].is_none() {
+                    assignments[j] = Some(clusters);
+                }
+                if visited[j] {
+                    continue;
+                }
+                visited[j] = true;
+                let region = neighbours(j);
+                if region.len() >= self.min_samples {
+                    core[j] = true;
+                    queue.extend(region);
+                }
+            }
+            clusters += 1;
+        }
+
+        self.clusters = clusters;
+        self.core_points.clear();
+        self.core_clusters.clear();
+        for i in (0..points.len()).filter(|i| core[*i]) {
+            self.core_points.push(points[i].clone());
+            self.core_clusters.push(assignments[i].unwrap_or(0));
+        }
+        let metrics = clustering_metrics(&points, &assignments, context.seed);
+        context.end_epoch(data, self, 1, 1, metrics, None, points.len());
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let x = transform(&self.standardiser, x);
+        let (i, distance) = nearest(&self.core_points, &x);
+        if distance <= self.eps * self.eps {
+            one_hot(self.core_clusters[i], self.clusters)
+        } else {
+            vec![0.0; self.clusters]
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+/// index into a condensed distance matrix (`i != j`)
+fn condensed(n: usize, i: usize, j: usize) -> usize {
+    let (i, j) = if i < j { (i, j) } else { (j, i) };
+    n * i - i * (i + 1) / 2 + j - i - 1
+}
+
+/// # hierarchical clustering with the nearest neighbour chain algorithm
+///
+/// `O(n^2)` time and memory for the reducible linkages ward, average, complete and single.
+///
+/// returns:
+///     the merges `(a, b, distance)`, the merged cluster keeps the index `a`
+fn merge_tree(
+    points: &[Vec<f64>],
+    linkage: &str,
+    cancel: &dyn Fn() -> bool,
+) -> Vec<(usize, usize, f64)> {
+    let n = points.len();
+    let ward = linkage == "ward";
+    let mut distances = vec![0.0; n * n.saturating_sub(1) / 2];
+    for i in 0..n {
+        for j in i + 1..n {
+            let d = squared_distance(&points[i], &points[j]);
+            // ward works on squared distances
+            distances[condensed(n, i, j)] = if ward { d } else { d.sqrt() };
+        }
+    }
+
+    let mut active = vec![true; n];
+    let mut sizes = vec![1.0f64; n];
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+    let mut chain: Vec<usize> = Vec::new();
+    while merges.len() + 1 < n {
+        if cancel() {
+            break;
+        }
+        if chain.is_empty() {
+            chain.push(active.iter().position(|a| *a).unwrap_or(0));
+        }
+        let (a, b) = loop {
+            let a = chain[chain.len() - 1];
+            let previous = (chain.len() >= 2).then(|| chain[chain.len() - 2]);
+            // the previous chain element wins ties, otherwise the chain could cycle
+            let mut best = previous.map(|p| (p, distances[condensed(n, a, p)]));
+            for k in (0..n).filter(|k| active[*k] && *k != a) {
+                let d = distances[condensed(n, a, k)];
+                if best.is_none_or(|(_, b)| d < b) {
+                    best = Some((k, d));
+                }
+            }
+            let (b, _) = best.unwrap_or((a, 0.0));
+            if Some(b) == previous {
+                chain.truncate(chain.len() - 2);
+                break (a, b);
+            }
+            chain.push(b);
+        };
+
+        let d_ab = distances[condensed(n, a, b)];
+        let (sa, sb) = (sizes[a], sizes[b]);
+        for k in (0..n).filter(|k| active[*k] && *k != a && *k != b) {
+            let (d_ak, d_bk, sk) = (
+                distances[condensed(n, a, k)],
+                distances[condensed(n, b, k)],
+                sizes[k],
+            );
+            // Lance-Williams update
+            distances[condensed(n, a, k)] = match linkage {
+                "single" => d_ak.min(d_bk),
+                "complete" => d_ak.max(d_bk),
+                "average" => (sa * d_ak + sb * d_bk) / (sa + sb),
+                _ => ((sa + sk) * d_ak + (sb + sk) * d_bk - sk * d_ab) / (sa + sb + sk),
+            };
+        }
+        active[b] = false;
+        sizes[a] += sb;
+        merges.push((a, b, d_ab));
+    }
+    merges
+}
+
+/// root of a union-find set, with path halving
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+impl Model for Agglomerative {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int(
+                "n_clusters",
+                "number of clusters to stop merging at",
+                3,
+                1,
+                100,
+            ),
+            Hyperparameter::choice("linkage", "distance between two clusters", "ward", LINKAGES),
+            standardise_hyperparameter(),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(Agglomerative {
+            n_clusters: int_param(params, "n_clusters") as usize,
+            linkage: text_param(params, "linkage"),
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            centroids: Vec::new(),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        if context.train.len() > MAX_AGGLOMERATIVE_SAMPLES {
+            return Err(format!(
+                "agglomerative clustering supports at most {} samples, got {} (use k-means instead)",
+                MAX_AGGLOMERATIVE_SAMPLES,
+                context.train.len()
+            )
+            .into());
+        }
+        let (standardiser, points) = fit_points(data, &context.train, self.standardiser.is_some());
+        check_cluster_count(points.len(), self.n_clusters)?;
+        self.standardiser = standardiser;
+
+        let control = context.control;
+        let mut merges = merge_tree(&points, &self.linkage, &|| control.is_stopped());
+        if control.is_stopped() {
+            return Err("stopped before the clustering was complete".into());
+        }
+        // all linkages are monotone, so the cheapest merges form the cut at n_clusters
+        merges.sort_by(|x, y| x.2.total_cmp(&y.2));
+        let mut parents: Vec<usize> = (0..points.len()).collect();
+        for (a, b, _) in merges.iter().take(points.len() - self.n_clusters.max(1)) {
+            let (ra, rb) = (find(&mut parents, *a), find(&mut parents, *b));
+            parents[rb] = ra;
+        }
+        let mut roots: Vec<usize> = (0..points.len()).map(|i| find(&mut parents, i)).collect();
+        let mut ids = roots.clone();
+        ids.sort_unstable();
+        ids.dedup();
+        for root in roots.iter_mut() {
+            *root = ids.binary_search(root).unwrap_or(0);
+        }
+        let assignments: Vec<Option<usize>> = roots.into_iter().map(Some).collect();
+        self.centroids = cluster_means(&points, &assignments, ids.len());
+
+        let metrics = clustering_metrics(&points, &assignments, context.seed);
+        context.end_epoch(data, self, 1, 1, metrics, None, points.len());
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let (c, _) = nearest(&self.centroids, &transform(&self.standardiser, x));
+        one_hot(c, self.centroids.len())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl GaussianMixture {
+    /// `ln(weight * N(x | component))` of every component
+    fn log_densities(&self, x: &[f64]) -> Vec<f64> {
+        (0..self.weights.len())
+            .map(|c| {
+                let log_likelihood: f64 = x
+                    .iter()
+                    .zip(self.means[c].iter().zip(&self.variances[c]))
+                    .map(|(v, (mean, variance))| {
+                        -0.5 * ((2.0 * std::f64::consts::PI * variance).ln()
+                            + (v - mean).powi(2) / variance)
+                    })
+                    .sum();
+                self.weights[c].max(f64::MIN_POSITIVE).ln() + log_likelihood
+            })
+            .collect()
+    }
+}
+
+/// `ln(sum(exp(values)))` without overflow
+fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if !max.is_finite() {
+        return max;
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<f64>().ln()
+}
+
+impl Model for GaussianMixture {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int(
+                "components",
+                "number of normal distributions (clusters)",
+                3,
+                1,
+                100,
+            ),
+            Hyperparameter::int("max_iter", "maximal number of EM iterations", 100, 1, 10000),
+            Hyperparameter::float(
+                "tolerance",
+                "stop once the mean log likelihood improves by less than this",
+                1e-3,
+                1e-8,
+                1.0,
+                true,
+            ),
+            standardise_hyperparameter(),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(GaussianMixture {
+            components: int_param(params, "components") as usize,
+            max_iter: int_param(params, "max_iter") as usize,
+            tolerance: float_param(params, "tolerance"),
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            ..GaussianMixture::default()
+        })
+    }
+
+    /// every EM iteration is one epoch
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let (standardiser, points) = fit_points(data, &context.train, self.standardiser.is_some());
+        check_cluster_count(points.len(), self.components)?;
+        self.standardiser = standardiser;
+        let (n, size, k) = (points.len(), data.input_size(), self.components);
+
+        // start from k-means++ centres with the overall variance
+        let all: Vec<Option<usize>> = vec![Some(0); n];
+        let mean = &cluster_means(&points, &all, 1)[0];
+        let variance: Vec<f64> = (0..size)
+            .map(|f| {
+                points.iter().map(|p| (p[f] - mean[f]).powi(2)).sum::<f64>() / n as f64
+                    + MIN_VARIANCE
+            })
+            .collect();
+        self.means = kmeans_plus_plus(&points, k, &mut Rng::new(context.seed));
+        self.variances = vec![variance; k];
+        self.weights = vec![1.0 / k as f64; k];
+
+        let mut previous = f64::NEG_INFINITY;
+        for iteration in 1..=self.max_iter {
+            // E step
+            let mut log_likelihood = 0.0;
+            let responsibilities: Vec<Vec<f64>> = points
+                .iter()
+                .map(|p| {
+                    let densities = self.log_densities(p);
+                    log_likelihood += log_sum_exp(&densities);
+                    softmax(&densities)
+                })
+                .collect();
+            log_likelihood /= n as f64;
+
+            // M step
+            for c in 0..k {
+                let total: f64 = responsibilities
+                    .iter()
+                    .map(|r| r[c])
+                    .sum::<f64>()
+                    .max(f64::MIN_POSITIVE);
+                self.weights[c] = total / n as f64;
+                for f in 0..size {
+                    let m = points
+                        .iter()
+                        .zip(&responsibilities)
+                        .map(|(p, r)| r[c] * p[f])
+                        .sum::<f64>()
+                        / total;
+                    let v = points
+                        .iter()
+                        .zip(&responsibilities)
+                        .map(|(p, r)| r[c] * (p[f] - m).powi(2))
+                        .sum::<f64>()
+                        / total;
+                    self.means[c][f] = m;
+                    self.variances[c][f] = v + MIN_VARIANCE;
+                }
+            }
+
+            let assignments: Vec<Option<usize>> =
+                responsibilities.iter().map(|r| cluster_of(r)).collect();
+            let mut metrics = clustering_metrics(&points, &assignments, context.seed);
+            metrics.insert("log_likelihood".to_string(), log_likelihood);
+            let converged = (log_likelihood - previous).abs() < self.tolerance;
+            previous = log_likelihood;
+            if !context.end_epoch(data, self, iteration, self.max_iter, metrics, None, n)
+                || converged
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        softmax(&self.log_densities(&transform(&self.standardiser, x)))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
@@ -0,0 +1,133 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Cluster assignments
+//!
+//! The latest clustering run writes the cluster of every item to `<project>/clusters.toml`,
+//! so the items can be browsed per cluster. Items with several samples (e.g. windows of a
+//! recording) get the cluster most of their samples fall into.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// file name of the cluster assignment inside a project directory
+pub(crate) const CLUSTERS_FILE_NAME: &str = "clusters.toml";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Cluster of one item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ItemCluster {
+    pub(crate) item_id: u64,
+    /// `None` for noise (samples DBSCAN assigns to no cluster)
+    pub(crate) cluster: Option<usize>,
+    /// fraction of the samples of the item in `cluster`
+    pub(crate) share: f64,
+}
+
+/// Clusters of all items, written by a clustering run
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct ClusterAssignment {
+    /// the run that produced the assignment
+    pub(crate) run_id: String,
+    pub(crate) model: String,
+    pub(crate) clusters: usize,
+    /// sorted by item id
+    pub(crate) items: Vec<ItemCluster>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl ClusterAssignment {
+    /// # assignment from the clusters of single samples
+    ///
+    /// `samples` are `(item id, cluster)` pairs, an item gets the cluster of most of its
+    /// samples (ties go to the lower cluster, noise loses every tie).
+    pub(crate) fn from_samples(
+        run_id: &str,
+        model: &str,
+        samples: impl IntoIterator<Item = (u64, Option<usize>)>,
+    ) -> ClusterAssignment {
+        let mut votes: BTreeMap<u64, BTreeMap<Option<usize>, usize>> = BTreeMap::new();
+        for (item_id, cluster) in samples {
+            *votes
+                .entry(item_id)
+                .or_default()
+                .entry(cluster)
+                .or_default() += 1;
+        }
+
+        let mut clusters = 0;
+        let items = votes
+            .into_iter()
+            .map(|(item_id, counts)| {
+                let total: usize = counts.values().sum();
+                // `Some` sorts after `None`, so iterating in reverse prefers clusters
+                let (cluster, count) =
+                    counts.iter().rev().fold(
+                        (None, 0),
+                        |best, (c, n)| if *n >= best.1 { (*c, *n) } else { best },
+                    );
+                if let Some(c) = cluster {
+                    clusters = clusters.max(c + 1);
+                }
+                ItemCluster {
+                    item_id,
+                    cluster,
+                    share: count as f64 / total.max(1) as f64,
+                }
+            })
+            .collect();
+
+        ClusterAssignment {
+            run_id: run_id.to_string(),
+            model: model.to_string(),
+            clusters,
+            items,
+        }
+    }
+
+    /// cluster of an item, `None` for noise and items added after the run
+    pub(crate) fn cluster_of(&self, item_id: u64) -> Option<usize> {
+        self.items
+            .binary_search_by_key(&item_id, |c| c.item_id)
+            .ok()
+            .and_then(|i| self.items[i].cluster)
+    }
+
+    /// items of one cluster (`None` for the noise items), most typical first
+    pub(crate) fn items_of(&self, cluster: Option<usize>) -> Vec<&ItemCluster> {
+        let mut items: Vec<&ItemCluster> =
+            self.items.iter().filter(|c| c.cluster == cluster).collect();
+        items.sort_by(|a, b| b.share.total_cmp(&a.share));
+        items
+    }
+
+    /// number of items per cluster, the last entry counts the noise items
+    pub(crate) fn sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.clusters + 1];
+        for item in &self.items {
+            sizes[item.cluster.unwrap_or(self.clusters)] += 1;
+        }
+        sizes
+    }
+}
+
+pub(crate) fn save_clusters(
+    project_dir: &Path,
+    assignment: &ClusterAssignment,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        project_dir.join(CLUSTERS_FILE_NAME),
+        toml::to_string(assignment)?,
+    )?;
+    Ok(())
+}
+
+pub(crate) fn load_clusters(project_dir: &Path) -> Result<ClusterAssignment, Box<dyn Error>> {
+    let contents = fs::read_to_string(project_dir.join(CLUSTERS_FILE_NAME))?;
+    Ok(toml::from_str(&contents)?)
+}
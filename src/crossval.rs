@@ -0,0 +1,341 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Cross-validation
+//!
+//! Cross-validating a model trains one run per fold of the split assignment (see
+//! [`crate::splits`]): fold `k` validates the model trained on all other folds, the test split
+//! is never used. The metrics of the folds are summarised by their mean and standard
+//! deviation, optionally a last run refits the model on all folds together.
+//!
+//! The runs belong to the cross-validation, which is kept in `<project>/crossval/<id>.toml`
+//! with the metrics of every fold and the summary.
+
+use crate::metrics::{format_metric, Metrics};
+use crate::model::Params;
+use crate::store::unix_now;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// directory inside a project holding all cross-validations
+pub(crate) const CROSSVAL_DIR: &str = "crossval";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Result of a single fold
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct FoldResult {
+    /// 0 based
+    pub(crate) fold: usize,
+    /// empty if the run could not even be created
+    pub(crate) run_id: String,
+    /// final metrics of the run, validated on the fold
+    pub(crate) metrics: Metrics,
+    pub(crate) error: Option<String>,
+}
+
+/// Mean and standard deviation of a metric over the folds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct MetricSummary {
+    pub(crate) mean: f64,
+    /// population standard deviation (like scikit-learn reports it)
+    pub(crate) std: f64,
+    /// number of folds with the metric
+    pub(crate) folds: usize,
+}
+
+/// Description of a cross-validation and its folds so far
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct CrossValidation {
+    pub(crate) id: String,
+    /// id of the model in the model registry
+    pub(crate) model: String,
+    /// unix timestamp (seconds) of the start
+    pub(crate) started: u64,
+    /// unix timestamp (seconds) of the end, `None` while it is running
+    #[serde(default)]
+    pub(crate) finished: Option<u64>,
+    #[serde(default)]
+    pub(crate) error: Option<String>,
+    pub(crate) seed: u64,
+    /// hyperparameters of all runs
+    pub(crate) params: Params,
+    /// number of folds of the split assignment
+    pub(crate) folds: usize,
+    /// whether a last run trains on all folds together
+    pub(crate) refit: bool,
+    /// finished folds, ordered by fold
+    #[serde(default)]
+    pub(crate) results: Vec<FoldResult>,
+    /// metrics of the successful folds
+    #[serde(default)]
+    pub(crate) summary: BTreeMap<String, MetricSummary>,
+    /// run trained on all folds, once it is there
+    #[serde(default)]
+    pub(crate) refit_run: Option<String>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// # mean and standard deviation of every metric over the given metrics
+///
+/// metrics missing in some of them are summarised over the ones that have them.
+pub(crate) fn summarise(metrics: &[&Metrics]) -> BTreeMap<String, MetricSummary> {
+    let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for metrics in metrics {
+        for (name, value) in metrics.iter() {
+            if value.is_finite() {
+                values.entry(name.clone()).or_default().push(*value);
+            }
+        }
+    }
+    values
+        .into_iter()
+        .map(|(name, values)| {
+            let n = values.len() as f64;
+            let mean = values.iter().sum::<f64>() / n;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+            (
+                name,
+                MetricSummary {
+                    mean,
+                    std: variance.sqrt(),
+                    folds: values.len(),
+                },
+            )
+        })
+        .collect()
+}
+
+impl CrossValidation {
+    /// a new cross-validation, not saved yet
+    pub(crate) fn new(
+        model: &str,
+        seed: u64,
+        params: Params,
+        folds: usize,
+        refit: bool,
+    ) -> CrossValidation {
+        CrossValidation {
+            id: String::new(),
+            model: model.to_string(),
+            started: unix_now(),
+            finished: None,
+            error: None,
+            seed,
+            params,
+            folds,
+            refit,
+            results: Vec::new(),
+            summary: BTreeMap::new(),
+            refit_run: None,
+        }
+    }
+
+    /// add the result of a fold and summarise all successful folds again
+    pub(crate) fn add_fold(&mut self, result: FoldResult) {
+        let index = self.results.partition_point(|r| r.fold < result.fold);
+        self.results.insert(index, result);
+        let metrics: Vec<&Metrics> = self
+            .results
+            .iter()
+            .filter(|r| r.error.is_none())
+            .map(|r| &r.metrics)
+            .collect();
+        self.summary = summarise(&metrics);
+    }
+
+    /// # the summary in one line per metric, e.g. `val_accuracy 0.9120 ± 0.0153`
+    ///
+    /// validation metrics come first, they are what cross-validation is about.
+    pub(crate) fn summary_lines(&self) -> Vec<String> {
+        let (mut lines, train): (Vec<String>, Vec<String>) = self
+            .summary
+            .iter()
+            .map(|(name, s)| {
+                let mut line = format!(
+                    "{} {} ± {}",
+                    name,
+                    format_metric(s.mean),
+                    format_metric(s.std)
+                );
+                if s.folds < self.folds {
+                    line.push_str(&format!(" ({} of {} folds)", s.folds, self.folds));
+                }
+                line
+            })
+            .partition(|line| line.starts_with("val_"));
+        lines.extend(train);
+        lines
+    }
+
+    /// # everything about the folds, for the Training tab
+    ///
+    /// one line per fold with its run and validation metrics, the summary and the refit run.
+    pub(crate) fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{}: {}-fold cross-validation of {}, {} / {} folds done",
+            self.id,
+            self.folds,
+            self.model,
+            self.results.len(),
+            self.folds
+        )];
+        for result in &self.results {
+            let text = match &result.error {
+                Some(error) => format!("failed: {}", error),
+                None => result
+                    .metrics
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("val_"))
+                    .map(|(name, value)| format!("{} {}", name, format_metric(*value)))
+                    .collect::<Vec<String>>()
+                    .join("  "),
+            };
+            lines.push(format!(
+                "fold {:>2}  {:<18}  {}",
+                result.fold + 1,
+                result.run_id,
+                text
+            ));
+        }
+        if !self.summary.is_empty() {
+            lines.push("mean ± std:".to_string());
+            lines.extend(self.summary_lines().into_iter().map(|l| format!("  {}", l)));
+        }
+        if let Some(run_id) = &self.refit_run {
+            lines.push(format!("refitted on all folds: {}", run_id));
+        }
+        if let Some(error) = &self.error {
+            lines.push(error.clone());
+        }
+        lines
+    }
+}
+
+pub(crate) fn crossval_path(project_dir: &Path, id: &str) -> PathBuf {
+    project_dir.join(CROSSVAL_DIR).join(format!("{}.toml", id))
+}
+
+/// # create the file of a new cross-validation
+///
+/// the id is named after the start time.
+///
+/// returns:
+///     Result with the cross-validation and its new id
+pub(crate) fn create_crossval(
+    project_dir: &Path,
+    mut crossval: CrossValidation,
+) -> Result<CrossValidation, Box<dyn Error>> {
+    fs::create_dir_all(project_dir.join(CROSSVAL_DIR))?;
+    let mut counter = 0;
+    loop {
+        crossval.id = if counter == 0 {
+            format!("cv-{}", crossval.started)
+        } else {
+            format!("cv-{}-{}", crossval.started, counter)
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(crossval_path(project_dir, &crossval.id))
+        {
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    save_crossval(project_dir, &crossval)?;
+    Ok(crossval)
+}
+
+pub(crate) fn save_crossval(
+    project_dir: &Path,
+    crossval: &CrossValidation,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        crossval_path(project_dir, &crossval.id),
+        toml::to_string(crossval)?,
+    )?;
+    Ok(())
+}
+
+pub(crate) fn load_crossval(
+    project_dir: &Path,
+    id: &str,
+) -> Result<CrossValidation, Box<dyn Error>> {
+    let contents = fs::read_to_string(crossval_path(project_dir, id))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(values: &[(&str, f64)]) -> Metrics {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn summarise_gives_mean_and_population_std() {
+        let folds = [
+            metrics(&[("val_accuracy", 0.8), ("loss", 0.5)]),
+            metrics(&[("val_accuracy", 0.9), ("loss", f64::NAN)]),
+            metrics(&[("val_accuracy", 1.0)]),
+        ];
+        let summary = summarise(&folds.iter().collect::<Vec<&Metrics>>());
+
+        let accuracy = summary["val_accuracy"];
+        assert!((accuracy.mean - 0.9).abs() < 1e-12);
+        assert!((accuracy.std - (0.02f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(accuracy.folds, 3);
+        // missing and non-finite values are left out
+        assert_eq!(
+            summary["loss"],
+            MetricSummary {
+                mean: 0.5,
+                std: 0.0,
+                folds: 1
+            }
+        );
+        assert!(summarise(&[]).is_empty());
+    }
+
+    #[test]
+    fn failed_folds_are_not_summarised() {
+        let mut crossval = CrossValidation::new("mlp", 1, Params::new(), 3, false);
+        for (fold, accuracy) in [(2, 0.7), (0, 0.9)] {
+            crossval.add_fold(FoldResult {
+                fold,
+                run_id: format!("run-{}", fold),
+                metrics: metrics(&[("val_accuracy", accuracy), ("loss", 0.1)]),
+                error: None,
+            });
+        }
+        crossval.add_fold(FoldResult {
+            fold: 1,
+            run_id: String::new(),
+            metrics: metrics(&[("val_accuracy", 0.0)]),
+            error: Some("failed".to_string()),
+        });
+
+        let folds: Vec<usize> = crossval.results.iter().map(|r| r.fold).collect();
+        assert_eq!(folds, [0, 1, 2]);
+        assert!((crossval.summary["val_accuracy"].mean - 0.8).abs() < 1e-12);
+        assert_eq!(crossval.summary["val_accuracy"].folds, 2);
+
+        let lines = crossval.summary_lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("val_accuracy "));
+        assert!(lines[0].ends_with(" (2 of 3 folds)"));
+        assert!(lines[1].starts_with("loss "));
+    }
+}
@@ -0,0 +1,239 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Statistics dashboard of the Projects tab
+
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::charts::{draw_bar_chart, export_png, export_svg, palette_colour, Bar, BarChart};
+use crate::classes::{class_colour, class_index, LabelClass};
+use crate::debug_println;
+use crate::helper::show_error_message;
+use crate::project::SharedProject;
+use crate::stats::{compute_stats, DatasetStats, Histogram};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// number of charts shown in the dashboard, see [`stats_charts`]
+const CHART_COUNT: usize = 7;
+
+fn histogram_chart(title: &str, histogram: &Histogram, colour: usize) -> BarChart {
+    BarChart {
+        title: title.to_string(),
+        bars: histogram
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(bin, count)| Bar {
+                label: histogram.bin_label(bin),
+                value: *count as f64,
+                colour: palette_colour(colour),
+            })
+            .collect(),
+    }
+}
+
+/// all charts of the dashboard, the class chart uses the colours of the project's classes
+fn stats_charts(stats: &DatasetStats, classes: &[LabelClass]) -> Vec<BarChart> {
+    let classes = BarChart {
+        title: "labels per class".to_string(),
+        bars: stats
+            .labels_per_class
+            .iter()
+            .enumerate()
+            .map(|(i, (class, count))| Bar {
+                label: class.clone(),
+                value: *count as f64,
+                // classes that are used but not defined keep a palette colour
+                colour: match class_index(classes, class) {
+                    Some(index) => class_colour(classes, index),
+                    None => palette_colour(classes.len() + i),
+                },
+            })
+            .collect(),
+    };
+
+    let modalities = BarChart {
+        title: "items per modality".to_string(),
+        bars: stats
+            .items_per_modality
+            .iter()
+            .map(|(modality, count)| Bar {
+                label: modality.name().to_string(),
+                value: *count as f64,
+                colour: palette_colour(7),
+            })
+            .collect(),
+    };
+
+    vec![
+        classes,
+        modalities,
+        histogram_chart("image width [px]", &stats.image_widths, 0),
+        histogram_chart("image height [px]", &stats.image_heights, 0),
+        histogram_chart("sound duration [s]", &stats.audio_durations, 1),
+        histogram_chart("boxes per image", &stats.boxes_per_image, 2),
+        histogram_chart("box size (sqrt area) [px]", &stats.box_sizes, 2),
+    ]
+}
+
+fn summary_text(stats: &DatasetStats) -> String {
+    let modalities = stats
+        .items_per_modality
+        .iter()
+        .map(|(modality, count)| format!("{} {}", count, modality.name()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{} items ({})\nannotation coverage: {:.1} %\nclass imbalance ratio: {}",
+        stats.items,
+        if modalities.is_empty() {
+            "-".to_string()
+        } else {
+            modalities
+        },
+        100.0 * stats.coverage,
+        stats
+            .imbalance_ratio
+            .map(|r| format!("{:.2}", r))
+            .unwrap_or_else(|| "-".to_string()),
+    )
+}
+
+/// ask for a file name and write the charts as PNG or SVG (depending on `svg`)
+fn export_dialog(charts: Rc<RefCell<Vec<BarChart>>>, svg: bool) {
+    let dialog = gtk::FileChooserDialog::builder()
+        .title(if svg {
+            "Export charts as SVG"
+        } else {
+            "Export charts as PNG"
+        })
+        .action(gtk::FileChooserAction::Save)
+        .build();
+
+    dialog.set_current_name(if svg {
+        "statistics.svg"
+    } else {
+        "statistics.png"
+    });
+    dialog.add_buttons(&[
+        ("Cancel", gtk::ResponseType::Cancel),
+        ("Export", gtk::ResponseType::Accept),
+    ]);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                let result = if svg {
+                    export_svg(&charts.borrow(), &path)
+                } else {
+                    export_png(&charts.borrow(), &path)
+                };
+
+                match result {
+                    Ok(()) => debug_println!("[INFO] exported charts to {}", path.display()),
+                    Err(e) => show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("EXPORT ERROR"),
+                        Some(&format!("Unable to export charts:\n{}", e)),
+                    ),
+                }
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+/// Statistics page: dataset summary and charts, drawn natively with cairo
+pub(crate) fn statistics_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let button_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let refresh_btn = Button::with_label("compute statistics");
+    let png_btn = Button::with_label("export PNG");
+    let svg_btn = Button::with_label("export SVG");
+
+    button_box.append(&refresh_btn);
+    button_box.append(&png_btn);
+    button_box.append(&svg_btn);
+
+    let summary_label = Label::builder().halign(gtk::Align::Start).build();
+
+    // charts
+    // ---------------------------------------------------------------------------------------------
+    let charts: Rc<RefCell<Vec<BarChart>>> =
+        Rc::new(RefCell::new(vec![BarChart::default(); CHART_COUNT]));
+
+    let flow_box = gtk::FlowBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .max_children_per_line(3)
+        .homogeneous(true)
+        .build();
+
+    let mut areas = Vec::with_capacity(CHART_COUNT);
+    for index in 0..CHART_COUNT {
+        let area = gtk::DrawingArea::builder()
+            .content_width(300)
+            .content_height(200)
+            .build();
+
+        let charts = charts.clone();
+        area.set_draw_func(move |_, cr, width, height| {
+            if let Some(chart) = charts.borrow().get(index) {
+                if let Err(e) = draw_bar_chart(cr, width as f64, height as f64, chart) {
+                    debug_println!("[ERROR: STATISTICS] unable to draw chart: {}", e);
+                }
+            }
+        });
+
+        flow_box.insert(&area, -1);
+        areas.push(area);
+    }
+
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .vexpand(true)
+        .child(&flow_box)
+        .build();
+
+    refresh_btn.connect_clicked(
+        gtk::glib::clone!(@strong project, @strong charts, @strong summary_label => move |_| {
+            let project_ref = project.borrow();
+            let Some(project) = project_ref.as_ref() else {
+                summary_label.set_text("no project opened");
+                return;
+            };
+
+            let stats = compute_stats(&project.store);
+            summary_label.set_text(&summary_text(&stats));
+            charts.replace(stats_charts(&stats, &project.config.classes));
+            for area in &areas {
+                area.queue_draw();
+            }
+        }),
+    );
+
+    png_btn.connect_clicked(gtk::glib::clone!(@strong charts => move |_| {
+        export_dialog(charts.clone(), false);
+    }));
+    svg_btn.connect_clicked(gtk::glib::clone!(@strong charts => move |_| {
+        export_dialog(charts.clone(), true);
+    }));
+
+    vbox.append(&button_box);
+    vbox.append(&summary_label);
+    vbox.append(&scrolled_window);
+
+    vbox
+}
@@ -0,0 +1,724 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Training data of a project
+//!
+//! Turns the items of a project into samples: an input vector, the class index and the split
+//! of the item. Depending on the model the input is a compact feature vector
+//! ([`Representation::Features`], for classical models) or the preprocessed data itself
+//! ([`Representation::Tensor`], for neural networks):
+//!
+//! - images: 8x8 thumbnail and histogram per channel / the preprocessed image `[c, h, w]`
+//! - sound: mean and std of the MFCCs / the log mel spectrogram `[1, bands, frames]`
+//! - sensors: one sample per window, the window features / the window `[channels, length]`
+//! - tabular: one sample per row, the preprocessed columns in both cases
+//!
+//! All preprocessing is taken from the saved `preprocessing.toml`, so a model always sees the
+//! data exactly as designed in the Preprocessing tab.
+
+use crate::audio::cached_features;
+use crate::debug_println;
+use crate::imagebuf::ImageBuf;
+use crate::pipeline::{cached_pipeline_output, load_preprocessing, PreprocessingConfig};
+use crate::pixbuf::load_image;
+use crate::project::Project;
+use crate::rng::Rng;
+use crate::sensor::{preprocess_recording, sliding_windows, window_features};
+use crate::splits::{load_splits, Split, SplitAssignment};
+use crate::store::{Annotation, Modality};
+use crate::tabular::{
+    is_missing, load_fitted_tabular, parse_number, read_table, FittedTabular, Table,
+};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// fraction of the items held out for validation if the project has no split assignment
+pub(crate) const VAL_FRACTION: f64 = 0.2;
+
+/// side length of the image thumbnails of [`Representation::Features`]
+const THUMBNAIL_SIZE: usize = 8;
+/// bins of the per channel histograms of [`Representation::Features`]
+const HISTOGRAM_BINS: usize = 16;
+
+/// images larger than this (longest side) are scaled down for [`Representation::Tensor`]
+pub(crate) const MAX_TENSOR_SIZE: usize = 128;
+/// spectrograms are cropped or padded to this number of frames
+pub(crate) const AUDIO_FRAMES: usize = 128;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Form of the model inputs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Representation {
+    /// fixed length feature vectors
+    #[default]
+    Features,
+    /// the preprocessed data, laid out as described by [`Dataset::shape`]
+    Tensor,
+}
+
+/// A single training sample
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sample {
+    /// item the sample was taken from (several samples per item for sensors and tables)
+    pub(crate) item_id: u64,
+    pub(crate) x: Vec<f32>,
+    /// index into [`Dataset::classes`], `None` for unlabelled samples
+    pub(crate) label: Option<usize>,
+    pub(crate) split: Split,
+    /// cross-validation fold of the item, if the split assignment has folds
+    pub(crate) fold: Option<usize>,
+}
+
+/// All samples of a project
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Dataset {
+    pub(crate) modality: Modality,
+    pub(crate) representation: Representation,
+    /// shape of every input, e.g. `[channels, height, width]` (row major)
+    pub(crate) shape: Vec<usize>,
+    /// class names, the position is the label
+    pub(crate) classes: Vec<String>,
+    pub(crate) samples: Vec<Sample>,
+}
+
+/// An item of a [`DatasetSource`]
+#[derive(Debug, Clone)]
+pub(crate) struct SourceItem {
+    pub(crate) id: u64,
+    pub(crate) path: PathBuf,
+    /// primary class of the item (images and sound)
+    pub(crate) class: Option<String>,
+    /// interval annotations of the item (sensors)
+    pub(crate) annotations: Vec<Annotation>,
+}
+
+/// Everything needed to build a [`Dataset`], taken from the project on the main thread
+///
+/// the dataset itself is built in the background, without access to the project.
+#[derive(Debug, Clone)]
+pub(crate) struct DatasetSource {
+    pub(crate) project_dir: PathBuf,
+    pub(crate) modality: Modality,
+    /// class names of the project config, they come first (in this order) in the dataset
+    pub(crate) classes: Vec<String>,
+    pub(crate) items: Vec<SourceItem>,
+    pub(crate) splits: Option<SplitAssignment>,
+    pub(crate) preprocessing: PreprocessingConfig,
+    /// column holding the class of tabular projects
+    pub(crate) label_column: String,
+    /// seed of the validation hold-out of projects without splits
+    pub(crate) seed: u64,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Representation {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Representation::Features => "features",
+            Representation::Tensor => "tensor",
+        }
+    }
+}
+
+impl Dataset {
+    /// length of every input
+    pub(crate) fn input_size(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// indices of the samples of one split
+    pub(crate) fn indices(&self, split: Split) -> Vec<usize> {
+        (0..self.samples.len())
+            .filter(|i| self.samples[*i].split == split)
+            .collect()
+    }
+
+    /// indices of the labelled samples of one split
+    pub(crate) fn labelled(&self, split: Split) -> Vec<usize> {
+        (0..self.samples.len())
+            .filter(|i| self.samples[*i].split == split && self.samples[*i].label.is_some())
+            .collect()
+    }
+
+    /// # labelled training and validation samples of a cross-validation fold
+    ///
+    /// the fold validates, the other folds train (as do non-test samples without a fold).
+    ///
+    /// returns:
+    ///     the training and the validation indices
+    pub(crate) fn fold(&self, fold: usize) -> (Vec<usize>, Vec<usize>) {
+        (0..self.samples.len())
+            .filter(|i| self.samples[*i].split != Split::Test && self.samples[*i].label.is_some())
+            .partition(|i| self.samples[*i].fold != Some(fold))
+    }
+
+    /// number of samples per class among the given samples
+    pub(crate) fn class_counts(&self, indices: &[usize]) -> Vec<usize> {
+        let mut counts = vec![0; self.classes.len()];
+        for i in indices {
+            if let Some(label) = self.samples[*i].label {
+                counts[label] += 1;
+            }
+        }
+        counts
+    }
+}
+
+impl DatasetSource {
+    /// # collect the inputs of a dataset from a project
+    ///
+    /// returns:
+    ///     Result with the source, an error for empty projects
+    pub(crate) fn from_project(
+        project: &Project,
+        label_column: &str,
+        seed: u64,
+    ) -> Result<DatasetSource, Box<dyn Error>> {
+        let modality = project.modality().ok_or("the project has no items")?;
+        let dir = project.dir().to_path_buf();
+        let store = &project.store;
+
+        // csv files are added as sensor recordings, tabular projects use them as tables
+        let matches =
+            |m: Modality| m == modality || (modality == Modality::Tabular && m == Modality::Sensor);
+        let items: Vec<SourceItem> = store
+            .items()
+            .filter(|item| matches(item.modality))
+            .map(|item| SourceItem {
+                id: item.id,
+                path: PathBuf::from(&item.path),
+                class: store.primary_class(item.id).map(str::to_string),
+                annotations: if modality == Modality::Sensor {
+                    store
+                        .annotations_of_item(item.id)
+                        .into_iter()
+                        .cloned()
+                        .collect()
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+        if items.is_empty() {
+            return Err(format!("the project has no {} items", modality.name()).into());
+        }
+
+        Ok(DatasetSource {
+            preprocessing: load_preprocessing(&dir)?,
+            splits: load_splits(&dir).ok(),
+            project_dir: dir,
+            modality,
+            classes: project
+                .config
+                .classes
+                .iter()
+                .map(|c| c.name.clone())
+                .collect(),
+            items,
+            label_column: label_column.to_string(),
+            seed,
+        })
+    }
+
+    /// split and fold of every item, a random hold-out if the project has no splits
+    pub(crate) fn item_splits(&self) -> HashMap<u64, (Split, Option<usize>)> {
+        match &self.splits {
+            Some(splits) => self
+                .items
+                .iter()
+                .map(|item| {
+                    let fold = splits
+                        .items
+                        .binary_search_by_key(&item.id, |s| s.item_id)
+                        .ok()
+                        .and_then(|i| splits.items[i].fold);
+                    // items added after the assignment are used for training
+                    let split = splits.split_of(item.id).unwrap_or(Split::Train);
+                    (item.id, (split, fold))
+                })
+                .collect(),
+            None => {
+                let mut ids: Vec<u64> = self.items.iter().map(|item| item.id).collect();
+                ids.sort_unstable();
+                Rng::new(self.seed).shuffle(&mut ids);
+                let val = (ids.len() as f64 * VAL_FRACTION).round() as usize;
+                ids.iter()
+                    .enumerate()
+                    .map(|(i, id)| {
+                        let split = if i < val { Split::Val } else { Split::Train };
+                        (*id, (split, None))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// # rows of all tables of the training split, what the tabular transformer is fitted on
+    ///
+    /// the splits are the ones of a training run, so validation and test rows never reach
+    /// the fitted statistics. Tables are aligned to the columns of the first one.
+    ///
+    /// returns:
+    ///     Result with the table, an error if the project is not tabular or a table lacks
+    ///     a column of the first one
+    pub(crate) fn training_table(&self) -> Result<Table, Box<dyn Error>> {
+        if self.modality != Modality::Tabular {
+            return Err("the project has no tables".into());
+        }
+        let splits = self.item_splits();
+        let mut training: Option<Table> = None;
+        for item in &self.items {
+            if splits.get(&item.id).map(|(split, _)| *split) != Some(Split::Train) {
+                continue;
+            }
+            let table = read_table(&item.path)?;
+            match &mut training {
+                Some(training) => {
+                    let table = table
+                        .select(&training.columns)
+                        .map_err(|e| format!("{}: {}", item.path.display(), e))?;
+                    training.rows.extend(table.rows);
+                }
+                None => training = Some(table),
+            }
+        }
+        training.ok_or_else(|| "the training split has no tables".into())
+    }
+}
+
+/// drop the alpha channel and convert to the given number of channels (1 or 3)
+fn with_channels(image: &ImageBuf, channels: usize) -> ImageBuf {
+    let colour = match image.channels {
+        1 | 2 => {
+            let mut rgb = ImageBuf::new(image.width, image.height, 3);
+            for (out, pixel) in rgb
+                .data
+                .chunks_exact_mut(3)
+                .zip(image.data.chunks_exact(image.channels))
+            {
+                out.fill(pixel[0]);
+            }
+            rgb
+        }
+        4 => {
+            let mut rgb = ImageBuf::new(image.width, image.height, 3);
+            for (out, pixel) in rgb.data.chunks_exact_mut(3).zip(image.data.chunks_exact(4)) {
+                out.copy_from_slice(&pixel[..3]);
+            }
+            rgb
+        }
+        _ => image.clone(),
+    };
+    if channels == 1 {
+        colour.to_gray()
+    } else {
+        colour
+    }
+}
+
+/// thumbnail (interleaved) followed by a normalised histogram of every channel
+fn image_features(image: &ImageBuf) -> Vec<f32> {
+    let thumbnail = image.resize_area(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut features = thumbnail.data;
+    let pixels = (image.width * image.height).max(1) as f32;
+    for c in 0..image.channels {
+        let mut histogram = [0.0; HISTOGRAM_BINS];
+        for value in image.data.iter().skip(c).step_by(image.channels) {
+            let bin =
+                ((value.clamp(0.0, 1.0) * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1);
+            histogram[bin] += 1.0;
+        }
+        features.extend(histogram.iter().map(|count| count / pixels));
+    }
+    features
+}
+
+/// planar `[channels, height, width]` layout of an interleaved image
+fn image_tensor(image: &ImageBuf) -> Vec<f32> {
+    let mut tensor = Vec::with_capacity(image.data.len());
+    for c in 0..image.channels {
+        tensor.extend(image.data.iter().skip(c).step_by(image.channels));
+    }
+    tensor
+}
+
+/// size of the image tensors: the size of the first image, scaled down if necessary
+fn tensor_size(width: usize, height: usize) -> (usize, usize) {
+    let longest = width.max(height).max(1);
+    if longest <= MAX_TENSOR_SIZE {
+        return (width.max(1), height.max(1));
+    }
+    let scale = MAX_TENSOR_SIZE as f64 / longest as f64;
+    (
+        ((width as f64 * scale).round() as usize).max(1),
+        ((height as f64 * scale).round() as usize).max(1),
+    )
+}
+
+/// mean and standard deviation of every coefficient over all frames
+fn mean_std_columns(frames: &[Vec<f32>]) -> Vec<f32> {
+    let width = frames.first().map_or(0, Vec::len);
+    let n = frames.len().max(1) as f32;
+    let mut features = vec![0.0; 2 * width];
+    for c in 0..width {
+        let mean = frames.iter().map(|f| f[c]).sum::<f32>() / n;
+        let variance = frames.iter().map(|f| (f[c] - mean).powi(2)).sum::<f32>() / n;
+        features[c] = mean;
+        features[width + c] = variance.sqrt();
+    }
+    features
+}
+
+/// `[1, bands, AUDIO_FRAMES]` spectrogram, cropped or padded with its minimum
+fn spectrogram_tensor(mel: &[Vec<f32>], bands: usize) -> Vec<f32> {
+    let floor = mel.iter().flatten().copied().fold(f32::INFINITY, f32::min);
+    let floor = if floor.is_finite() { floor } else { 0.0 };
+    let mut tensor = vec![floor; bands * AUDIO_FRAMES];
+    for (t, frame) in mel.iter().take(AUDIO_FRAMES).enumerate() {
+        for (b, value) in frame.iter().take(bands).enumerate() {
+            tensor[b * AUDIO_FRAMES + t] = *value;
+        }
+    }
+    tensor
+}
+
+/// a labelled input before the class names are turned into indices
+struct RawSample {
+    item_id: u64,
+    x: Vec<f32>,
+    label: Option<String>,
+}
+
+/// inputs of a single item (several for sensors and tables), also returns the shape
+fn item_samples(
+    source: &DatasetSource,
+    item: &SourceItem,
+    representation: Representation,
+    fitted: &Option<FittedTabular>,
+    image_size: &mut Option<(usize, usize, usize)>,
+) -> Result<(Vec<RawSample>, Vec<usize>), Box<dyn Error>> {
+    let config = &source.preprocessing;
+    let single = |x: Vec<f32>| RawSample {
+        item_id: item.id,
+        x,
+        label: item.class.clone(),
+    };
+
+    match source.modality {
+        Modality::Image => {
+            let image = if config.image.steps.is_empty() {
+                load_image(&item.path)?
+            } else {
+                cached_pipeline_output(&source.project_dir, &item.path, &config.image)?.0
+            };
+            // every image gets the channels (and for tensors the size) of the first one
+            let (width, height, channels) = *image_size.get_or_insert_with(|| {
+                let (width, height) = tensor_size(image.width, image.height);
+                let channels = if image.channels <= 2 { 1 } else { 3 };
+                (width, height, channels)
+            });
+            let image = with_channels(&image, channels);
+            match representation {
+                Representation::Features => {
+                    let x = image_features(&image);
+                    let shape = vec![x.len()];
+                    Ok((vec![single(x)], shape))
+                }
+                Representation::Tensor => {
+                    let image = if (image.width, image.height) == (width, height) {
+                        image
+                    } else if image.width >= width && image.height >= height {
+                        image.resize_area(width, height)
+                    } else {
+                        image.resize_bilinear(width, height)
+                    };
+                    Ok((
+                        vec![single(image_tensor(&image))],
+                        vec![channels, height, width],
+                    ))
+                }
+            }
+        }
+        Modality::Sound => {
+            let (features, _) = cached_features(&source.project_dir, &item.path, &config.audio)?;
+            match representation {
+                Representation::Features => {
+                    let x = mean_std_columns(&features.mfcc);
+                    let shape = vec![x.len()];
+                    Ok((vec![single(x)], shape))
+                }
+                Representation::Tensor => {
+                    let bands = config.audio.mel_bands;
+                    Ok((
+                        vec![single(spectrogram_tensor(&features.mel, bands))],
+                        vec![1, bands, AUDIO_FRAMES],
+                    ))
+                }
+            }
+        }
+        Modality::Sensor => {
+            let signals = preprocess_recording(&item.path, &config.sensor)?;
+            let annotations: Vec<&Annotation> = item.annotations.iter().collect();
+            let windows = sliding_windows(&signals, &annotations, &config.sensor);
+            let mut shape = Vec::new();
+            let samples = windows
+                .iter()
+                .map(|window| {
+                    let x: Vec<f32> = match representation {
+                        Representation::Features => {
+                            window_features(&signals, window, &config.sensor.features)
+                                .into_iter()
+                                .map(|v| v as f32)
+                                .collect()
+                        }
+                        Representation::Tensor => signals
+                            .values
+                            .iter()
+                            .flat_map(|channel| {
+                                channel[window.start..window.end].iter().map(|v| *v as f32)
+                            })
+                            .collect(),
+                    };
+                    shape = match representation {
+                        Representation::Features => vec![x.len()],
+                        Representation::Tensor => {
+                            vec![signals.values.len(), window.end - window.start]
+                        }
+                    };
+                    // windows overlapping several classes are labelled with the first one
+                    RawSample {
+                        item_id: item.id,
+                        x,
+                        label: window.labels.first().cloned(),
+                    }
+                })
+                .collect();
+            Ok((samples, shape))
+        }
+        Modality::Tabular => {
+            let table = read_table(&item.path)?;
+            let table = match fitted {
+                Some(fitted) => fitted.transform(&table)?,
+                None => table,
+            };
+            let label_index = table.column_index(&source.label_column)?;
+            let mut samples = Vec::with_capacity(table.rows.len());
+            for (r, row) in table.rows.iter().enumerate() {
+                let x = row
+                    .iter()
+                    .enumerate()
+                    .filter(|(c, _)| *c != label_index)
+                    .map(|(c, cell)| {
+                        parse_number(cell).map(|v| v as f32).ok_or_else(|| {
+                            format!(
+                                "column {:?} of row {} is not numeric or missing",
+                                table.columns[c],
+                                r + 1
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<f32>, String>>()?;
+                let label = &row[label_index];
+                samples.push(RawSample {
+                    item_id: item.id,
+                    x,
+                    label: (!is_missing(label)).then(|| label.trim().to_string()),
+                });
+            }
+            Ok((samples, vec![table.columns.len() - 1]))
+        }
+    }
+}
+
+/// # build the dataset of a project
+///
+/// items that can not be loaded are skipped (and logged). `progress` is called with the
+/// number of processed and all items after every item.
+///
+/// returns:
+///     Result with the dataset, an error if no sample could be built or `cancel` was set
+pub(crate) fn build_dataset(
+    source: &DatasetSource,
+    representation: Representation,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Dataset, Box<dyn Error>> {
+    let splits = source.item_splits();
+    let fitted = if source.modality == Modality::Tabular {
+        load_fitted_tabular(&source.project_dir)?
+    } else {
+        None
+    };
+    if let Some(label) = fitted.as_ref().and_then(|f| f.label_column.as_ref()) {
+        if *label != source.label_column {
+            return Err(format!(
+                "the tabular transformer was fitted for the label column {:?}, fit it again",
+                label
+            )
+            .into());
+        }
+    }
+
+    let mut image_size = None;
+    let mut shape: Option<Vec<usize>> = None;
+    let mut raw = Vec::new();
+    let mut skipped = 0;
+    for (i, item) in source.items.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("cancelled".into());
+        }
+        match item_samples(source, item, representation, &fitted, &mut image_size) {
+            Ok((samples, item_shape)) if !samples.is_empty() => {
+                let expected = shape.get_or_insert(item_shape.clone());
+                if *expected == item_shape {
+                    raw.extend(samples);
+                } else {
+                    debug_println!(
+                        "[ERROR: DATASET] skipping {}: shape {:?} differs from {:?}",
+                        item.path.display(),
+                        item_shape,
+                        expected
+                    );
+                    skipped += 1;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                debug_println!("[ERROR: DATASET] skipping {}: {}", item.path.display(), e);
+                skipped += 1;
+            }
+        }
+        progress(i + 1, source.items.len());
+    }
+
+    let shape = shape.ok_or("no sample could be built from the items of the project")?;
+    if skipped > 0 {
+        debug_println!(
+            "[INFO: DATASET] skipped {} of {} items",
+            skipped,
+            source.items.len()
+        );
+    }
+
+    // classes of the project config first, then all other labels alphabetically
+    let used: BTreeSet<&str> = raw.iter().filter_map(|s| s.label.as_deref()).collect();
+    let mut classes: Vec<String> = source
+        .classes
+        .iter()
+        .filter(|c| used.contains(c.as_str()))
+        .cloned()
+        .collect();
+    for label in used {
+        if !classes.iter().any(|c| c == label) {
+            classes.push(label.to_string());
+        }
+    }
+
+    let samples = raw
+        .into_iter()
+        .map(|sample| {
+            let (split, fold) = splits
+                .get(&sample.item_id)
+                .copied()
+                .unwrap_or((Split::Train, None));
+            Sample {
+                label: sample
+                    .label
+                    .and_then(|label| classes.iter().position(|c| *c == label)),
+                item_id: sample.item_id,
+                x: sample.x,
+                split,
+                fold,
+            }
+        })
+        .collect();
+
+    Ok(Dataset {
+        modality: source.modality,
+        representation,
+        shape,
+        classes,
+        samples,
+    })
+}
+
+/// short description, e.g. `1200 samples [3, 64, 64], 4 classes (train 840, val 180, test 180)`
+pub(crate) fn describe_dataset(dataset: &Dataset) -> String {
+    format!(
+        "{} samples {:?}, {} classes (train {}, val {}, test {})",
+        dataset.samples.len(),
+        dataset.shape,
+        dataset.classes.len(),
+        dataset.indices(Split::Train).len(),
+        dataset.indices(Split::Val).len(),
+        dataset.indices(Split::Test).len(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splits::{ItemSplit, SplitConfig};
+    use std::fs;
+
+    #[test]
+    fn training_table_has_only_the_training_split() {
+        let dir = std::env::temp_dir().join(format!("ai-lab-dataset-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tables = [
+            (1, Split::Train, "size,label\n1,cat\n2,dog\n"),
+            (2, Split::Val, "size,label\n100,cat\n"),
+            (3, Split::Train, "label,size,extra\ndog,3,x\n"),
+            (4, Split::Test, "size,label\n200,dog\n"),
+        ];
+        let mut items = Vec::new();
+        let mut splits = Vec::new();
+        for (id, split, contents) in tables {
+            let path = dir.join(format!("{}.csv", id));
+            fs::write(&path, contents).unwrap();
+            items.push(SourceItem {
+                id,
+                path,
+                class: None,
+                annotations: Vec::new(),
+            });
+            splits.push(ItemSplit {
+                item_id: id,
+                split,
+                fold: None,
+            });
+        }
+        let mut source = DatasetSource {
+            project_dir: dir.clone(),
+            modality: Modality::Tabular,
+            classes: Vec::new(),
+            items,
+            splits: Some(SplitAssignment {
+                config: SplitConfig::default(),
+                items: splits,
+            }),
+            preprocessing: PreprocessingConfig::default(),
+            label_column: "label".to_string(),
+            seed: 1,
+        };
+
+        let table = source.training_table().unwrap();
+        assert_eq!(table.columns, ["size", "label"]);
+        assert_eq!(table.rows, [["1", "cat"], ["2", "dog"], ["3", "dog"]]);
+
+        // every table needs the columns of the first one
+        fs::write(dir.join("3.csv"), "label\ndog\n").unwrap();
+        assert!(source.training_table().is_err());
+
+        source.modality = Modality::Image;
+        assert!(source.training_table().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,478 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Duplicate and near-duplicate detection
+//!
+//! Duplicates across train and test silently inflate metrics. Exact duplicates are found by
+//! the SHA-256 content hash, near-duplicates (re-encoded, resized, slightly edited images)
+//! by a 64 bit perceptual hash and a maximum Hamming distance.
+//!
+//! Found clusters can either be reduced to their first item or marked with the meta value
+//! [`DUPLICATE_GROUP_KEY`], which makes the split manager keep them in the same split.
+
+use crate::helper::UnionFind;
+use crate::imagebuf::ImageBuf;
+use crate::snapshot::content_hash;
+use crate::splits::{load_splits, save_splits, SPLITS_FILE_NAME};
+use crate::store::{AnnotationStore, Modality};
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// meta key marking items of the same duplicate cluster
+pub(crate) const DUPLICATE_GROUP_KEY: &str = "duplicate_group";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Kind of perceptual hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HashKind {
+    /// aHash: 8x8 thumbnail, bit set if the pixel is brighter than the mean
+    Average,
+    /// dHash: 9x8 thumbnail, bit set if a pixel is brighter than its right neighbour
+    Difference,
+    /// pHash: low frequencies of the DCT of a 32x32 thumbnail compared to their median
+    Perceptual,
+}
+
+impl HashKind {
+    pub(crate) const ALL: [HashKind; 3] = [
+        HashKind::Average,
+        HashKind::Difference,
+        HashKind::Perceptual,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            HashKind::Average => "aHash",
+            HashKind::Difference => "dHash",
+            HashKind::Perceptual => "pHash",
+        }
+    }
+
+    pub(crate) fn hash(&self, image: &ImageBuf) -> u64 {
+        match self {
+            HashKind::Average => average_hash(image),
+            HashKind::Difference => difference_hash(image),
+            HashKind::Perceptual => perceptual_hash(image),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicateKind {
+    /// identical file content
+    Exact,
+    /// perceptual hashes within the Hamming threshold
+    Near,
+}
+
+/// A set of items that are (near) copies of each other
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DuplicateCluster {
+    pub(crate) kind: DuplicateKind,
+    /// sorted, the first item is the one that is kept when dropping duplicates
+    pub(crate) item_ids: Vec<u64>,
+    /// largest Hamming distance of a member to the first item (0 for exact duplicates)
+    pub(crate) max_distance: u32,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// number of differing bits
+pub(crate) fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn bits_from(values: impl Iterator<Item = bool>) -> u64 {
+    values
+        .take(64)
+        .enumerate()
+        .fold(0, |hash, (i, bit)| if bit { hash | (1 << i) } else { hash })
+}
+
+pub(crate) fn average_hash(image: &ImageBuf) -> u64 {
+    let small = image.to_gray().resize_area(8, 8);
+    let mean = small.data.iter().sum::<f32>() / small.data.len() as f32;
+    bits_from(small.data.iter().map(|v| *v > mean))
+}
+
+pub(crate) fn difference_hash(image: &ImageBuf) -> u64 {
+    let small = image.to_gray().resize_area(9, 8);
+    bits_from(
+        (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .map(|(x, y)| small.get(x, y, 0) > small.get(x + 1, y, 0)),
+    )
+}
+
+pub(crate) fn perceptual_hash(image: &ImageBuf) -> u64 {
+    const N: usize = 32;
+    const LOW: usize = 8;
+
+    let small = image.to_gray().resize_area(N, N);
+
+    // 2D DCT-II, only the LOW x LOW lowest frequencies are needed
+    let cos_table: Vec<f32> = (0..LOW)
+        .flat_map(|u| {
+            (0..N).map(move |x| {
+                ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / (2 * N) as f32).cos()
+            })
+        })
+        .collect();
+
+    let mut rows = vec![0.0f32; N * LOW]; // DCT along x for every row
+    for y in 0..N {
+        for u in 0..LOW {
+            rows[y * LOW + u] = (0..N)
+                .map(|x| small.get(x, y, 0) * cos_table[u * N + x])
+                .sum();
+        }
+    }
+
+    let mut coefficients = Vec::with_capacity(LOW * LOW);
+    for v in 0..LOW {
+        for u in 0..LOW {
+            coefficients.push(
+                (0..N)
+                    .map(|y| rows[y * LOW + u] * cos_table[v * N + y])
+                    .sum::<f32>(),
+            );
+        }
+    }
+
+    // the DC term only reflects the overall brightness and is left out of the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    bits_from(coefficients.iter().map(|c| *c > median))
+}
+
+/// clusters of items with identical content hash
+pub(crate) fn exact_duplicates(hashes: &[(u64, String)]) -> Vec<DuplicateCluster> {
+    let mut by_hash: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+    for (item_id, hash) in hashes {
+        by_hash.entry(hash.as_str()).or_default().push(*item_id);
+    }
+
+    let mut clusters: Vec<DuplicateCluster> = by_hash
+        .into_values()
+        .filter(|ids| ids.len() > 1)
+        .map(|mut item_ids| {
+            item_ids.sort_unstable();
+            DuplicateCluster {
+                kind: DuplicateKind::Exact,
+                item_ids,
+                max_distance: 0,
+            }
+        })
+        .collect();
+
+    clusters.sort_by_key(|c| c.item_ids[0]);
+    clusters
+}
+
+/// # clusters of items whose perceptual hashes differ in at most `threshold` bits
+///
+/// clusters are transitive (if a ~ b and b ~ c then a, b and c form one cluster). To avoid
+/// comparing all pairs, the hashes are cut into `threshold + 1` chunks: two hashes within
+/// the threshold must agree on at least one chunk, so only items sharing a chunk value are
+/// compared.
+pub(crate) fn near_duplicates(hashes: &[(u64, u64)], threshold: u32) -> Vec<DuplicateCluster> {
+    let mut union_find = UnionFind::new(hashes.len());
+
+    let chunks = (threshold as usize + 1).min(64);
+    let bounds: Vec<usize> = (0..=chunks).map(|i| i * 64 / chunks).collect();
+
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (i, (_, hash)) in hashes.iter().enumerate() {
+        for chunk in 0..chunks {
+            let width = bounds[chunk + 1] - bounds[chunk];
+            let mask = if width == 64 {
+                u64::MAX
+            } else {
+                (1 << width) - 1
+            };
+            let value = (hash >> bounds[chunk]) & mask;
+            buckets.entry((chunk, value)).or_default().push(i);
+        }
+    }
+
+    for members in buckets.values() {
+        for (n, a) in members.iter().enumerate() {
+            for b in &members[n + 1..] {
+                if hamming(hashes[*a].1, hashes[*b].1) <= threshold {
+                    union_find.union(*a, *b);
+                }
+            }
+        }
+    }
+
+    let mut clusters: Vec<DuplicateCluster> = union_find
+        .groups()
+        .into_iter()
+        .map(|members| {
+            let mut members: Vec<(u64, u64)> = members.iter().map(|i| hashes[*i]).collect();
+            members.sort_unstable();
+            let first = members[0].1;
+            DuplicateCluster {
+                kind: DuplicateKind::Near,
+                max_distance: members
+                    .iter()
+                    .map(|(_, h)| hamming(first, *h))
+                    .max()
+                    .unwrap_or(0),
+                item_ids: members.into_iter().map(|(id, _)| id).collect(),
+            }
+        })
+        .collect();
+
+    clusters.sort_by_key(|c| c.item_ids[0]);
+    clusters
+}
+
+/// ids and files of the images of a store, the input of [`find_duplicates`]
+pub(crate) fn image_files(store: &AnnotationStore) -> Vec<(u64, PathBuf)> {
+    store
+        .items_of_modality(Modality::Image)
+        .into_iter()
+        .map(|item| (item.id, PathBuf::from(&item.path)))
+        .collect()
+}
+
+/// # find exact and near-duplicate images
+///
+/// every image file is read and decoded, so this takes a while for large projects and
+/// belongs into a background thread. `load` decodes an image file (the UI passes a
+/// gdk-pixbuf based loader). Near-duplicate clusters that only contain identical files are
+/// left out, they are already reported as exact duplicates.
+///
+/// returns:
+///     exact clusters followed by near-duplicate clusters
+pub(crate) fn find_duplicates(
+    images: &[(u64, PathBuf)],
+    kind: HashKind,
+    threshold: u32,
+    load: impl Fn(&Path) -> Result<ImageBuf, Box<dyn Error>>,
+) -> Vec<DuplicateCluster> {
+    let mut content_hashes = Vec::new();
+    let mut perceptual_hashes = Vec::new();
+
+    for (item_id, path) in images {
+        if let Ok(hash) = content_hash(path) {
+            content_hashes.push((*item_id, hash));
+        }
+        if let Ok(image) = load(path) {
+            perceptual_hashes.push((*item_id, kind.hash(&image)));
+        }
+    }
+
+    let content_of: HashMap<u64, &str> = content_hashes
+        .iter()
+        .map(|(id, hash)| (*id, hash.as_str()))
+        .collect();
+
+    let mut clusters = exact_duplicates(&content_hashes);
+    clusters.extend(
+        near_duplicates(&perceptual_hashes, threshold)
+            .into_iter()
+            .filter(|cluster| {
+                let first = content_of.get(&cluster.item_ids[0]);
+                first.is_none()
+                    || cluster
+                        .item_ids
+                        .iter()
+                        .any(|id| content_of.get(id) != first)
+            }),
+    );
+    clusters
+}
+
+/// remove all items but the first of every cluster from the store
+///
+/// returns:
+///     Result with the number of removed items
+pub(crate) fn drop_duplicates(
+    store: &mut AnnotationStore,
+    clusters: &[DuplicateCluster],
+) -> Result<usize, Box<dyn Error>> {
+    let mut removed = 0;
+    for cluster in clusters {
+        for item_id in &cluster.item_ids[1..] {
+            if store.item(*item_id).is_some() {
+                store.remove_item(*item_id)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// # force the items of every cluster into the same split
+///
+/// marks the members with a shared [`DUPLICATE_GROUP_KEY`] value, so every future split
+/// assignment keeps them together, and moves them into the split of the cluster's first
+/// item in the current assignment (if there is one).
+pub(crate) fn group_duplicates(
+    store: &mut AnnotationStore,
+    clusters: &[DuplicateCluster],
+) -> Result<(), Box<dyn Error>> {
+    for cluster in clusters {
+        let group = format!("dup-{}", cluster.item_ids[0]);
+        store.set_meta(&cluster.item_ids, DUPLICATE_GROUP_KEY, Some(&group))?;
+    }
+
+    let project_dir = store.project_dir();
+    if !project_dir.join(SPLITS_FILE_NAME).exists() {
+        return Ok(());
+    }
+
+    let mut assignment = load_splits(project_dir)?;
+    for cluster in clusters {
+        let Some(first) = assignment
+            .items
+            .iter()
+            .find(|s| s.item_id == cluster.item_ids[0])
+            .cloned()
+        else {
+            continue;
+        };
+        for item_split in assignment.items.iter_mut() {
+            if cluster.item_ids.contains(&item_split.item_id) {
+                item_split.split = first.split;
+                item_split.fold = first.fold;
+            }
+        }
+    }
+    save_splits(project_dir, &assignment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn ids(clusters: &[DuplicateCluster]) -> Vec<Vec<u64>> {
+        clusters.iter().map(|c| c.item_ids.clone()).collect()
+    }
+
+    #[test]
+    fn near_duplicates_are_transitive() {
+        // 1 ~ 2 and 2 ~ 3 within 2 bits, 1 and 3 are 4 bits apart
+        let hashes = [(1, 0b0000), (2, 0b0011), (3, 0b1111), (4, u64::MAX)];
+        let clusters = near_duplicates(&hashes, 2);
+        assert_eq!(ids(&clusters), vec![vec![1, 2, 3]]);
+        assert_eq!(clusters[0].kind, DuplicateKind::Near);
+        assert_eq!(clusters[0].max_distance, 4);
+
+        assert!(near_duplicates(&hashes, 1).is_empty());
+        assert_eq!(
+            ids(&near_duplicates(&[(7, 42), (5, 42), (6, 43)], 0)),
+            vec![vec![5, 7]]
+        );
+    }
+
+    #[test]
+    fn near_duplicates_find_differences_in_every_chunk() {
+        // threshold 3 cuts the hash into 4 chunks, 3 of them differ
+        let other = (1 << 1) | (1 << 17) | (1 << 33);
+        assert_eq!(
+            ids(&near_duplicates(&[(1, 0), (2, other)], 3)),
+            vec![vec![1, 2]]
+        );
+        assert!(near_duplicates(&[(1, 0), (2, other | (1 << 49))], 3).is_empty());
+    }
+
+    #[test]
+    fn near_duplicates_match_all_pairs() {
+        let mut rng = Rng::new(3);
+        let mut hashes = Vec::new();
+        for id in 0..200 {
+            let hash = if id % 4 == 0 || hashes.is_empty() {
+                rng.next_u64()
+            } else {
+                // a copy of an earlier hash with a few flipped bits
+                let (_, base): (u64, u64) = hashes[rng.below(hashes.len())];
+                (0..rng.below(6)).fold(base, |hash, _| hash ^ (1 << rng.below(64)))
+            };
+            hashes.push((id, hash));
+        }
+
+        for threshold in [0, 3, 8] {
+            let mut union_find = UnionFind::new(hashes.len());
+            for a in 0..hashes.len() {
+                for b in a + 1..hashes.len() {
+                    if hamming(hashes[a].1, hashes[b].1) <= threshold {
+                        union_find.union(a, b);
+                    }
+                }
+            }
+            let mut expected: Vec<Vec<u64>> = union_find
+                .groups()
+                .into_iter()
+                .map(|group| group.into_iter().map(|i| hashes[i].0).collect())
+                .collect();
+            expected.sort();
+            assert_eq!(ids(&near_duplicates(&hashes, threshold)), expected);
+        }
+    }
+
+    #[test]
+    fn perceptual_hash_survives_small_changes() {
+        let mut image = ImageBuf::new(64, 48, 1);
+        for y in 0..48 {
+            for x in 0..64 {
+                image.set(x, y, 0, ((x * y) % 97) as f32 / 97.0);
+            }
+        }
+        let mut brighter = image.clone();
+        for value in brighter.data.iter_mut() {
+            *value = (*value * 0.9 + 0.05).min(1.0);
+        }
+        assert!(hamming(perceptual_hash(&image), perceptual_hash(&brighter)) <= 4);
+    }
+
+    #[test]
+    fn find_duplicates_reports_near_copies_once() {
+        let dir = std::env::temp_dir().join(format!("ai-lab-dedup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // the "decoder" reads the first line of a file as the seed of a random image, so
+        // files with the same first line are different files of the same picture
+        let files = [
+            (1, "3\noriginal"),
+            (2, "3\noriginal"),
+            (3, "3\nre-encoded"),
+            (4, "8\nsomething else"),
+        ];
+        let images: Vec<(u64, PathBuf)> = files
+            .iter()
+            .map(|(id, contents)| {
+                let path = dir.join(format!("{}.img", id));
+                std::fs::write(&path, contents).unwrap();
+                (*id, path)
+            })
+            .collect();
+        let load = |path: &Path| -> Result<ImageBuf, Box<dyn Error>> {
+            let contents = std::fs::read_to_string(path)?;
+            let mut rng = Rng::new(contents.lines().next().unwrap_or_default().parse()?);
+            let mut image = ImageBuf::new(16, 16, 1);
+            image
+                .data
+                .iter_mut()
+                .for_each(|v| *v = rng.next_f64() as f32);
+            Ok(image)
+        };
+
+        let clusters = find_duplicates(&images, HashKind::Perceptual, 2, load);
+        assert_eq!(ids(&clusters), vec![vec![1, 2], vec![1, 2, 3]]);
+        assert_eq!(clusters[0].kind, DuplicateKind::Exact);
+        assert_eq!(clusters[1].kind, DuplicateKind::Near);
+
+        // a near cluster of identical files only is the exact cluster already
+        let clusters = find_duplicates(&images[..2], HashKind::Perceptual, 2, load);
+        assert_eq!(ids(&clusters), vec![vec![1, 2]]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
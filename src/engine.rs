@@ -0,0 +1,277 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Background execution of preprocessing steps
+//!
+//! A run hands a list of tasks (one input file and the work to do on it) to a pool of worker
+//! threads. Progress is streamed back over a channel, so the GTK main thread only polls it and
+//! never blocks. Outputs are cached inside the project, keyed by the content of the input and
+//! the parameters of the step, so unchanged items are skipped when a run is repeated.
+
+use crate::snapshot::content_hash;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// directory inside a project holding all cached outputs (one sub directory per kind of step)
+pub(crate) const CACHE_DIR: &str = "cache";
+
+/// number of failures whose messages are kept for the summary
+const MAX_KEPT_ERRORS: usize = 50;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Work done on a single input, returns whether the output came from the cache
+///
+/// errors are plain strings, since they are sent back from the worker threads
+pub(crate) type Work = Arc<dyn Fn(&Path) -> Result<bool, String> + Send + Sync>;
+
+/// An input file together with the work to do on it
+#[derive(Clone)]
+pub(crate) struct Task {
+    pub(crate) input: PathBuf,
+    pub(crate) work: Work,
+}
+
+/// Messages from the workers to the polling side
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Event {
+    Done {
+        input: PathBuf,
+        cached: bool,
+    },
+    Failed {
+        input: PathBuf,
+        error: String,
+    },
+    /// all workers stopped, either because every task ran or the run was cancelled
+    Finished,
+}
+
+/// Accumulated state of a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Progress {
+    pub(crate) total: usize,
+    pub(crate) done: usize,
+    pub(crate) cached: usize,
+    pub(crate) failed: usize,
+    /// the first [`MAX_KEPT_ERRORS`] failures
+    pub(crate) errors: Vec<(PathBuf, String)>,
+    pub(crate) finished: bool,
+    pub(crate) cancelled: bool,
+}
+
+/// A started run, dropping it does not stop the workers (use [`Run::cancel`])
+pub(crate) struct Run {
+    cancel: Arc<AtomicBool>,
+    events: Receiver<Event>,
+    pub(crate) progress: Progress,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Progress {
+    fn update(&mut self, event: Event) {
+        match event {
+            Event::Done { cached, .. } => {
+                self.done += 1;
+                if cached {
+                    self.cached += 1;
+                }
+            }
+            Event::Failed { input, error } => {
+                self.done += 1;
+                self.failed += 1;
+                if self.errors.len() < MAX_KEPT_ERRORS {
+                    self.errors.push((input, error));
+                }
+            }
+            Event::Finished => self.finished = true,
+        }
+    }
+
+    /// fraction of the tasks that ran, for a progress bar
+    pub(crate) fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            return if self.finished { 1.0 } else { 0.0 };
+        }
+        self.done as f64 / self.total as f64
+    }
+
+    /// one line summary, e.g. `120 / 500 items (80 cached, 2 failed)`
+    pub(crate) fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} / {} items ({} cached, {} failed)",
+            self.done, self.total, self.cached, self.failed
+        );
+        if self.cancelled && self.finished {
+            summary.push_str(" - cancelled");
+        } else if self.cancelled {
+            summary.push_str(" - cancelling ...");
+        } else if self.finished {
+            summary.push_str(" - finished");
+        }
+        summary
+    }
+}
+
+impl Run {
+    /// ask the workers to stop, tasks that already started still finish
+    pub(crate) fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.progress.cancelled = true;
+    }
+
+    /// # take all events sent since the last call into the progress
+    ///
+    /// returns:
+    ///     true once the run is finished
+    pub(crate) fn poll(&mut self) -> bool {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => self.progress.update(event),
+                Err(TryRecvError::Empty) => break,
+                // the workers are gone without a last word, nothing more will come
+                Err(TryRecvError::Disconnected) => {
+                    self.progress.finished = true;
+                    break;
+                }
+            }
+        }
+        self.progress.finished
+    }
+}
+
+/// number of worker threads used if not chosen otherwise (one per core)
+pub(crate) fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+fn worker(tasks: &[Task], next: &AtomicUsize, cancel: &AtomicBool, events: &Sender<Event>) {
+    while !cancel.load(Ordering::Relaxed) {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(task) = tasks.get(index) else {
+            break;
+        };
+        let event = match (task.work)(&task.input) {
+            Ok(cached) => Event::Done {
+                input: task.input.clone(),
+                cached,
+            },
+            Err(error) => Event::Failed {
+                input: task.input.clone(),
+                error,
+            },
+        };
+        if events.send(event).is_err() {
+            // nobody polls anymore
+            break;
+        }
+    }
+}
+
+/// # run tasks in the background on `threads` worker threads
+///
+/// tasks are taken in order by whichever worker is free, so long and short tasks mix well.
+///
+/// returns:
+///     the run to poll for progress and to cancel
+pub(crate) fn start(tasks: Vec<Task>, threads: usize) -> Run {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender, events) = mpsc::channel();
+    let total = tasks.len();
+    let threads = threads.clamp(1, total.max(1));
+
+    let worker_cancel = cancel.clone();
+    thread::spawn(move || {
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let (tasks, next, cancel) = (&tasks, &next, &worker_cancel);
+                scope.spawn(move || worker(tasks, next, cancel, &sender));
+            }
+        });
+        let _ = sender.send(Event::Finished);
+    });
+
+    Run {
+        cancel,
+        events,
+        progress: Progress {
+            total,
+            ..Progress::default()
+        },
+    }
+}
+
+/// # run `work` on every job on up to `threads` threads, blocking until all are done
+///
+/// for work that already runs in a background thread (e.g. the trials of a search). `on_done`
+/// gets every result in the calling thread as soon as it is there, together with the index of
+/// its job. Jobs not started before `cancel` is set are skipped.
+pub(crate) fn run_jobs<T: Sync, R: Send>(
+    jobs: &[T],
+    threads: usize,
+    cancel: &AtomicBool,
+    work: &(dyn Fn(&T) -> R + Sync),
+    on_done: &mut dyn FnMut(usize, R),
+) {
+    let next = AtomicUsize::new(0);
+    let (sender, results) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || {
+                while !cancel.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, work(job))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (index, result) in results {
+            on_done(index, result);
+        }
+    });
+}
+
+/// # cache key of an input file processed by a step with the given parameters
+///
+/// changes whenever the content of the file or any parameter changes.
+///
+/// returns:
+///     Result with the hex encoded key
+pub(crate) fn cache_key(
+    input: &Path,
+    step: &str,
+    params: &impl Serialize,
+) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    hasher.update(content_hash(input)?.as_bytes());
+    hasher.update(step.as_bytes());
+    hasher.update(serde_json::to_string(params)?.as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// path of a cached output, `<project>/cache/<kind>/<key>.<extension>`
+pub(crate) fn cache_file(project_dir: &Path, kind: &str, key: &str, extension: &str) -> PathBuf {
+    project_dir
+        .join(CACHE_DIR)
+        .join(kind)
+        .join(format!("{}.{}", key, extension))
+}
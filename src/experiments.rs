@@ -0,0 +1,775 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Run history of the Training tab
+//!
+//! A table of all training runs of the project, sortable by every column and filtered by free
+//! text. Selecting a run shows everything recorded about it (hyperparameters, snapshot,
+//! preprocessing, seed, times, metrics and notes); selecting two or more runs overlays their
+//! curves and lists their settings side by side, highlighting the differences.
+
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::charts::LineChart;
+use crate::crossval::load_crossval;
+use crate::debug_println;
+use crate::external::{load_report, EXTERNAL_MODEL};
+use crate::helper::show_error_message;
+use crate::metrics::format_metric;
+use crate::model::EpochReport;
+use crate::pipeline::{load_preprocessing, PreprocessingConfig, PREPROCESSING_FILE_NAME};
+use crate::project::SharedProject;
+use crate::runs::{add_note, format_unix_time, load_epochs, load_runs, run_dir, RunRecord};
+use crate::training::{curve_names, draw_charts, format_duration, metric_series};
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Columns of the run table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Run,
+    Model,
+    Started,
+    Duration,
+    Status,
+    Snapshot,
+    Seed,
+    /// the final value of the metric chosen above the table
+    Metric,
+    Notes,
+}
+
+const COLUMNS: [Column; 9] = [
+    Column::Run,
+    Column::Model,
+    Column::Started,
+    Column::Duration,
+    Column::Status,
+    Column::Snapshot,
+    Column::Seed,
+    Column::Metric,
+    Column::Notes,
+];
+
+impl Column {
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Run => "run",
+            Column::Model => "model",
+            Column::Started => "started (UTC)",
+            Column::Duration => "duration",
+            Column::Status => "status",
+            Column::Snapshot => "snapshot",
+            Column::Seed => "seed",
+            Column::Metric => "metric",
+            Column::Notes => "notes",
+        }
+    }
+
+    /// width of the column in characters
+    fn width(&self) -> i32 {
+        match self {
+            Column::Run => 18,
+            Column::Model => 16,
+            Column::Started => 16,
+            Column::Duration => 8,
+            Column::Status => 8,
+            Column::Snapshot => 14,
+            Column::Seed => 10,
+            Column::Metric => 10,
+            Column::Notes => 30,
+        }
+    }
+
+    fn cell(&self, run: &RunRecord, metric: &str) -> String {
+        match self {
+            Column::Run => run.id.clone(),
+            Column::Model => run.model.clone(),
+            Column::Started => format_unix_time(run.started),
+            Column::Duration => run
+                .duration()
+                .map(|d| format_duration(Duration::from_secs(d)))
+                .unwrap_or_default(),
+            Column::Status => run.status().to_string(),
+            Column::Snapshot => run.snapshot.clone().unwrap_or_default(),
+            Column::Seed => run.seed.to_string(),
+            Column::Metric => run
+                .metrics
+                .get(metric)
+                .map(|v| format_metric(*v))
+                .unwrap_or_default(),
+            Column::Notes => run
+                .notes
+                .last()
+                .map(|note| note.text.lines().next().unwrap_or_default().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// order of two runs by this column, runs without a value come last
+    fn compare(&self, a: &RunRecord, b: &RunRecord, metric: &str) -> Ordering {
+        match self {
+            Column::Started => a.started.cmp(&b.started),
+            Column::Duration => a.duration().cmp(&b.duration()),
+            Column::Seed => a.seed.cmp(&b.seed),
+            Column::Metric => match (a.metrics.get(metric), b.metrics.get(metric)) {
+                (Some(x), Some(y)) => x.total_cmp(y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            Column::Notes => a.notes.len().cmp(&b.notes.len()),
+            _ => self.cell(a, metric).cmp(&self.cell(b, metric)),
+        }
+    }
+}
+
+/// # whether a run matches the filter text
+///
+/// every word of the filter has to appear (case-insensitively) in the id, model, status,
+/// snapshot, error, search, notes, hyperparameters (`name=value`) or final metrics of the run.
+fn matches_filter(run: &RunRecord, filter: &str) -> bool {
+    let mut haystack = vec![
+        run.id.clone(),
+        run.model.clone(),
+        run.status().to_string(),
+        run.snapshot.clone().unwrap_or_default(),
+        run.error.clone().unwrap_or_default(),
+        run.group.clone().unwrap_or_default(),
+    ];
+    haystack.extend(run.notes.iter().map(|note| note.text.clone()));
+    haystack.extend(
+        run.params
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value.display())),
+    );
+    haystack.extend(run.metrics.keys().cloned());
+    let haystack = haystack.join("\n").to_lowercase();
+    filter
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| haystack.contains(word))
+}
+
+/// the preprocessing config saved with a run, `None` for runs from before it was recorded
+fn run_preprocessing(project_dir: &Path, run_id: &str) -> Option<PreprocessingConfig> {
+    let dir = run_dir(project_dir, run_id);
+    if !dir.join(PREPROCESSING_FILE_NAME).is_file() {
+        return None;
+    }
+    load_preprocessing(&dir).ok()
+}
+
+/// preprocessing of a run in a few lines, e.g. `image: resize (width 224, height 224)`
+fn describe_preprocessing(config: &PreprocessingConfig) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut steps = |name: &str, described: Vec<String>| {
+        if !described.is_empty() {
+            lines.push(format!("{}: {}", name, described.join(", ")));
+        }
+    };
+    steps(
+        "image",
+        config.image.steps.iter().map(|s| s.describe()).collect(),
+    );
+    steps(
+        "augmentation",
+        config
+            .augmentation
+            .steps
+            .iter()
+            .map(|s| s.describe())
+            .collect(),
+    );
+    steps(
+        "tabular",
+        config.tabular.steps.iter().map(|s| s.describe()).collect(),
+    );
+    lines.push(format!(
+        "class balance: {}, class weights {}",
+        config.imbalance.resampling.name(),
+        config.imbalance.weighting.name()
+    ));
+    lines
+}
+
+/// every value of a TOML document by its path, e.g. `image.steps[0].width = 224`
+fn flatten_toml(value: &toml::Value, path: &str, values: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten_toml(value, &path, values);
+            }
+        }
+        toml::Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                flatten_toml(value, &format!("{}[{}]", path, i), values);
+            }
+        }
+        toml::Value::String(s) => {
+            values.insert(path.to_string(), s.clone());
+        }
+        value => {
+            values.insert(path.to_string(), value.to_string());
+        }
+    }
+}
+
+/// # everything recorded about a run, for the details below the table
+fn describe_run(project_dir: &Path, run: &RunRecord) -> String {
+    let mut lines = vec![format!(
+        "{}  {}  {}  seed {}  snapshot {}",
+        run.id,
+        run.model,
+        run.status(),
+        run.seed,
+        run.snapshot.as_deref().unwrap_or("none")
+    )];
+    let mut times = format!("started {}", format_unix_time(run.started));
+    if let Some(finished) = run.finished {
+        times.push_str(&format!(
+            ", finished {} ({})",
+            format_unix_time(finished),
+            format_duration(Duration::from_secs(run.duration().unwrap_or(0)))
+        ));
+    }
+    lines.push(times);
+    if let Some(group) = &run.group {
+        match run.fold {
+            Some(fold) => lines.push(format!("fold {} of {}", fold + 1, group)),
+            None => lines.push(format!("part of {}", group)),
+        }
+        // the runs of a cross-validation are judged together
+        if let Ok(crossval) = load_crossval(project_dir, group) {
+            lines.push(format!("{}-fold mean ± std:", crossval.folds));
+            lines.extend(
+                crossval
+                    .summary_lines()
+                    .into_iter()
+                    .map(|l| format!("  {}", l)),
+            );
+        }
+    }
+    if run.model == EXTERNAL_MODEL {
+        if let Ok(report) = load_report(&run_dir(project_dir, &run.id)) {
+            let exit = report.exit_code.map_or("no exit code".to_string(), |code| {
+                format!("exit code {}", code)
+            });
+            lines.push(format!("external trainer {:?}, {}", report.command, exit));
+            if let Some(checkpoint) = report.checkpoints.last() {
+                lines.push(format!(
+                    "{} checkpoints, latest after epoch {}: {}",
+                    report.checkpoints.len(),
+                    checkpoint.epoch,
+                    checkpoint.path
+                ));
+            }
+        }
+    }
+    if let Some(error) = &run.error {
+        lines.push(format!("error: {}", error));
+    }
+    if let Some(best) = &run.best {
+        lines.push(format!(
+            "best epoch {}: {} {}",
+            best.epoch,
+            best.metric,
+            format_metric(best.value)
+        ));
+    }
+    let params: Vec<String> = run
+        .params
+        .iter()
+        .map(|(name, value)| format!("{} {}", name, value.display()))
+        .collect();
+    lines.push(format!("hyperparameters: {}", params.join(", ")));
+    let metrics: Vec<String> = run
+        .metrics
+        .iter()
+        .map(|(name, value)| format!("{} {}", name, format_metric(*value)))
+        .collect();
+    lines.push(format!("metrics: {}", metrics.join(", ")));
+    match run_preprocessing(project_dir, &run.id) {
+        Some(config) => lines.extend(describe_preprocessing(&config)),
+        None => lines.push("preprocessing: not recorded".to_string()),
+    }
+    for note in &run.notes {
+        lines.push(format!(
+            "note {}: {}",
+            format_unix_time(note.time),
+            note.text
+        ));
+    }
+    lines.join("\n")
+}
+
+/// # settings and results of several runs side by side
+///
+/// returns:
+///     rows of a name and one value per run; preprocessing settings only appear where the
+///     runs differ
+fn comparison_rows(project_dir: &Path, runs: &[&RunRecord]) -> Vec<(String, Vec<String>)> {
+    let mut rows: Vec<(String, Vec<String>)> = Vec::new();
+    let mut row = |name: String, value: &dyn Fn(&RunRecord) -> String| {
+        rows.push((name, runs.iter().map(|run| value(run)).collect()));
+    };
+    row("model".to_string(), &|run| run.model.clone());
+    row("snapshot".to_string(), &|run| {
+        run.snapshot.clone().unwrap_or_default()
+    });
+    row("seed".to_string(), &|run| run.seed.to_string());
+    row("started".to_string(), &|run| format_unix_time(run.started));
+    row("status".to_string(), &|run| run.status().to_string());
+
+    let params: BTreeSet<&String> = runs.iter().flat_map(|run| run.params.keys()).collect();
+    for name in params {
+        row(name.clone(), &|run| {
+            run.params
+                .get(name)
+                .map(|value| value.display())
+                .unwrap_or_default()
+        });
+    }
+    let metrics: BTreeSet<&String> = runs.iter().flat_map(|run| run.metrics.keys()).collect();
+    for name in metrics {
+        row(format!("final {}", name), &|run| {
+            run.metrics
+                .get(name)
+                .map(|v| format_metric(*v))
+                .unwrap_or_default()
+        });
+    }
+    row("best epoch".to_string(), &|run| {
+        run.best
+            .as_ref()
+            .map(|b| format!("{} ({} {})", b.epoch, b.metric, format_metric(b.value)))
+            .unwrap_or_default()
+    });
+
+    let preprocessing: Vec<BTreeMap<String, String>> = runs
+        .iter()
+        .map(|run| {
+            let mut values = BTreeMap::new();
+            if let Some(value) = run_preprocessing(project_dir, &run.id)
+                .and_then(|config| toml::Value::try_from(config).ok())
+            {
+                flatten_toml(&value, "", &mut values);
+            }
+            values
+        })
+        .collect();
+    let keys: BTreeSet<&String> = preprocessing.iter().flat_map(|v| v.keys()).collect();
+    for key in keys {
+        let values: Vec<String> = preprocessing
+            .iter()
+            .map(|v| v.get(key).cloned().unwrap_or_default())
+            .collect();
+        if values.iter().any(|v| *v != values[0]) {
+            rows.push((format!("preprocessing {}", key), values));
+        }
+    }
+    rows
+}
+
+/// # run table with details and comparison
+///
+/// returns:
+///     the widget and a function reloading the runs of the project (e.g. after a run ended)
+pub(crate) fn runs_ui(project: &SharedProject) -> (gtk::Box, Rc<dyn Fn()>) {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    // all runs of the project, the rows show `shown` (indices into `runs`)
+    let runs: Rc<RefCell<Vec<RunRecord>>> = Rc::default();
+    let shown: Rc<RefCell<Vec<usize>>> = Rc::default();
+    let sort = Rc::new(Cell::new((Column::Started, true)));
+    let metric_names: Rc<RefCell<Vec<String>>> = Rc::default();
+    let epochs: Rc<RefCell<HashMap<String, Vec<EpochReport>>>> = Rc::default();
+
+    // filter, metric column and the table
+    // ---------------------------------------------------------------------------------------------
+    let filter_entry = gtk::SearchEntry::builder()
+        .placeholder_text("filter, e.g. mlp stopped batch_size=32")
+        .hexpand(true)
+        .build();
+    let metric_dd = gtk::DropDown::from_strings(&[]);
+    metric_dd.set_tooltip_text(Some("final metric shown in the metric column"));
+    let reload_btn = Button::with_label("reload");
+    let controls = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    controls.append(&filter_entry);
+    controls.append(&Label::new(Some("metric")));
+    controls.append(&metric_dd);
+    controls.append(&reload_btn);
+
+    let header = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .margin_start(6)
+        .build();
+    let header_buttons: Vec<Button> = COLUMNS
+        .iter()
+        .map(|column| {
+            let label = Label::builder()
+                .label(column.title())
+                .width_chars(column.width())
+                .max_width_chars(column.width())
+                .xalign(0.0)
+                .build();
+            let button = Button::builder().child(&label).has_frame(false).build();
+            header.append(&button);
+            button
+        })
+        .collect();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Multiple)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .child(&list)
+        .build();
+
+    // details and notes of the selected runs
+    // ---------------------------------------------------------------------------------------------
+    let details_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .selectable(true)
+        .wrap(true)
+        .visible(false)
+        .build();
+    let note_entry = gtk::Entry::builder()
+        .placeholder_text("note on the selected runs")
+        .hexpand(true)
+        .build();
+    let note_btn = Button::with_label("add note");
+    let note_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .visible(false)
+        .build();
+    note_box.append(&note_entry);
+    note_box.append(&note_btn);
+
+    // curves and settings of two or more runs
+    // ---------------------------------------------------------------------------------------------
+    let compared: Rc<RefCell<Vec<String>>> = Rc::default();
+    let curve_names_shown: Rc<RefCell<Vec<String>>> = Rc::default();
+    let curve_dd = gtk::DropDown::from_strings(&[]);
+    let differences_check = gtk::CheckButton::with_label("only differences");
+    differences_check.set_active(true);
+    let compare_controls = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    compare_controls.append(&Label::new(Some("curves of")));
+    compare_controls.append(&curve_dd);
+    compare_controls.append(&differences_check);
+
+    let compare_area = gtk::DrawingArea::builder()
+        .content_height(260)
+        .hexpand(true)
+        .build();
+    compare_area.set_draw_func(
+        gtk::glib::clone!(@strong compared, @strong epochs, @strong curve_dd, @strong curve_names_shown => move |_, cr, width, height| {
+            let Some(name) = curve_names_shown.borrow().get(curve_dd.selected() as usize).cloned() else {
+                return;
+            };
+            let epochs = epochs.borrow();
+            let series = compared
+                .borrow()
+                .iter()
+                .enumerate()
+                .flat_map(|(i, id)| {
+                    metric_series(
+                        epochs.get(id).map(Vec::as_slice).unwrap_or_default(),
+                        &name,
+                        &format!("{} ", id),
+                        i,
+                    )
+                })
+                .collect();
+            let chart = LineChart {
+                title: name,
+                x_label: "epoch".to_string(),
+                series,
+            };
+            draw_charts(cr, width, height, &[chart]);
+        }),
+    );
+    curve_dd.connect_selected_notify(gtk::glib::clone!(@strong compare_area => move |_| {
+        compare_area.queue_draw();
+    }));
+
+    let diff_grid = gtk::Grid::builder()
+        .row_spacing(3)
+        .column_spacing(15)
+        .build();
+    let diff_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .child(&diff_grid)
+        .build();
+    let compare_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .visible(false)
+        .build();
+    compare_box.append(&compare_controls);
+    compare_box.append(&compare_area);
+    compare_box.append(&diff_window);
+
+    let fill_diff = gtk::glib::clone!(@strong project, @strong runs, @strong compared, @strong diff_grid, @strong differences_check => move || {
+        while let Some(child) = diff_grid.first_child() {
+            diff_grid.remove(&child);
+        }
+        let project = project.borrow();
+        let Some(project) = project.as_ref() else {
+            return;
+        };
+        let runs = runs.borrow();
+        let compared = compared.borrow();
+        let selected: Vec<&RunRecord> = compared
+            .iter()
+            .filter_map(|id| runs.iter().find(|run| run.id == *id))
+            .collect();
+        for (i, run) in selected.iter().enumerate() {
+            let label = Label::builder().halign(gtk::Align::Start).build();
+            label.set_markup(&format!("<b>{}</b>", gtk::glib::markup_escape_text(&run.id)));
+            diff_grid.attach(&label, i as i32 + 1, 0, 1, 1);
+        }
+        let mut row = 1;
+        for (name, values) in comparison_rows(project.dir(), &selected) {
+            let differs = values.iter().any(|v| *v != values[0]);
+            if !differs && differences_check.is_active() {
+                continue;
+            }
+            let cells = std::iter::once(name).chain(values);
+            for (column, text) in cells.enumerate() {
+                let label = Label::builder().halign(gtk::Align::Start).selectable(true).build();
+                let text = gtk::glib::markup_escape_text(&text);
+                // differing settings stand out
+                label.set_markup(&if differs { format!("<b>{}</b>", text) } else { text.to_string() });
+                diff_grid.attach(&label, column as i32, row, 1, 1);
+            }
+            row += 1;
+        }
+    });
+    differences_check.connect_toggled(gtk::glib::clone!(@strong fill_diff => move |_| {
+        fill_diff();
+    }));
+
+    // table contents
+    // ---------------------------------------------------------------------------------------------
+    let update_headers = gtk::glib::clone!(@strong sort, @strong metric_names, @strong metric_dd, @strong header_buttons => move || {
+        let (sorted_by, ascending) = sort.get();
+        let metric = metric_names.borrow().get(metric_dd.selected() as usize).cloned();
+        for (column, button) in COLUMNS.iter().zip(&header_buttons) {
+            let mut title = match (column, &metric) {
+                (Column::Metric, Some(metric)) => metric.clone(),
+                _ => column.title().to_string(),
+            };
+            if *column == sorted_by {
+                title.push_str(if ascending { " ▲" } else { " ▼" });
+            }
+            if let Some(label) = button.child().and_downcast::<Label>() {
+                label.set_text(&title);
+            }
+        }
+    });
+
+    let fill_table = gtk::glib::clone!(@strong runs, @strong shown, @strong sort, @strong metric_names, @strong metric_dd, @strong filter_entry, @strong list, @strong update_headers => move || {
+        let runs_ref = runs.borrow();
+        let selected: BTreeSet<String> = list
+            .selected_rows()
+            .iter()
+            .filter_map(|row| shown.borrow().get(row.index() as usize).map(|i| runs_ref[*i].id.clone()))
+            .collect();
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+
+        let metric = metric_names.borrow().get(metric_dd.selected() as usize).cloned().unwrap_or_default();
+        let filter = filter_entry.text().to_string();
+        let (column, ascending) = sort.get();
+        let mut indices: Vec<usize> = (0..runs_ref.len())
+            .filter(|i| matches_filter(&runs_ref[*i], &filter))
+            .collect();
+        indices.sort_by(|a, b| {
+            let order = column.compare(&runs_ref[*a], &runs_ref[*b], &metric);
+            if ascending { order } else { order.reverse() }
+        });
+
+        for i in &indices {
+            let run = &runs_ref[*i];
+            let row = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(10)
+                .build();
+            for column in COLUMNS {
+                row.append(&Label::builder()
+                    .label(column.cell(run, &metric))
+                    .width_chars(column.width())
+                    .max_width_chars(column.width())
+                    .ellipsize(gtk::pango::EllipsizeMode::End)
+                    .xalign(0.0)
+                    .build());
+            }
+            list.append(&row);
+        }
+        shown.replace(indices.clone());
+        drop(runs_ref);
+        for (position, i) in indices.iter().enumerate() {
+            if selected.contains(&runs.borrow()[*i].id) {
+                if let Some(row) = list.row_at_index(position as i32) {
+                    list.select_row(Some(&row));
+                }
+            }
+        }
+        update_headers();
+    });
+
+    let reload: Rc<dyn Fn()> = Rc::new(
+        gtk::glib::clone!(@strong project, @strong runs, @strong epochs, @strong metric_names, @strong metric_dd, @strong fill_table => move || {
+            let loaded = match project.borrow().as_ref() {
+                Some(project) => load_runs(project.dir()),
+                None => Vec::new(),
+            };
+            // validation metrics first, they are what runs are usually compared by
+            let names: BTreeSet<&String> = loaded.iter().flat_map(|run| run.metrics.keys()).collect();
+            let (mut names, train): (Vec<String>, Vec<String>) =
+                names.into_iter().cloned().partition(|name| name.starts_with("val_"));
+            names.extend(train);
+            if names != *metric_names.borrow() {
+                let chosen = metric_names.borrow().get(metric_dd.selected() as usize).cloned();
+                let index = names.iter().position(|name| Some(name) == chosen.as_ref()).unwrap_or(0);
+                metric_names.replace(names.clone());
+                let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+                metric_dd.set_model(Some(&gtk::StringList::new(&strings)));
+                metric_dd.set_selected(index as u32);
+            }
+            runs.replace(loaded);
+            epochs.borrow_mut().clear();
+            fill_table();
+        }),
+    );
+
+    // selection
+    // ---------------------------------------------------------------------------------------------
+    list.connect_selected_rows_changed(gtk::glib::clone!(@strong project, @strong runs, @strong shown, @strong epochs, @strong compared, @strong curve_dd, @strong curve_names_shown, @strong details_label, @strong note_box, @strong compare_box, @strong compare_area, @strong fill_diff => move |list| {
+        let project = project.borrow();
+        let Some(project) = project.as_ref() else {
+            return;
+        };
+        let runs = runs.borrow();
+        let selected: Vec<&RunRecord> = list
+            .selected_rows()
+            .iter()
+            .filter_map(|row| shown.borrow().get(row.index() as usize).map(|i| &runs[*i]))
+            .collect();
+
+        note_box.set_visible(!selected.is_empty());
+        details_label.set_visible(selected.len() == 1);
+        if let [run] = selected.as_slice() {
+            details_label.set_text(&describe_run(project.dir(), run));
+        }
+        compare_box.set_visible(selected.len() >= 2);
+        if selected.len() < 2 {
+            return;
+        }
+
+        let ids: Vec<String> = selected.iter().map(|run| run.id.clone()).collect();
+        for id in &ids {
+            epochs
+                .borrow_mut()
+                .entry(id.clone())
+                .or_insert_with(|| load_epochs(project.dir(), id));
+        }
+        let all: Vec<EpochReport> = ids
+            .iter()
+            .flat_map(|id| epochs.borrow()[id].clone())
+            .collect();
+        let names = curve_names(&all);
+        if names != *curve_names_shown.borrow() {
+            let chosen = curve_names_shown.borrow().get(curve_dd.selected() as usize).cloned();
+            let index = names.iter().position(|name| Some(name) == chosen.as_ref()).unwrap_or(0);
+            curve_names_shown.replace(names.clone());
+            let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+            curve_dd.set_model(Some(&gtk::StringList::new(&strings)));
+            curve_dd.set_selected(index as u32);
+        }
+        compared.replace(ids);
+        fill_diff();
+        compare_area.queue_draw();
+    }));
+
+    for (column, button) in COLUMNS.into_iter().zip(&header_buttons) {
+        button.connect_clicked(
+            gtk::glib::clone!(@strong sort, @strong fill_table => move |_| {
+                // a second click on the same column reverses the order
+                let (sorted_by, ascending) = sort.get();
+                sort.set((column, sorted_by != column || !ascending));
+                fill_table();
+            }),
+        );
+    }
+    filter_entry.connect_search_changed(gtk::glib::clone!(@strong fill_table => move |_| {
+        fill_table();
+    }));
+    metric_dd.connect_selected_notify(gtk::glib::clone!(@strong fill_table => move |_| {
+        fill_table();
+    }));
+    reload_btn.connect_clicked(gtk::glib::clone!(@strong reload => move |_| {
+        reload();
+    }));
+
+    note_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong runs, @strong shown, @strong list, @strong note_entry, @strong reload => move |_| {
+        let text = note_entry.text().trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        let ids: Vec<String> = list
+            .selected_rows()
+            .iter()
+            .filter_map(|row| shown.borrow().get(row.index() as usize).map(|i| runs.borrow()[*i].id.clone()))
+            .collect();
+        if let Some(project) = project.borrow().as_ref() {
+            for id in &ids {
+                if let Err(e) = add_note(project.dir(), id, &text) {
+                    debug_println!("[ERROR: RUNS] unable to add a note to {}: {}", id, e);
+                    show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("RUNS ERROR"),
+                        Some(&format!("Unable to add the note to {}:\n{}", id, e)),
+                    );
+                    return;
+                }
+            }
+        }
+        note_entry.set_text("");
+        reload();
+    }));
+
+    vbox.append(&controls);
+    vbox.append(&header);
+    vbox.append(&scrolled_window);
+    vbox.append(&details_label);
+    vbox.append(&note_box);
+    vbox.append(&compare_box);
+
+    (vbox, reload)
+}
@@ -0,0 +1,958 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! External trainers
+//!
+//! Training scripts outside of AI Lab (e.g. in Python) plug in as the external trainer of a
+//! project, a shell command kept in the training settings. A run of the external trainer is a
+//! normal run with the model id [`EXTERNAL_MODEL`]:
+//!
+//! 1. the job goes to `job.json` in the run directory: the dataset manifest (every item with its
+//!    file, class, split and fold), the classes, the split settings, the preprocessing config,
+//!    the hyperparameters and the seed
+//! 2. the command runs with `sh -c` in the project directory, it finds the job through the
+//!    environment variables `AI_LAB_JOB` and `AI_LAB_RUN_DIR`
+//! 3. the trainer reports JSON lines on its stdout, or with the socket transport on the Unix
+//!    socket named by `AI_LAB_SOCKET` (lines that are no events are only logged):
+//!    - `{"event": "status", "message": "loading images"}`
+//!    - `{"event": "epoch", "epoch": 3, "epochs": 10, "metrics": {"loss": 0.4, "val_accuracy": 0.8},
+//!      "learning_rate": 0.001, "samples": 1200, "seconds": 2.5}` (the last three are optional)
+//!    - `{"event": "checkpoint", "epoch": 3, "path": "checkpoints/epoch-3.pt"}`
+//!    - `{"event": "metrics", "metrics": {...}}`, the final metrics (default: the last epoch)
+//!    - `{"event": "error", "message": "out of memory"}`
+//! 4. pause, resume and stop requests go to the stdin of the trainer as JSON lines:
+//!    `{"command": "pause"}`, `{"command": "resume"}` and `{"command": "stop"}`; a trainer
+//!    that does not stop within [`STOP_GRACE_SECS`] is killed, with all processes it started
+//!
+//! The run is over when the trainer exits, it failed if the exit status is not zero or the
+//! trainer sent an error. What the trainer reported besides the epochs is kept in
+//! `external.toml` in the run directory.
+
+use crate::checkpoint::BestEpoch;
+use crate::dataset::DatasetSource;
+use crate::debug_println;
+use crate::helper::ProblemType;
+use crate::metrics::Metrics;
+use crate::model::{EpochReport, Params, TrainingControl};
+use crate::pipeline::PreprocessingConfig;
+use crate::splits::{Split, SplitConfig};
+use crate::store::{unix_now, Annotation, Modality};
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// model id of the runs of external trainers
+pub(crate) const EXTERNAL_MODEL: &str = "external";
+
+/// version of the protocol, part of the job
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+/// file name of the job inside a run directory
+pub(crate) const JOB_FILE_NAME: &str = "job.json";
+
+/// file name of what the trainer reported inside a run directory
+pub(crate) const EXTERNAL_FILE_NAME: &str = "external.toml";
+
+/// file name of the Unix socket of the socket transport inside a run directory
+const SOCKET_FILE_NAME: &str = "events.sock";
+
+/// seconds a trainer gets to stop on its own before it is killed
+pub(crate) const STOP_GRACE_SECS: u64 = 10;
+
+/// interval of checking the trainer and the pause / stop requests
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// lines of stderr kept for the error message of a failed trainer
+const STDERR_LINES: usize = 5;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// How the trainer sends its events
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Transport {
+    #[default]
+    Stdout,
+    /// a Unix socket in the run directory, stdout stays free for the log of the trainer
+    Socket,
+}
+
+/// External trainer of a project, part of the training settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct ExternalConfig {
+    /// shell command run in the project directory, e.g. `python3 train.py`
+    pub(crate) command: String,
+    pub(crate) transport: Transport,
+    /// hyperparameters handed to the trainer as they are
+    pub(crate) params: Params,
+}
+
+/// An item of the dataset manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ManifestItem {
+    pub(crate) id: u64,
+    pub(crate) path: PathBuf,
+    /// primary class of the item (images and sound), tables hold their classes in a column
+    pub(crate) class: Option<String>,
+    pub(crate) split: Split,
+    pub(crate) fold: Option<usize>,
+    /// interval annotations of the item (sensors)
+    #[serde(default)]
+    pub(crate) annotations: Vec<Annotation>,
+}
+
+/// Everything an external trainer gets, the content of [`JOB_FILE_NAME`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Job {
+    pub(crate) protocol: u32,
+    pub(crate) run_id: String,
+    /// where the trainer keeps its model and checkpoints
+    pub(crate) run_dir: PathBuf,
+    pub(crate) project_dir: PathBuf,
+    pub(crate) problem: ProblemType,
+    pub(crate) modality: Modality,
+    /// column holding the class of tabular projects
+    pub(crate) label_column: String,
+    /// class names of the project, items may still have others
+    pub(crate) classes: Vec<String>,
+    pub(crate) seed: u64,
+    pub(crate) params: Params,
+    /// settings of the split assignment, `None` for a random hold-out
+    pub(crate) splits: Option<SplitConfig>,
+    pub(crate) preprocessing: PreprocessingConfig,
+    pub(crate) items: Vec<ManifestItem>,
+    /// socket the events go to, `None` for stdout
+    pub(crate) socket: Option<PathBuf>,
+}
+
+/// A line sent by the trainer
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum TrainerEvent {
+    Status {
+        message: String,
+    },
+    Epoch {
+        epoch: usize,
+        epochs: usize,
+        #[serde(default)]
+        metrics: Metrics,
+        #[serde(default)]
+        learning_rate: Option<f64>,
+        #[serde(default)]
+        samples: usize,
+        /// measured by AI Lab if the trainer does not say
+        #[serde(default)]
+        seconds: Option<f64>,
+    },
+    Checkpoint {
+        epoch: usize,
+        /// relative to the run directory, or absolute
+        path: String,
+    },
+    Metrics {
+        metrics: Metrics,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// A request sent to the trainer
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum TrainerCommand {
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// A checkpoint the trainer reported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ExternalCheckpoint {
+    pub(crate) epoch: usize,
+    pub(crate) path: String,
+    /// unix timestamp (seconds) of the event
+    pub(crate) written: u64,
+}
+
+/// What the trainer reported besides the epochs, the content of [`EXTERNAL_FILE_NAME`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct ExternalReport {
+    pub(crate) command: String,
+    pub(crate) transport: Transport,
+    /// `None` while running or if the trainer was killed by a signal
+    pub(crate) exit_code: Option<i32>,
+    /// final metrics, from the metrics event or else the last epoch
+    pub(crate) metrics: Metrics,
+    pub(crate) best: Option<BestEpoch>,
+    pub(crate) checkpoints: Vec<ExternalCheckpoint>,
+    /// error event of the trainer
+    pub(crate) error: Option<String>,
+    /// lines that were no events
+    pub(crate) other_lines: usize,
+}
+
+/// A line read from the trainer
+enum Line {
+    Event(String),
+    Stderr(String),
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Transport {
+    pub(crate) const ALL: [Transport; 2] = [Transport::Stdout, Transport::Socket];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Transport::Stdout => "stdout",
+            Transport::Socket => "unix socket",
+        }
+    }
+}
+
+/// # the job of a run, with the manifest of all items of the project
+///
+/// items get the split (and fold) they would get in a built-in run.
+pub(crate) fn job(
+    source: &DatasetSource,
+    problem: ProblemType,
+    run_id: &str,
+    run_dir: &Path,
+    seed: u64,
+    config: &ExternalConfig,
+) -> Job {
+    let splits = source.item_splits();
+    let items = source
+        .items
+        .iter()
+        .map(|item| {
+            let (split, fold) = splits
+                .get(&item.id)
+                .copied()
+                .unwrap_or((Split::Train, None));
+            ManifestItem {
+                id: item.id,
+                path: item.path.clone(),
+                class: item.class.clone(),
+                split,
+                fold,
+                annotations: item.annotations.clone(),
+            }
+        })
+        .collect();
+    Job {
+        protocol: PROTOCOL_VERSION,
+        run_id: run_id.to_string(),
+        run_dir: run_dir.to_path_buf(),
+        project_dir: source.project_dir.clone(),
+        problem,
+        modality: source.modality,
+        label_column: source.label_column.clone(),
+        classes: source.classes.clone(),
+        seed,
+        params: config.params.clone(),
+        splits: source.splits.as_ref().map(|s| s.config.clone()),
+        preprocessing: source.preprocessing.clone(),
+        items,
+        socket: (config.transport == Transport::Socket).then(|| run_dir.join(SOCKET_FILE_NAME)),
+    }
+}
+
+/// # hyperparameters of the external trainer from `name = value` lines (TOML)
+///
+/// returns:
+///     Result with the hyperparameters, `Err` for anything but numbers, strings and booleans
+pub(crate) fn parse_params(text: &str) -> Result<Params, Box<dyn Error>> {
+    toml::from_str(text)
+        .map_err(|e| format!("the hyperparameters are no `name = value` lines: {}", e).into())
+}
+
+/// hyperparameters of the external trainer as `name = value` lines
+pub(crate) fn format_params(params: &Params) -> String {
+    toml::to_string(params).unwrap_or_default()
+}
+
+pub(crate) fn save_report(run_dir: &Path, report: &ExternalReport) -> Result<(), Box<dyn Error>> {
+    fs::write(run_dir.join(EXTERNAL_FILE_NAME), toml::to_string(report)?)?;
+    Ok(())
+}
+
+pub(crate) fn load_report(run_dir: &Path) -> Result<ExternalReport, Box<dyn Error>> {
+    let contents = fs::read_to_string(run_dir.join(EXTERNAL_FILE_NAME))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// send every line of `reader` as an event (or stderr) line until it ends
+fn forward_lines<R: Read + Send + 'static>(
+    reader: R,
+    sender: Sender<Line>,
+    stderr: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let line = if stderr {
+                Line::Stderr(line)
+            } else {
+                Line::Event(line)
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// # accept connections on the socket one after the other until `done` is set
+///
+/// a connection is read to its end before the next one is accepted, so the events keep their
+/// order even if the trainer connects for every event. Once `done` is set, the connections
+/// still waiting in the backlog are read before the thread ends.
+fn forward_socket(
+    listener: UnixListener,
+    sender: Sender<Line>,
+    done: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>, Box<dyn Error>> {
+    listener.set_nonblocking(true)?;
+    Ok(thread::spawn(move || loop {
+        // read before accepting: a trainer that exited before cannot connect any more
+        let exited = done.load(Ordering::Relaxed);
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if stream.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                for line in BufReader::new(stream).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    if sender.send(Line::Event(line)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if exited {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            }
+            Err(e) => {
+                debug_println!("[ERROR: EXTERNAL] unable to accept a connection: {}", e);
+                break;
+            }
+        }
+    }))
+}
+
+/// # kill the trainer together with the processes it started
+///
+/// the trainer runs in a process group of its own (with its pid as the group id), so this
+/// also reaches the actual training script behind the `sh -c` of the command.
+fn kill_trainer(child: &mut Child) {
+    let group = format!("-{}", child.id());
+    let killed = Command::new("kill")
+        .args(["-KILL", "--", &group])
+        .stderr(Stdio::null())
+        .status();
+    if !killed.is_ok_and(|status| status.success()) {
+        let _ = child.kill();
+    }
+}
+
+fn send_command(stdin: &mut Option<ChildStdin>, command: TrainerCommand) {
+    let Some(pipe) = stdin.as_mut() else {
+        return;
+    };
+    let line = serde_json::to_string(&command).unwrap_or_default();
+    // the trainer may not read its stdin at all
+    if writeln!(pipe, "{}", line)
+        .and_then(|_| pipe.flush())
+        .is_err()
+    {
+        *stdin = None;
+    }
+}
+
+/// # run the external trainer on a job, blocking until it exits
+///
+/// `status` gets the status events, `report` every epoch. The best epoch is picked by
+/// `best_metric` like in built-in runs.
+///
+/// returns:
+///     Result with what the trainer reported, `Err` if it could not be started, failed or
+///     sent an error
+pub(crate) fn run_trainer(
+    config: &ExternalConfig,
+    job: &Job,
+    best_metric: &str,
+    control: &TrainingControl,
+    status: &mut dyn FnMut(String),
+    report: &mut dyn FnMut(EpochReport),
+) -> Result<ExternalReport, Box<dyn Error>> {
+    if config.command.trim().is_empty() {
+        return Err("the project has no external trainer command".into());
+    }
+    let job_path = job.run_dir.join(JOB_FILE_NAME);
+    fs::write(&job_path, serde_json::to_string_pretty(job)?)?;
+
+    let (sender, lines) = mpsc::channel();
+    let done = Arc::new(AtomicBool::new(false));
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&config.command)
+        .current_dir(&job.project_dir)
+        .env("AI_LAB_JOB", &job_path)
+        .env("AI_LAB_RUN_DIR", &job.run_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0);
+    let socket = match &job.socket {
+        Some(path) => {
+            // a socket left over by a crashed run is in the way
+            let _ = fs::remove_file(path);
+            command.env("AI_LAB_SOCKET", path);
+            let listener = UnixListener::bind(path)?;
+            Some(forward_socket(listener, sender.clone(), done.clone())?)
+        }
+        None => None,
+    };
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("unable to start {:?}: {}", config.command, e))?;
+    debug_println!(
+        "[INFO: EXTERNAL] started {:?} for {} (pid {})",
+        config.command,
+        job.run_id,
+        child.id()
+    );
+
+    let mut stdin = child.stdin.take();
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(forward_lines(stdout, sender.clone(), false));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(forward_lines(stderr, sender.clone(), true));
+    }
+    readers.extend(socket);
+    drop(sender);
+
+    let mut outcome = ExternalReport {
+        command: config.command.clone(),
+        transport: config.transport,
+        ..ExternalReport::default()
+    };
+    let mut stderr: VecDeque<String> = VecDeque::new();
+    let mut final_metrics = None;
+    let mut paused = false;
+    let mut paused_since = None;
+    let mut stop_sent: Option<Instant> = None;
+    let mut epoch_started = Instant::now();
+    let mut exit = None;
+    let mut exited = Instant::now();
+    loop {
+        match lines.recv_timeout(Duration::from_millis(POLL_INTERVAL_MS)) {
+            Ok(Line::Stderr(line)) => {
+                debug_println!("[INFO: EXTERNAL] {}", line);
+                if stderr.len() == STDERR_LINES {
+                    stderr.pop_front();
+                }
+                stderr.push_back(line);
+            }
+            Ok(Line::Event(line)) => match serde_json::from_str::<TrainerEvent>(&line) {
+                Ok(TrainerEvent::Status { message }) => status(message),
+                Ok(TrainerEvent::Epoch {
+                    epoch,
+                    epochs,
+                    metrics,
+                    learning_rate,
+                    samples,
+                    seconds,
+                }) => {
+                    if let Some(value) = metrics.get(best_metric).copied() {
+                        let beaten = outcome
+                            .best
+                            .as_ref()
+                            .is_none_or(|b| b.beaten_by(best_metric, value));
+                        if value.is_finite() && beaten {
+                            outcome.best = Some(BestEpoch {
+                                epoch,
+                                metric: best_metric.to_string(),
+                                value,
+                            });
+                        }
+                    }
+                    outcome.metrics = metrics.clone();
+                    report(EpochReport {
+                        epoch,
+                        epochs,
+                        metrics,
+                        learning_rate,
+                        samples,
+                        seconds: seconds.unwrap_or_else(|| epoch_started.elapsed().as_secs_f64()),
+                    });
+                    epoch_started = Instant::now();
+                }
+                Ok(TrainerEvent::Checkpoint { epoch, path }) => {
+                    status(format!("checkpoint after epoch {}", epoch));
+                    outcome.checkpoints.push(ExternalCheckpoint {
+                        epoch,
+                        path,
+                        written: unix_now(),
+                    });
+                }
+                Ok(TrainerEvent::Metrics { metrics }) => final_metrics = Some(metrics),
+                Ok(TrainerEvent::Error { message }) => outcome.error = Some(message),
+                Err(_) => {
+                    debug_println!("[INFO: EXTERNAL] {}", line);
+                    outcome.other_lines += 1;
+                }
+            },
+            Err(RecvTimeoutError::Disconnected) if exit.is_some() => break,
+            // stdout and stderr may end before the trainer exits
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(Duration::from_millis(POLL_INTERVAL_MS))
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        // children of the trainer may keep its stdout open
+        if exit.is_some() && exited.elapsed() > Duration::from_secs(STOP_GRACE_SECS) {
+            debug_println!(
+                "[INFO: EXTERNAL] stopped reading the output of {}",
+                job.run_id
+            );
+            readers.clear();
+            break;
+        }
+
+        // the requests of the user go to the trainer as they change
+        if exit.is_none() {
+            if control.is_stopped() {
+                match stop_sent {
+                    None => {
+                        send_command(&mut stdin, TrainerCommand::Stop);
+                        stop_sent = Some(Instant::now());
+                    }
+                    Some(sent) if sent.elapsed() > Duration::from_secs(STOP_GRACE_SECS) => {
+                        debug_println!("[INFO: EXTERNAL] killing the trainer of {}", job.run_id);
+                        kill_trainer(&mut child);
+                    }
+                    Some(_) => {}
+                }
+            } else if control.is_paused() != paused {
+                paused = !paused;
+                if paused {
+                    send_command(&mut stdin, TrainerCommand::Pause);
+                    paused_since = Some(Instant::now());
+                } else {
+                    send_command(&mut stdin, TrainerCommand::Resume);
+                    // the pause does not count into the epoch
+                    if let Some(since) = paused_since.take() {
+                        epoch_started += since.elapsed();
+                    }
+                }
+            }
+            exit = child.try_wait()?;
+            if exit.is_some() {
+                // the lines still on their way are read, then the readers end
+                done.store(true, Ordering::Relaxed);
+                stdin = None;
+                exited = Instant::now();
+            }
+        }
+    }
+    let Some(exit) = exit else {
+        return Err("the trainer did not exit".into());
+    };
+    for reader in readers {
+        let _ = reader.join();
+    }
+    if let Some(path) = &job.socket {
+        let _ = fs::remove_file(path);
+    }
+
+    outcome.exit_code = exit.code();
+    if let Some(metrics) = final_metrics {
+        outcome.metrics = metrics;
+    }
+    if let Err(e) = save_report(&job.run_dir, &outcome) {
+        debug_println!("[ERROR: EXTERNAL] unable to save the report: {}", e);
+    }
+    debug_println!("[INFO: EXTERNAL] {} exited with {}", job.run_id, exit);
+
+    if let Some(error) = &outcome.error {
+        return Err(error.clone().into());
+    }
+    // a trainer killed after a stop request did what it was asked
+    if !exit.success() && stop_sent.is_none() {
+        let mut message = format!("the trainer exited with {}", exit);
+        if let Some(line) = stderr.back() {
+            message.push_str(&format!(": {}", line));
+        }
+        return Err(message.into());
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ParamValue;
+    use std::os::unix::net::UnixStream;
+
+    fn event(line: &str) -> TrainerEvent {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn trainer_events_are_parsed() {
+        assert_eq!(
+            event(r#"{"event": "status", "message": "loading images"}"#),
+            TrainerEvent::Status {
+                message: "loading images".to_string()
+            }
+        );
+        assert_eq!(
+            event(
+                r#"{"event": "epoch", "epoch": 3, "epochs": 10, "metrics": {"loss": 0.5},
+                    "learning_rate": 0.001, "samples": 1200, "seconds": 2.5}"#
+            ),
+            TrainerEvent::Epoch {
+                epoch: 3,
+                epochs: 10,
+                metrics: Metrics::from([("loss".to_string(), 0.5)]),
+                learning_rate: Some(0.001),
+                samples: 1200,
+                seconds: Some(2.5),
+            }
+        );
+        assert_eq!(
+            event(r#"{"event": "epoch", "epoch": 1, "epochs": 2}"#),
+            TrainerEvent::Epoch {
+                epoch: 1,
+                epochs: 2,
+                metrics: Metrics::new(),
+                learning_rate: None,
+                samples: 0,
+                seconds: None,
+            }
+        );
+        assert_eq!(
+            event(r#"{"event": "checkpoint", "epoch": 3, "path": "epoch-3.pt"}"#),
+            TrainerEvent::Checkpoint {
+                epoch: 3,
+                path: "epoch-3.pt".to_string()
+            }
+        );
+        assert_eq!(
+            event(r#"{"event": "metrics", "metrics": {"val_accuracy": 0.9}}"#),
+            TrainerEvent::Metrics {
+                metrics: Metrics::from([("val_accuracy".to_string(), 0.9)])
+            }
+        );
+        assert_eq!(
+            event(r#"{"event": "error", "message": "out of memory"}"#),
+            TrainerEvent::Error {
+                message: "out of memory".to_string()
+            }
+        );
+
+        // anything else is a log line of the trainer
+        for line in [
+            "epoch 3 done",
+            r#"{"event": "progress", "done": 0.5}"#,
+            r#"{"event": "epoch", "epoch": 3}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<TrainerEvent>(line).is_err(),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn trainer_commands_are_serialised() {
+        for (command, line) in [
+            (TrainerCommand::Pause, r#"{"command":"pause"}"#),
+            (TrainerCommand::Resume, r#"{"command":"resume"}"#),
+            (TrainerCommand::Stop, r#"{"command":"stop"}"#),
+        ] {
+            assert_eq!(serde_json::to_string(&command).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let params = parse_params("epochs = 4\nlearning_rate = 0.01\nmodel = \"resnet\"").unwrap();
+        assert_eq!(params["epochs"], ParamValue::Int(4));
+        assert_eq!(params["learning_rate"], ParamValue::Float(0.01));
+        assert_eq!(params["model"], ParamValue::Text("resnet".to_string()));
+        assert_eq!(parse_params(&format_params(&params)).unwrap(), params);
+        assert!(parse_params("layers = [1, 2]").is_err());
+    }
+
+    // the stub trainer of the repository
+    // ---------------------------------------------------------------------------------------------
+
+    /// a job for the stub trainer in a fresh directory
+    fn stub_job(name: &str, transport: Transport, epochs: i64) -> Job {
+        let project_dir = std::env::temp_dir().join(format!(
+            "ai-lab-external-{}-{}",
+            name.replace(' ', "-"),
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&project_dir);
+        let run_dir = project_dir.join("runs").join("run-1");
+        fs::create_dir_all(&run_dir).unwrap();
+        Job {
+            protocol: PROTOCOL_VERSION,
+            run_id: "run-1".to_string(),
+            socket: (transport == Transport::Socket).then(|| run_dir.join(SOCKET_FILE_NAME)),
+            run_dir,
+            project_dir,
+            problem: ProblemType::Classification,
+            modality: Modality::Image,
+            label_column: String::new(),
+            classes: vec!["cat".to_string(), "dog".to_string()],
+            seed: 1,
+            params: Params::from([("epochs".to_string(), ParamValue::Int(epochs))]),
+            splits: None,
+            preprocessing: PreprocessingConfig::default(),
+            items: (1..=3)
+                .map(|id| ManifestItem {
+                    id,
+                    path: PathBuf::from(format!("{}.png", id)),
+                    class: Some("cat".to_string()),
+                    split: Split::Train,
+                    fold: None,
+                    annotations: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn stub_config(job: &Job, env: &str) -> ExternalConfig {
+        let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("scripts/stub-trainer.sh");
+        ExternalConfig {
+            command: format!("STUB_DELAY=0.01 {} bash '{}'", env, script.display()),
+            transport: if job.socket.is_some() {
+                Transport::Socket
+            } else {
+                Transport::Stdout
+            },
+            params: job.params.clone(),
+        }
+    }
+
+    /// runs the stub, returns its result and the reported epochs
+    fn run_stub(
+        job: &Job,
+        env: &str,
+        stop_after: Option<usize>,
+    ) -> (Result<ExternalReport, String>, Vec<EpochReport>) {
+        let control = TrainingControl::default();
+        let mut epochs = Vec::new();
+        let result = run_trainer(
+            &stub_config(job, env),
+            job,
+            "val_loss",
+            &control,
+            &mut |_| {},
+            &mut |report| {
+                if Some(report.epoch) == stop_after {
+                    control.stop();
+                }
+                epochs.push(report);
+            },
+        );
+        (result.map_err(|e| e.to_string()), epochs)
+    }
+
+    fn check_stub_run(transport: Transport) {
+        let job = stub_job(transport.name(), transport, 4);
+        let (result, epochs) = run_stub(&job, "", None);
+        let report = result.unwrap();
+
+        let numbers: Vec<usize> = epochs.iter().map(|e| e.epoch).collect();
+        assert_eq!(numbers, [1, 2, 3, 4]);
+        assert!(epochs.iter().all(|e| e.epochs == 4 && e.samples == 3));
+        assert_eq!(report.exit_code, Some(0));
+        assert_eq!(report.metrics, epochs[3].metrics);
+        // the validation loss of the stub falls with every epoch
+        assert_eq!(report.best.as_ref().map(|b| b.epoch), Some(4));
+        let checkpoints: Vec<usize> = report.checkpoints.iter().map(|c| c.epoch).collect();
+        assert_eq!(checkpoints, [2, 4]);
+        // the greeting of the stub on stdout is no event
+        assert_eq!(report.other_lines, 1);
+
+        assert_eq!(load_report(&job.run_dir).unwrap(), report);
+        let written: Job =
+            serde_json::from_str(&fs::read_to_string(job.run_dir.join(JOB_FILE_NAME)).unwrap())
+                .unwrap();
+        assert_eq!(written, job);
+        assert!(job.run_dir.join("stub-model.txt").is_file());
+        if let Some(socket) = &job.socket {
+            assert!(!socket.exists());
+        }
+        fs::remove_dir_all(&job.project_dir).unwrap();
+    }
+
+    fn check_stub_stop(transport: Transport) {
+        let job = stub_job(&format!("stop-{}", transport.name()), transport, 50);
+        let (result, epochs) = run_stub(&job, "", Some(2));
+        let report = result.unwrap();
+        // the stop request is read before the next epoch starts
+        assert!((2..=3).contains(&epochs.len()), "{} epochs", epochs.len());
+        assert_eq!(report.exit_code, Some(0));
+        assert!(!job.run_dir.join("stub-model.txt").exists());
+        fs::remove_dir_all(&job.project_dir).unwrap();
+    }
+
+    fn check_stub_failure(transport: Transport) {
+        let job = stub_job(&format!("fail-{}", transport.name()), transport, 5);
+        let (result, epochs) = run_stub(&job, "STUB_FAIL=2", None);
+        assert_eq!(
+            result.unwrap_err(),
+            "the stub failed after epoch 2 as asked"
+        );
+        assert_eq!(epochs.len(), 1);
+        let report = load_report(&job.run_dir).unwrap();
+        assert_eq!(report.exit_code, Some(1));
+        assert!(report.error.is_some());
+        fs::remove_dir_all(&job.project_dir).unwrap();
+    }
+
+    #[test]
+    fn stub_trainer_over_stdout() {
+        check_stub_run(Transport::Stdout);
+        check_stub_stop(Transport::Stdout);
+        check_stub_failure(Transport::Stdout);
+    }
+
+    #[test]
+    #[ignore = "the stub needs socat, an nc with -U or python3 for the socket transport"]
+    fn stub_trainer_over_socket() {
+        check_stub_run(Transport::Socket);
+        check_stub_stop(Transport::Socket);
+        check_stub_failure(Transport::Socket);
+    }
+
+    #[test]
+    fn socket_connections_waiting_at_the_exit_are_read() {
+        let job = stub_job("backlog", Transport::Socket, 1);
+        let config = ExternalConfig {
+            command: "sleep 0.3".to_string(),
+            transport: Transport::Socket,
+            ..ExternalConfig::default()
+        };
+        let socket = job.socket.clone().unwrap();
+        let epoch = |epoch: usize| {
+            format!(
+                "{{\"event\": \"epoch\", \"epoch\": {}, \"epochs\": 10}}\n",
+                epoch
+            )
+        };
+        let client = thread::spawn(move || {
+            while !socket.exists() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            // the first connection keeps the reader busy until the trainer exited, the others
+            // wait in the backlog meanwhile
+            let mut first = UnixStream::connect(&socket).unwrap();
+            first.write_all(epoch(1).as_bytes()).unwrap();
+            for e in 2..=10 {
+                let mut connection = UnixStream::connect(&socket).unwrap();
+                connection.write_all(epoch(e).as_bytes()).unwrap();
+            }
+            thread::sleep(Duration::from_millis(600));
+        });
+
+        let mut epochs = Vec::new();
+        let report = run_trainer(
+            &config,
+            &job,
+            "val_loss",
+            &TrainingControl::default(),
+            &mut |_| {},
+            &mut |report| epochs.push(report.epoch),
+        )
+        .unwrap();
+        client.join().unwrap();
+        assert_eq!(epochs, (1..=10).collect::<Vec<_>>());
+        assert_eq!(report.exit_code, Some(0));
+        fs::remove_dir_all(&job.project_dir).unwrap();
+    }
+
+    #[test]
+    fn killing_a_trainer_kills_what_it_started() {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg("sleep 30 & echo $!; wait")
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let mut pid = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut pid)
+            .unwrap();
+        let stat = Path::new("/proc").join(pid.trim()).join("stat");
+        assert!(stat.exists());
+
+        kill_trainer(&mut child);
+        child.wait().unwrap();
+        // gone, or a zombie waiting for a parent to reap it
+        let started = Instant::now();
+        while fs::read_to_string(&stat).is_ok_and(|stat| !stat.contains(") Z ")) {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "the sleep survived"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn failed_trainers_are_errors() {
+        // the last line on stderr explains a crash
+        let job = stub_job("crash", Transport::Stdout, 1);
+        let config = ExternalConfig {
+            command: "echo 'no such model' >&2; exit 3".to_string(),
+            ..ExternalConfig::default()
+        };
+        let error = run_trainer(
+            &config,
+            &job,
+            "val_loss",
+            &TrainingControl::default(),
+            &mut |_| {},
+            &mut |_| {},
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.ends_with(": no such model"), "{}", error);
+        fs::remove_dir_all(&job.project_dir).unwrap();
+    }
+}
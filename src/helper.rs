@@ -7,6 +7,9 @@ use gtk::{ApplicationWindow, /* Box as GtkBox, CellRendererText, */ MessageDialo
 // use gtk::{Button, ColorButton, Dialog, DropDown, Entry, Label, Orientation, ResponseType};
 
 /* use gtk::glib::IsA; */
+use crate::classes::LabelClass;
+use crate::store::Modality;
+
 use home::home_dir;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -40,6 +43,9 @@ macro_rules! debug_println {
 
 // --- end macros ----------------------------------------------------------------------------------
 
+/// name of the class every classification project starts with, it can not be deleted
+pub(crate) const BACKGROUND_CLASS: &str = "default / background";
+
 // --- begin structs -------------------------------------------------------------------------------
 
 /// Struct for representing content of dotfile for this application
@@ -57,11 +63,29 @@ pub(crate) struct DotFileConfig {
     pub(crate) projects: Vec<String>,
 }
 
+/// Kind of problem a project solves, chosen when the project is created
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProblemType {
+    #[default]
+    Classification,
+    Clustering,
+}
+
 /// some example struct for the config
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct Config {
     title: String,
     pub(crate) owner: Owner,
+    /// label classes of the project, the position is the class index
+    #[serde(default)]
+    pub(crate) classes: Vec<LabelClass>,
+    #[serde(default)]
+    pub(crate) problem: ProblemType,
+    /// kind of data, `None` for configs written before it was stored (see
+    /// [`crate::project::Project::modality`])
+    #[serde(default)]
+    pub(crate) modality: Option<Modality>,
 }
 
 /// even more example structs for the config
@@ -73,6 +97,15 @@ pub(crate) struct Owner {
 
 // --- end structs ---------------------------------------------------------------------------------
 
+impl ProblemType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ProblemType::Classification => "classification",
+            ProblemType::Clustering => "clustering",
+        }
+    }
+}
+
 pub(crate) fn update_dotfile(
     new_project_path: &str,
     dotfile_path: Option<&str>,
@@ -146,6 +179,8 @@ pub(crate) fn save_config(file_path: &str, config: &Config) -> Result<(), Box<dy
 ///     - `name` is the name of the config
 ///     - `dob` is the date of birth
 ///     - `title` is the title of the config
+///     - `classes` are the label classes
+///     - `problem` and `modality` are the kind of problem and data of the project
 ///
 /// returns:
 ///     Config struct
@@ -153,6 +188,9 @@ pub(crate) fn generate_config(
     name: Option<&str>,
     dob: Option<&str>,
     title: Option<&str>,
+    classes: Vec<LabelClass>,
+    problem: ProblemType,
+    modality: Option<Modality>,
 ) -> Config {
     let owner = Owner {
         name: name.unwrap_or("Default Name").to_string(),
@@ -162,6 +200,51 @@ pub(crate) fn generate_config(
     Config {
         title: title.unwrap_or("Default Title").to_string(),
         owner, // owner: owner,
+        classes,
+        problem,
+        modality,
+    }
+}
+
+/// Disjoint set (union-find) over the indices `0..n`
+///
+/// used to merge items into groups, e.g. duplicate clusters or split units.
+pub(crate) struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    pub(crate) fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            // path halving
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            // the smaller index stays the root, so groups are ordered by their first member
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    /// all groups with more than one member, each sorted, ordered by their first member
+    pub(crate) fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: std::collections::BTreeMap<usize, Vec<usize>> = Default::default();
+        for i in 0..self.parent.len() {
+            let root = self.find(i);
+            groups.entry(root).or_default().push(i);
+        }
+        groups.into_values().filter(|g| g.len() > 1).collect()
     }
 }
 
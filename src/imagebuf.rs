@@ -0,0 +1,263 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Floating point image buffer
+//!
+//! Images are decoded by gdk-pixbuf (see [`crate::pixbuf`]) and then converted into an
+//! [`ImageBuf`]: interleaved channels, row major, every value between 0 and 1. All image
+//! processing (hashing, preprocessing, augmentation, feature extraction) works on this type.
+
+use std::error::Error;
+
+/// first bytes of an image in the raw format, see [`ImageBuf::to_raw`]
+const RAW_MAGIC: &[u8; 4] = b"AIF1";
+
+/// Interleaved `f32` image with values between 0 and 1
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImageBuf {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// 1 (gray), 3 (rgb) or 4 (rgba)
+    pub(crate) channels: usize,
+    pub(crate) data: Vec<f32>,
+}
+
+impl ImageBuf {
+    /// black image
+    pub(crate) fn new(width: usize, height: usize, channels: usize) -> ImageBuf {
+        ImageBuf {
+            width,
+            height,
+            channels,
+            data: vec![0.0; width * height * channels],
+        }
+    }
+
+    /// # convert 8 bit pixel data
+    ///
+    /// `rowstride` is the number of bytes per row, which may be larger than
+    /// `width * channels` (rows of gdk-pixbuf are padded).
+    pub(crate) fn from_u8(
+        width: usize,
+        height: usize,
+        channels: usize,
+        rowstride: usize,
+        bytes: &[u8],
+    ) -> ImageBuf {
+        let mut image = ImageBuf::new(width, height, channels);
+        for y in 0..height {
+            let row = &bytes[y * rowstride..y * rowstride + width * channels];
+            let out = &mut image.data[y * width * channels..(y + 1) * width * channels];
+            for (o, b) in out.iter_mut().zip(row) {
+                *o = *b as f32 / 255.0;
+            }
+        }
+        image
+    }
+
+    /// rgba bytes (4 bytes per pixel, no padding) e.g. for a `gdk::MemoryTexture`
+    pub(crate) fn to_rgba8(&self) -> Vec<u8> {
+        let to_byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let mut bytes = Vec::with_capacity(self.width * self.height * 4);
+        for pixel in self.data.chunks_exact(self.channels) {
+            match self.channels {
+                1 | 2 => {
+                    let v = to_byte(pixel[0]);
+                    bytes.extend([v, v, v]);
+                }
+                _ => bytes.extend([to_byte(pixel[0]), to_byte(pixel[1]), to_byte(pixel[2])]),
+            }
+            bytes.push(match self.channels {
+                2 => to_byte(pixel[1]),
+                4 => to_byte(pixel[3]),
+                _ => 255,
+            });
+        }
+        bytes
+    }
+
+    /// # lossless bytes of the image
+    ///
+    /// the magic `AIF1`, width, height and channels (`u32` each) and then the values as `f32`,
+    /// all little endian. Unlike PNG this keeps values outside of 0 to 1 (e.g. after
+    /// [`crate::imageops::normalise`]) and does not round to 8 bits.
+    pub(crate) fn to_raw(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.data.len() * 4);
+        bytes.extend(RAW_MAGIC);
+        for size in [self.width, self.height, self.channels] {
+            bytes.extend((size as u32).to_le_bytes());
+        }
+        for v in &self.data {
+            bytes.extend(v.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// # read an image written by [`ImageBuf::to_raw`]
+    ///
+    /// returns:
+    ///     Result with the image, an error if the bytes are no (complete) raw image
+    pub(crate) fn from_raw(bytes: &[u8]) -> Result<ImageBuf, Box<dyn Error>> {
+        let header = bytes.get(..16).ok_or("raw image without a header")?;
+        if &header[..4] != RAW_MAGIC {
+            return Err("not a raw image".into());
+        }
+        let size = |i: usize| {
+            u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]) as usize
+        };
+        let (width, height, channels) = (size(4), size(8), size(12));
+        let data = &bytes[16..];
+        if data.len() != width * height * channels * 4 {
+            return Err(format!(
+                "raw image of {}x{}x{} has {} bytes of data",
+                width,
+                height,
+                channels,
+                data.len()
+            )
+            .into());
+        }
+        Ok(ImageBuf {
+            width,
+            height,
+            channels,
+            data: data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, x: usize, y: usize, c: usize) -> f32 {
+        self.data[(y * self.width + x) * self.channels + c]
+    }
+
+    #[inline]
+    pub(crate) fn set(&mut self, x: usize, y: usize, c: usize, value: f32) {
+        self.data[(y * self.width + x) * self.channels + c] = value;
+    }
+
+    /// luminance (ITU-R BT.601), the alpha channel is dropped
+    pub(crate) fn to_gray(&self) -> ImageBuf {
+        if self.channels <= 2 {
+            return ImageBuf {
+                width: self.width,
+                height: self.height,
+                channels: 1,
+                data: self
+                    .data
+                    .chunks_exact(self.channels)
+                    .map(|p| p[0])
+                    .collect(),
+            };
+        }
+        ImageBuf {
+            width: self.width,
+            height: self.height,
+            channels: 1,
+            data: self
+                .data
+                .chunks_exact(self.channels)
+                .map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2])
+                .collect(),
+        }
+    }
+
+    /// # bilinear resize
+    ///
+    /// good for enlarging and moderate shrinking, use [`ImageBuf::resize_area`] to shrink
+    /// by a large factor.
+    pub(crate) fn resize_bilinear(&self, width: usize, height: usize) -> ImageBuf {
+        let mut out = ImageBuf::new(width, height, self.channels);
+        if self.width == 0 || self.height == 0 {
+            return out;
+        }
+        let sx = self.width as f32 / width.max(1) as f32;
+        let sy = self.height as f32 / height.max(1) as f32;
+
+        for y in 0..height {
+            let fy = ((y as f32 + 0.5) * sy - 0.5).clamp(0.0, (self.height - 1) as f32);
+            let y0 = fy.floor() as usize;
+            let y1 = (y0 + 1).min(self.height - 1);
+            let wy = fy - y0 as f32;
+            for x in 0..width {
+                let fx = ((x as f32 + 0.5) * sx - 0.5).clamp(0.0, (self.width - 1) as f32);
+                let x0 = fx.floor() as usize;
+                let x1 = (x0 + 1).min(self.width - 1);
+                let wx = fx - x0 as f32;
+                for c in 0..self.channels {
+                    let top = self.get(x0, y0, c) * (1.0 - wx) + self.get(x1, y0, c) * wx;
+                    let bottom = self.get(x0, y1, c) * (1.0 - wx) + self.get(x1, y1, c) * wx;
+                    out.set(x, y, c, top * (1.0 - wy) + bottom * wy);
+                }
+            }
+        }
+        out
+    }
+
+    /// # area (box filter) resize
+    ///
+    /// every output pixel is the mean of the input pixels it covers, which avoids aliasing
+    /// when shrinking a lot (e.g. to the 8x8 thumbnails of the perceptual hashes).
+    pub(crate) fn resize_area(&self, width: usize, height: usize) -> ImageBuf {
+        if width >= self.width || height >= self.height {
+            return self.resize_bilinear(width, height);
+        }
+        let mut out = ImageBuf::new(width, height, self.channels);
+        for y in 0..height {
+            let y0 = y * self.height / height;
+            let y1 = ((y + 1) * self.height / height).max(y0 + 1);
+            for x in 0..width {
+                let x0 = x * self.width / width;
+                let x1 = ((x + 1) * self.width / width).max(x0 + 1);
+                let n = ((y1 - y0) * (x1 - x0)) as f32;
+                for c in 0..self.channels {
+                    let mut sum = 0.0;
+                    for yy in y0..y1 {
+                        for xx in x0..x1 {
+                            sum += self.get(xx, yy, c);
+                        }
+                    }
+                    out.set(x, y, c, sum / n);
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_images_round_trip() {
+        let image = ImageBuf {
+            width: 3,
+            height: 2,
+            channels: 2,
+            data: vec![
+                0.0,
+                1.0,
+                -1.75,
+                2.5,
+                0.123_456_79,
+                1e-7,
+                0.5,
+                3.0,
+                -0.0,
+                7.25,
+                1.0,
+                0.25,
+            ],
+        };
+        let bytes = image.to_raw();
+        assert_eq!(bytes.len(), 16 + 12 * 4);
+        assert_eq!(ImageBuf::from_raw(&bytes).unwrap(), image);
+
+        assert!(ImageBuf::from_raw(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ImageBuf::from_raw(&bytes[..10]).is_err());
+        assert!(ImageBuf::from_raw(b"\x89PNG\r\n\x1a\n0000000000000000").is_err());
+    }
+}
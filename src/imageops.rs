@@ -0,0 +1,270 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Image operations on [`ImageBuf`]
+//!
+//! The building blocks of the preprocessing pipeline. Every operation returns a new image,
+//! operations that change pixel values leave the alpha channel (if any) untouched.
+
+use crate::imagebuf::ImageBuf;
+
+/// number of channels that carry colour (the alpha channel of 2 and 4 channel images is not)
+fn colour_channels(image: &ImageBuf) -> usize {
+    match image.channels {
+        2 | 4 => image.channels - 1,
+        channels => channels,
+    }
+}
+
+/// cut out a rectangle, clamped to the image
+pub(crate) fn crop(image: &ImageBuf, x: usize, y: usize, width: usize, height: usize) -> ImageBuf {
+    let x = x.min(image.width);
+    let y = y.min(image.height);
+    let width = width.min(image.width - x);
+    let height = height.min(image.height - y);
+
+    let mut out = ImageBuf::new(width, height, image.channels);
+    let row_len = width * image.channels;
+    for row in 0..height {
+        let start = ((y + row) * image.width + x) * image.channels;
+        out.data[row * row_len..(row + 1) * row_len]
+            .copy_from_slice(&image.data[start..start + row_len]);
+    }
+    out
+}
+
+/// add borders filled with `value` (the alpha channel of the border is opaque)
+pub(crate) fn pad(
+    image: &ImageBuf,
+    top: usize,
+    right: usize,
+    bottom: usize,
+    left: usize,
+    value: f32,
+) -> ImageBuf {
+    let width = image.width + left + right;
+    let height = image.height + top + bottom;
+    let colour = colour_channels(image);
+
+    let mut out = ImageBuf::new(width, height, image.channels);
+    for pixel in out.data.chunks_exact_mut(image.channels) {
+        for (c, v) in pixel.iter_mut().enumerate() {
+            *v = if c < colour { value } else { 1.0 };
+        }
+    }
+
+    let row_len = image.width * image.channels;
+    for row in 0..image.height {
+        let start = ((top + row) * width + left) * image.channels;
+        out.data[start..start + row_len]
+            .copy_from_slice(&image.data[row * row_len..(row + 1) * row_len]);
+    }
+    out
+}
+
+/// `(v - mean) / std` per colour channel, gray images use the first entries
+pub(crate) fn normalise(image: &ImageBuf, mean: &[f64; 3], std: &[f64; 3]) -> ImageBuf {
+    let colour = colour_channels(image).min(3);
+    let mut out = image.clone();
+    for pixel in out.data.chunks_exact_mut(image.channels) {
+        for c in 0..colour {
+            let std = if std[c] == 0.0 { 1.0 } else { std[c] };
+            pixel[c] = ((pixel[c] as f64 - mean[c]) / std) as f32;
+        }
+    }
+    out
+}
+
+const BINS: usize = 256;
+
+fn bin(v: f32) -> usize {
+    ((v.clamp(0.0, 1.0) * (BINS - 1) as f32).round()) as usize
+}
+
+/// mapping of every bin to its (normalised) cumulative count
+fn cumulative_mapping(histogram: &[f32; BINS]) -> [f32; BINS] {
+    let total: f32 = histogram.iter().sum();
+    let mut mapping = [0.0; BINS];
+    let mut sum = 0.0;
+    for (m, count) in mapping.iter_mut().zip(histogram) {
+        sum += count;
+        *m = if total > 0.0 { sum / total } else { 0.0 };
+    }
+    mapping
+}
+
+/// global histogram equalisation, every colour channel on its own
+pub(crate) fn equalise_histogram(image: &ImageBuf) -> ImageBuf {
+    let mut out = image.clone();
+    for c in 0..colour_channels(image) {
+        let mut histogram = [0.0f32; BINS];
+        for pixel in image.data.chunks_exact(image.channels) {
+            histogram[bin(pixel[c])] += 1.0;
+        }
+        let mapping = cumulative_mapping(&histogram);
+        for pixel in out.data.chunks_exact_mut(image.channels) {
+            pixel[c] = mapping[bin(pixel[c])];
+        }
+    }
+    out
+}
+
+/// # contrast limited adaptive histogram equalisation (CLAHE)
+///
+/// the image is cut into `tiles` x `tiles` regions which are equalised on their own, with
+/// every histogram bin clipped at `clip_limit` times the mean bin count (the clipped counts
+/// are spread over all bins). The mappings of the four closest tiles are interpolated
+/// bilinearly to avoid visible tile borders.
+pub(crate) fn clahe(image: &ImageBuf, tiles: usize, clip_limit: f64) -> ImageBuf {
+    let mut out = image.clone();
+    if image.width == 0 || image.height == 0 {
+        return out;
+    }
+    let tiles_x = tiles.clamp(1, image.width);
+    let tiles_y = tiles.clamp(1, image.height);
+    let tile_w = image.width.div_ceil(tiles_x);
+    let tile_h = image.height.div_ceil(tiles_y);
+
+    for c in 0..colour_channels(image) {
+        let mut mappings = vec![[0.0f32; BINS]; tiles_x * tiles_y];
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let mut histogram = [0.0f32; BINS];
+                let mut pixels = 0;
+                for y in ty * tile_h..((ty + 1) * tile_h).min(image.height) {
+                    for x in tx * tile_w..((tx + 1) * tile_w).min(image.width) {
+                        histogram[bin(image.get(x, y, c))] += 1.0;
+                        pixels += 1;
+                    }
+                }
+
+                let limit = (clip_limit * pixels as f64 / BINS as f64).max(1.0) as f32;
+                let mut excess = 0.0;
+                for count in histogram.iter_mut() {
+                    if *count > limit {
+                        excess += *count - limit;
+                        *count = limit;
+                    }
+                }
+                for count in histogram.iter_mut() {
+                    *count += excess / BINS as f32;
+                }
+                mappings[ty * tiles_x + tx] = cumulative_mapping(&histogram);
+            }
+        }
+
+        // position of a pixel in "tile centre" coordinates
+        let grid = |p: usize, size: usize, count: usize| {
+            let g = ((p as f32 + 0.5) / size as f32 - 0.5).clamp(0.0, (count - 1) as f32);
+            let g0 = g.floor() as usize;
+            (g0, (g0 + 1).min(count - 1), g - g0 as f32)
+        };
+
+        for y in 0..image.height {
+            let (y0, y1, wy) = grid(y, tile_h, tiles_y);
+            for x in 0..image.width {
+                let (x0, x1, wx) = grid(x, tile_w, tiles_x);
+                let b = bin(image.get(x, y, c));
+                let m = |tx: usize, ty: usize| mappings[ty * tiles_x + tx][b];
+                let top = m(x0, y0) * (1.0 - wx) + m(x1, y0) * wx;
+                let bottom = m(x0, y1) * (1.0 - wx) + m(x1, y1) * wx;
+                out.set(x, y, c, top * (1.0 - wy) + bottom * wy);
+            }
+        }
+    }
+    out
+}
+
+/// median of the `(2 * radius + 1)^2` neighbourhood, removes salt and pepper noise
+pub(crate) fn median_filter(image: &ImageBuf, radius: usize) -> ImageBuf {
+    let mut out = image.clone();
+    if radius == 0 || image.width == 0 || image.height == 0 {
+        return out;
+    }
+    let mut window = Vec::with_capacity((2 * radius + 1).pow(2));
+    for c in 0..colour_channels(image) {
+        for y in 0..image.height {
+            for x in 0..image.width {
+                window.clear();
+                for yy in y.saturating_sub(radius)..=(y + radius).min(image.height - 1) {
+                    for xx in x.saturating_sub(radius)..=(x + radius).min(image.width - 1) {
+                        window.push(image.get(xx, yy, c));
+                    }
+                }
+                let middle = window.len() / 2;
+                let (_, median, _) = window.select_nth_unstable_by(middle, f32::total_cmp);
+                out.set(x, y, c, *median);
+            }
+        }
+    }
+    out
+}
+
+/// separable gaussian blur (the kernel reaches 3 sigma, borders are clamped)
+pub(crate) fn gaussian_blur(image: &ImageBuf, sigma: f64) -> ImageBuf {
+    if sigma <= 0.0 || image.width == 0 || image.height == 0 {
+        return image.clone();
+    }
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp() as f32)
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / sum).collect();
+
+    let colour = colour_channels(image);
+    let blur = |source: &ImageBuf, horizontal: bool| {
+        let mut out = source.clone();
+        for y in 0..source.height {
+            for x in 0..source.width {
+                for c in 0..colour {
+                    let mut value = 0.0;
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let offset = k as isize - radius;
+                        let (xx, yy) = if horizontal {
+                            (
+                                (x as isize + offset).clamp(0, source.width as isize - 1) as usize,
+                                y,
+                            )
+                        } else {
+                            (
+                                x,
+                                (y as isize + offset).clamp(0, source.height as isize - 1) as usize,
+                            )
+                        };
+                        value += source.get(xx, yy, c) * weight;
+                    }
+                    out.set(x, y, c, value);
+                }
+            }
+        }
+        out
+    };
+    blur(&blur(image, true), false)
+}
+
+/// # map the value range of the colour channels to 0 .. 1
+///
+/// only for showing images whose values left the 0 .. 1 range (e.g. after normalisation),
+/// images inside the range are returned unchanged.
+pub(crate) fn rescale_for_display(image: &ImageBuf) -> ImageBuf {
+    let colour = colour_channels(image);
+    let (mut min, mut max) = (f32::MAX, f32::MIN);
+    for pixel in image.data.chunks_exact(image.channels) {
+        for v in &pixel[..colour] {
+            min = min.min(*v);
+            max = max.max(*v);
+        }
+    }
+    if min >= 0.0 && max <= 1.0 || max <= min {
+        return image.clone();
+    }
+
+    let mut out = image.clone();
+    for pixel in out.data.chunks_exact_mut(image.channels) {
+        for v in &mut pixel[..colour] {
+            *v = (*v - min) / (max - min);
+        }
+    }
+    out
+}
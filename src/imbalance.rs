@@ -0,0 +1,410 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Class imbalance handling
+//!
+//! Random over- and under-sampling of the training items, SMOTE for tabular features and
+//! class weights computed from the label counts. Only the training split is ever resampled,
+//! validation and test keep the real class distribution.
+
+use crate::rng::Rng;
+use crate::splits::{Split, SplitAssignment};
+use crate::store::AnnotationStore;
+use crate::tabular::{format_number, is_missing, parse_number, Table};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// How the training items are resampled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Resampling {
+    #[default]
+    None,
+    /// repeat random items of the smaller classes
+    RandomOver,
+    /// drop random items of the larger classes
+    RandomUnder,
+    /// synthesise samples of the smaller classes between neighbouring samples (tabular only)
+    Smote,
+}
+
+/// How the loss weights of the classes are derived from the label counts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ClassWeighting {
+    None,
+    /// `total / (classes * count)`
+    #[default]
+    Balanced,
+    /// `1 / sqrt(count)`, a milder version of balanced
+    InverseSqrt,
+    /// `(1 - beta) / (1 - beta^count)`, the "effective number of samples" of Cui et al.
+    EffectiveNumber,
+}
+
+/// Imbalance handling of a project, part of the preprocessing config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ImbalanceConfig {
+    pub(crate) resampling: Resampling,
+    /// size of every class after resampling, relative to the largest class (over-sampling,
+    /// SMOTE) or the smallest class (under-sampling); 1 balances the classes completely
+    pub(crate) ratio: f64,
+    /// number of nearest neighbours SMOTE interpolates between
+    pub(crate) neighbours: usize,
+    pub(crate) weighting: ClassWeighting,
+    /// `beta` of [`ClassWeighting::EffectiveNumber`]
+    pub(crate) beta: f64,
+    pub(crate) seed: u64,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for ImbalanceConfig {
+    fn default() -> Self {
+        ImbalanceConfig {
+            resampling: Resampling::None,
+            ratio: 1.0,
+            neighbours: 5,
+            weighting: ClassWeighting::Balanced,
+            beta: 0.999,
+            seed: 42,
+        }
+    }
+}
+
+impl Resampling {
+    pub(crate) const ALL: [Resampling; 4] = [
+        Resampling::None,
+        Resampling::RandomOver,
+        Resampling::RandomUnder,
+        Resampling::Smote,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Resampling::None => "none",
+            Resampling::RandomOver => "random over-sampling",
+            Resampling::RandomUnder => "random under-sampling",
+            Resampling::Smote => "SMOTE (tabular)",
+        }
+    }
+}
+
+impl ClassWeighting {
+    pub(crate) const ALL: [ClassWeighting; 4] = [
+        ClassWeighting::None,
+        ClassWeighting::Balanced,
+        ClassWeighting::InverseSqrt,
+        ClassWeighting::EffectiveNumber,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ClassWeighting::None => "none",
+            ClassWeighting::Balanced => "balanced",
+            ClassWeighting::InverseSqrt => "inverse square root",
+            ClassWeighting::EffectiveNumber => "effective number",
+        }
+    }
+}
+
+/// number of samples per label
+pub(crate) fn label_counts(labels: &[String]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for label in labels {
+        *counts.entry(label.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// # labels of the items resampling applies to
+///
+/// the items of the training split if the project has splits, all items otherwise. items
+/// without annotations are left out, multi label items count with their primary class.
+///
+/// returns:
+///     (item id, class) pairs in item id order
+pub(crate) fn training_labels(
+    store: &AnnotationStore,
+    splits: Option<&SplitAssignment>,
+) -> Vec<(u64, String)> {
+    let mut ids: Vec<u64> = match splits {
+        Some(splits) => splits.items_of(Split::Train),
+        None => store.items().map(|item| item.id).collect(),
+    };
+    ids.sort_unstable();
+    ids.into_iter()
+        .filter_map(|id| Some((id, store.primary_class(id)?.to_string())))
+        .collect()
+}
+
+/// # number of samples per class after resampling
+///
+/// returns:
+///     the counts, equal to the given ones for [`Resampling::None`]
+pub(crate) fn target_counts(
+    counts: &BTreeMap<String, usize>,
+    config: &ImbalanceConfig,
+) -> BTreeMap<String, usize> {
+    let ratio = config.ratio.clamp(0.01, 1.0);
+    let largest = counts.values().copied().max().unwrap_or(0);
+    let smallest = counts
+        .values()
+        .copied()
+        .filter(|c| *c > 0)
+        .min()
+        .unwrap_or(0);
+
+    counts
+        .iter()
+        .map(|(class, count)| {
+            let target = match config.resampling {
+                Resampling::None => *count,
+                Resampling::RandomOver | Resampling::Smote => {
+                    (*count).max((ratio * largest as f64).ceil() as usize)
+                }
+                Resampling::RandomUnder => (*count).min((smallest as f64 / ratio).ceil() as usize),
+            };
+            (class.clone(), target)
+        })
+        .collect()
+}
+
+/// # loss weight per class
+///
+/// the weights are scaled so that the mean weight over all samples is 1, i.e. the overall
+/// magnitude of the loss (and therefore the sensible learning rate) does not change.
+///
+/// returns:
+///     map from class to weight, empty classes get weight 0
+pub(crate) fn class_weights(
+    counts: &BTreeMap<String, usize>,
+    config: &ImbalanceConfig,
+) -> BTreeMap<String, f64> {
+    let classes = counts.values().filter(|c| **c > 0).count().max(1) as f64;
+    let total: usize = counts.values().sum();
+    let beta = config.beta.clamp(0.0, 1.0 - 1e-9);
+
+    let raw: BTreeMap<String, f64> = counts
+        .iter()
+        .map(|(class, count)| {
+            let n = *count as f64;
+            let weight = if *count == 0 {
+                0.0
+            } else {
+                match config.weighting {
+                    ClassWeighting::None => 1.0,
+                    ClassWeighting::Balanced => total as f64 / (classes * n),
+                    ClassWeighting::InverseSqrt => 1.0 / n.sqrt(),
+                    ClassWeighting::EffectiveNumber => (1.0 - beta) / (1.0 - beta.powf(n)),
+                }
+            };
+            (class.clone(), weight)
+        })
+        .collect();
+
+    let weighted: f64 = raw.iter().map(|(class, w)| w * counts[class] as f64).sum();
+    let scale = if weighted > 0.0 {
+        total as f64 / weighted
+    } else {
+        1.0
+    };
+    raw.into_iter()
+        .map(|(class, w)| (class, w * scale))
+        .collect()
+}
+
+/// # indices of the samples after random over- or under-sampling
+///
+/// every class is brought to its [`target_counts`]: over-sampling keeps all samples and adds
+/// random repetitions, under-sampling keeps a random subset. [`Resampling::Smote`] needs
+/// features, for plain items it falls back to random over-sampling.
+///
+/// returns:
+///     indices into `labels` (repeated for over-sampled classes), in ascending order
+pub(crate) fn resample_indices(labels: &[String], config: &ImbalanceConfig) -> Vec<usize> {
+    let targets = target_counts(&label_counts(labels), config);
+    let mut rng = Rng::new(config.seed);
+    let mut indices = Vec::with_capacity(targets.values().sum());
+
+    for (class, target) in &targets {
+        let mut members: Vec<usize> = (0..labels.len()).filter(|i| labels[*i] == *class).collect();
+        if *target <= members.len() {
+            rng.shuffle(&mut members);
+            members.truncate(*target);
+            indices.extend(members);
+        } else {
+            let extra = target - members.len();
+            let repeats: Vec<usize> = (0..extra)
+                .map(|_| members[rng.below(members.len())])
+                .collect();
+            indices.extend(members);
+            indices.extend(repeats);
+        }
+    }
+
+    indices.sort_unstable();
+    indices
+}
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// # synthetic samples of the smaller classes (SMOTE)
+///
+/// each synthetic sample lies on the line between a random sample of the class and one of
+/// its `neighbours` nearest neighbours of the same class. classes with a single sample can
+/// only be repeated.
+///
+/// returns:
+///     the synthetic features and their labels (the originals are not included)
+pub(crate) fn smote(
+    features: &[Vec<f64>],
+    labels: &[String],
+    config: &ImbalanceConfig,
+) -> (Vec<Vec<f64>>, Vec<String>) {
+    let smote_config = ImbalanceConfig {
+        resampling: Resampling::Smote,
+        ..config.clone()
+    };
+    let targets = target_counts(&label_counts(labels), &smote_config);
+    let mut rng = Rng::new(config.seed);
+    let (mut synthetic, mut synthetic_labels) = (Vec::new(), Vec::new());
+
+    for (class, target) in &targets {
+        let members: Vec<usize> = (0..labels.len()).filter(|i| labels[*i] == *class).collect();
+        if *target <= members.len() {
+            continue;
+        }
+
+        // nearest neighbours within the class, brute force
+        let neighbours: Vec<Vec<usize>> = members
+            .iter()
+            .map(|i| {
+                let mut others: Vec<(f64, usize)> = members
+                    .iter()
+                    .filter(|j| *j != i)
+                    .map(|j| (squared_distance(&features[*i], &features[*j]), *j))
+                    .collect();
+                others.sort_by(|a, b| a.0.total_cmp(&b.0));
+                others
+                    .into_iter()
+                    .take(config.neighbours.max(1))
+                    .map(|(_, j)| j)
+                    .collect()
+            })
+            .collect();
+
+        for _ in members.len()..*target {
+            let pick = rng.below(members.len());
+            let sample = &features[members[pick]];
+            let generated = match neighbours[pick].as_slice() {
+                [] => sample.clone(),
+                near => {
+                    let other = &features[near[rng.below(near.len())]];
+                    let t = rng.next_f64();
+                    sample
+                        .iter()
+                        .zip(other)
+                        .map(|(a, b)| a + t * (b - a))
+                        .collect()
+                }
+            };
+            synthetic.push(generated);
+            synthetic_labels.push(class.clone());
+        }
+    }
+
+    (synthetic, synthetic_labels)
+}
+
+/// # table with SMOTE samples appended
+///
+/// all columns except the label column have to be numeric and complete, i.e. categorical
+/// columns have to be encoded and missing values imputed by the tabular pipeline first.
+///
+/// returns:
+///     Result with the original rows followed by the synthetic ones
+pub(crate) fn smote_table(
+    table: &Table,
+    label_column: &str,
+    config: &ImbalanceConfig,
+) -> Result<Table, Box<dyn Error>> {
+    let label_index = table.column_index(label_column)?;
+    let mut features = Vec::with_capacity(table.rows.len());
+    let mut labels = Vec::with_capacity(table.rows.len());
+
+    for (r, row) in table.rows.iter().enumerate() {
+        if is_missing(&row[label_index]) {
+            return Err(format!("row {} has no label", r + 1).into());
+        }
+        let mut values = Vec::with_capacity(row.len() - 1);
+        for (c, cell) in row.iter().enumerate() {
+            if c == label_index {
+                continue;
+            }
+            values.push(parse_number(cell).ok_or_else(|| {
+                format!(
+                    "column {:?} is not numeric or has missing values (row {}), encode and impute it in the tabular pipeline first",
+                    table.columns[c],
+                    r + 1
+                )
+            })?);
+        }
+        features.push(values);
+        labels.push(row[label_index].trim().to_string());
+    }
+
+    let (synthetic, synthetic_labels) = smote(&features, &labels, config);
+    let mut resampled = table.clone();
+    for (values, label) in synthetic.into_iter().zip(synthetic_labels) {
+        let mut values = values.into_iter().map(format_number);
+        let row = (0..table.columns.len())
+            .map(|c| {
+                if c == label_index {
+                    label.clone()
+                } else {
+                    values.next().unwrap_or_default()
+                }
+            })
+            .collect();
+        resampled.rows.push(row);
+    }
+    Ok(resampled)
+}
+
+/// # table resampled according to the config
+///
+/// rows are repeated or dropped by random over- or under-sampling, SMOTE appends synthetic
+/// rows (see [`smote_table`]).
+///
+/// returns:
+///     Result with the resampled table
+pub(crate) fn resample_table(
+    table: &Table,
+    label_column: &str,
+    config: &ImbalanceConfig,
+) -> Result<Table, Box<dyn Error>> {
+    if config.resampling == Resampling::Smote {
+        return smote_table(table, label_column, config);
+    }
+
+    let index = table.column_index(label_column)?;
+    let labels: Vec<String> = table
+        .rows
+        .iter()
+        .map(|row| row[index].trim().to_string())
+        .collect();
+    Ok(Table {
+        columns: table.columns.clone(),
+        rows: resample_indices(&labels, config)
+            .into_iter()
+            .map(|i| table.rows[i].clone())
+            .collect(),
+    })
+}
@@ -0,0 +1,370 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Linear classifiers
+//!
+//! Multinomial logistic regression and a one-vs-rest linear SVM, both trained with stochastic
+//! gradient descent on standardised features. An epoch is one pass over the training samples
+//! in a random (seeded) order. Both write checkpoints and resume from them.
+
+use crate::dataset::Dataset;
+use crate::metrics::{softmax, Metrics};
+use crate::model::{
+    float_param, int_param, load_json, save_json, FitContext, Hyperparameter, Model, Params,
+    Standardiser,
+};
+use crate::rng::Rng;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+/// probabilities are clipped to this before taking logarithms
+const PROBABILITY_FLOOR: f64 = 1e-12;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Softmax regression with L2 regularisation, trained with mini-batch gradient descent
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct LogisticRegression {
+    learning_rate: f64,
+    epochs: usize,
+    batch_size: usize,
+    l2: f64,
+    standardiser: Standardiser,
+    /// `[classes][features + 1]`, the last column is the bias
+    weights: Vec<Vec<f64>>,
+}
+
+/// One-vs-rest linear SVM (hinge loss), trained with stochastic gradient descent
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct LinearSvm {
+    c: f64,
+    learning_rate: f64,
+    epochs: usize,
+    standardiser: Standardiser,
+    /// `[classes][features + 1]`, the last column is the bias
+    weights: Vec<Vec<f64>>,
+}
+
+/// State of the training loop besides the weights, for checkpoints
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LoopState {
+    rng: Rng,
+    /// training inputs in the order of the last epoch (shuffled again every epoch)
+    order: Vec<usize>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// `w . x + b` for every class
+fn linear_scores(weights: &[Vec<f64>], x: &[f64]) -> Vec<f64> {
+    weights
+        .iter()
+        .map(|w| {
+            let (bias, w) = w.split_last().unwrap_or((&0.0, &[]));
+            w.iter().zip(x).map(|(a, b)| a * b).sum::<f64>() + bias
+        })
+        .collect()
+}
+
+/// standardised training inputs and labels (unlabelled samples are dropped)
+fn training_inputs(
+    data: &Dataset,
+    standardiser: &Standardiser,
+    context: &FitContext,
+) -> Vec<(Vec<f64>, usize, f64)> {
+    context
+        .train
+        .iter()
+        .filter_map(|i| {
+            let sample = &data.samples[*i];
+            let x = standardiser
+                .apply(&sample.x)
+                .iter()
+                .map(|v| *v as f64)
+                .collect();
+            Some((x, sample.label?, context.weight(data, *i)))
+        })
+        .collect()
+}
+
+/// # where the training loop starts
+///
+/// returns:
+///     Result with the first epoch and the loop state, taken from the checkpoint when resuming
+fn loop_start(
+    context: &mut FitContext,
+    inputs: usize,
+) -> Result<(usize, LoopState), Box<dyn Error>> {
+    match context.resume::<LoopState>()? {
+        Some((epoch, state)) if state.order.len() == inputs => Ok((epoch + 1, state)),
+        Some(_) => Err("the training data changed since the checkpoint".into()),
+        None => Ok((
+            1,
+            LoopState {
+                rng: Rng::new(context.seed),
+                order: (0..inputs).collect(),
+            },
+        )),
+    }
+}
+
+fn standardised(standardiser: &Standardiser, x: &[f32]) -> Vec<f64> {
+    standardiser.apply(x).iter().map(|v| *v as f64).collect()
+}
+
+impl Model for LogisticRegression {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::float(
+                "learning_rate",
+                "step size of gradient descent",
+                0.1,
+                1e-5,
+                10.0,
+                true,
+            ),
+            Hyperparameter::int("epochs", "passes over the training data", 100, 1, 10000),
+            Hyperparameter::int("batch_size", "samples per gradient step", 32, 1, 4096),
+            Hyperparameter::float(
+                "l2",
+                "strength of the L2 penalty on the weights",
+                1e-4,
+                0.0,
+                1.0,
+                false,
+            ),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(LogisticRegression {
+            learning_rate: float_param(params, "learning_rate"),
+            epochs: int_param(params, "epochs") as usize,
+            batch_size: int_param(params, "batch_size") as usize,
+            l2: float_param(params, "l2"),
+            ..LogisticRegression::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        // a resumed run continues with the standardiser and weights of the checkpoint
+        let resuming = context.is_resuming();
+        if !resuming {
+            self.standardiser = Standardiser::fit(data, &context.train);
+        }
+        let inputs = training_inputs(data, &self.standardiser, context);
+        if inputs.is_empty() {
+            return Err("no labelled training samples".into());
+        }
+        let features = data.input_size();
+        if !resuming {
+            self.weights = vec![vec![0.0; features + 1]; data.classes.len()];
+        }
+
+        let (first_epoch, mut state) = loop_start(context, inputs.len())?;
+        for epoch in first_epoch..=self.epochs {
+            // a stop within the epoch goes back to the weights and state after the last epoch
+            let finished = (self.weights.clone(), state.clone());
+            state.rng.shuffle(&mut state.order);
+            let (mut loss, mut total_weight) = (0.0, 0.0);
+            let mut interrupted = false;
+            for batch in state.order.chunks(self.batch_size.max(1)) {
+                let mut gradient = vec![vec![0.0; features + 1]; self.weights.len()];
+                for j in batch {
+                    let (x, label, weight) = &inputs[*j];
+                    let p = softmax(&linear_scores(&self.weights, x));
+                    loss -= weight * p[*label].max(PROBABILITY_FLOOR).ln();
+                    total_weight += weight;
+                    for (k, g) in gradient.iter_mut().enumerate() {
+                        let error = weight * (p[k] - if k == *label { 1.0 } else { 0.0 });
+                        for (g, v) in g.iter_mut().zip(x) {
+                            *g += error * v;
+                        }
+                        g[features] += error;
+                    }
+                }
+                let n = batch.len() as f64;
+                for (w, g) in self.weights.iter_mut().zip(&gradient) {
+                    for f in 0..=features {
+                        // the bias is not regularised
+                        let penalty = if f < features { self.l2 * w[f] } else { 0.0 };
+                        w[f] -= self.learning_rate * (g[f] / n + penalty);
+                    }
+                }
+                if context.should_stop() {
+                    interrupted = true;
+                    break;
+                }
+            }
+            if interrupted {
+                // continuing a partial epoch would not give the same model, drop it
+                (self.weights, state) = finished;
+                context.end_interrupted_epoch(self, epoch - 1, &state)?;
+                break;
+            }
+
+            let metrics = Metrics::from([(
+                "loss".to_string(),
+                loss / total_weight.max(f64::MIN_POSITIVE),
+            )]);
+            let samples = inputs.len();
+            let go_on = context.end_epoch(
+                data,
+                self,
+                epoch,
+                self.epochs,
+                metrics,
+                Some(self.learning_rate),
+                samples,
+            );
+            context.checkpoint(self, epoch, &state)?;
+            if !go_on {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        softmax(&linear_scores(
+            &self.weights,
+            &standardised(&self.standardiser, x),
+        ))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl Model for LinearSvm {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::float(
+                "c",
+                "inverse strength of the regularisation",
+                1.0,
+                1e-4,
+                1e4,
+                true,
+            ),
+            Hyperparameter::float(
+                "learning_rate",
+                "initial step size, decays with 1 / sqrt(epoch)",
+                0.01,
+                1e-6,
+                1.0,
+                true,
+            ),
+            Hyperparameter::int("epochs", "passes over the training data", 50, 1, 10000),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(LinearSvm {
+            c: float_param(params, "c"),
+            learning_rate: float_param(params, "learning_rate"),
+            epochs: int_param(params, "epochs") as usize,
+            ..LinearSvm::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        // a resumed run continues with the standardiser and weights of the checkpoint
+        let resuming = context.is_resuming();
+        if !resuming {
+            self.standardiser = Standardiser::fit(data, &context.train);
+        }
+        let inputs = training_inputs(data, &self.standardiser, context);
+        if inputs.is_empty() {
+            return Err("no labelled training samples".into());
+        }
+        let features = data.input_size();
+        let classes = data.classes.len();
+        if !resuming {
+            self.weights = vec![vec![0.0; features + 1]; classes];
+        }
+        // the usual `1/2 |w|^2 + C sum(hinge)` objective, divided by `C n`
+        let lambda = 1.0 / (self.c * inputs.len() as f64);
+
+        let (first_epoch, mut state) = loop_start(context, inputs.len())?;
+        for epoch in first_epoch..=self.epochs {
+            // a stop within the epoch goes back to the weights and state after the last epoch
+            let finished = (self.weights.clone(), state.clone());
+            state.rng.shuffle(&mut state.order);
+            let rate = self.learning_rate / (epoch as f64).sqrt();
+            let (mut loss, mut total_weight) = (0.0, 0.0);
+            let mut interrupted = false;
+            for (step, j) in state.order.iter().enumerate() {
+                let (x, label, weight) = &inputs[*j];
+                total_weight += weight;
+                for (k, w) in self.weights.iter_mut().enumerate() {
+                    let y = if k == *label { 1.0 } else { -1.0 };
+                    let margin = y * linear_scores(std::slice::from_ref(w), x)[0];
+                    for v in w.iter_mut().take(features) {
+                        *v *= 1.0 - rate * lambda;
+                    }
+                    if margin < 1.0 {
+                        loss += weight * (1.0 - margin) / classes as f64;
+                        for (v, xv) in w.iter_mut().zip(x) {
+                            *v += rate * weight * y * xv;
+                        }
+                        w[features] += rate * weight * y;
+                    }
+                }
+                // checking every sample would be wasteful
+                if step % 256 == 0 && context.should_stop() {
+                    interrupted = true;
+                    break;
+                }
+            }
+            if interrupted {
+                // continuing a partial epoch would not give the same model, drop it
+                (self.weights, state) = finished;
+                context.end_interrupted_epoch(self, epoch - 1, &state)?;
+                break;
+            }
+
+            let metrics = Metrics::from([(
+                "hinge_loss".to_string(),
+                loss / total_weight.max(f64::MIN_POSITIVE),
+            )]);
+            let go_on = context.end_epoch(
+                data,
+                self,
+                epoch,
+                self.epochs,
+                metrics,
+                Some(rate),
+                inputs.len(),
+            );
+            context.checkpoint(self, epoch, &state)?;
+            if !go_on {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// softmax of the margins, only a rough calibration of the decision values
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        softmax(&linear_scores(
+            &self.weights,
+            &standardised(&self.standardiser, x),
+        ))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
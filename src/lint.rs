@@ -0,0 +1,338 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Annotation quality checks ("annotation lint")
+//!
+//! Looks for annotations that are almost certainly mistakes (zero-area or out-of-bounds boxes,
+//! self-intersecting polygons, segments behind the end of a sound file, ...) and for dataset
+//! level problems (unlabelled items, conflicting labels, rare or unknown classes).
+
+use crate::helper::BACKGROUND_CLASS;
+use crate::media::{image_dimensions, wav_info};
+use crate::store::{Annotation, AnnotationStore, Item, Modality, Shape};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LintKind {
+    ZeroAreaBox,
+    OutOfBoundsBox,
+    DegeneratePolygon,
+    SelfIntersectingPolygon,
+    OutOfBoundsPoint,
+    InvalidSegment,
+    SegmentBeyondEnd,
+    UnlabelledItem,
+    ConflictingLabels,
+    UnknownClass,
+    RareClass,
+}
+
+impl LintKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            LintKind::ZeroAreaBox => "zero-area box",
+            LintKind::OutOfBoundsBox => "box out of bounds",
+            LintKind::DegeneratePolygon => "degenerate polygon",
+            LintKind::SelfIntersectingPolygon => "self-intersecting polygon",
+            LintKind::OutOfBoundsPoint => "point out of bounds",
+            LintKind::InvalidSegment => "invalid segment",
+            LintKind::SegmentBeyondEnd => "segment beyond end of file",
+            LintKind::UnlabelledItem => "unlabelled item",
+            LintKind::ConflictingLabels => "conflicting labels",
+            LintKind::UnknownClass => "unknown class",
+            LintKind::RareClass => "rare class",
+        }
+    }
+}
+
+/// A single finding of the lint pass
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LintIssue {
+    pub(crate) kind: LintKind,
+    /// item to jump to in the annotator, `None` for class level issues
+    pub(crate) item_id: Option<u64>,
+    pub(crate) annotation_id: Option<u64>,
+    pub(crate) message: String,
+}
+
+/// Parameters of the lint pass
+#[derive(Debug, Clone)]
+pub(crate) struct LintConfig {
+    /// classes with fewer annotations than this are reported
+    pub(crate) min_examples: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig { min_examples: 10 }
+    }
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// orientation of the triangle (a, b, c): > 0 counter clockwise, < 0 clockwise, 0 collinear
+fn orientation(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn on_segment(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> bool {
+    p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+/// do the closed segments (a, b) and (c, d) touch or cross?
+fn segments_intersect(a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)) -> bool {
+    let o1 = orientation(a, b, c);
+    let o2 = orientation(a, b, d);
+    let o3 = orientation(c, d, a);
+    let o4 = orientation(c, d, b);
+
+    if o1 * o2 < 0.0 && o3 * o4 < 0.0 {
+        return true;
+    }
+    (o1 == 0.0 && on_segment(a, b, c))
+        || (o2 == 0.0 && on_segment(a, b, d))
+        || (o3 == 0.0 && on_segment(c, d, a))
+        || (o4 == 0.0 && on_segment(c, d, b))
+}
+
+/// does any pair of non-adjacent edges of the closed polygon intersect?
+pub(crate) fn is_self_intersecting(points: &[(f32, f32)]) -> bool {
+    let n = points.len();
+    if n < 4 {
+        return false;
+    }
+    let edge = |i: usize| (points[i], points[(i + 1) % n]);
+    for i in 0..n {
+        for j in i + 2..n {
+            // the first and the last edge share a vertex
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            let (a, b) = edge(i);
+            let (c, d) = edge(j);
+            if segments_intersect(a, b, c, d) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn polygon_area(points: &[(f32, f32)]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f32>()
+        .abs()
+        / 2.0
+}
+
+fn issue(kind: LintKind, item: &Item, annotation: Option<&Annotation>, detail: &str) -> LintIssue {
+    LintIssue {
+        kind,
+        item_id: Some(item.id),
+        annotation_id: annotation.map(|a| a.id),
+        message: match annotation {
+            Some(annotation) => format!("{} ({}): {}", item.path, annotation.class, detail),
+            None => format!("{}: {}", item.path, detail),
+        },
+    }
+}
+
+/// checks of a single shape, `size` is the image size or the sound duration (if known)
+fn lint_shape(
+    item: &Item,
+    annotation: &Annotation,
+    image_size: Option<(u32, u32)>,
+    duration: Option<f64>,
+    issues: &mut Vec<LintIssue>,
+) {
+    let inside = |x: f32, y: f32| {
+        image_size.is_none_or(|(w, h)| x >= 0.0 && y >= 0.0 && x <= w as f32 && y <= h as f32)
+    };
+
+    match &annotation.shape {
+        Shape::Label => {}
+        Shape::BoundingBox { x, y, w, h } => {
+            if *w <= 0.0 || *h <= 0.0 {
+                issues.push(issue(
+                    LintKind::ZeroAreaBox,
+                    item,
+                    Some(annotation),
+                    &format!("box {} x {} px", w, h),
+                ));
+            } else if !inside(*x, *y) || !inside(x + w, y + h) {
+                issues.push(issue(
+                    LintKind::OutOfBoundsBox,
+                    item,
+                    Some(annotation),
+                    &format!("box ({}, {}, {}, {}) outside of the image", x, y, w, h),
+                ));
+            }
+        }
+        Shape::Polygon { points } => {
+            // a crossing polygon may have a zero (signed) area, so check crossings first
+            if points.len() >= 3 && is_self_intersecting(points) {
+                issues.push(issue(
+                    LintKind::SelfIntersectingPolygon,
+                    item,
+                    Some(annotation),
+                    "edges of the polygon cross each other",
+                ));
+            } else if points.len() < 3 || polygon_area(points) == 0.0 {
+                issues.push(issue(
+                    LintKind::DegeneratePolygon,
+                    item,
+                    Some(annotation),
+                    &format!("polygon with {} points and no area", points.len()),
+                ));
+            }
+            if points.iter().any(|(x, y)| !inside(*x, *y)) {
+                issues.push(issue(
+                    LintKind::OutOfBoundsPoint,
+                    item,
+                    Some(annotation),
+                    "polygon point outside of the image",
+                ));
+            }
+        }
+        Shape::Keypoint { x, y } => {
+            if !inside(*x, *y) {
+                issues.push(issue(
+                    LintKind::OutOfBoundsPoint,
+                    item,
+                    Some(annotation),
+                    &format!("keypoint ({}, {}) outside of the image", x, y),
+                ));
+            }
+        }
+        Shape::Segment { start, end } => {
+            if *start < 0.0 || end <= start {
+                issues.push(issue(
+                    LintKind::InvalidSegment,
+                    item,
+                    Some(annotation),
+                    &format!("segment {} s .. {} s", start, end),
+                ));
+            } else if let Some(duration) = duration {
+                if *end as f64 > duration {
+                    issues.push(issue(
+                        LintKind::SegmentBeyondEnd,
+                        item,
+                        Some(annotation),
+                        &format!("segment ends at {} s, file is {:.2} s long", end, duration),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// # run all checks over a project
+///
+/// `classes` are the classes defined in the project config, annotations of any other class
+/// are reported as unknown (e.g. classes that were deleted after labelling). With an empty
+/// list this check is skipped.
+///
+/// returns:
+///     all issues, item level issues first (in item order), class level issues last
+pub(crate) fn lint(
+    store: &AnnotationStore,
+    classes: &[String],
+    config: &LintConfig,
+) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let known: BTreeSet<&str> = classes.iter().map(String::as_str).collect();
+
+    for item in store.items() {
+        let annotations = store.annotations_of_item(item.id);
+        if annotations.is_empty() {
+            issues.push(issue(
+                LintKind::UnlabelledItem,
+                item,
+                None,
+                "no annotations",
+            ));
+            continue;
+        }
+
+        // file properties are only read for items that need them
+        let needs_size = annotations
+            .iter()
+            .any(|a| !matches!(a.shape, Shape::Label | Shape::Segment { .. }));
+        let image_size = if item.modality == Modality::Image && needs_size {
+            image_dimensions(Path::new(&item.path))
+        } else {
+            None
+        };
+        let duration = if item.modality == Modality::Sound {
+            wav_info(Path::new(&item.path))
+                .ok()
+                .map(|info| info.duration())
+        } else {
+            None
+        };
+
+        for annotation in &annotations {
+            lint_shape(item, annotation, image_size, duration, &mut issues);
+
+            if !known.is_empty() && !known.contains(annotation.class.as_str()) {
+                issues.push(issue(
+                    LintKind::UnknownClass,
+                    item,
+                    Some(annotation),
+                    "class is not (or no longer) defined in the project",
+                ));
+            }
+        }
+
+        let labels: BTreeSet<&str> = annotations
+            .iter()
+            .filter(|a| a.shape == Shape::Label)
+            .map(|a| a.class.as_str())
+            .collect();
+        if labels.len() > 1 {
+            issues.push(issue(
+                LintKind::ConflictingLabels,
+                item,
+                None,
+                &format!(
+                    "labelled as {}",
+                    labels.into_iter().collect::<Vec<_>>().join(" and ")
+                ),
+            ));
+        }
+    }
+
+    // class level checks, defined classes without any annotation count as zero examples
+    let mut examples: BTreeMap<&str, usize> = known
+        .iter()
+        .filter(|class| **class != BACKGROUND_CLASS)
+        .map(|class| (*class, 0))
+        .collect();
+    for annotation in store.annotations() {
+        *examples.entry(annotation.class.as_str()).or_insert(0) += 1;
+    }
+    for (class, count) in examples {
+        if count < config.min_examples {
+            issues.push(LintIssue {
+                kind: LintKind::RareClass,
+                item_id: None,
+                annotation_id: None,
+                message: format!(
+                    "class '{}' has only {} examples (minimum {})",
+                    class, count, config.min_examples
+                ),
+            });
+        }
+    }
+
+    issues
+}
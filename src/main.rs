@@ -8,9 +8,62 @@ mod workspace;
 use workspace::projects_ui;
 
 mod annotation;
+mod audio;
+mod augment;
+mod baseline;
+mod bayes;
+mod charts;
+mod checkpoint;
+mod class_editor;
+mod classes;
+mod cluster;
+mod clusters;
+mod crossval;
+mod dashboard;
+mod dataset;
+mod dedup;
+mod engine;
+mod experiments;
+mod external;
 mod helper;
-
-use annotation::annotation_ui;
+mod imagebuf;
+mod imageops;
+mod imbalance;
+mod linear;
+mod lint;
+mod media;
+mod metrics;
+mod model;
+mod neighbours;
+mod neural;
+mod nn;
+mod pipeline;
+mod pixbuf;
+mod prediction;
+mod preprocessing;
+mod project;
+mod rng;
+mod runs;
+mod search;
+mod sensor;
+mod snapshot;
+mod splits;
+mod stats;
+mod store;
+mod tabular;
+mod trainer;
+mod training;
+mod trees;
+mod tuning;
+
+use annotation::{annotation_ui, CurrentItem, JumpToItem};
+use prediction::prediction_ui;
+use preprocessing::preprocessing_ui;
+use project::SharedProject;
+use training::training_ui;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
 /// Sets up and runs the main application.
 ///
@@ -54,20 +107,39 @@ fn build_ui(app: &Application) {
     let notebook = Notebook::new();
     window.set_child(Some(&notebook));
 
-    notebook.append_page(&projects_ui(), Some(&Label::new(Some("Projects"))));
-    notebook.append_page(&annotation_ui(), Some(&Label::new(Some("Annotation"))));
+    // the currently opened project, shared by all tabs
+    let project: SharedProject = Rc::new(RefCell::new(None));
+
+    // other tabs (e.g. the lint page) link to items, which are then shown in the annotator
+    let current_item: CurrentItem = Rc::new(Cell::new(None));
+    let annotation_page = annotation_ui(&project, &current_item);
+    let jump_to_item: JumpToItem = Rc::new(
+        gtk::glib::clone!(@weak notebook, @weak annotation_page, @strong current_item => move |item_id: u64| {
+            current_item.set(Some(item_id));
+            notebook.set_current_page(notebook.page_num(&annotation_page));
+        }),
+    );
+
+    notebook.append_page(
+        &projects_ui(&project, &jump_to_item),
+        Some(&Label::new(Some("Projects"))),
+    );
+    notebook.append_page(&annotation_page, Some(&Label::new(Some("Annotation"))));
 
-    let page3_label = Label::new(Some("Preprocessing"));
-    notebook.append_page(&page3_label, Some(&Label::new(Some("Preprocessing"))));
+    notebook.append_page(
+        &preprocessing_ui(&project),
+        Some(&Label::new(Some("Preprocessing"))),
+    );
 
-    let page4_label = Label::new(Some("Training"));
-    notebook.append_page(&page4_label, Some(&Label::new(Some("Training"))));
+    notebook.append_page(&training_ui(&project), Some(&Label::new(Some("Training"))));
 
     let page5_label = Label::new(Some("Postprocessing"));
     notebook.append_page(&page5_label, Some(&Label::new(Some("Postprocessing"))));
 
-    let page6_label = Label::new(Some("Prediction"));
-    notebook.append_page(&page6_label, Some(&Label::new(Some("Prediction"))));
+    notebook.append_page(
+        &prediction_ui(&project),
+        Some(&Label::new(Some("Prediction"))),
+    );
 
     let page7_label = Label::new(Some("Evaluation"));
     notebook.append_page(&page7_label, Some(&Label::new(Some("Evaluation"))));
@@ -0,0 +1,185 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Reading basic properties of media files
+//!
+//! Only the file headers are parsed, so this is cheap enough to run over a whole dataset
+//! (image dimensions, sound duration) without decoding any pixel or sample data.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Format information of a (PCM or IEEE float) `.wav` file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WavInfo {
+    /// 1 = integer PCM, 3 = IEEE float
+    pub(crate) format: u16,
+    pub(crate) channels: u16,
+    pub(crate) sample_rate: u32,
+    pub(crate) bits_per_sample: u16,
+    /// number of samples per channel
+    pub(crate) frames: u64,
+    /// byte offset of the sample data inside the file
+    pub(crate) data_offset: u64,
+}
+
+impl WavInfo {
+    /// length of the recording in seconds
+    pub(crate) fn duration(&self) -> f64 {
+        self.frames as f64 / self.sample_rate.max(1) as f64
+    }
+}
+
+fn read_u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// # width and height of an image
+///
+/// supports PNG, JPEG, GIF and BMP by reading only the file header.
+///
+/// returns:
+///     `None` if the file can not be read or the format is not supported
+pub(crate) fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut header = [0u8; 26];
+    reader.read_exact(&mut header[..4]).ok()?;
+
+    match &header[..4] {
+        [0x89, b'P', b'N', b'G'] => {
+            // signature (8) + chunk length (4) + "IHDR" (4) + width (4) + height (4)
+            reader.read_exact(&mut header[4..24]).ok()?;
+            let width = u32::from_be_bytes(header[16..20].try_into().ok()?);
+            let height = u32::from_be_bytes(header[20..24].try_into().ok()?);
+            Some((width, height))
+        }
+        [b'G', b'I', b'F', b'8'] => {
+            reader.read_exact(&mut header[4..10]).ok()?;
+            Some((
+                read_u16_le(&header[6..]) as u32,
+                read_u16_le(&header[8..]) as u32,
+            ))
+        }
+        [b'B', b'M', ..] => {
+            reader.read_exact(&mut header[4..26]).ok()?;
+            let width = i32::from_le_bytes(header[18..22].try_into().ok()?);
+            let height = i32::from_le_bytes(header[22..26].try_into().ok()?);
+            // a negative height marks a top-down bitmap
+            Some((width.unsigned_abs(), height.unsigned_abs()))
+        }
+        [0xFF, 0xD8, ..] => jpeg_dimensions(&mut reader, [header[2], header[3]]),
+        _ => None,
+    }
+}
+
+/// walk the JPEG segments until the first start-of-frame marker
+fn jpeg_dimensions(reader: &mut BufReader<File>, first: [u8; 2]) -> Option<(u32, u32)> {
+    let mut marker = first;
+    loop {
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // fill bytes in front of a marker
+        while marker[1] == 0xFF {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte).ok()?;
+            marker[1] = byte[0];
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = u16::from_be_bytes(length) as i64;
+
+        match marker[1] {
+            // SOF0 .. SOF15 without DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker[1], 0xC4 | 0xC8 | 0xCC) => {
+                let mut frame = [0u8; 5];
+                reader.read_exact(&mut frame).ok()?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                return Some((width, height));
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length - 2)).ok()?;
+            }
+        }
+
+        reader.read_exact(&mut marker).ok()?;
+    }
+}
+
+/// # parse the header of a `.wav` file
+///
+/// returns:
+///     Result with the format information, an error for anything that is not a
+///     RIFF / WAVE file with a `fmt ` and a `data` chunk
+pub(crate) fn wav_info(path: &Path) -> Result<WavInfo, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(format!("{}: not a RIFF / WAVE file", path.display()).into());
+    }
+
+    let mut format = None;
+    let mut offset = 12u64;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = read_u32_le(&chunk[4..]) as u64;
+        offset += 8;
+
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut fmt = vec![0u8; size as usize];
+                reader.read_exact(&mut fmt)?;
+                if fmt.len() < 16 {
+                    return Err(format!("{}: broken fmt chunk", path.display()).into());
+                }
+                let mut tag = read_u16_le(&fmt[0..]);
+                // WAVE_FORMAT_EXTENSIBLE stores the real format in the sub format GUID
+                if tag == 0xFFFE && fmt.len() >= 26 {
+                    tag = read_u16_le(&fmt[24..]);
+                }
+                format = Some((
+                    tag,
+                    read_u16_le(&fmt[2..]),
+                    read_u32_le(&fmt[4..]),
+                    read_u16_le(&fmt[14..]),
+                ));
+            }
+            b"data" => {
+                let (format, channels, sample_rate, bits_per_sample) = format
+                    .ok_or_else(|| format!("{}: data chunk before fmt chunk", path.display()))?;
+                let frame_size = channels as u64 * (bits_per_sample as u64 / 8);
+                if frame_size == 0 {
+                    return Err(format!("{}: invalid sample format", path.display()).into());
+                }
+                return Ok(WavInfo {
+                    format,
+                    channels,
+                    sample_rate,
+                    bits_per_sample,
+                    frames: size / frame_size,
+                    data_offset: offset,
+                });
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(size as i64))?;
+            }
+        }
+
+        // chunks are padded to an even size
+        offset += size;
+        if size % 2 == 1 {
+            reader.seek(SeekFrom::Current(1))?;
+            offset += 1;
+        }
+    }
+}
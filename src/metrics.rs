@@ -0,0 +1,135 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Evaluation metrics
+//!
+//! Metrics are passed around as a map from name to value, so models, the dashboard and the
+//! run records do not have to agree on a fixed set of metrics.
+
+use std::collections::BTreeMap;
+
+/// metric name -> value, e.g. `accuracy`, `macro_f1`, `loss`, `val_loss`
+pub(crate) type Metrics = BTreeMap<String, f64>;
+
+/// probabilities are clipped to this before taking logarithms
+const PROBABILITY_FLOOR: f64 = 1e-12;
+
+/// index of the largest score (the first one for ties)
+pub(crate) fn argmax(scores: &[f64]) -> usize {
+    scores
+        .iter()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (i, s)| {
+            if *s > best.1 {
+                (i, *s)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+/// probabilities from unnormalised log scores (`-inf` scores get probability 0)
+pub(crate) fn softmax(scores: &[f64]) -> Vec<f64> {
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if !max.is_finite() {
+        return vec![1.0 / scores.len().max(1) as f64; scores.len()];
+    }
+    let exp: Vec<f64> = scores.iter().map(|s| (s - max).exp()).collect();
+    let sum: f64 = exp.iter().sum();
+    exp.iter().map(|e| e / sum).collect()
+}
+
+/// `matrix[truth][predicted]` counts
+pub(crate) fn confusion_matrix(
+    truth: &[usize],
+    predicted: &[usize],
+    classes: usize,
+) -> Vec<Vec<usize>> {
+    let mut matrix = vec![vec![0; classes]; classes];
+    for (t, p) in truth.iter().zip(predicted) {
+        if *t < classes && *p < classes {
+            matrix[*t][*p] += 1;
+        }
+    }
+    matrix
+}
+
+/// fraction of correct predictions
+pub(crate) fn accuracy(truth: &[usize], predicted: &[usize]) -> f64 {
+    if truth.is_empty() {
+        return 0.0;
+    }
+    let correct = truth.iter().zip(predicted).filter(|(t, p)| t == p).count();
+    correct as f64 / truth.len() as f64
+}
+
+/// unweighted mean of the F1 scores of all classes that occur in truth or prediction
+pub(crate) fn macro_f1(confusion: &[Vec<usize>]) -> f64 {
+    let classes = confusion.len();
+    let mut scores = Vec::new();
+    for c in 0..classes {
+        let tp = confusion[c][c] as f64;
+        let actual: usize = confusion[c].iter().sum();
+        let predicted: usize = confusion.iter().map(|row| row[c]).sum();
+        if actual == 0 && predicted == 0 {
+            continue;
+        }
+        scores.push(2.0 * tp / (actual + predicted) as f64);
+    }
+    if scores.is_empty() {
+        return 0.0;
+    }
+    scores.iter().sum::<f64>() / scores.len() as f64
+}
+
+/// mean cross-entropy of the true classes
+pub(crate) fn log_loss(truth: &[usize], probabilities: &[Vec<f64>]) -> f64 {
+    if truth.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = truth
+        .iter()
+        .zip(probabilities)
+        .map(|(t, p)| {
+            -p.get(*t)
+                .copied()
+                .unwrap_or(0.0)
+                .max(PROBABILITY_FLOOR)
+                .ln()
+        })
+        .sum();
+    total / truth.len() as f64
+}
+
+/// # accuracy, macro F1 and loss of class probabilities
+///
+/// returns:
+///     the metrics `accuracy`, `macro_f1` and `loss`
+pub(crate) fn classification_metrics(
+    truth: &[usize],
+    probabilities: &[Vec<f64>],
+    classes: usize,
+) -> Metrics {
+    let predicted: Vec<usize> = probabilities.iter().map(|p| argmax(p)).collect();
+    let confusion = confusion_matrix(truth, &predicted, classes);
+    Metrics::from([
+        ("accuracy".to_string(), accuracy(truth, &predicted)),
+        ("macro_f1".to_string(), macro_f1(&confusion)),
+        ("loss".to_string(), log_loss(truth, probabilities)),
+    ])
+}
+
+/// whether larger values of a metric are better (losses and errors are minimised)
+pub(crate) fn higher_is_better(name: &str) -> bool {
+    !(name.ends_with("loss") || name.ends_with("inertia") || name.ends_with("error"))
+}
+
+/// value of a metric for tables, e.g. `0.9731`
+pub(crate) fn format_metric(value: f64) -> String {
+    if value.abs() >= 1000.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.4}", value)
+    }
+}
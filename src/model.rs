@@ -0,0 +1,760 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Models and the model registry
+//!
+//! Every model implements [`Model`]. The [`registry`] lists all models together with the
+//! problem types and modalities they support, so the Training tab only offers compatible
+//! models and builds their hyperparameter forms from the [`Hyperparameter`] descriptions.
+//!
+//! Models are trained in a background thread. They report the end of every epoch through
+//! the [`FitContext`], which also carries the pause / stop requests of the user and the
+//! checkpoints of the run.
+
+use crate::augment::AugmentationConfig;
+use crate::baseline::{MajorityClass, NearestCentroid};
+use crate::bayes::GaussianNaiveBayes;
+use crate::checkpoint::Checkpointer;
+use crate::cluster::{Agglomerative, Dbscan, GaussianMixture, KMeans, MiniBatchKMeans};
+use crate::dataset::{Dataset, Representation};
+use crate::helper::ProblemType;
+use crate::linear::{LinearSvm, LogisticRegression};
+use crate::metrics::{classification_metrics, Metrics};
+use crate::neighbours::KNearestNeighbours;
+use crate::neural::{Cnn, Mlp};
+use crate::store::Modality;
+use crate::trees::{DecisionTree, GradientBoosting, RandomForest};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// all modalities, for models that work on any feature vectors
+pub(crate) const ALL_MODALITIES: &[Modality] = &[
+    Modality::Image,
+    Modality::Sound,
+    Modality::Sensor,
+    Modality::Tabular,
+];
+
+/// interval of checking whether a paused run was resumed
+const PAUSE_POLL_MS: u64 = 50;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Value of a hyperparameter
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum ParamValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// hyperparameter name -> value
+pub(crate) type Params = BTreeMap<String, ParamValue>;
+
+/// Type and range of a hyperparameter
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ParamKind {
+    Int {
+        min: i64,
+        max: i64,
+    },
+    /// `log` ranges are searched (and best edited) on a logarithmic scale
+    Float {
+        min: f64,
+        max: f64,
+        log: bool,
+    },
+    Choice(&'static [&'static str]),
+    Bool,
+}
+
+/// Description of a hyperparameter, used to build forms and search spaces
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Hyperparameter {
+    pub(crate) name: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) kind: ParamKind,
+    pub(crate) default: ParamValue,
+}
+
+/// Pause / stop requests of the user, shared with the training thread
+#[derive(Debug, Default)]
+pub(crate) struct TrainingControl {
+    stop: AtomicBool,
+    pause: AtomicBool,
+}
+
+/// What a model reports at the end of an epoch (iterative models) or of the fit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct EpochReport {
+    /// 1 based
+    pub(crate) epoch: usize,
+    pub(crate) epochs: usize,
+    /// training metrics of the model (e.g. `loss`) and validation metrics (`val_` prefix)
+    pub(crate) metrics: Metrics,
+    pub(crate) learning_rate: Option<f64>,
+    /// number of samples processed in this epoch
+    pub(crate) samples: usize,
+    /// wall time of the epoch (including the validation), without pauses
+    #[serde(default)]
+    pub(crate) seconds: f64,
+}
+
+/// Everything a model gets besides the data while it is trained
+pub(crate) struct FitContext<'a> {
+    /// samples to train on (indices into the dataset, possibly repeated by resampling)
+    pub(crate) train: Vec<usize>,
+    /// samples evaluated at the end of every epoch
+    pub(crate) val: Vec<usize>,
+    /// loss weight per class (1 for all classes without class weighting)
+    pub(crate) class_weights: Vec<f64>,
+    pub(crate) seed: u64,
+    /// augmentation pipeline of the project, for models training on images
+    pub(crate) augmentation: Option<AugmentationConfig>,
+    pub(crate) control: &'a TrainingControl,
+    /// start of the current epoch, moved forward by the time spent paused
+    pub(crate) epoch_started: Instant,
+    /// checkpoints and best epoch of the run, `None` for runs that keep neither
+    pub(crate) checkpoints: Option<Checkpointer>,
+    pub(crate) report: &'a mut dyn FnMut(EpochReport),
+}
+
+/// constructor of an untrained model, type erased
+type CreateModel = fn(&Params) -> Result<Box<dyn Model>, Box<dyn Error>>;
+/// loader of a trained model, type erased
+type LoadModel = fn(&Path) -> Result<Box<dyn Model>, Box<dyn Error>>;
+
+/// An entry of the [`registry`]
+#[derive(Clone)]
+pub(crate) struct ModelSpec {
+    /// stable identifier, stored in run records
+    pub(crate) id: &'static str,
+    pub(crate) name: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) problem: ProblemType,
+    pub(crate) modalities: &'static [Modality],
+    pub(crate) representation: Representation,
+    pub(crate) hyperparameters: fn() -> Vec<Hyperparameter>,
+    create: CreateModel,
+    load: LoadModel,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// A trainable model
+///
+/// `predict` returns class probabilities for classifiers and cluster memberships (one-hot or
+/// soft) for clustering models, in both cases the index of the largest value is the answer.
+pub(crate) trait Model: Send {
+    /// hyperparameters of the model with their ranges and defaults
+    fn hyperparameters() -> Vec<Hyperparameter>
+    where
+        Self: Sized;
+
+    /// untrained model with the given hyperparameters (complete, see [`resolve_params`])
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized;
+
+    /// train on `context.train`, reporting every epoch with [`FitContext::end_epoch`]
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>>;
+
+    fn predict(&self, x: &[f32]) -> Vec<f64>;
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>>;
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized;
+}
+
+impl ParamValue {
+    pub(crate) fn as_f64(&self) -> f64 {
+        match self {
+            ParamValue::Bool(b) => *b as i64 as f64,
+            ParamValue::Int(i) => *i as f64,
+            ParamValue::Float(f) => *f,
+            ParamValue::Text(t) => t.parse().unwrap_or(0.0),
+        }
+    }
+
+    /// value as shown in forms and tables
+    pub(crate) fn display(&self) -> String {
+        match self {
+            ParamValue::Bool(b) => b.to_string(),
+            ParamValue::Int(i) => i.to_string(),
+            ParamValue::Float(f) => format!("{}", f),
+            ParamValue::Text(t) => t.clone(),
+        }
+    }
+}
+
+impl Hyperparameter {
+    pub(crate) fn int(
+        name: &'static str,
+        description: &'static str,
+        default: i64,
+        min: i64,
+        max: i64,
+    ) -> Hyperparameter {
+        Hyperparameter {
+            name,
+            description,
+            kind: ParamKind::Int { min, max },
+            default: ParamValue::Int(default),
+        }
+    }
+
+    pub(crate) fn float(
+        name: &'static str,
+        description: &'static str,
+        default: f64,
+        min: f64,
+        max: f64,
+        log: bool,
+    ) -> Hyperparameter {
+        Hyperparameter {
+            name,
+            description,
+            kind: ParamKind::Float { min, max, log },
+            default: ParamValue::Float(default),
+        }
+    }
+
+    pub(crate) fn choice(
+        name: &'static str,
+        description: &'static str,
+        default: &'static str,
+        options: &'static [&'static str],
+    ) -> Hyperparameter {
+        Hyperparameter {
+            name,
+            description,
+            kind: ParamKind::Choice(options),
+            default: ParamValue::Text(default.to_string()),
+        }
+    }
+
+    pub(crate) fn bool(
+        name: &'static str,
+        description: &'static str,
+        default: bool,
+    ) -> Hyperparameter {
+        Hyperparameter {
+            name,
+            description,
+            kind: ParamKind::Bool,
+            default: ParamValue::Bool(default),
+        }
+    }
+
+    /// # check a value against the kind and range
+    ///
+    /// returns:
+    ///     Result with the value converted to the kind of the hyperparameter
+    pub(crate) fn check(&self, value: &ParamValue) -> Result<ParamValue, Box<dyn Error>> {
+        let out_of_range = || format!("{} = {} is out of range", self.name, value.display());
+        match (&self.kind, value) {
+            (ParamKind::Int { min, max }, ParamValue::Int(_) | ParamValue::Float(_)) => {
+                let v = value.as_f64().round() as i64;
+                if v < *min || v > *max {
+                    return Err(out_of_range().into());
+                }
+                Ok(ParamValue::Int(v))
+            }
+            (ParamKind::Float { min, max, .. }, ParamValue::Int(_) | ParamValue::Float(_)) => {
+                let v = value.as_f64();
+                if !v.is_finite() || v < *min || v > *max {
+                    return Err(out_of_range().into());
+                }
+                Ok(ParamValue::Float(v))
+            }
+            (ParamKind::Choice(options), ParamValue::Text(t)) if options.contains(&t.as_str()) => {
+                Ok(value.clone())
+            }
+            (ParamKind::Bool, ParamValue::Bool(_)) => Ok(value.clone()),
+            _ => Err(format!("{} = {} has the wrong type", self.name, value.display()).into()),
+        }
+    }
+}
+
+/// # complete and check hyperparameters
+///
+/// missing hyperparameters get their default, unknown ones are an error.
+///
+/// returns:
+///     Result with a value for every hyperparameter
+pub(crate) fn resolve_params(
+    hyperparameters: &[Hyperparameter],
+    params: &Params,
+) -> Result<Params, Box<dyn Error>> {
+    if let Some(unknown) = params
+        .keys()
+        .find(|name| !hyperparameters.iter().any(|h| h.name == name.as_str()))
+    {
+        return Err(format!("unknown hyperparameter {:?}", unknown).into());
+    }
+    hyperparameters
+        .iter()
+        .map(|h| {
+            let value = match params.get(h.name) {
+                Some(value) => h.check(value)?,
+                None => h.default.clone(),
+            };
+            Ok((h.name.to_string(), value))
+        })
+        .collect()
+}
+
+/// typed access to resolved hyperparameters
+pub(crate) fn int_param(params: &Params, name: &str) -> i64 {
+    params.get(name).map_or(0, |v| v.as_f64().round() as i64)
+}
+
+pub(crate) fn float_param(params: &Params, name: &str) -> f64 {
+    params.get(name).map_or(0.0, ParamValue::as_f64)
+}
+
+pub(crate) fn text_param(params: &Params, name: &str) -> String {
+    params
+        .get(name)
+        .map(ParamValue::display)
+        .unwrap_or_default()
+}
+
+pub(crate) fn bool_param(params: &Params, name: &str) -> bool {
+    matches!(params.get(name), Some(ParamValue::Bool(true)))
+}
+
+impl TrainingControl {
+    pub(crate) fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// the stop request as a flag, for code that only knows about cancelling
+    pub(crate) fn stop_flag(&self) -> &AtomicBool {
+        &self.stop
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.pause.store(paused, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.pause.load(Ordering::Relaxed)
+    }
+
+    /// # block the training thread as long as the run is paused (and not stopped)
+    ///
+    /// returns:
+    ///     the time spent waiting
+    pub(crate) fn wait_while_paused(&self) -> Duration {
+        let start = Instant::now();
+        while self.is_paused() && !self.is_stopped() {
+            thread::sleep(Duration::from_millis(PAUSE_POLL_MS));
+        }
+        start.elapsed()
+    }
+}
+
+impl FitContext<'_> {
+    /// # end of an epoch
+    ///
+    /// evaluates the model on the validation samples (unless `metrics` already contains the
+    /// validation metrics), passes the report on and waits while the run is paused.
+    ///
+    /// returns:
+    ///     whether training should go on (false once the user stopped the run)
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn end_epoch(
+        &mut self,
+        data: &Dataset,
+        model: &dyn Model,
+        epoch: usize,
+        epochs: usize,
+        mut metrics: Metrics,
+        learning_rate: Option<f64>,
+        samples: usize,
+    ) -> bool {
+        if !metrics.keys().any(|name| name.starts_with("val_")) {
+            metrics.extend(self.validation_metrics(data, model));
+        }
+        let report = EpochReport {
+            epoch,
+            epochs,
+            metrics,
+            learning_rate,
+            samples,
+            seconds: self.epoch_started.elapsed().as_secs_f64(),
+        };
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.observe(model, &report);
+        }
+        (self.report)(report);
+        let go_on = !self.should_stop();
+        self.epoch_started = Instant::now();
+        go_on
+    }
+
+    /// whether the run continues from a checkpoint, the model was loaded from it
+    pub(crate) fn is_resuming(&self) -> bool {
+        self.checkpoints
+            .as_ref()
+            .is_some_and(Checkpointer::is_resuming)
+    }
+
+    /// # state of the training loop to continue from
+    ///
+    /// returns:
+    ///     Result with the last finished epoch and the state given to
+    ///     [`FitContext::checkpoint`], `None` when the run starts from scratch
+    pub(crate) fn resume<S: DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<(usize, S)>, Box<dyn Error>> {
+        match &mut self.checkpoints {
+            Some(checkpoints) => checkpoints.take_resume(),
+            None => Ok(None),
+        }
+    }
+
+    /// # checkpoint after a completely finished epoch
+    ///
+    /// written every few epochs as configured for the run and always once the user stopped
+    /// it, `state` is everything besides the model the training loop needs to go on. A run
+    /// stopped within an epoch ends with [`FitContext::end_interrupted_epoch`] instead.
+    pub(crate) fn checkpoint<S: Serialize>(
+        &mut self,
+        model: &dyn Model,
+        epoch: usize,
+        state: &S,
+    ) -> Result<(), Box<dyn Error>> {
+        let stopped = self.control.is_stopped();
+        match &mut self.checkpoints {
+            Some(checkpoints) if stopped || checkpoints.due(epoch) => {
+                checkpoints.save(model, epoch, state)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// # end of an epoch the user stopped in between
+    ///
+    /// the partial epoch is neither reported nor considered for the best model. `model` and
+    /// `state` have to be the ones after `last_epoch`, the last finished epoch, which is
+    /// checkpointed (nothing is, if the run stopped within its first epoch).
+    pub(crate) fn end_interrupted_epoch<S: Serialize>(
+        &mut self,
+        model: &dyn Model,
+        last_epoch: usize,
+        state: &S,
+    ) -> Result<(), Box<dyn Error>> {
+        if last_epoch == 0 {
+            return Ok(());
+        }
+        self.checkpoint(model, last_epoch, state)
+    }
+
+    /// metrics of the model on the validation samples, with a `val_` prefix
+    pub(crate) fn validation_metrics(&self, data: &Dataset, model: &dyn Model) -> Metrics {
+        if self.val.is_empty() {
+            return Metrics::new();
+        }
+        evaluate(model, data, &self.val)
+            .into_iter()
+            .map(|(name, value)| (format!("val_{}", name), value))
+            .collect()
+    }
+
+    /// for checks within an epoch (e.g. after every batch), waits while paused
+    pub(crate) fn should_stop(&mut self) -> bool {
+        let paused = self.control.wait_while_paused();
+        self.epoch_started += paused;
+        self.control.is_stopped()
+    }
+
+    /// loss weight of a sample
+    pub(crate) fn weight(&self, data: &Dataset, sample: usize) -> f64 {
+        data.samples[sample]
+            .label
+            .and_then(|label| self.class_weights.get(label).copied())
+            .unwrap_or(1.0)
+    }
+}
+
+/// # classification metrics of a model on some samples
+///
+/// unlabelled samples are ignored.
+pub(crate) fn evaluate(model: &dyn Model, data: &Dataset, indices: &[usize]) -> Metrics {
+    let (truth, probabilities): (Vec<usize>, Vec<Vec<f64>>) = indices
+        .iter()
+        .filter_map(|i| {
+            let sample = &data.samples[*i];
+            Some((sample.label?, model.predict(&sample.x)))
+        })
+        .unzip();
+    classification_metrics(&truth, &probabilities, data.classes.len())
+}
+
+/// write a serialisable model as JSON
+pub(crate) fn save_json<T: Serialize>(path: &Path, model: &T) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string(model)?)?;
+    Ok(())
+}
+
+pub(crate) fn load_json<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn create_boxed<M: Model + 'static>(params: &Params) -> Result<Box<dyn Model>, Box<dyn Error>> {
+    Ok(Box::new(M::new(params)?))
+}
+
+fn load_boxed<M: Model + 'static>(path: &Path) -> Result<Box<dyn Model>, Box<dyn Error>> {
+    Ok(Box::new(M::load(path)?))
+}
+
+impl ModelSpec {
+    /// registry entry of the model type `M`
+    pub(crate) fn of<M: Model + 'static>(
+        id: &'static str,
+        name: &'static str,
+        description: &'static str,
+        problem: ProblemType,
+        modalities: &'static [Modality],
+        representation: Representation,
+    ) -> ModelSpec {
+        ModelSpec {
+            id,
+            name,
+            description,
+            problem,
+            modalities,
+            representation,
+            hyperparameters: M::hyperparameters,
+            create: create_boxed::<M>,
+            load: load_boxed::<M>,
+        }
+    }
+
+    /// # untrained model
+    ///
+    /// returns:
+    ///     Result with the model, an error for invalid hyperparameters
+    pub(crate) fn create(&self, params: &Params) -> Result<Box<dyn Model>, Box<dyn Error>> {
+        let params = resolve_params(&(self.hyperparameters)(), params)?;
+        (self.create)(&params)
+    }
+
+    /// trained model saved with [`Model::save`]
+    pub(crate) fn load(&self, path: &Path) -> Result<Box<dyn Model>, Box<dyn Error>> {
+        (self.load)(path)
+    }
+
+    pub(crate) fn supports(&self, problem: ProblemType, modality: Modality) -> bool {
+        self.problem == problem && self.modalities.contains(&modality)
+    }
+}
+
+/// all available models
+pub(crate) fn registry() -> Vec<ModelSpec> {
+    vec![
+        ModelSpec::of::<MajorityClass>(
+            "majority_class",
+            "Majority class",
+            "always predicts the most frequent training class, the baseline every model has to beat",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<NearestCentroid>(
+            "nearest_centroid",
+            "Nearest centroid",
+            "assigns the class whose mean feature vector is closest",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<LogisticRegression>(
+            "logistic_regression",
+            "Logistic regression",
+            "linear softmax classifier trained with mini-batch gradient descent",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<LinearSvm>(
+            "linear_svm",
+            "Linear SVM",
+            "one-vs-rest linear support vector machine (hinge loss)",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<KNearestNeighbours>(
+            "knn",
+            "k-nearest neighbours",
+            "vote of the closest training samples, no training but slow predictions",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<GaussianNaiveBayes>(
+            "gaussian_naive_bayes",
+            "Naive Bayes",
+            "independent normal distribution of every feature per class",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<DecisionTree>(
+            "decision_tree",
+            "Decision tree",
+            "a single CART tree, easy to inspect but prone to overfitting",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<RandomForest>(
+            "random_forest",
+            "Random forest",
+            "many decorrelated trees grown in parallel on bootstrap samples",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<GradientBoosting>(
+            "gradient_boosting",
+            "Gradient-boosted trees",
+            "shallow trees fitted one after another to the errors of the previous ones",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<Mlp>(
+            "mlp",
+            "Multilayer perceptron",
+            "fully connected neural network on the preprocessed data, trained on the CPU",
+            ProblemType::Classification,
+            ALL_MODALITIES,
+            Representation::Tensor,
+        ),
+        ModelSpec::of::<Cnn>(
+            "cnn",
+            "Small CNN",
+            "convolutional network for images, spectrograms (2D) and sensor windows (1D), \
+             trained on the CPU",
+            ProblemType::Classification,
+            &[Modality::Image, Modality::Sound, Modality::Sensor],
+            Representation::Tensor,
+        ),
+        ModelSpec::of::<KMeans>(
+            "kmeans",
+            "k-means",
+            "k round clusters around their means, started several times with k-means++",
+            ProblemType::Clustering,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<MiniBatchKMeans>(
+            "minibatch_kmeans",
+            "Mini-batch k-means",
+            "k-means updated from small random batches, for large projects",
+            ProblemType::Clustering,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<Dbscan>(
+            "dbscan",
+            "DBSCAN",
+            "clusters of densely packed samples of any shape, isolated samples are noise",
+            ProblemType::Clustering,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<Agglomerative>(
+            "agglomerative",
+            "Agglomerative clustering",
+            "merges the closest clusters bottom-up (at most a few thousand samples)",
+            ProblemType::Clustering,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+        ModelSpec::of::<GaussianMixture>(
+            "gaussian_mixture",
+            "Gaussian mixture",
+            "soft clusters as normal distributions with diagonal covariances, fitted with EM",
+            ProblemType::Clustering,
+            ALL_MODALITIES,
+            Representation::Features,
+        ),
+    ]
+}
+
+pub(crate) fn model_spec(id: &str) -> Option<ModelSpec> {
+    registry().into_iter().find(|spec| spec.id == id)
+}
+
+/// models of the registry usable for a project
+pub(crate) fn compatible_models(problem: ProblemType, modality: Modality) -> Vec<ModelSpec> {
+    registry()
+        .into_iter()
+        .filter(|spec| spec.supports(problem, modality))
+        .collect()
+}
+
+/// # per feature standardisation
+///
+/// most models train much better on features with zero mean and unit variance. models keep
+/// the fitted means and scales, so predictions apply the same transformation.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct Standardiser {
+    pub(crate) mean: Vec<f32>,
+    pub(crate) scale: Vec<f32>,
+}
+
+impl Standardiser {
+    pub(crate) fn fit(data: &Dataset, indices: &[usize]) -> Standardiser {
+        let size = data.input_size();
+        let n = indices.len().max(1) as f64;
+        let mut mean = vec![0.0f64; size];
+        let mut square = vec![0.0f64; size];
+        for i in indices {
+            for (j, v) in data.samples[*i].x.iter().enumerate() {
+                mean[j] += *v as f64;
+                square[j] += (*v as f64) * (*v as f64);
+            }
+        }
+        let mut standardiser = Standardiser::default();
+        for j in 0..size {
+            let m = mean[j] / n;
+            let std = (square[j] / n - m * m).max(0.0).sqrt();
+            standardiser.mean.push(m as f32);
+            // constant features stay constant (zero) instead of exploding
+            standardiser
+                .scale
+                .push(if std > 1e-8 { 1.0 / std as f32 } else { 0.0 });
+        }
+        standardiser
+    }
+
+    pub(crate) fn apply(&self, x: &[f32]) -> Vec<f32> {
+        x.iter()
+            .zip(self.mean.iter().zip(&self.scale))
+            .map(|(v, (m, s))| (v - m) * s)
+            .collect()
+    }
+}
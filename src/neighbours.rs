@@ -0,0 +1,148 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! k-nearest neighbours classifier
+//!
+//! Keeps the (standardised) training samples and votes among the `k` closest ones. The
+//! search is brute force, which is fine for the few thousand samples of typical projects.
+
+use crate::dataset::Dataset;
+use crate::metrics::Metrics;
+use crate::model::{
+    bool_param, int_param, load_json, save_json, text_param, FitContext, Hyperparameter, Model,
+    Params, Standardiser,
+};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+/// ways of weighting the votes of the neighbours
+const VOTE_WEIGHTINGS: &[&str] = &["uniform", "distance"];
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Majority vote of the `k` nearest training samples
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct KNearestNeighbours {
+    k: usize,
+    /// votes weighted by `1 / distance` instead of one vote per neighbour
+    distance_weighted: bool,
+    standardiser: Option<Standardiser>,
+    /// the training inputs (standardised if enabled)
+    inputs: Vec<Vec<f32>>,
+    labels: Vec<usize>,
+    /// weight of the vote of every class (class weights of the training run)
+    vote_weights: Vec<f64>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl KNearestNeighbours {
+    fn transform(&self, x: &[f32]) -> Vec<f32> {
+        match &self.standardiser {
+            Some(standardiser) => standardiser.apply(x),
+            None => x.to_vec(),
+        }
+    }
+}
+
+impl Model for KNearestNeighbours {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int("k", "number of neighbours that vote", 5, 1, 200),
+            Hyperparameter::choice(
+                "weights",
+                "weighting of the votes",
+                "uniform",
+                VOTE_WEIGHTINGS,
+            ),
+            Hyperparameter::bool(
+                "standardise",
+                "scale every feature to zero mean and unit variance first",
+                true,
+            ),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(KNearestNeighbours {
+            k: int_param(params, "k") as usize,
+            distance_weighted: text_param(params, "weights") == "distance",
+            standardiser: bool_param(params, "standardise").then(Standardiser::default),
+            ..KNearestNeighbours::default()
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        if self.standardiser.is_some() {
+            self.standardiser = Some(Standardiser::fit(data, &context.train));
+        }
+        self.inputs.clear();
+        self.labels.clear();
+        for i in &context.train {
+            let sample = &data.samples[*i];
+            if let Some(label) = sample.label {
+                self.inputs.push(self.transform(&sample.x));
+                self.labels.push(label);
+            }
+        }
+        if self.labels.is_empty() {
+            return Err("no labelled training samples".into());
+        }
+        self.vote_weights = (0..data.classes.len())
+            .map(|c| context.class_weights.get(c).copied().unwrap_or(1.0))
+            .collect();
+
+        // the training samples are their own nearest neighbours, only validation is meaningful
+        let samples = self.labels.len();
+        context.end_epoch(data, self, 1, 1, Metrics::new(), None, samples);
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let x = self.transform(x);
+        let mut distances: Vec<(f64, usize)> = self
+            .inputs
+            .iter()
+            .zip(&self.labels)
+            .map(|(input, label)| {
+                let distance = input
+                    .iter()
+                    .zip(&x)
+                    .map(|(a, b)| ((a - b) as f64).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                (distance, *label)
+            })
+            .collect();
+        let k = self.k.clamp(1, distances.len().max(1));
+        if distances.len() > k {
+            distances.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0));
+            distances.truncate(k);
+        }
+
+        let mut votes = vec![0.0; self.vote_weights.len()];
+        for (distance, label) in distances {
+            let vote = if self.distance_weighted {
+                1.0 / distance.max(1e-9)
+            } else {
+                1.0
+            };
+            votes[label] += vote * self.vote_weights[label];
+        }
+        let total: f64 = votes.iter().sum();
+        if total <= 0.0 {
+            return vec![1.0 / votes.len().max(1) as f64; votes.len()];
+        }
+        votes.iter().map(|v| v / total).collect()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
@@ -0,0 +1,652 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Neural network classifiers
+//!
+//! A multilayer perceptron for any tensor data and a small convolutional network for images,
+//! spectrograms (2D convolutions) and sensor windows (1D convolutions), trained on the CPU.
+//! The samples of a mini-batch are spread over all cores. Training images go through the
+//! augmentation pipeline of the project, and with a `patience` the validation loss stops the
+//! training early and the best weights are kept. The weights, the optimiser state and the
+//! state of the training loop go into the checkpoints of the run.
+
+use crate::augment::{AugmentationConfig, Sample, SampleShape};
+use crate::dataset::Dataset;
+use crate::engine::default_threads;
+use crate::imagebuf::ImageBuf;
+use crate::metrics::{softmax, Metrics};
+use crate::model::{
+    float_param, int_param, load_json, save_json, text_param, FitContext, Hyperparameter, Model,
+    Params,
+};
+use crate::nn::{scheduled_rate, Network, NetworkBuilder, Optimiser, OPTIMISERS, SCHEDULES};
+use crate::rng::Rng;
+use crate::store::{Modality, Shape};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+use std::thread;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Optimisation settings shared by all networks
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct TrainSettings {
+    pub(crate) epochs: usize,
+    pub(crate) batch_size: usize,
+    pub(crate) learning_rate: f64,
+    /// one of [`OPTIMISERS`]
+    pub(crate) optimiser: String,
+    pub(crate) momentum: f64,
+    pub(crate) weight_decay: f64,
+    /// one of [`SCHEDULES`]
+    pub(crate) schedule: String,
+    /// epochs without improvement of the validation loss before stopping, 0 to never stop
+    pub(crate) patience: usize,
+}
+
+/// A trained network together with everything needed to feed it
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct NeuralNet {
+    pub(crate) settings: TrainSettings,
+    pub(crate) network: Network,
+    pub(crate) optimiser: Optimiser,
+    /// mean and inverse standard deviation of every input channel
+    pub(crate) scaling: Vec<(f32, f32)>,
+}
+
+/// State of the training loop besides the network, for checkpoints
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LoopState {
+    rng: Rng,
+    /// training samples in the order of the last epoch (shuffled again every epoch)
+    order: Vec<usize>,
+    /// lowest monitored loss so far and the weights of its epoch
+    best: Option<(f64, Vec<f32>)>,
+    epochs_without_improvement: usize,
+}
+
+/// Fully connected layers with ReLU activations and dropout
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Mlp {
+    hidden_layers: usize,
+    units: usize,
+    dropout: f64,
+    net: NeuralNet,
+}
+
+/// Blocks of convolution, ReLU and max pooling, global average pooling and a small head
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Cnn {
+    blocks: usize,
+    filters: usize,
+    kernel_size: usize,
+    dense_units: usize,
+    dropout: f64,
+    net: NeuralNet,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// a model built around a [`NeuralNet`], trained by [`fit_network`]
+trait NetworkModel: Model {
+    fn net(&self) -> &NeuralNet;
+    fn net_mut(&mut self) -> &mut NeuralNet;
+    /// freshly initialised network for inputs of `shape` and `classes` outputs
+    fn build(&self, shape: &[usize], classes: usize, seed: u64) -> Network;
+}
+
+/// hyperparameters of [`TrainSettings`]
+fn training_hyperparameters(epochs: i64, learning_rate: f64) -> Vec<Hyperparameter> {
+    vec![
+        Hyperparameter::int("epochs", "passes over the training data", epochs, 1, 10000),
+        Hyperparameter::int("batch_size", "samples per gradient step", 32, 1, 4096),
+        Hyperparameter::float(
+            "learning_rate",
+            "initial step size of the optimiser",
+            learning_rate,
+            1e-6,
+            1.0,
+            true,
+        ),
+        Hyperparameter::choice("optimiser", "update rule", "adam", OPTIMISERS),
+        Hyperparameter::float("momentum", "momentum of SGD", 0.9, 0.0, 0.999, false),
+        Hyperparameter::float(
+            "weight_decay",
+            "L2 penalty on all parameters",
+            1e-4,
+            0.0,
+            0.1,
+            false,
+        ),
+        Hyperparameter::choice(
+            "schedule",
+            "decay of the learning rate over the epochs",
+            "cosine",
+            SCHEDULES,
+        ),
+        Hyperparameter::int(
+            "patience",
+            "stop after this many epochs without a better validation loss (0: never)",
+            10,
+            0,
+            1000,
+        ),
+    ]
+}
+
+impl TrainSettings {
+    fn from_params(params: &Params) -> TrainSettings {
+        TrainSettings {
+            epochs: int_param(params, "epochs") as usize,
+            batch_size: int_param(params, "batch_size") as usize,
+            learning_rate: float_param(params, "learning_rate"),
+            optimiser: text_param(params, "optimiser"),
+            momentum: float_param(params, "momentum"),
+            weight_decay: float_param(params, "weight_decay"),
+            schedule: text_param(params, "schedule"),
+            patience: int_param(params, "patience") as usize,
+        }
+    }
+}
+
+impl NeuralNet {
+    fn new(params: &Params) -> NeuralNet {
+        NeuralNet {
+            settings: TrainSettings::from_params(params),
+            ..NeuralNet::default()
+        }
+    }
+
+    /// standardise every channel of an input
+    fn scale(&self, x: &[f32]) -> Vec<f32> {
+        let size = x.len() / self.scaling.len().max(1);
+        x.iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let (mean, inverse) = self
+                    .scaling
+                    .get(i / size.max(1))
+                    .copied()
+                    .unwrap_or((0.0, 1.0));
+                (v - mean) * inverse
+            })
+            .collect()
+    }
+
+    fn probabilities(&self, x: &[f32]) -> Vec<f64> {
+        let logits: Vec<f64> = self
+            .network
+            .forward(&self.scale(x), None)
+            .iter()
+            .map(|v| *v as f64)
+            .collect();
+        softmax(&logits)
+    }
+}
+
+/// mean and inverse standard deviation of every channel (first dimension) of the inputs
+fn channel_scaling(data: &Dataset, indices: &[usize], channels: usize) -> Vec<(f32, f32)> {
+    let size = data.input_size() / channels.max(1);
+    (0..channels)
+        .map(|c| {
+            let (mut sum, mut square, mut n) = (0.0f64, 0.0f64, 0.0f64);
+            for i in indices {
+                for v in &data.samples[*i].x[c * size..(c + 1) * size] {
+                    sum += *v as f64;
+                    square += (*v as f64) * (*v as f64);
+                    n += 1.0;
+                }
+            }
+            let mean = sum / n.max(1.0);
+            let std = (square / n.max(1.0) - mean * mean).max(0.0).sqrt();
+            (mean as f32, if std > 1e-8 { 1.0 / std as f32 } else { 1.0 })
+        })
+        .collect()
+}
+
+/// interleaved image of a planar `[channels, height, width]` tensor
+fn tensor_image(x: &[f32], [channels, height, width]: [usize; 3]) -> ImageBuf {
+    let mut image = ImageBuf::new(width, height, channels);
+    for c in 0..channels {
+        for (p, v) in x[c * height * width..(c + 1) * height * width]
+            .iter()
+            .enumerate()
+        {
+            image.data[p * channels + c] = *v;
+        }
+    }
+    image
+}
+
+fn image_tensor(image: &ImageBuf) -> Vec<f32> {
+    let mut tensor = Vec::with_capacity(image.data.len());
+    for c in 0..image.channels {
+        tensor.extend(image.data.iter().skip(c).step_by(image.channels));
+    }
+    tensor
+}
+
+/// # augmented image tensor and its soft target
+///
+/// the class of a sample travels as a whole-image label shape, so mixup blends the targets
+/// with the same weights as the images.
+fn augmented_image(
+    config: &AugmentationConfig,
+    shape: [usize; 3],
+    sample: (&[f32], usize),
+    partner: Option<(&[f32], usize)>,
+    classes: usize,
+    rng: &mut Rng,
+) -> (Vec<f32>, Vec<f32>) {
+    let to_sample = |(x, label): (&[f32], usize)| Sample {
+        image: tensor_image(x, shape),
+        mask: None,
+        shapes: vec![SampleShape {
+            class: label.to_string(),
+            shape: Shape::Label,
+            weight: 1.0,
+        }],
+    };
+    let partner = partner.map(to_sample);
+    let out = config.augment(&to_sample(sample), partner.as_ref(), rng);
+
+    let mut target = vec![0.0; classes];
+    for s in &out.shapes {
+        if let Some(t) = s
+            .class
+            .parse::<usize>()
+            .ok()
+            .and_then(|c| target.get_mut(c))
+        {
+            *t += s.weight;
+        }
+    }
+    let total: f32 = target.iter().sum();
+    if total > 0.0 {
+        target.iter_mut().for_each(|t| *t /= total);
+    } else {
+        target[sample.1] = 1.0;
+    }
+    (image_tensor(&out.image), target)
+}
+
+/// # summed gradient and loss of a batch
+///
+/// the samples are spread over `threads` threads. Every sample has its own seed for dropout
+/// and augmentation, so the result does not depend on the number of threads.
+///
+/// returns:
+///     Result with the gradient and the loss, an error if a thread panicked
+#[allow(clippy::too_many_arguments)]
+fn batch_gradient(
+    net: &NeuralNet,
+    data: &Dataset,
+    batch: &[usize],
+    seeds: &[u64],
+    train: &[usize],
+    augmentation: Option<&AugmentationConfig>,
+    class_weights: &[f64],
+    threads: usize,
+) -> Result<(Vec<f32>, f64), Box<dyn Error>> {
+    let classes = data.classes.len();
+    let shape = net.network.input_shape;
+    let chunk = batch.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| -> Result<(Vec<f32>, f64), Box<dyn Error>> {
+        let handles: Vec<_> = batch
+            .chunks(chunk)
+            .zip(seeds.chunks(chunk))
+            .map(|(samples, seeds)| {
+                scope.spawn(move || {
+                    let mut grads = vec![0.0; net.network.params.len()];
+                    let mut loss = 0.0;
+                    for (i, seed) in samples.iter().zip(seeds) {
+                        let mut rng = Rng::new(*seed);
+                        let sample = &data.samples[*i];
+                        let label = sample.label.unwrap_or(0);
+                        let (x, target) = match augmentation {
+                            Some(config) => {
+                                let partner = &data.samples[train[rng.below(train.len())]];
+                                augmented_image(
+                                    config,
+                                    shape,
+                                    (&sample.x, label),
+                                    Some((&partner.x, partner.label.unwrap_or(0))),
+                                    classes,
+                                    &mut rng,
+                                )
+                            }
+                            None => {
+                                let mut target = vec![0.0; classes];
+                                target[label] = 1.0;
+                                (sample.x.clone(), target)
+                            }
+                        };
+                        let weight = class_weights.get(label).copied().unwrap_or(1.0) as f32;
+                        loss += net.network.accumulate_gradient(
+                            &net.scale(&x),
+                            &target,
+                            weight,
+                            &mut rng,
+                            &mut grads,
+                        );
+                    }
+                    (grads, loss)
+                })
+            })
+            .collect();
+
+        let mut total = (vec![0.0; net.network.params.len()], 0.0);
+        for handle in handles {
+            let (grads, loss) = handle
+                .join()
+                .map_err(|_| "computing the gradient of a batch panicked")?;
+            for (t, g) in total.0.iter_mut().zip(grads) {
+                *t += g;
+            }
+            total.1 += loss;
+        }
+        Ok(total)
+    })
+}
+
+/// # the training loop of all networks
+///
+/// mini-batch training with the optimiser and learning rate schedule of the settings. Every
+/// epoch reports the training loss and the validation metrics; with a patience, the weights
+/// of the epoch with the lowest validation loss are restored at the end. A run resumed from a
+/// checkpoint goes on with the epoch after it.
+fn fit_network<M: NetworkModel>(
+    model: &mut M,
+    data: &Dataset,
+    context: &mut FitContext,
+) -> Result<(), Box<dyn Error>> {
+    let train: Vec<usize> = context
+        .train
+        .iter()
+        .copied()
+        .filter(|i| data.samples[*i].label.is_some())
+        .collect();
+    if train.is_empty() {
+        return Err("no labelled training samples".into());
+    }
+
+    // when resuming, the network, its scaling and the optimiser come with the loaded model
+    let (first_epoch, mut state) = match context.resume::<LoopState>()? {
+        Some((epoch, state)) => {
+            let mut order = state.order.clone();
+            order.sort_unstable();
+            let mut expected = train.clone();
+            expected.sort_unstable();
+            if order != expected {
+                return Err("the training data changed since the checkpoint".into());
+            }
+            (epoch + 1, state)
+        }
+        None => {
+            let network = model.build(&data.shape, data.classes.len(), context.seed);
+            let channels = network.input_shape[0];
+            let net = model.net_mut();
+            net.network = network;
+            net.scaling = channel_scaling(data, &train, channels);
+            net.optimiser = Optimiser::new(
+                &net.settings.optimiser,
+                net.settings.momentum,
+                net.settings.weight_decay,
+            );
+            let state = LoopState {
+                rng: Rng::new(context.seed),
+                order: train.clone(),
+                best: None,
+                epochs_without_improvement: 0,
+            };
+            (1, state)
+        }
+    };
+    let settings = model.net().settings.clone();
+
+    // the augmentation pipeline is designed for images
+    let augmentation = context.augmentation.clone().filter(|config| {
+        data.modality == Modality::Image && data.shape.len() == 3 && !config.steps.is_empty()
+    });
+    let threads = default_threads();
+    let batch_size = settings.batch_size.max(1);
+
+    for epoch in first_epoch..=settings.epochs {
+        // a stop within the epoch goes back to the network and state after the last epoch
+        let finished = (model.net().clone(), state.clone());
+        let rate = scheduled_rate(
+            &settings.schedule,
+            settings.learning_rate,
+            epoch,
+            settings.epochs,
+        );
+        state.rng.shuffle(&mut state.order);
+        let (mut loss, mut samples) = (0.0, 0);
+        let mut interrupted = false;
+        for batch in state.order.chunks(batch_size) {
+            let seeds: Vec<u64> = batch.iter().map(|_| state.rng.next_u64()).collect();
+            let net = model.net_mut();
+            let (mut grads, batch_loss) = batch_gradient(
+                net,
+                data,
+                batch,
+                &seeds,
+                &train,
+                augmentation.as_ref(),
+                &context.class_weights,
+                threads,
+            )?;
+            let n = batch.len() as f32;
+            grads.iter_mut().for_each(|g| *g /= n);
+            net.optimiser.step(&mut net.network.params, &grads, rate);
+            loss += batch_loss;
+            samples += batch.len();
+            if context.should_stop() {
+                interrupted = true;
+                break;
+            }
+        }
+        if interrupted {
+            // continuing a partial epoch would not give the same model, drop it
+            (*model.net_mut(), state) = finished;
+            context.end_interrupted_epoch(&*model, epoch - 1, &state)?;
+            break;
+        }
+
+        let mut metrics = Metrics::from([("loss".to_string(), loss / samples.max(1) as f64)]);
+        metrics.extend(context.validation_metrics(data, &*model));
+        let monitored = metrics
+            .get("val_loss")
+            .or(metrics.get("loss"))
+            .copied()
+            .unwrap_or(f64::INFINITY);
+        if state.best.as_ref().is_none_or(|(b, _)| monitored < *b) {
+            state.best = Some((monitored, model.net().network.params.clone()));
+            state.epochs_without_improvement = 0;
+        } else {
+            state.epochs_without_improvement += 1;
+        }
+        let early_stop =
+            settings.patience > 0 && state.epochs_without_improvement >= settings.patience;
+        let go_on = context.end_epoch(
+            data,
+            &*model,
+            epoch,
+            settings.epochs,
+            metrics,
+            Some(rate),
+            samples,
+        );
+        context.checkpoint(&*model, epoch, &state)?;
+        if !go_on || early_stop {
+            break;
+        }
+    }
+
+    if settings.patience > 0 {
+        if let Some((_, params)) = state.best {
+            model.net_mut().network.params = params;
+        }
+    }
+    Ok(())
+}
+
+impl NetworkModel for Mlp {
+    fn net(&self) -> &NeuralNet {
+        &self.net
+    }
+
+    fn net_mut(&mut self) -> &mut NeuralNet {
+        &mut self.net
+    }
+
+    fn build(&self, shape: &[usize], classes: usize, seed: u64) -> Network {
+        let mut builder = NetworkBuilder::new(shape, seed);
+        for _ in 0..self.hidden_layers {
+            builder = builder.dense(self.units).relu().dropout(self.dropout);
+        }
+        builder.dense(classes).build()
+    }
+}
+
+impl Model for Mlp {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        let mut hyperparameters = vec![
+            Hyperparameter::int("hidden_layers", "number of hidden layers", 2, 0, 10),
+            Hyperparameter::int("units", "neurons per hidden layer", 128, 1, 4096),
+            Hyperparameter::float(
+                "dropout",
+                "dropout rate after every hidden layer",
+                0.2,
+                0.0,
+                0.9,
+                false,
+            ),
+        ];
+        hyperparameters.extend(training_hyperparameters(50, 1e-3));
+        hyperparameters
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(Mlp {
+            hidden_layers: int_param(params, "hidden_layers") as usize,
+            units: int_param(params, "units") as usize,
+            dropout: float_param(params, "dropout"),
+            net: NeuralNet::new(params),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        fit_network(self, data, context)
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        self.net.probabilities(x)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl NetworkModel for Cnn {
+    fn net(&self) -> &NeuralNet {
+        &self.net
+    }
+
+    fn net_mut(&mut self) -> &mut NeuralNet {
+        &mut self.net
+    }
+
+    fn build(&self, shape: &[usize], classes: usize, seed: u64) -> Network {
+        let mut builder = NetworkBuilder::new(shape, seed);
+        for block in 0..self.blocks {
+            builder = builder
+                .conv(self.filters << block, self.kernel_size)
+                .relu()
+                .max_pool(2);
+        }
+        builder = builder.global_average_pool();
+        if self.dense_units > 0 {
+            builder = builder.dense(self.dense_units).relu().dropout(self.dropout);
+        }
+        builder.dense(classes).build()
+    }
+}
+
+impl Model for Cnn {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        let mut hyperparameters = vec![
+            Hyperparameter::int(
+                "blocks",
+                "convolution blocks, every block halves the resolution",
+                3,
+                1,
+                6,
+            ),
+            Hyperparameter::int(
+                "filters",
+                "filters of the first block, doubled in every further block",
+                16,
+                1,
+                256,
+            ),
+            Hyperparameter::choice(
+                "kernel_size",
+                "side length of the kernels",
+                "3",
+                &["3", "5", "7"],
+            ),
+            Hyperparameter::int(
+                "dense_units",
+                "neurons of the hidden dense layer (0: none)",
+                64,
+                0,
+                4096,
+            ),
+            Hyperparameter::float(
+                "dropout",
+                "dropout rate before the output layer",
+                0.3,
+                0.0,
+                0.9,
+                false,
+            ),
+        ];
+        hyperparameters.extend(training_hyperparameters(30, 1e-3));
+        hyperparameters
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(Cnn {
+            blocks: int_param(params, "blocks") as usize,
+            filters: int_param(params, "filters") as usize,
+            kernel_size: text_param(params, "kernel_size").parse()?,
+            dense_units: int_param(params, "dense_units") as usize,
+            dropout: float_param(params, "dropout"),
+            net: NeuralNet::new(params),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        fit_network(self, data, context)
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        self.net.probabilities(x)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
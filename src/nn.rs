@@ -0,0 +1,766 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Neural network building blocks
+//!
+//! A small CPU implementation of the layers, the loss and the optimisers of the neural
+//! network models in [`crate::neural`]. All parameters of a network live in one flat vector,
+//! so optimisers, checkpoints and the gradients summed over several threads all work on plain
+//! slices. Inputs are planar tensors `[channels, height, width]`; 1D data (sensor windows)
+//! has a height of 1, so the same convolution serves images, spectrograms and signals.
+
+use crate::rng::Rng;
+
+use serde::{Deserialize, Serialize};
+
+/// learning rate schedules, see [`scheduled_rate`]
+pub(crate) const SCHEDULES: &[&str] = &["constant", "step", "cosine", "exponential"];
+/// optimisers, see [`Optimiser`]
+pub(crate) const OPTIMISERS: &[&str] = &["adam", "sgd"];
+
+/// probabilities are clipped to this before taking logarithms
+const PROBABILITY_FLOOR: f64 = 1e-12;
+/// first and second moment decay of Adam
+const ADAM_BETAS: (f32, f32) = (0.9, 0.999);
+const ADAM_EPSILON: f32 = 1e-8;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A layer of a [`Network`], the parameters are stored in [`Network::params`] from `offset`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "layer", rename_all = "snake_case")]
+pub(crate) enum Layer {
+    /// fully connected, the weights are `[outputs][inputs]` followed by the biases
+    Dense {
+        inputs: usize,
+        outputs: usize,
+        offset: usize,
+    },
+    /// zero padded ("same") convolution with stride 1, the weights are
+    /// `[filters][channels][kernel_height][kernel_width]` followed by the biases
+    Conv {
+        channels: usize,
+        filters: usize,
+        height: usize,
+        width: usize,
+        kernel_height: usize,
+        kernel_width: usize,
+        offset: usize,
+    },
+    /// maximum of non-overlapping windows, remaining rows / columns are dropped
+    MaxPool {
+        channels: usize,
+        height: usize,
+        width: usize,
+        pool_height: usize,
+        pool_width: usize,
+    },
+    /// mean of every channel
+    GlobalAveragePool {
+        channels: usize,
+        size: usize,
+    },
+    Relu,
+    /// zeroes a `rate` of the activations while training
+    Dropout {
+        rate: f32,
+    },
+}
+
+/// A feed-forward network ending in class scores (logits)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct Network {
+    /// `[channels, height, width]`
+    pub(crate) input_shape: [usize; 3],
+    pub(crate) layers: Vec<Layer>,
+    pub(crate) params: Vec<f32>,
+}
+
+/// Adds layers to a network and keeps track of the shape of the activations
+pub(crate) struct NetworkBuilder {
+    network: Network,
+    shape: [usize; 3],
+    rng: Rng,
+}
+
+/// SGD with momentum or Adam, the state is kept for checkpoints
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct Optimiser {
+    pub(crate) adam: bool,
+    /// momentum of SGD
+    pub(crate) momentum: f32,
+    /// L2 penalty added to the gradients
+    pub(crate) weight_decay: f32,
+    /// velocity of SGD, first moments of Adam
+    first: Vec<f32>,
+    /// second moments of Adam
+    second: Vec<f32>,
+    steps: u64,
+}
+
+/// What a layer remembers of the forward pass for the backward pass
+enum Cache {
+    None,
+    /// position of the maximum of every pooling window
+    Indices(Vec<usize>),
+    /// dropout scale of every activation (0 or `1 / (1 - rate)`)
+    Mask(Vec<f32>),
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Layer {
+    /// number of parameters of the layer
+    fn param_count(&self) -> usize {
+        match self {
+            Layer::Dense {
+                inputs, outputs, ..
+            } => (inputs + 1) * outputs,
+            Layer::Conv {
+                channels,
+                filters,
+                kernel_height,
+                kernel_width,
+                ..
+            } => (channels * kernel_height * kernel_width + 1) * filters,
+            _ => 0,
+        }
+    }
+
+    fn forward(&self, params: &[f32], x: &[f32], rng: Option<&mut Rng>) -> (Vec<f32>, Cache) {
+        match self {
+            Layer::Dense {
+                inputs,
+                outputs,
+                offset,
+            } => {
+                let weights = &params[*offset..offset + inputs * outputs];
+                let bias = &params[offset + inputs * outputs..offset + (inputs + 1) * outputs];
+                let out = weights
+                    .chunks_exact(*inputs)
+                    .zip(bias)
+                    .map(|(w, b)| b + w.iter().zip(x).map(|(w, x)| w * x).sum::<f32>())
+                    .collect();
+                (out, Cache::None)
+            }
+            Layer::Conv {
+                channels,
+                filters,
+                height,
+                width,
+                kernel_height,
+                kernel_width,
+                offset,
+            } => {
+                let (h, w, kh, kw) = (*height, *width, *kernel_height, *kernel_width);
+                let kernel = channels * kh * kw;
+                let bias_offset = offset + kernel * filters;
+                let mut out = vec![0.0; filters * h * w];
+                for f in 0..*filters {
+                    let plane = &mut out[f * h * w..(f + 1) * h * w];
+                    plane.fill(params[bias_offset + f]);
+                    for c in 0..*channels {
+                        let input = &x[c * h * w..(c + 1) * h * w];
+                        for i in 0..kh {
+                            for j in 0..kw {
+                                let weight = params[offset + f * kernel + (c * kh + i) * kw + j];
+                                for_each_tap(h, w, kh, kw, i, j, |out_index, in_index| {
+                                    plane[out_index] += weight * input[in_index];
+                                });
+                            }
+                        }
+                    }
+                }
+                (out, Cache::None)
+            }
+            Layer::MaxPool {
+                channels,
+                height,
+                width,
+                pool_height,
+                pool_width,
+            } => {
+                let (oh, ow) = (height / pool_height, width / pool_width);
+                let mut out = Vec::with_capacity(channels * oh * ow);
+                let mut indices = Vec::with_capacity(channels * oh * ow);
+                for c in 0..*channels {
+                    for oy in 0..oh {
+                        for ox in 0..ow {
+                            let mut best = (f32::NEG_INFINITY, 0);
+                            for y in oy * pool_height..(oy + 1) * pool_height {
+                                for x_ in ox * pool_width..(ox + 1) * pool_width {
+                                    let index = (c * height + y) * width + x_;
+                                    if x[index] > best.0 {
+                                        best = (x[index], index);
+                                    }
+                                }
+                            }
+                            out.push(best.0);
+                            indices.push(best.1);
+                        }
+                    }
+                }
+                (out, Cache::Indices(indices))
+            }
+            Layer::GlobalAveragePool { channels, size } => {
+                let out = (0..*channels)
+                    .map(|c| x[c * size..(c + 1) * size].iter().sum::<f32>() / *size as f32)
+                    .collect();
+                (out, Cache::None)
+            }
+            Layer::Relu => (x.iter().map(|v| v.max(0.0)).collect(), Cache::None),
+            Layer::Dropout { rate } => match rng {
+                Some(rng) if *rate > 0.0 => {
+                    let keep = 1.0 / (1.0 - rate);
+                    let mask: Vec<f32> = x
+                        .iter()
+                        .map(|_| if rng.chance(*rate as f64) { 0.0 } else { keep })
+                        .collect();
+                    (
+                        x.iter().zip(&mask).map(|(v, m)| v * m).collect(),
+                        Cache::Mask(mask),
+                    )
+                }
+                // dropout is the identity at inference time
+                _ => (x.to_vec(), Cache::None),
+            },
+        }
+    }
+
+    /// # backward pass
+    ///
+    /// adds the gradients of the parameters to `grads` (same layout as the parameters).
+    ///
+    /// returns:
+    ///     the gradient with respect to the input `x`
+    fn backward(
+        &self,
+        params: &[f32],
+        x: &[f32],
+        out: &[f32],
+        cache: &Cache,
+        grad: &[f32],
+        grads: &mut [f32],
+    ) -> Vec<f32> {
+        match self {
+            Layer::Dense {
+                inputs,
+                outputs,
+                offset,
+            } => {
+                let mut grad_in = vec![0.0; *inputs];
+                for o in 0..*outputs {
+                    let g = grad[o];
+                    if g == 0.0 {
+                        continue;
+                    }
+                    let row = offset + o * inputs;
+                    for i in 0..*inputs {
+                        grads[row + i] += g * x[i];
+                        grad_in[i] += g * params[row + i];
+                    }
+                    grads[offset + inputs * outputs + o] += g;
+                }
+                grad_in
+            }
+            Layer::Conv {
+                channels,
+                filters,
+                height,
+                width,
+                kernel_height,
+                kernel_width,
+                offset,
+            } => {
+                let (h, w, kh, kw) = (*height, *width, *kernel_height, *kernel_width);
+                let kernel = channels * kh * kw;
+                let bias_offset = offset + kernel * filters;
+                let mut grad_in = vec![0.0; channels * h * w];
+                for f in 0..*filters {
+                    let plane = &grad[f * h * w..(f + 1) * h * w];
+                    grads[bias_offset + f] += plane.iter().sum::<f32>();
+                    for c in 0..*channels {
+                        let input = &x[c * h * w..(c + 1) * h * w];
+                        let input_grad = &mut grad_in[c * h * w..(c + 1) * h * w];
+                        for i in 0..kh {
+                            for j in 0..kw {
+                                let index = offset + f * kernel + (c * kh + i) * kw + j;
+                                let weight = params[index];
+                                let mut weight_grad = 0.0;
+                                for_each_tap(h, w, kh, kw, i, j, |out_index, in_index| {
+                                    weight_grad += plane[out_index] * input[in_index];
+                                    input_grad[in_index] += weight * plane[out_index];
+                                });
+                                grads[index] += weight_grad;
+                            }
+                        }
+                    }
+                }
+                grad_in
+            }
+            Layer::MaxPool { .. } => {
+                let mut grad_in = vec![0.0; x.len()];
+                if let Cache::Indices(indices) = cache {
+                    for (g, index) in grad.iter().zip(indices) {
+                        grad_in[*index] += g;
+                    }
+                }
+                grad_in
+            }
+            Layer::GlobalAveragePool { channels, size } => {
+                let mut grad_in = vec![0.0; channels * size];
+                for c in 0..*channels {
+                    grad_in[c * size..(c + 1) * size].fill(grad[c] / *size as f32);
+                }
+                grad_in
+            }
+            Layer::Relu => grad
+                .iter()
+                .zip(out)
+                .map(|(g, o)| if *o > 0.0 { *g } else { 0.0 })
+                .collect(),
+            Layer::Dropout { .. } => match cache {
+                Cache::Mask(mask) => grad.iter().zip(mask).map(|(g, m)| g * m).collect(),
+                _ => grad.to_vec(),
+            },
+        }
+    }
+}
+
+/// # all (output, input) index pairs of one kernel tap of a "same" convolution
+///
+/// the tap `(i, j)` of a `kh x kw` kernel connects output pixel `(y, x)` with input pixel
+/// `(y + i - kh / 2, x + j - kw / 2)`, pairs outside the input (the zero padding) are skipped.
+fn for_each_tap(
+    h: usize,
+    w: usize,
+    kh: usize,
+    kw: usize,
+    i: usize,
+    j: usize,
+    mut f: impl FnMut(usize, usize),
+) {
+    let (dy, dx) = (
+        i as isize - (kh / 2) as isize,
+        j as isize - (kw / 2) as isize,
+    );
+    let ys = (-dy).max(0) as usize..(h as isize - dy).clamp(0, h as isize) as usize;
+    let xs = (-dx).max(0) as usize..(w as isize - dx).clamp(0, w as isize) as usize;
+    for y in ys {
+        let in_row = (y as isize + dy) as usize * w;
+        for x in xs.clone() {
+            f(y * w + x, in_row + (x as isize + dx) as usize);
+        }
+    }
+}
+
+impl NetworkBuilder {
+    /// start a network for inputs of the given shape (1 to 3 dimensions)
+    pub(crate) fn new(input_shape: &[usize], seed: u64) -> NetworkBuilder {
+        let shape = match input_shape {
+            [c, h, w] => [*c, *h, *w],
+            [c, w] => [*c, 1, *w],
+            [w] => [1, 1, *w],
+            other => [1, 1, other.iter().product()],
+        };
+        NetworkBuilder {
+            network: Network {
+                input_shape: shape,
+                ..Network::default()
+            },
+            shape,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// number of activations after the layers added so far
+    pub(crate) fn size(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// `[channels, height, width]` of the activations after the layers added so far
+    pub(crate) fn shape(&self) -> [usize; 3] {
+        self.shape
+    }
+
+    fn push(&mut self, layer: Layer, fan_in: usize) {
+        let count = layer.param_count();
+        let weights = count - count / (fan_in + 1);
+        // He initialisation for the ReLU activations, zero biases
+        let std = (2.0 / fan_in.max(1) as f64).sqrt();
+        for _ in 0..weights {
+            let value = (self.rng.normal() * std) as f32;
+            self.network.params.push(value);
+        }
+        self.network
+            .params
+            .extend(std::iter::repeat_n(0.0, count - weights));
+        self.network.layers.push(layer);
+    }
+
+    /// fully connected layer (flattens its input)
+    pub(crate) fn dense(mut self, outputs: usize) -> NetworkBuilder {
+        let inputs = self.size();
+        let offset = self.network.params.len();
+        self.push(
+            Layer::Dense {
+                inputs,
+                outputs,
+                offset,
+            },
+            inputs,
+        );
+        self.shape = [1, 1, outputs];
+        self
+    }
+
+    /// convolution, the kernel height is 1 for 1D inputs
+    pub(crate) fn conv(mut self, filters: usize, kernel: usize) -> NetworkBuilder {
+        let [channels, height, width] = self.shape;
+        let kernel_height = if height == 1 { 1 } else { kernel };
+        let offset = self.network.params.len();
+        self.push(
+            Layer::Conv {
+                channels,
+                filters,
+                height,
+                width,
+                kernel_height,
+                kernel_width: kernel,
+                offset,
+            },
+            channels * kernel_height * kernel,
+        );
+        self.shape = [filters, height, width];
+        self
+    }
+
+    /// max pooling by `pool` in both directions (only along the width for 1D inputs), skipped
+    /// once the activations are too small
+    pub(crate) fn max_pool(mut self, pool: usize) -> NetworkBuilder {
+        let [channels, height, width] = self.shape;
+        let pool_height = if height >= pool { pool } else { 1 };
+        let pool_width = if width >= pool { pool } else { 1 };
+        if pool_height * pool_width > 1 {
+            self.network.layers.push(Layer::MaxPool {
+                channels,
+                height,
+                width,
+                pool_height,
+                pool_width,
+            });
+            self.shape = [channels, height / pool_height, width / pool_width];
+        }
+        self
+    }
+
+    pub(crate) fn global_average_pool(mut self) -> NetworkBuilder {
+        let [channels, height, width] = self.shape;
+        self.network.layers.push(Layer::GlobalAveragePool {
+            channels,
+            size: height * width,
+        });
+        self.shape = [1, 1, channels];
+        self
+    }
+
+    pub(crate) fn relu(mut self) -> NetworkBuilder {
+        self.network.layers.push(Layer::Relu);
+        self
+    }
+
+    pub(crate) fn dropout(mut self, rate: f64) -> NetworkBuilder {
+        if rate > 0.0 {
+            self.network.layers.push(Layer::Dropout {
+                rate: rate.min(0.95) as f32,
+            });
+        }
+        self
+    }
+
+    pub(crate) fn build(self) -> Network {
+        self.network
+    }
+}
+
+/// `-ln(p)` of the soft target and the gradient `p - t` of the logits, both times `weight`
+fn cross_entropy(logits: &[f32], target: &[f32], weight: f32) -> (f64, Vec<f32>) {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|z| (z - max).exp()).collect();
+    let total: f32 = exp.iter().sum();
+    let mut loss = 0.0;
+    let gradient = exp
+        .iter()
+        .zip(target)
+        .map(|(e, t)| {
+            let p = e / total;
+            if *t > 0.0 {
+                loss -= *t as f64 * (p as f64).max(PROBABILITY_FLOOR).ln();
+            }
+            weight * (p - t)
+        })
+        .collect();
+    (weight as f64 * loss, gradient)
+}
+
+impl Network {
+    /// the input shape, flattened
+    pub(crate) fn input_size(&self) -> usize {
+        self.input_shape.iter().product()
+    }
+
+    /// class scores of an input, dropout is only applied with a random generator
+    pub(crate) fn forward(&self, x: &[f32], mut rng: Option<&mut Rng>) -> Vec<f32> {
+        self.layers.iter().fold(x.to_vec(), |x, layer| {
+            layer.forward(&self.params, &x, rng.as_deref_mut()).0
+        })
+    }
+
+    /// # loss and gradient of one sample
+    ///
+    /// `target` is a probability distribution over the classes (one-hot or a mixup blend),
+    /// the gradient of the weighted cross-entropy is added to `grads`.
+    ///
+    /// returns:
+    ///     the weighted loss
+    pub(crate) fn accumulate_gradient(
+        &self,
+        x: &[f32],
+        target: &[f32],
+        weight: f32,
+        rng: &mut Rng,
+        grads: &mut [f32],
+    ) -> f64 {
+        let mut activations = vec![x.to_vec()];
+        let mut caches = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let (out, cache) =
+                layer.forward(&self.params, &activations[activations.len() - 1], Some(rng));
+            activations.push(out);
+            caches.push(cache);
+        }
+
+        let (loss, mut grad) = cross_entropy(&activations[self.layers.len()], target, weight);
+        for (i, layer) in self.layers.iter().enumerate().rev() {
+            grad = layer.backward(
+                &self.params,
+                &activations[i],
+                &activations[i + 1],
+                &caches[i],
+                &grad,
+                grads,
+            );
+        }
+        loss
+    }
+}
+
+impl Optimiser {
+    pub(crate) fn new(name: &str, momentum: f64, weight_decay: f64) -> Optimiser {
+        Optimiser {
+            adam: name == "adam",
+            momentum: momentum as f32,
+            weight_decay: weight_decay as f32,
+            ..Optimiser::default()
+        }
+    }
+
+    /// one update of the parameters with the (mean) gradient of a batch
+    pub(crate) fn step(&mut self, params: &mut [f32], grads: &[f32], learning_rate: f64) {
+        if self.first.len() != params.len() {
+            self.first = vec![0.0; params.len()];
+            self.second = if self.adam {
+                vec![0.0; params.len()]
+            } else {
+                Vec::new()
+            };
+            self.steps = 0;
+        }
+        self.steps += 1;
+        let rate = learning_rate as f32;
+
+        if self.adam {
+            let (b1, b2) = ADAM_BETAS;
+            let correction1 = 1.0 - b1.powi(self.steps.min(i32::MAX as u64) as i32);
+            let correction2 = 1.0 - b2.powi(self.steps.min(i32::MAX as u64) as i32);
+            for i in 0..params.len() {
+                let g = grads[i] + self.weight_decay * params[i];
+                self.first[i] = b1 * self.first[i] + (1.0 - b1) * g;
+                self.second[i] = b2 * self.second[i] + (1.0 - b2) * g * g;
+                let m = self.first[i] / correction1;
+                let v = self.second[i] / correction2;
+                params[i] -= rate * m / (v.sqrt() + ADAM_EPSILON);
+            }
+        } else {
+            for i in 0..params.len() {
+                let g = grads[i] + self.weight_decay * params[i];
+                self.first[i] = self.momentum * self.first[i] + g;
+                params[i] -= rate * self.first[i];
+            }
+        }
+    }
+}
+
+/// # learning rate of an epoch (1 based)
+///
+/// - *step*: divided by 10 after every third of the epochs
+/// - *cosine*: cosine decay from the base rate towards zero
+/// - *exponential*: multiplied by 0.95 every epoch
+pub(crate) fn scheduled_rate(schedule: &str, base: f64, epoch: usize, epochs: usize) -> f64 {
+    let done = epoch.saturating_sub(1);
+    match schedule {
+        "step" => base * 0.1f64.powi((done / (epochs / 3).max(1)) as i32),
+        "cosine" => {
+            base * 0.5 * (1.0 + (std::f64::consts::PI * done as f64 / epochs.max(1) as f64).cos())
+        }
+        "exponential" => base * 0.95f64.powi(done as i32),
+        _ => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// step of the central differences
+    const EPSILON: f32 = 1e-2;
+
+    fn random(rng: &mut Rng, n: usize) -> Vec<f32> {
+        (0..n).map(|_| rng.uniform(-1.0, 1.0) as f32).collect()
+    }
+
+    /// analytic and numeric gradient agree up to the f32 rounding of the differences
+    fn assert_close(analytic: &[f32], numeric: &[f32], what: &str) {
+        assert_eq!(analytic.len(), numeric.len(), "{}", what);
+        for (i, (a, n)) in analytic.iter().zip(numeric).enumerate() {
+            let tolerance = 1e-2 * a.abs().max(n.abs()).max(1.0);
+            assert!((a - n).abs() < tolerance, "{}[{}]: {} vs {}", what, i, a, n);
+        }
+    }
+
+    /// central differences of `f` with respect to every value of `values`
+    fn numeric_gradient(values: &[f32], f: impl Fn(&[f32]) -> f64) -> Vec<f32> {
+        let mut values = values.to_vec();
+        (0..values.len())
+            .map(|i| {
+                let original = values[i];
+                values[i] = original + EPSILON;
+                let up = f(&values);
+                values[i] = original - EPSILON;
+                let down = f(&values);
+                values[i] = original;
+                ((up - down) / (2.0 * EPSILON as f64)) as f32
+            })
+            .collect()
+    }
+
+    /// # check the backward pass of a layer
+    ///
+    /// the loss is the dot product of the output with fixed random weights, so the gradient
+    /// of the output is those weights.
+    fn check_layer(layer: &Layer, params: &[f32], x: &[f32], rng: &mut Rng) {
+        let (out, cache) = layer.forward(params, x, None);
+        let weights = random(rng, out.len());
+        let loss = |params: &[f32], x: &[f32]| {
+            let (out, _) = layer.forward(params, x, None);
+            out.iter().zip(&weights).map(|(o, w)| (o * w) as f64).sum()
+        };
+
+        let mut grads = vec![0.0; params.len()];
+        let grad_in = layer.backward(params, x, &out, &cache, &weights, &mut grads);
+        let name = format!("{:?}", layer);
+        assert_close(
+            &grad_in,
+            &numeric_gradient(x, |x| loss(params, x)),
+            &format!("{} input", name),
+        );
+        assert_close(
+            &grads,
+            &numeric_gradient(params, |params| loss(params, x)),
+            &format!("{} params", name),
+        );
+    }
+
+    #[test]
+    fn dense_gradients_match_finite_differences() {
+        let mut rng = Rng::new(1);
+        let layer = Layer::Dense {
+            inputs: 4,
+            outputs: 3,
+            offset: 2,
+        };
+        // the parameters of other layers before the offset get no gradient
+        let params = random(&mut rng, 2 + layer.param_count());
+        let x = random(&mut rng, 4);
+        check_layer(&layer, &params, &x, &mut rng);
+    }
+
+    #[test]
+    fn relu_gradients_match_finite_differences() {
+        let mut rng = Rng::new(2);
+        // away from the kink at 0, the differences are not defined there
+        let x: Vec<f32> = random(&mut rng, 8)
+            .into_iter()
+            .map(|v| v + 0.1 * v.signum())
+            .collect();
+        check_layer(&Layer::Relu, &[], &x, &mut rng);
+    }
+
+    #[test]
+    fn conv_and_pool_gradients_match_finite_differences() {
+        let mut rng = Rng::new(3);
+        let conv = Layer::Conv {
+            channels: 2,
+            filters: 2,
+            height: 4,
+            width: 5,
+            kernel_height: 3,
+            kernel_width: 3,
+            offset: 0,
+        };
+        let params = random(&mut rng, conv.param_count());
+        let x = random(&mut rng, 2 * 4 * 5);
+        check_layer(&conv, &params, &x, &mut rng);
+
+        let pool = Layer::GlobalAveragePool {
+            channels: 2,
+            size: 20,
+        };
+        check_layer(&pool, &[], &x, &mut rng);
+    }
+
+    #[test]
+    fn cross_entropy_gradient_matches_finite_differences() {
+        let mut rng = Rng::new(4);
+        let logits = random(&mut rng, 4);
+        // a mixup blend of two classes
+        let target = [0.7, 0.0, 0.3, 0.0];
+        let (_, gradient) = cross_entropy(&logits, &target, 0.5);
+        let numeric = numeric_gradient(&logits, |logits| cross_entropy(logits, &target, 0.5).0);
+        assert_close(&gradient, &numeric, "cross entropy");
+    }
+
+    #[test]
+    fn network_gradient_matches_finite_differences() {
+        let mut network = NetworkBuilder::new(&[6], 5)
+            .dense(5)
+            .relu()
+            .dense(3)
+            .build();
+        let mut rng = Rng::new(6);
+        network.params = random(&mut rng, network.params.len());
+        let x = random(&mut rng, 6);
+        let target = [0.0, 1.0, 0.0];
+
+        let mut grads = vec![0.0; network.params.len()];
+        network.accumulate_gradient(&x, &target, 1.0, &mut rng, &mut grads);
+        let numeric = numeric_gradient(&network.params, |params| {
+            let network = Network {
+                params: params.to_vec(),
+                ..network.clone()
+            };
+            cross_entropy(&network.forward(&x, None), &target, 1.0).0
+        });
+        assert_close(&grads, &numeric, "network params");
+    }
+}
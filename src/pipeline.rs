@@ -0,0 +1,370 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Preprocessing pipelines of a project
+//!
+//! The pipelines are saved to `<project>/preprocessing.toml`. Training and prediction load
+//! them from there, so both apply exactly the steps that were designed and previewed in the
+//! Preprocessing tab.
+
+use crate::audio::AudioConfig;
+use crate::augment::AugmentationConfig;
+use crate::engine::{cache_file, cache_key};
+use crate::imagebuf::ImageBuf;
+use crate::imageops::{
+    clahe, crop, equalise_histogram, gaussian_blur, median_filter, normalise, pad,
+};
+use crate::imbalance::ImbalanceConfig;
+use crate::pixbuf::load_image;
+use crate::sensor::SensorConfig;
+use crate::tabular::TabularPipeline;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// file inside a project holding the preprocessing pipelines
+pub(crate) const PREPROCESSING_FILE_NAME: &str = "preprocessing.toml";
+
+/// sub directory of the project cache holding the preprocessed images
+pub(crate) const IMAGE_CACHE: &str = "images";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A single step of an image pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum ImageOp {
+    Resize {
+        width: usize,
+        height: usize,
+    },
+    Crop {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    Pad {
+        top: usize,
+        right: usize,
+        bottom: usize,
+        left: usize,
+        value: f64,
+    },
+    Grayscale,
+    /// `(v - mean) / std` per channel (r, g, b)
+    Normalise {
+        mean: [f64; 3],
+        std: [f64; 3],
+    },
+    EqualiseHistogram,
+    Clahe {
+        tiles: usize,
+        clip_limit: f64,
+    },
+    /// denoising with a median filter
+    Median {
+        radius: usize,
+    },
+    /// denoising with a gaussian blur
+    Gaussian {
+        sigma: f64,
+    },
+}
+
+/// Image preprocessing: the steps are applied in order
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct ImagePipeline {
+    #[serde(default)]
+    pub(crate) steps: Vec<ImageOp>,
+}
+
+/// Content of [`PREPROCESSING_FILE_NAME`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct PreprocessingConfig {
+    #[serde(default)]
+    pub(crate) image: ImagePipeline,
+    /// random transformations of training samples (applied after the image pipeline)
+    #[serde(default)]
+    pub(crate) augmentation: AugmentationConfig,
+    /// feature extraction of sound / speech projects
+    #[serde(default)]
+    pub(crate) audio: AudioConfig,
+    /// resampling, filters and windows of sequential sensor projects
+    #[serde(default)]
+    pub(crate) sensor: SensorConfig,
+    /// steps of tabular projects, the fitted transformer is saved next to this file
+    #[serde(default)]
+    pub(crate) tabular: TabularPipeline,
+    /// resampling of the training split and class weights
+    #[serde(default)]
+    pub(crate) imbalance: ImbalanceConfig,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl ImageOp {
+    /// one operation of every kind with default parameters (e.g. for an "add step" menu)
+    pub(crate) fn defaults() -> Vec<ImageOp> {
+        vec![
+            ImageOp::Resize {
+                width: 224,
+                height: 224,
+            },
+            ImageOp::Crop {
+                x: 0,
+                y: 0,
+                width: 224,
+                height: 224,
+            },
+            ImageOp::Pad {
+                top: 8,
+                right: 8,
+                bottom: 8,
+                left: 8,
+                value: 0.0,
+            },
+            ImageOp::Grayscale,
+            // ImageNet statistics
+            ImageOp::Normalise {
+                mean: [0.485, 0.456, 0.406],
+                std: [0.229, 0.224, 0.225],
+            },
+            ImageOp::EqualiseHistogram,
+            ImageOp::Clahe {
+                tiles: 8,
+                clip_limit: 2.0,
+            },
+            ImageOp::Median { radius: 1 },
+            ImageOp::Gaussian { sigma: 1.0 },
+        ]
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ImageOp::Resize { .. } => "resize",
+            ImageOp::Crop { .. } => "crop",
+            ImageOp::Pad { .. } => "pad",
+            ImageOp::Grayscale => "grayscale",
+            ImageOp::Normalise { .. } => "normalise",
+            ImageOp::EqualiseHistogram => "histogram equalisation",
+            ImageOp::Clahe { .. } => "CLAHE",
+            ImageOp::Median { .. } => "denoise (median)",
+            ImageOp::Gaussian { .. } => "denoise (gaussian)",
+        }
+    }
+
+    /// # named numeric parameters
+    ///
+    /// together with [`ImageOp::set_param`] this lets the UI build a form for any step.
+    pub(crate) fn params(&self) -> Vec<(&'static str, f64)> {
+        match self {
+            ImageOp::Resize { width, height } => {
+                vec![("width", *width as f64), ("height", *height as f64)]
+            }
+            ImageOp::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => vec![
+                ("x", *x as f64),
+                ("y", *y as f64),
+                ("width", *width as f64),
+                ("height", *height as f64),
+            ],
+            ImageOp::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                value,
+            } => vec![
+                ("top", *top as f64),
+                ("right", *right as f64),
+                ("bottom", *bottom as f64),
+                ("left", *left as f64),
+                ("value", *value),
+            ],
+            ImageOp::Grayscale | ImageOp::EqualiseHistogram => Vec::new(),
+            ImageOp::Normalise { mean, std } => vec![
+                ("mean r", mean[0]),
+                ("mean g", mean[1]),
+                ("mean b", mean[2]),
+                ("std r", std[0]),
+                ("std g", std[1]),
+                ("std b", std[2]),
+            ],
+            ImageOp::Clahe { tiles, clip_limit } => {
+                vec![("tiles", *tiles as f64), ("clip limit", *clip_limit)]
+            }
+            ImageOp::Median { radius } => vec![("radius", *radius as f64)],
+            ImageOp::Gaussian { sigma } => vec![("sigma", *sigma)],
+        }
+    }
+
+    /// set the parameter at `index` of [`ImageOp::params`], out of range indices are ignored
+    pub(crate) fn set_param(&mut self, index: usize, value: f64) {
+        let count = value.max(0.0).round() as usize;
+        match self {
+            ImageOp::Resize { width, height } => match index {
+                0 => *width = count.max(1),
+                1 => *height = count.max(1),
+                _ => {}
+            },
+            ImageOp::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => match index {
+                0 => *x = count,
+                1 => *y = count,
+                2 => *width = count.max(1),
+                3 => *height = count.max(1),
+                _ => {}
+            },
+            ImageOp::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                value: fill,
+            } => match index {
+                0 => *top = count,
+                1 => *right = count,
+                2 => *bottom = count,
+                3 => *left = count,
+                4 => *fill = value,
+                _ => {}
+            },
+            ImageOp::Grayscale | ImageOp::EqualiseHistogram => {}
+            ImageOp::Normalise { mean, std } => match index {
+                0..=2 => mean[index] = value,
+                3..=5 => std[index - 3] = value,
+                _ => {}
+            },
+            ImageOp::Clahe { tiles, clip_limit } => match index {
+                0 => *tiles = count.max(1),
+                1 => *clip_limit = value.max(1.0),
+                _ => {}
+            },
+            ImageOp::Median { radius } => {
+                if index == 0 {
+                    *radius = count;
+                }
+            }
+            ImageOp::Gaussian { sigma } => {
+                if index == 0 {
+                    *sigma = value.max(0.0);
+                }
+            }
+        }
+    }
+
+    /// short description with parameters, e.g. `resize (width 224, height 224)`
+    pub(crate) fn describe(&self) -> String {
+        let params = self.params();
+        if params.is_empty() {
+            return self.name().to_string();
+        }
+        let params: Vec<String> = params
+            .iter()
+            .map(|(name, value)| format!("{} {}", name, value))
+            .collect();
+        format!("{} ({})", self.name(), params.join(", "))
+    }
+
+    pub(crate) fn apply(&self, image: &ImageBuf) -> ImageBuf {
+        match self {
+            ImageOp::Resize { width, height } => image.resize_area(*width, *height),
+            ImageOp::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => crop(image, *x, *y, *width, *height),
+            ImageOp::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                value,
+            } => pad(image, *top, *right, *bottom, *left, *value as f32),
+            ImageOp::Grayscale => image.to_gray(),
+            ImageOp::Normalise { mean, std } => normalise(image, mean, std),
+            ImageOp::EqualiseHistogram => equalise_histogram(image),
+            ImageOp::Clahe { tiles, clip_limit } => clahe(image, *tiles, *clip_limit),
+            ImageOp::Median { radius } => median_filter(image, *radius),
+            ImageOp::Gaussian { sigma } => gaussian_blur(image, *sigma),
+        }
+    }
+}
+
+impl ImagePipeline {
+    pub(crate) fn apply(&self, image: &ImageBuf) -> ImageBuf {
+        self.steps
+            .iter()
+            .fold(image.clone(), |image, step| step.apply(&image))
+    }
+}
+
+/// # run the image pipeline on an image file, once per content and pipeline
+///
+/// the output is cached as a raw `f32` image (see [`ImageBuf::to_raw`]), so training gets
+/// exactly the values of the pipeline, including the ones outside of 0 to 1 after a
+/// normalisation. It is written to a temporary file first, so an interrupted run never
+/// leaves a truncated image behind that would be taken from the cache later.
+///
+/// returns:
+///     Result with the preprocessed image and whether it came from the cache
+pub(crate) fn cached_pipeline_output(
+    project_dir: &Path,
+    path: &Path,
+    pipeline: &ImagePipeline,
+) -> Result<(ImageBuf, bool), Box<dyn Error>> {
+    let key = cache_key(path, IMAGE_CACHE, pipeline)?;
+    let output = cache_file(project_dir, IMAGE_CACHE, &key, "f32");
+    if let Ok(bytes) = fs::read(&output) {
+        if let Ok(image) = ImageBuf::from_raw(&bytes) {
+            return Ok((image, true));
+        }
+    }
+
+    let image = pipeline.apply(&load_image(path)?);
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = output.with_extension("f32.partial");
+    fs::write(&partial, image.to_raw())?;
+    fs::rename(&partial, &output)?;
+    Ok((image, false))
+}
+
+/// # load the preprocessing config of a project
+///
+/// returns:
+///     Result with the config, the default (empty) config if the project has none yet
+pub(crate) fn load_preprocessing(
+    project_dir: &Path,
+) -> Result<PreprocessingConfig, Box<dyn Error>> {
+    let path = project_dir.join(PREPROCESSING_FILE_NAME);
+    if !path.exists() {
+        return Ok(PreprocessingConfig::default());
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub(crate) fn save_preprocessing(
+    project_dir: &Path,
+    config: &PreprocessingConfig,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        project_dir.join(PREPROCESSING_FILE_NAME),
+        toml::to_string(config)?,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Conversion between gdk-pixbuf / gdk textures and [`ImageBuf`]
+
+use gtk::gdk;
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::prelude::*;
+
+use crate::imagebuf::ImageBuf;
+
+use std::error::Error;
+use std::path::Path;
+
+fn pixbuf_to_image(pixbuf: &Pixbuf) -> ImageBuf {
+    let bytes = pixbuf.read_pixel_bytes();
+    ImageBuf::from_u8(
+        pixbuf.width() as usize,
+        pixbuf.height() as usize,
+        pixbuf.n_channels() as usize,
+        pixbuf.rowstride() as usize,
+        &bytes,
+    )
+}
+
+/// decode an image file (any format supported by gdk-pixbuf)
+pub(crate) fn load_image(path: &Path) -> Result<ImageBuf, Box<dyn Error>> {
+    let pixbuf = Pixbuf::from_file(path)?;
+    Ok(pixbuf_to_image(&pixbuf))
+}
+
+/// decode an image file scaled to exactly `width` x `height` pixels
+///
+/// much faster than [`load_image`] followed by a resize for large photos, since the
+/// decoder can skip most of the data.
+pub(crate) fn load_image_at_size(
+    path: &Path,
+    width: i32,
+    height: i32,
+) -> Result<ImageBuf, Box<dyn Error>> {
+    let pixbuf = Pixbuf::from_file_at_scale(path, width, height, false)?;
+    Ok(pixbuf_to_image(&pixbuf))
+}
+
+/// texture for showing an [`ImageBuf`] in a `gtk::Picture`
+pub(crate) fn image_to_texture(image: &ImageBuf) -> gdk::Texture {
+    let bytes = gtk::glib::Bytes::from_owned(image.to_rgba8());
+    gdk::MemoryTexture::new(
+        image.width as i32,
+        image.height as i32,
+        gdk::MemoryFormat::R8g8b8a8,
+        &bytes,
+        image.width * 4,
+    )
+    .upcast()
+}
+
+/// pixbuf of an [`ImageBuf`], for drawing it with cairo (e.g. below overlays)
+pub(crate) fn image_to_pixbuf(image: &ImageBuf) -> Pixbuf {
+    let bytes = gtk::glib::Bytes::from_owned(image.to_rgba8());
+    Pixbuf::from_bytes(
+        &bytes,
+        Colorspace::Rgb,
+        true,
+        8,
+        image.width as i32,
+        image.height as i32,
+        image.width as i32 * 4,
+    )
+}
@@ -0,0 +1,165 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Prediction tab
+
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::debug_println;
+use crate::helper::show_error_message;
+use crate::project::SharedProject;
+use crate::tabular::{load_fitted_tabular, preview_text, read_table, write_table, Table};
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::Path;
+use std::rc::Rc;
+
+/// number of rows shown in the preview of the transformed table
+const PREVIEW_ROWS: usize = 20;
+
+/// # transform a new table with the fitted tabular transformer of the project
+///
+/// the statistics (means, categories, quantiles, ...) learned in the Preprocessing tab are
+/// reused as they are, so new data is transformed exactly like the training data. New data
+/// needs no label column.
+///
+/// returns:
+///     Result with the transformed table
+fn transform_table(project_dir: &Path, path: &Path) -> Result<Table, Box<dyn Error>> {
+    let fitted = load_fitted_tabular(project_dir)?.ok_or(
+        "the project has no fitted tabular transformer yet, fit one in the Preprocessing tab",
+    )?;
+    let table = read_table(path)?;
+    fitted.transform(&table)
+}
+
+/// Prediction page: apply the preprocessing of the project to new data
+pub fn prediction_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_top(15)
+        .margin_bottom(24)
+        .margin_start(50)
+        .margin_end(50)
+        .spacing(10)
+        .build();
+
+    let transformed: Rc<RefCell<Option<Table>>> = Rc::default();
+
+    // buttons
+    // ---------------------------------------------------------------------------------------------
+    let open_btn = Button::with_label("open table ...");
+    let export_btn = Button::with_label("export preprocessed table ...");
+    export_btn.set_sensitive(false);
+
+    let buttons = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    buttons.append(&open_btn);
+    buttons.append(&export_btn);
+
+    let source_label = Label::builder()
+        .label("no table opened")
+        .halign(gtk::Align::Start)
+        .build();
+
+    // preview
+    // ---------------------------------------------------------------------------------------------
+    let preview_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .valign(gtk::Align::Start)
+        .selectable(true)
+        .build();
+    preview_label.add_css_class("monospace");
+    let preview_window = gtk::ScrolledWindow::builder()
+        .hexpand(true)
+        .vexpand(true)
+        .child(&preview_label)
+        .build();
+
+    open_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong transformed, @strong export_btn, @strong source_label, @strong preview_label => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREDICTION ERROR"),
+                Some("Please open a project first."),
+            );
+            return;
+        };
+
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Open a table")
+            .action(gtk::FileChooserAction::Open)
+            .build();
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Accept),
+        ]);
+
+        dialog.connect_response(gtk::glib::clone!(@strong transformed, @strong export_btn, @strong source_label, @strong preview_label => move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    match transform_table(&dir, &path) {
+                        Ok(table) => {
+                            debug_println!("[INFO: PREDICTION] transformed {} rows of {}", table.rows.len(), path.display());
+                            source_label.set_text(&format!("{} ({} rows)", path.display(), table.rows.len()));
+                            preview_label.set_text(&preview_text(&table, PREVIEW_ROWS));
+                            transformed.replace(Some(table));
+                            export_btn.set_sensitive(true);
+                        }
+                        Err(e) => show_error_message(
+                            None::<&gtk::Widget>,
+                            Some("PREDICTION ERROR"),
+                            Some(&format!("Unable to transform {}:\n{}", path.display(), e)),
+                        ),
+                    }
+                }
+            }
+            dialog.close();
+        }));
+
+        dialog.show();
+    }));
+
+    export_btn.connect_clicked(gtk::glib::clone!(@strong transformed => move |_| {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Export the preprocessed table")
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.set_current_name("preprocessed.csv");
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Export", gtk::ResponseType::Accept),
+        ]);
+
+        dialog.connect_response(gtk::glib::clone!(@strong transformed => move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let (Some(path), Some(table)) = (
+                    dialog.file().and_then(|file| file.path()),
+                    transformed.borrow().as_ref(),
+                ) {
+                    match write_table(&path, table) {
+                        Ok(()) => debug_println!("[INFO: PREDICTION] exported preprocessed table to {}", path.display()),
+                        Err(e) => show_error_message(
+                            None::<&gtk::Widget>,
+                            Some("EXPORT ERROR"),
+                            Some(&format!("Unable to export the table:\n{}", e)),
+                        ),
+                    }
+                }
+            }
+            dialog.close();
+        }));
+
+        dialog.show();
+    }));
+
+    vbox.append(&buttons);
+    vbox.append(&source_label);
+    vbox.append(&preview_window);
+
+    vbox
+}
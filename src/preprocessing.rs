@@ -0,0 +1,2492 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Preprocessing tab
+
+use gtk::cairo;
+use gtk::gdk::prelude::GdkCairoContextExt;
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::audio::{cached_features, AudioConfig, AudioFeatures};
+use crate::augment::{sample_from_annotations, Augment, AugmentationConfig, Sample};
+use crate::charts::{
+    draw_bar_chart, draw_heatmap, draw_signals, draw_waveform, palette_colour, Bar, BarChart, Rgb,
+};
+use crate::classes::{class_colour, class_index, class_names, LabelClass};
+use crate::dataset::DatasetSource;
+use crate::debug_println;
+use crate::engine::{default_threads, start, Run, Task, Work};
+use crate::helper::show_error_message;
+use crate::imagebuf::ImageBuf;
+use crate::imageops::rescale_for_display;
+use crate::imbalance::{
+    class_weights, label_counts, resample_table, target_counts, training_labels, ClassWeighting,
+    ImbalanceConfig, Resampling,
+};
+use crate::media::image_dimensions;
+use crate::pipeline::{
+    cached_pipeline_output, load_preprocessing, save_preprocessing, ImageOp, ImagePipeline,
+};
+use crate::pixbuf::{image_to_pixbuf, image_to_texture, load_image, load_image_at_size};
+use crate::project::SharedProject;
+use crate::sensor::{
+    export_features, preprocess_recording, sliding_windows, GapFill, SensorConfig, SignalFilter,
+    Signals, Window, WindowLabelling,
+};
+use crate::splits::load_splits;
+use crate::store::{Modality, Shape};
+use crate::tabular::{
+    is_missing, load_fitted_tabular, preview_text, read_table, save_fitted_tabular, write_table,
+    DeriveOp, ImputeStrategy, Scaling, Table, TabularPipeline, TabularStep,
+};
+use crate::trainer::{load_training, TrainingConfig};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// larger sample images are scaled down for the preview to keep the UI responsive
+const PREVIEW_MAX_SIZE: u32 = 1024;
+
+/// number of images offered as preview samples
+const SAMPLE_COUNT: usize = 200;
+
+/// size of the images in the augmentation preview grid
+const AUGMENT_PREVIEW_SIZE: u32 = 320;
+/// number of augmented versions shown (in rows of [`AUGMENT_PREVIEW_COLUMNS`])
+const AUGMENT_PREVIEW_COUNT: usize = 9;
+const AUGMENT_PREVIEW_COLUMNS: u32 = 3;
+/// number of other sample images loaded as mixup partners
+const MIXUP_PARTNERS: usize = 3;
+
+/// number of rows shown in the table previews
+const TABLE_PREVIEW_ROWS: usize = 15;
+
+/// interval of polling the progress of a background run
+const PROGRESS_INTERVAL_MS: u64 = 100;
+
+/// load a sample image, scaled down to [`PREVIEW_MAX_SIZE`] if necessary
+///
+/// returns:
+///     Result with the image and whether it was scaled down
+pub(crate) fn load_preview_image(path: &Path) -> Result<(ImageBuf, bool), Box<dyn Error>> {
+    match image_dimensions(path) {
+        Some((width, height)) if width.max(height) > PREVIEW_MAX_SIZE => {
+            let scale = PREVIEW_MAX_SIZE as f64 / width.max(height) as f64;
+            let image = load_image_at_size(
+                path,
+                (width as f64 * scale).round().max(1.0) as i32,
+                (height as f64 * scale).round().max(1.0) as i32,
+            )?;
+            Ok((image, true))
+        }
+        _ => Ok((load_image(path)?, false)),
+    }
+}
+
+/// paths of (up to [`SAMPLE_COUNT`]) items of the given modality of the opened project
+pub(crate) fn sample_items(project: &SharedProject, modality: Modality) -> Vec<String> {
+    project
+        .borrow()
+        .as_ref()
+        .map(|p| {
+            p.store
+                .items_of_modality(modality)
+                .into_iter()
+                .take(SAMPLE_COUNT)
+                .map(|item| item.path.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn preprocessing_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_top(15)
+        .margin_bottom(24)
+        .margin_start(50)
+        .margin_end(50)
+        .spacing(10)
+        .build();
+
+    let stack = gtk::Stack::builder().vexpand(true).build();
+    stack.add_titled(&image_pipeline_ui(project), Some("image"), "Image pipeline");
+    stack.add_titled(
+        &augmentation_ui(project),
+        Some("augmentation"),
+        "Augmentation",
+    );
+    stack.add_titled(&audio_features_ui(project), Some("audio"), "Audio features");
+    stack.add_titled(
+        &sensor_windows_ui(project),
+        Some("sensor"),
+        "Sensor windows",
+    );
+    stack.add_titled(&tabular_ui(project), Some("tabular"), "Tabular");
+    stack.add_titled(&class_balance_ui(project), Some("balance"), "Class balance");
+
+    let switcher = gtk::StackSwitcher::builder()
+        .stack(&stack)
+        .halign(gtk::Align::Start)
+        .build();
+
+    vbox.append(&switcher);
+    vbox.append(&stack);
+    vbox.append(&run_ui(project));
+
+    vbox
+}
+
+/// # tasks running the saved pipelines over all items of the project they apply to
+///
+/// the pipelines are read from `preprocessing.toml`, so unsaved changes of the pages are not
+/// part of a run.
+///
+/// returns:
+///     Result with the tasks, images first
+fn preprocessing_tasks(project: &SharedProject) -> Result<Vec<Task>, Box<dyn Error>> {
+    let project = project.borrow();
+    let project = project.as_ref().ok_or("no project is opened")?;
+    let dir = project.dir().to_path_buf();
+    let config = load_preprocessing(&dir)?;
+    let mut tasks = Vec::new();
+
+    if !config.image.steps.is_empty() {
+        let (image_dir, pipeline) = (dir.clone(), config.image);
+        let work: Work = Arc::new(move |path: &Path| {
+            cached_pipeline_output(&image_dir, path, &pipeline)
+                .map(|(_, cached)| cached)
+                .map_err(|e| e.to_string())
+        });
+        tasks.extend(
+            project
+                .store
+                .items_of_modality(Modality::Image)
+                .into_iter()
+                .map(|item| Task {
+                    input: PathBuf::from(&item.path),
+                    work: work.clone(),
+                }),
+        );
+    }
+
+    let audio = config.audio;
+    let work: Work = Arc::new(move |path: &Path| {
+        cached_features(&dir, path, &audio)
+            .map(|(_, cached)| cached)
+            .map_err(|e| e.to_string())
+    });
+    tasks.extend(
+        project
+            .store
+            .items_of_modality(Modality::Sound)
+            .into_iter()
+            .map(|item| Task {
+                input: PathBuf::from(&item.path),
+                work: work.clone(),
+            }),
+    );
+
+    Ok(tasks)
+}
+
+/// Run bar: executes the saved pipelines over all items in the background
+fn run_ui(project: &SharedProject) -> gtk::Box {
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+
+    let run_btn = Button::with_label("run on all items");
+    let cancel_btn = Button::with_label("cancel");
+    cancel_btn.set_sensitive(false);
+    let threads_spin = gtk::SpinButton::with_range(1.0, 64.0, 1.0);
+    threads_spin.set_value(default_threads() as f64);
+    let progress_bar = gtk::ProgressBar::builder()
+        .show_text(true)
+        .text("not running")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+
+    let run: Rc<RefCell<Option<Run>>> = Rc::default();
+
+    run_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong run, @strong cancel_btn, @strong progress_bar, @strong threads_spin => move |run_btn| {
+        let tasks = match preprocessing_tasks(&project) {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("PREPROCESSING ERROR"),
+                    Some(&format!("Unable to start the preprocessing:\n{}", e)),
+                );
+                return;
+            }
+        };
+        let threads = threads_spin.value() as usize;
+        debug_println!("[INFO: PREPROCESSING] running {} tasks on {} threads", tasks.len(), threads);
+
+        run.replace(Some(start(tasks, threads)));
+        run_btn.set_sensitive(false);
+        cancel_btn.set_sensitive(true);
+        progress_bar.set_fraction(0.0);
+
+        // the workers only send events, the widgets are updated from the main loop
+        gtk::glib::timeout_add_local(
+            Duration::from_millis(PROGRESS_INTERVAL_MS),
+            gtk::glib::clone!(@strong run, @strong run_btn, @strong cancel_btn, @strong progress_bar => move || {
+                let mut guard = run.borrow_mut();
+                let Some(current) = guard.as_mut() else {
+                    return gtk::glib::ControlFlow::Break;
+                };
+                let finished = current.poll();
+                progress_bar.set_fraction(current.progress.fraction());
+                progress_bar.set_text(Some(&current.progress.summary()));
+                if !finished {
+                    return gtk::glib::ControlFlow::Continue;
+                }
+
+                for (input, error) in &current.progress.errors {
+                    debug_println!("[ERROR: PREPROCESSING] {}: {}", input.display(), error);
+                }
+                debug_println!("[INFO: PREPROCESSING] {}", current.progress.summary());
+                run_btn.set_sensitive(true);
+                cancel_btn.set_sensitive(false);
+                gtk::glib::ControlFlow::Break
+            }),
+        );
+    }));
+
+    cancel_btn.connect_clicked(gtk::glib::clone!(@strong run => move |cancel_btn| {
+        if let Some(run) = run.borrow_mut().as_mut() {
+            run.cancel();
+        }
+        cancel_btn.set_sensitive(false);
+    }));
+
+    hbox.append(&run_btn);
+    hbox.append(&cancel_btn);
+    hbox.append(&Label::new(Some("threads")));
+    hbox.append(&threads_spin);
+    hbox.append(&progress_bar);
+
+    hbox
+}
+
+/// Image pipeline editor: chain operations, preview them next to the original, save them
+fn image_pipeline_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let pipeline: Rc<RefCell<ImagePipeline>> = Rc::default();
+    // project directory the pipeline was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let sample: Rc<RefCell<Option<(ImageBuf, bool)>>> = Rc::default();
+
+    // sample image
+    // ---------------------------------------------------------------------------------------------
+    let sample_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    let sample_names = gtk::StringList::new(&[]);
+    let sample_dd = gtk::DropDown::builder()
+        .model(&sample_names)
+        .hexpand(true)
+        .build();
+    sample_box.append(&Label::new(Some("sample image:")));
+    sample_box.append(&sample_dd);
+
+    // steps and parameters
+    // ---------------------------------------------------------------------------------------------
+    let steps_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .build();
+    let steps_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .width_request(320)
+        .vexpand(true)
+        .child(&steps_list)
+        .build();
+
+    let op_names: Vec<&str> = ImageOp::defaults().iter().map(ImageOp::name).collect();
+    let op_dd = gtk::DropDown::from_strings(&op_names);
+    let add_btn = Button::with_label("add step");
+    let remove_btn = Button::with_label("remove");
+    let up_btn = Button::with_label("up");
+    let down_btn = Button::with_label("down");
+
+    let step_buttons = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    step_buttons.append(&op_dd);
+    step_buttons.append(&add_btn);
+    step_buttons.append(&remove_btn);
+    step_buttons.append(&up_btn);
+    step_buttons.append(&down_btn);
+
+    let params_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+
+    let save_btn = Button::with_label("save pipeline to project");
+
+    let editor = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    editor.append(&steps_window);
+    editor.append(&step_buttons);
+    editor.append(&params_grid);
+    editor.append(&save_btn);
+
+    // preview
+    // ---------------------------------------------------------------------------------------------
+    let original_picture = gtk::Picture::builder()
+        .can_shrink(true)
+        .width_request(250)
+        .height_request(250)
+        .hexpand(true)
+        .build();
+    let processed_picture = gtk::Picture::builder()
+        .can_shrink(true)
+        .width_request(250)
+        .height_request(250)
+        .hexpand(true)
+        .build();
+    let preview_label = Label::builder().wrap(true).build();
+
+    let preview_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .hexpand(true)
+        .build();
+    preview_grid.attach(&Label::new(Some("original")), 0, 0, 1, 1);
+    preview_grid.attach(&Label::new(Some("preprocessed")), 1, 0, 1, 1);
+    preview_grid.attach(&original_picture, 0, 1, 1, 1);
+    preview_grid.attach(&processed_picture, 1, 1, 1, 1);
+    preview_grid.attach(&preview_label, 0, 2, 2, 1);
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .build();
+    hbox.append(&editor);
+    hbox.append(&preview_grid);
+
+    let update_preview = gtk::glib::clone!(@strong pipeline, @strong sample => move || {
+        let sample = sample.borrow();
+        let Some((image, scaled)) = sample.as_ref() else {
+            original_picture.set_paintable(None::<&gtk::gdk::Paintable>);
+            processed_picture.set_paintable(None::<&gtk::gdk::Paintable>);
+            preview_label.set_text("no sample image");
+            return;
+        };
+
+        let processed = pipeline.borrow().apply(image);
+        original_picture.set_paintable(Some(&image_to_texture(image)));
+        processed_picture.set_paintable(Some(&image_to_texture(&rescale_for_display(&processed))));
+        preview_label.set_text(&format!(
+            "{} x {} x {} -> {} x {} x {}{}",
+            image.width,
+            image.height,
+            image.channels,
+            processed.width,
+            processed.height,
+            processed.channels,
+            if *scaled {
+                " (preview on a scaled down copy of the image)"
+            } else {
+                ""
+            }
+        ));
+    });
+
+    let refresh_steps = gtk::glib::clone!(@strong pipeline, @strong steps_list => move |selected: Option<usize>| {
+        while let Some(child) = steps_list.first_child() {
+            steps_list.remove(&child);
+        }
+        for step in &pipeline.borrow().steps {
+            steps_list.append(&Label::builder().label(step.describe()).halign(gtk::Align::Start).build());
+        }
+        if let Some(row) = selected.and_then(|i| steps_list.row_at_index(i as i32)) {
+            steps_list.select_row(Some(&row));
+        }
+    });
+
+    // a spin button per parameter of the selected step
+    steps_list.connect_selected_rows_changed(gtk::glib::clone!(@strong pipeline, @strong params_grid, @strong update_preview => move |steps_list| {
+        while let Some(child) = params_grid.first_child() {
+            params_grid.remove(&child);
+        }
+        let Some(row) = steps_list.selected_row() else {
+            return;
+        };
+        let index = row.index() as usize;
+        let Some(step) = pipeline.borrow().steps.get(index).cloned() else {
+            return;
+        };
+
+        for (param, (name, value)) in step.params().into_iter().enumerate() {
+            let spin = gtk::SpinButton::with_range(-10000.0, 10000.0, 1.0);
+            spin.set_digits(3);
+            spin.set_value(value);
+            spin.connect_value_changed(gtk::glib::clone!(@strong pipeline, @strong row, @strong update_preview => move |spin| {
+                let description = {
+                    let mut pipeline = pipeline.borrow_mut();
+                    let Some(step) = pipeline.steps.get_mut(index) else {
+                        return;
+                    };
+                    step.set_param(param, spin.value());
+                    step.describe()
+                };
+                if let Some(label) = row.child().and_downcast::<Label>() {
+                    label.set_text(&description);
+                }
+                update_preview();
+            }));
+            params_grid.attach(&Label::new(Some(name)), 0, param as i32, 1, 1);
+            params_grid.attach(&spin, 1, param as i32, 1, 1);
+        }
+    }));
+
+    let selected_step = gtk::glib::clone!(@strong steps_list => move || {
+        steps_list.selected_row().map(|row| row.index() as usize)
+    });
+
+    add_btn.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview => move |_| {
+        let Some(op) = ImageOp::defaults().into_iter().nth(op_dd.selected() as usize) else {
+            return;
+        };
+        let count = {
+            let mut pipeline = pipeline.borrow_mut();
+            pipeline.steps.push(op);
+            pipeline.steps.len()
+        };
+        refresh_steps(Some(count - 1));
+        update_preview();
+    }));
+
+    remove_btn.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+        let Some(index) = selected_step() else {
+            return;
+        };
+        pipeline.borrow_mut().steps.remove(index);
+        refresh_steps(None);
+        update_preview();
+    }));
+
+    for (button, up) in [(&up_btn, true), (&down_btn, false)] {
+        button.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+            let Some(index) = selected_step() else {
+                return;
+            };
+            let count = pipeline.borrow().steps.len();
+            let other = if up { index.checked_sub(1) } else { Some(index + 1).filter(|i| *i < count) };
+            let Some(other) = other else {
+                return;
+            };
+            pipeline.borrow_mut().steps.swap(index, other);
+            refresh_steps(Some(other));
+            update_preview();
+        }));
+    }
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong pipeline => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Unable to save the pipeline, since no project is opened."),
+            );
+            return;
+        };
+
+        // keep the other sections of the file
+        let result = load_preprocessing(&dir).and_then(|mut config| {
+            config.image = pipeline.borrow().clone();
+            save_preprocessing(&dir, &config)
+        });
+        match result {
+            Ok(()) => debug_println!("[INFO: PREPROCESSING] saved image pipeline to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the pipeline:\n{}", e)),
+            ),
+        }
+    }));
+
+    // loading the sample image and the saved pipeline
+    // ---------------------------------------------------------------------------------------------
+    let sample_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    let load_sample = gtk::glib::clone!(@strong sample_paths, @strong sample, @strong update_preview => move |index: u32| {
+        let path = sample_paths.borrow().get(index as usize).cloned();
+        let loaded = path.and_then(|path| match load_preview_image(Path::new(&path)) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                debug_println!("[ERROR: PREPROCESSING] unable to load {}: {}", path, e);
+                None
+            }
+        });
+        sample.replace(loaded);
+        update_preview();
+    });
+
+    sample_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong load_sample => move |sample_dd| load_sample(sample_dd.selected())),
+    );
+
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong pipeline, @strong refresh_steps, @strong update_preview, @strong sample_dd => move |_| {
+        let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+        if *loaded_from.borrow() != dir {
+            let saved = dir
+                .as_deref()
+                .and_then(|dir| load_preprocessing(dir).ok())
+                .unwrap_or_default();
+            pipeline.replace(saved.image);
+            loaded_from.replace(dir);
+            refresh_steps(None);
+        }
+
+        let paths = sample_items(&project, Modality::Image);
+        if *sample_paths.borrow() != paths {
+            let names: Vec<String> = paths
+                .iter()
+                .map(|p| {
+                    Path::new(p)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| p.clone())
+                })
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            sample_paths.replace(paths);
+            sample_names.splice(0, sample_names.n_items(), &names);
+            load_sample(sample_dd.selected());
+        }
+        update_preview();
+    }));
+
+    vbox.append(&sample_box);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+/// # load an image item as augmentation sample
+///
+/// the image is scaled down to [`AUGMENT_PREVIEW_SIZE`], the annotations of the item are
+/// scaled with it.
+fn load_augment_sample(project: &SharedProject, path: &str) -> Result<Sample, Box<dyn Error>> {
+    let project_ref = project.borrow();
+    let project = project_ref.as_ref().ok_or("no project opened")?;
+    let item = project
+        .store
+        .items()
+        .find(|item| item.path == path)
+        .ok_or_else(|| format!("{} is not an item of the project", path))?;
+
+    let (width, height) = image_dimensions(Path::new(path)).ok_or("unknown image size")?;
+    let scale = (AUGMENT_PREVIEW_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let image = load_image_at_size(
+        Path::new(path),
+        (width as f64 * scale).round().max(1.0) as i32,
+        (height as f64 * scale).round().max(1.0) as i32,
+    )?;
+
+    let annotations = project.store.annotations_of_item(item.id);
+    Ok(sample_from_annotations(
+        image,
+        &annotations,
+        scale as f32,
+        &class_names(&project.config.classes),
+    ))
+}
+
+/// An augmented sample prepared for drawing
+struct AugmentPreview {
+    image: Pixbuf,
+    /// the mask coloured by class, transparent outside of the masked regions
+    mask: Option<Pixbuf>,
+    sample: Sample,
+}
+
+impl AugmentPreview {
+    fn new(sample: Sample, classes: &[LabelClass]) -> AugmentPreview {
+        let mask = sample.mask.as_ref().map(|mask| {
+            let mut coloured = ImageBuf::new(mask.width, mask.height, 4);
+            for y in 0..mask.height {
+                for x in 0..mask.width {
+                    let value = mask.get(x, y, 0);
+                    if value < 1.0 {
+                        continue;
+                    }
+                    let (r, g, b) = class_colour(classes, value as usize - 1);
+                    for (c, v) in [r, g, b, 0.4].into_iter().enumerate() {
+                        coloured.set(x, y, c, v as f32);
+                    }
+                }
+            }
+            image_to_pixbuf(&coloured)
+        });
+        AugmentPreview {
+            image: image_to_pixbuf(&sample.image),
+            mask,
+            sample,
+        }
+    }
+}
+
+/// draw an augmented sample with mask and shapes, scaled to fit `width` x `height`
+fn draw_augment_preview(
+    cr: &cairo::Context,
+    width: f64,
+    height: f64,
+    preview: &AugmentPreview,
+    classes: &[LabelClass],
+) -> Result<(), cairo::Error> {
+    let image = &preview.sample.image;
+    let scale = (width / image.width.max(1) as f64).min(height / image.height.max(1) as f64);
+    cr.translate(
+        (width - image.width as f64 * scale) / 2.0,
+        (height - image.height as f64 * scale) / 2.0,
+    );
+    cr.scale(scale, scale);
+
+    cr.set_source_pixbuf(&preview.image, 0.0, 0.0);
+    cr.paint()?;
+    if let Some(mask) = &preview.mask {
+        cr.set_source_pixbuf(mask, 0.0, 0.0);
+        cr.paint()?;
+    }
+
+    cr.set_line_width(2.0 / scale);
+    for shape in &preview.sample.shapes {
+        let index = class_index(classes, &shape.class).unwrap_or(classes.len());
+        let (r, g, b) = class_colour(classes, index);
+        cr.set_source_rgba(r, g, b, shape.weight as f64);
+        match &shape.shape {
+            Shape::BoundingBox { x, y, w, h } => {
+                cr.rectangle(*x as f64, *y as f64, *w as f64, *h as f64);
+                cr.stroke()?;
+            }
+            Shape::Polygon { points } => {
+                for (x, y) in points {
+                    cr.line_to(*x as f64, *y as f64);
+                }
+                cr.close_path();
+                cr.stroke()?;
+            }
+            Shape::Keypoint { x, y } => {
+                cr.arc(
+                    *x as f64,
+                    *y as f64,
+                    4.0 / scale,
+                    0.0,
+                    std::f64::consts::TAU,
+                );
+                cr.fill()?;
+            }
+            Shape::Label | Shape::Segment { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+/// Augmentation designer: random transformations with a grid of augmented sample versions
+fn augmentation_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let config: Rc<RefCell<AugmentationConfig>> = Rc::default();
+    // project directory the config was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let sample: Rc<RefCell<Option<Sample>>> = Rc::default();
+    let partners: Rc<RefCell<Vec<Sample>>> = Rc::default();
+    let previews: Rc<RefCell<Vec<AugmentPreview>>> = Rc::default();
+    let classes: Rc<RefCell<Vec<LabelClass>>> = Rc::default();
+
+    // sample image and seed
+    // ---------------------------------------------------------------------------------------------
+    let sample_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    let sample_names = gtk::StringList::new(&[]);
+    let sample_dd = gtk::DropDown::builder()
+        .model(&sample_names)
+        .hexpand(true)
+        .build();
+    let seed_spin = gtk::SpinButton::with_range(0.0, u32::MAX as f64, 1.0);
+    sample_box.append(&Label::new(Some("sample image:")));
+    sample_box.append(&sample_dd);
+    sample_box.append(&Label::new(Some("seed:")));
+    sample_box.append(&seed_spin);
+
+    // steps and parameters
+    // ---------------------------------------------------------------------------------------------
+    let steps_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .build();
+    let steps_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .width_request(320)
+        .vexpand(true)
+        .child(&steps_list)
+        .build();
+
+    let op_names: Vec<&str> = Augment::defaults().iter().map(Augment::name).collect();
+    let op_dd = gtk::DropDown::from_strings(&op_names);
+    let add_btn = Button::with_label("add step");
+    let remove_btn = Button::with_label("remove");
+    let up_btn = Button::with_label("up");
+    let down_btn = Button::with_label("down");
+
+    let step_buttons = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    step_buttons.append(&op_dd);
+    step_buttons.append(&add_btn);
+    step_buttons.append(&remove_btn);
+    step_buttons.append(&up_btn);
+    step_buttons.append(&down_btn);
+
+    let params_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+
+    let save_btn = Button::with_label("save augmentation to project");
+
+    let editor = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    editor.append(&steps_window);
+    editor.append(&step_buttons);
+    editor.append(&params_grid);
+    editor.append(&save_btn);
+
+    // preview grid
+    // ---------------------------------------------------------------------------------------------
+    let preview_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(5)
+        .hexpand(true)
+        .build();
+    let mut areas = Vec::with_capacity(AUGMENT_PREVIEW_COUNT);
+    for index in 0..AUGMENT_PREVIEW_COUNT {
+        let area = gtk::DrawingArea::builder()
+            .content_width(200)
+            .content_height(200)
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+        area.set_draw_func(gtk::glib::clone!(@strong previews, @strong classes => move |_, cr, width, height| {
+            if let Some(preview) = previews.borrow().get(index) {
+                if let Err(e) = draw_augment_preview(cr, width as f64, height as f64, preview, &classes.borrow()) {
+                    debug_println!("[ERROR: PREPROCESSING] unable to draw augmentation preview: {}", e);
+                }
+            }
+        }));
+        preview_grid.attach(
+            &area,
+            (index as u32 % AUGMENT_PREVIEW_COLUMNS) as i32,
+            (index as u32 / AUGMENT_PREVIEW_COLUMNS) as i32,
+            1,
+            1,
+        );
+        areas.push(area);
+    }
+    let preview_label = Label::builder().wrap(true).build();
+
+    let preview_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .hexpand(true)
+        .build();
+    preview_box.append(&preview_grid);
+    preview_box.append(&preview_label);
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .build();
+    hbox.append(&editor);
+    hbox.append(&preview_box);
+
+    let update_preview = gtk::glib::clone!(@strong config, @strong sample, @strong partners, @strong previews, @strong classes => move || {
+        let augmented = match sample.borrow().as_ref() {
+            Some(sample) => {
+                config
+                    .borrow()
+                    .preview(sample, &partners.borrow(), AUGMENT_PREVIEW_COUNT)
+            }
+            None => Vec::new(),
+        };
+        preview_label.set_text(if augmented.is_empty() {
+            "no sample image"
+        } else {
+            "augmented versions of the sample (the same seed always gives the same versions)"
+        });
+        let classes = classes.borrow();
+        previews.replace(
+            augmented
+                .into_iter()
+                .map(|sample| AugmentPreview::new(sample, &classes))
+                .collect(),
+        );
+        for area in &areas {
+            area.queue_draw();
+        }
+    });
+
+    seed_spin.connect_value_changed(
+        gtk::glib::clone!(@strong config, @strong update_preview => move |seed_spin| {
+            config.borrow_mut().seed = seed_spin.value() as u64;
+            update_preview();
+        }),
+    );
+
+    let refresh_steps = gtk::glib::clone!(@strong config, @strong steps_list => move |selected: Option<usize>| {
+        while let Some(child) = steps_list.first_child() {
+            steps_list.remove(&child);
+        }
+        for step in &config.borrow().steps {
+            steps_list.append(&Label::builder().label(step.describe()).halign(gtk::Align::Start).build());
+        }
+        if let Some(row) = selected.and_then(|i| steps_list.row_at_index(i as i32)) {
+            steps_list.select_row(Some(&row));
+        }
+    });
+
+    // a spin button per parameter of the selected step
+    steps_list.connect_selected_rows_changed(gtk::glib::clone!(@strong config, @strong params_grid, @strong update_preview => move |steps_list| {
+        while let Some(child) = params_grid.first_child() {
+            params_grid.remove(&child);
+        }
+        let Some(row) = steps_list.selected_row() else {
+            return;
+        };
+        let index = row.index() as usize;
+        let Some(step) = config.borrow().steps.get(index).cloned() else {
+            return;
+        };
+
+        for (param, (name, value)) in step.params().into_iter().enumerate() {
+            let spin = gtk::SpinButton::with_range(0.0, 1000.0, 0.05);
+            spin.set_digits(3);
+            spin.set_value(value);
+            spin.connect_value_changed(gtk::glib::clone!(@strong config, @strong row, @strong update_preview => move |spin| {
+                let description = {
+                    let mut config = config.borrow_mut();
+                    let Some(step) = config.steps.get_mut(index) else {
+                        return;
+                    };
+                    step.set_param(param, spin.value());
+                    step.describe()
+                };
+                if let Some(label) = row.child().and_downcast::<Label>() {
+                    label.set_text(&description);
+                }
+                update_preview();
+            }));
+            params_grid.attach(&Label::new(Some(name)), 0, param as i32, 1, 1);
+            params_grid.attach(&spin, 1, param as i32, 1, 1);
+        }
+    }));
+
+    let selected_step = gtk::glib::clone!(@strong steps_list => move || {
+        steps_list.selected_row().map(|row| row.index() as usize)
+    });
+
+    add_btn.connect_clicked(gtk::glib::clone!(@strong config, @strong refresh_steps, @strong update_preview => move |_| {
+        let Some(op) = Augment::defaults().into_iter().nth(op_dd.selected() as usize) else {
+            return;
+        };
+        let count = {
+            let mut config = config.borrow_mut();
+            config.steps.push(op);
+            config.steps.len()
+        };
+        refresh_steps(Some(count - 1));
+        update_preview();
+    }));
+
+    remove_btn.connect_clicked(gtk::glib::clone!(@strong config, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+        let Some(index) = selected_step() else {
+            return;
+        };
+        config.borrow_mut().steps.remove(index);
+        refresh_steps(None);
+        update_preview();
+    }));
+
+    for (button, up) in [(&up_btn, true), (&down_btn, false)] {
+        button.connect_clicked(gtk::glib::clone!(@strong config, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+            let Some(index) = selected_step() else {
+                return;
+            };
+            let count = config.borrow().steps.len();
+            let other = if up { index.checked_sub(1) } else { Some(index + 1).filter(|i| *i < count) };
+            let Some(other) = other else {
+                return;
+            };
+            config.borrow_mut().steps.swap(index, other);
+            refresh_steps(Some(other));
+            update_preview();
+        }));
+    }
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Unable to save the augmentation, since no project is opened."),
+            );
+            return;
+        };
+
+        // keep the other sections of the file
+        let result = load_preprocessing(&dir).and_then(|mut saved| {
+            saved.augmentation = config.borrow().clone();
+            save_preprocessing(&dir, &saved)
+        });
+        match result {
+            Ok(()) => debug_println!("[INFO: PREPROCESSING] saved augmentation to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the augmentation:\n{}", e)),
+            ),
+        }
+    }));
+
+    // loading the sample, its mixup partners and the saved config
+    // ---------------------------------------------------------------------------------------------
+    let sample_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    let load_sample = gtk::glib::clone!(@strong project, @strong sample_paths, @strong sample, @strong partners, @strong update_preview => move |index: u32| {
+        let paths = sample_paths.borrow();
+        let load = |path: &String| match load_augment_sample(&project, path) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                debug_println!("[ERROR: PREPROCESSING] unable to load {}: {}", path, e);
+                None
+            }
+        };
+        sample.replace(paths.get(index as usize).and_then(&load));
+        // the following samples are blended in by mixup
+        partners.replace(
+            paths
+                .iter()
+                .skip(index as usize + 1)
+                .take(MIXUP_PARTNERS)
+                .filter_map(&load)
+                .collect(),
+        );
+        drop(paths);
+        update_preview();
+    });
+
+    sample_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong load_sample => move |sample_dd| load_sample(sample_dd.selected())),
+    );
+
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong config, @strong classes, @strong refresh_steps, @strong update_preview, @strong sample_dd => move |_| {
+        classes.replace(
+            project
+                .borrow()
+                .as_ref()
+                .map(|p| p.config.classes.clone())
+                .unwrap_or_default(),
+        );
+
+        let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+        if *loaded_from.borrow() != dir {
+            let saved = dir
+                .as_deref()
+                .and_then(|dir| load_preprocessing(dir).ok())
+                .unwrap_or_default();
+            let seed = saved.augmentation.seed;
+            config.replace(saved.augmentation);
+            loaded_from.replace(dir);
+            seed_spin.set_value(seed as f64);
+            refresh_steps(None);
+        }
+
+        let paths = sample_items(&project, Modality::Image);
+        if *sample_paths.borrow() != paths {
+            let names: Vec<String> = paths
+                .iter()
+                .map(|p| {
+                    Path::new(p)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| p.clone())
+                })
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            sample_paths.replace(paths);
+            sample_names.splice(0, sample_names.n_items(), &names);
+            load_sample(sample_dd.selected());
+        }
+        update_preview();
+    }));
+
+    vbox.append(&sample_box);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+/// Audio front end: feature settings, previewed on a sample sound file next to its waveform
+fn audio_features_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let config: Rc<RefCell<AudioConfig>> = Rc::default();
+    // project directory the config was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let features: Rc<RefCell<Option<AudioFeatures>>> = Rc::default();
+
+    // sample sound file
+    // ---------------------------------------------------------------------------------------------
+    let sample_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    let sample_names = gtk::StringList::new(&[]);
+    let sample_dd = gtk::DropDown::builder()
+        .model(&sample_names)
+        .hexpand(true)
+        .build();
+    sample_box.append(&Label::new(Some("sample sound file:")));
+    sample_box.append(&sample_dd);
+
+    // settings
+    // ---------------------------------------------------------------------------------------------
+    let params_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let mono_check = gtk::CheckButton::with_label("mono mixdown");
+    let spins: Vec<gtk::SpinButton> = AudioConfig::default()
+        .params()
+        .into_iter()
+        .enumerate()
+        .map(|(param, (name, _))| {
+            let spin = gtk::SpinButton::with_range(0.0, 192000.0, 1.0);
+            spin.set_digits(if param == 1 { 2 } else { 0 });
+            spin.connect_value_changed(gtk::glib::clone!(@strong config => move |spin| {
+                config.borrow_mut().set_param(param, spin.value());
+            }));
+            params_grid.attach(
+                &Label::builder()
+                    .label(name)
+                    .halign(gtk::Align::Start)
+                    .build(),
+                0,
+                param as i32,
+                1,
+                1,
+            );
+            params_grid.attach(&spin, 1, param as i32, 1, 1);
+            spin
+        })
+        .collect();
+    mono_check.connect_toggled(gtk::glib::clone!(@strong config => move |check| {
+        config.borrow_mut().mono = check.is_active();
+    }));
+
+    let compute_btn = Button::with_label("compute features");
+    let save_btn = Button::with_label("save settings to project");
+    let status_label = Label::builder()
+        .wrap(true)
+        .halign(gtk::Align::Start)
+        .build();
+
+    let editor = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    editor.append(&params_grid);
+    editor.append(&mono_check);
+    editor.append(&compute_btn);
+    editor.append(&save_btn);
+    editor.append(&status_label);
+
+    // waveform and features
+    // ---------------------------------------------------------------------------------------------
+    let plots = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .hexpand(true)
+        .build();
+    let mut areas = Vec::new();
+    for plot in 0..4 {
+        let area = gtk::DrawingArea::builder()
+            .content_height(if plot == 0 { 100 } else { 150 })
+            .hexpand(true)
+            .vexpand(plot != 0)
+            .build();
+        area.set_draw_func(gtk::glib::clone!(@strong features => move |_, cr, width, height| {
+            let features = features.borrow();
+            let Some(features) = features.as_ref() else {
+                return;
+            };
+            let (width, height) = (width as f64, height as f64);
+            let result = match plot {
+                0 => draw_waveform(cr, width, height, &format!("waveform ({:.2} s at {} Hz)", features.duration, features.sample_rate), &features.envelope),
+                1 => draw_heatmap(cr, width, height, "spectrogram [dB]", &features.spectrogram),
+                2 => draw_heatmap(cr, width, height, "log mel energies", &features.mel),
+                _ => draw_heatmap(cr, width, height, "MFCC", &features.mfcc),
+            };
+            if let Err(e) = result {
+                debug_println!("[ERROR: PREPROCESSING] unable to draw audio features: {}", e);
+            }
+        }));
+        plots.append(&area);
+        areas.push(area);
+    }
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .vexpand(true)
+        .build();
+    hbox.append(&editor);
+    hbox.append(&plots);
+
+    // computing (or loading the cached) features of the sample
+    // ---------------------------------------------------------------------------------------------
+    let sample_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    let compute = gtk::glib::clone!(@strong project, @strong config, @strong features, @strong sample_paths, @strong sample_dd, @strong status_label => move || {
+        let path = sample_paths.borrow().get(sample_dd.selected() as usize).cloned();
+        let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+        let (Some(path), Some(dir)) = (path, dir) else {
+            features.replace(None);
+            status_label.set_text("no sound file");
+            for area in &areas {
+                area.queue_draw();
+            }
+            return;
+        };
+
+        let start = std::time::Instant::now();
+        match cached_features(&dir, Path::new(&path), &config.borrow()) {
+            Ok((computed, cached)) => {
+                status_label.set_text(&format!(
+                    "{} frames, {} frequency bins, {} mel bands, {} MFCCs\n{} in {:.0} ms",
+                    computed.spectrogram.len(),
+                    computed.spectrogram.first().map_or(0, Vec::len),
+                    computed.mel.first().map_or(0, Vec::len),
+                    computed.mfcc.first().map_or(0, Vec::len),
+                    if cached { "loaded from the cache" } else { "computed" },
+                    start.elapsed().as_secs_f64() * 1000.0,
+                ));
+                features.replace(Some(computed));
+            }
+            Err(e) => {
+                debug_println!("[ERROR: PREPROCESSING] unable to compute audio features of {}: {}", path, e);
+                status_label.set_text(&format!("unable to compute the features:\n{}", e));
+                features.replace(None);
+            }
+        }
+        for area in &areas {
+            area.queue_draw();
+        }
+    });
+
+    compute_btn.connect_clicked(gtk::glib::clone!(@strong compute => move |_| compute()));
+    sample_dd.connect_selected_notify(gtk::glib::clone!(@strong compute => move |_| compute()));
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Unable to save the audio settings, since no project is opened."),
+            );
+            return;
+        };
+
+        // keep the other sections of the file
+        let result = load_preprocessing(&dir).and_then(|mut saved| {
+            saved.audio = config.borrow().clone();
+            save_preprocessing(&dir, &saved)
+        });
+        match result {
+            Ok(()) => debug_println!("[INFO: PREPROCESSING] saved audio settings to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the audio settings:\n{}", e)),
+            ),
+        }
+    }));
+
+    vbox.connect_map(
+        gtk::glib::clone!(@strong project, @strong config, @strong compute => move |_| {
+            let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+            if *loaded_from.borrow() != dir {
+                let saved = dir
+                    .as_deref()
+                    .and_then(|dir| load_preprocessing(dir).ok())
+                    .unwrap_or_default();
+                for (spin, (_, value)) in spins.iter().zip(saved.audio.params()) {
+                    spin.set_value(value);
+                }
+                mono_check.set_active(saved.audio.mono);
+                // after the widgets, their signal handlers wrote into the config
+                config.replace(saved.audio);
+                loaded_from.replace(dir);
+            }
+
+            let paths = sample_items(&project, Modality::Sound);
+            if *sample_paths.borrow() != paths {
+                let names: Vec<String> = paths
+                    .iter()
+                    .map(|p| {
+                        Path::new(p)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_else(|| p.clone())
+                    })
+                    .collect();
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                sample_paths.replace(paths);
+                sample_names.splice(0, sample_names.n_items(), &names);
+                compute();
+            }
+        }),
+    );
+
+    vbox.append(&sample_box);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+/// labelled windows of every sensor recording of the project
+fn sensor_windows(
+    project: &SharedProject,
+    config: &SensorConfig,
+) -> Result<Vec<(u64, Signals, Vec<Window>)>, Box<dyn Error>> {
+    let project_ref = project.borrow();
+    let project = project_ref.as_ref().ok_or("no project opened")?;
+    project
+        .store
+        .items_of_modality(Modality::Sensor)
+        .into_iter()
+        .map(|item| {
+            let signals = preprocess_recording(Path::new(&item.path), config)
+                .map_err(|e| format!("{}: {}", item.path, e))?;
+            let annotations = project.store.annotations_of_item(item.id);
+            let windows = sliding_windows(&signals, &annotations, config);
+            Ok((item.id, signals, windows))
+        })
+        .collect()
+}
+
+/// Sensor preprocessing: resampling, filters and labelled sliding windows with a feature export
+fn sensor_windows_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    // project directory the config was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    // processed sample recording with its windows and annotated intervals
+    let preview: Rc<RefCell<Option<(Signals, Vec<Window>, Vec<(f64, f64, Rgb)>)>>> = Rc::default();
+
+    // sample recording
+    // ---------------------------------------------------------------------------------------------
+    let sample_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    let sample_names = gtk::StringList::new(&[]);
+    let sample_dd = gtk::DropDown::builder()
+        .model(&sample_names)
+        .hexpand(true)
+        .build();
+    sample_box.append(&Label::new(Some("sample recording:")));
+    sample_box.append(&sample_dd);
+
+    // settings
+    // ---------------------------------------------------------------------------------------------
+    let spin = |min: f64, max: f64, step: f64, digits: u32| {
+        let spin = gtk::SpinButton::with_range(min, max, step);
+        spin.set_digits(digits);
+        spin
+    };
+    let rate_spin = spin(0.1, 100000.0, 1.0, 1);
+    let gap_fill_names: Vec<&str> = GapFill::ALL.iter().map(GapFill::name).collect();
+    let gap_fill_dd = gtk::DropDown::from_strings(&gap_fill_names);
+    let max_gap_spin = spin(0.0, 3600.0, 0.01, 3);
+    let filter_dd =
+        gtk::DropDown::from_strings(&["no filter", "low-pass", "high-pass", "band-pass"]);
+    let low_spin = spin(0.0, 50000.0, 0.1, 2);
+    let high_spin = spin(0.0, 50000.0, 0.1, 2);
+    let normalise_check = gtk::CheckButton::with_label("z-score per channel");
+    let window_spin = spin(0.01, 3600.0, 0.1, 2);
+    let overlap_spin = spin(0.0, 0.99, 0.05, 2);
+    let labelling_names: Vec<&str> = WindowLabelling::ALL
+        .iter()
+        .map(WindowLabelling::name)
+        .collect();
+    let labelling_dd = gtk::DropDown::from_strings(&labelling_names);
+    let mean_check = gtk::CheckButton::with_label("mean");
+    let std_check = gtk::CheckButton::with_label("std");
+    let rms_check = gtk::CheckButton::with_label("RMS");
+    let bands_spin = spin(0.0, 64.0, 1.0, 0);
+
+    let feature_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    feature_box.append(&mean_check);
+    feature_box.append(&std_check);
+    feature_box.append(&rms_check);
+
+    let settings_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let rows: [(&str, &gtk::Widget); 12] = [
+        ("sample rate [Hz]", rate_spin.upcast_ref()),
+        ("gap filling", gap_fill_dd.upcast_ref()),
+        ("interpolate gaps up to [s]", max_gap_spin.upcast_ref()),
+        ("filter", filter_dd.upcast_ref()),
+        ("high-pass cutoff [Hz]", low_spin.upcast_ref()),
+        ("low-pass cutoff [Hz]", high_spin.upcast_ref()),
+        ("normalisation", normalise_check.upcast_ref()),
+        ("window [s]", window_spin.upcast_ref()),
+        ("overlap", overlap_spin.upcast_ref()),
+        ("window label", labelling_dd.upcast_ref()),
+        ("features", feature_box.upcast_ref()),
+        ("FFT band energies", bands_spin.upcast_ref()),
+    ];
+    for (row, (name, widget)) in rows.iter().enumerate() {
+        settings_grid.attach(
+            &Label::builder()
+                .label(*name)
+                .halign(gtk::Align::Start)
+                .build(),
+            0,
+            row as i32,
+            1,
+            1,
+        );
+        settings_grid.attach(*widget, 1, row as i32, 1, 1);
+    }
+
+    let read_config = gtk::glib::clone!(@strong rate_spin, @strong gap_fill_dd, @strong max_gap_spin, @strong filter_dd, @strong low_spin, @strong high_spin, @strong normalise_check, @strong window_spin, @strong overlap_spin, @strong labelling_dd, @strong mean_check, @strong std_check, @strong rms_check, @strong bands_spin => move || {
+        let mut config = SensorConfig {
+            sample_rate: rate_spin.value(),
+            gap_fill: GapFill::ALL[gap_fill_dd.selected() as usize % GapFill::ALL.len()],
+            max_gap: max_gap_spin.value(),
+            filter: match filter_dd.selected() {
+                1 => SignalFilter::LowPass { cutoff: high_spin.value() },
+                2 => SignalFilter::HighPass { cutoff: low_spin.value() },
+                3 => SignalFilter::BandPass { low: low_spin.value(), high: high_spin.value() },
+                _ => SignalFilter::None,
+            },
+            normalise: normalise_check.is_active(),
+            window: window_spin.value(),
+            overlap: overlap_spin.value(),
+            labelling: WindowLabelling::ALL[labelling_dd.selected() as usize % WindowLabelling::ALL.len()],
+            ..SensorConfig::default()
+        };
+        config.features.mean = mean_check.is_active();
+        config.features.std = std_check.is_active();
+        config.features.rms = rms_check.is_active();
+        config.features.fft_bands = bands_spin.value() as usize;
+        config
+    });
+
+    let show_config = move |config: &SensorConfig| {
+        rate_spin.set_value(config.sample_rate);
+        gap_fill_dd.set_selected(
+            GapFill::ALL
+                .iter()
+                .position(|g| *g == config.gap_fill)
+                .unwrap_or(0) as u32,
+        );
+        max_gap_spin.set_value(config.max_gap);
+        let (filter, low, high) = match config.filter {
+            SignalFilter::None => (0, 0.0, 0.0),
+            SignalFilter::LowPass { cutoff } => (1, 0.0, cutoff),
+            SignalFilter::HighPass { cutoff } => (2, cutoff, 0.0),
+            SignalFilter::BandPass { low, high } => (3, low, high),
+        };
+        filter_dd.set_selected(filter);
+        low_spin.set_value(low);
+        high_spin.set_value(high);
+        normalise_check.set_active(config.normalise);
+        window_spin.set_value(config.window);
+        overlap_spin.set_value(config.overlap);
+        labelling_dd.set_selected(
+            WindowLabelling::ALL
+                .iter()
+                .position(|l| *l == config.labelling)
+                .unwrap_or(0) as u32,
+        );
+        mean_check.set_active(config.features.mean);
+        std_check.set_active(config.features.std);
+        rms_check.set_active(config.features.rms);
+        bands_spin.set_value(config.features.fft_bands as f64);
+    };
+
+    let preview_btn = Button::with_label("preview windows");
+    let export_btn = Button::with_label("export feature table");
+    let save_btn = Button::with_label("save settings to project");
+    let status_label = Label::builder()
+        .wrap(true)
+        .halign(gtk::Align::Start)
+        .build();
+
+    let editor = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    editor.append(&settings_grid);
+    editor.append(&preview_btn);
+    editor.append(&export_btn);
+    editor.append(&save_btn);
+    editor.append(&status_label);
+
+    // signal plot with annotated intervals and window starts
+    // ---------------------------------------------------------------------------------------------
+    let area = gtk::DrawingArea::builder()
+        .content_width(500)
+        .content_height(300)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    area.set_draw_func(gtk::glib::clone!(@strong preview => move |_, cr, width, height| {
+        let preview = preview.borrow();
+        let Some((signals, windows, spans)) = preview.as_ref() else {
+            return;
+        };
+        let total = signals.values.first().map_or(0, Vec::len).max(1) as f64;
+        let marks: Vec<f64> = windows.iter().map(|w| w.start as f64 / total).collect();
+        let title = format!("{} at {} Hz", signals.names.join(", "), signals.sample_rate);
+        if let Err(e) = draw_signals(cr, width as f64, height as f64, &title, &signals.values, spans, &marks) {
+            debug_println!("[ERROR: PREPROCESSING] unable to draw sensor signals: {}", e);
+        }
+    }));
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .vexpand(true)
+        .build();
+    hbox.append(&editor);
+    hbox.append(&area);
+
+    // preview of the sample and export of all recordings
+    // ---------------------------------------------------------------------------------------------
+    let sample_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    let update_preview = gtk::glib::clone!(@strong project, @strong read_config, @strong preview, @strong sample_paths, @strong sample_dd, @strong status_label, @strong area => move || {
+        let path = sample_paths.borrow().get(sample_dd.selected() as usize).cloned();
+        let config = read_config();
+        let result = path.ok_or_else(|| "no sensor recording".into()).and_then(|path| {
+            let signals = preprocess_recording(Path::new(&path), &config)?;
+            let project_ref = project.borrow();
+            let project = project_ref.as_ref().ok_or("no project opened")?;
+            let item = project.store.items().find(|item| item.path == path).ok_or("unknown item")?;
+            let annotations = project.store.annotations_of_item(item.id);
+            let windows = sliding_windows(&signals, &annotations, &config);
+
+            let duration = signals.values.first().map_or(0, Vec::len).max(1) as f64 / signals.sample_rate;
+            let classes = &project.config.classes;
+            let spans: Vec<(f64, f64, Rgb)> = annotations
+                .iter()
+                .filter_map(|a| match a.shape {
+                    Shape::Segment { start, end } => {
+                        let index = class_index(classes, &a.class).unwrap_or(classes.len());
+                        Some((start as f64 / duration, end as f64 / duration, class_colour(classes, index)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            Ok::<_, Box<dyn Error>>((signals, windows, spans))
+        });
+
+        match result {
+            Ok((signals, windows, spans)) => {
+                let mut counts: Vec<(String, usize)> = Vec::new();
+                for window in &windows {
+                    let label = if window.labels.is_empty() { "(unlabelled)".to_string() } else { window.labels.join(" + ") };
+                    match counts.iter_mut().find(|(l, _)| *l == label) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((label, 1)),
+                    }
+                }
+                let counts: Vec<String> = counts.iter().map(|(l, c)| format!("{}: {}", l, c)).collect();
+                status_label.set_text(&format!("{} windows\n{}", windows.len(), counts.join("\n")));
+                preview.replace(Some((signals, windows, spans)));
+            }
+            Err(e) => {
+                status_label.set_text(&format!("unable to process the recording:\n{}", e));
+                preview.replace(None);
+            }
+        }
+        area.queue_draw();
+    });
+
+    preview_btn
+        .connect_clicked(gtk::glib::clone!(@strong update_preview => move |_| update_preview()));
+    sample_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong update_preview => move |_| update_preview()),
+    );
+
+    export_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong read_config => move |_| {
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Export window features as CSV")
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.set_current_name("sensor_features.csv");
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Export", gtk::ResponseType::Accept),
+        ]);
+
+        dialog.connect_response(gtk::glib::clone!(@strong project, @strong read_config => move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    let config = read_config();
+                    let result = sensor_windows(&project, &config)
+                        .and_then(|recordings| export_features(&path, &recordings, &config.features));
+                    match result {
+                        Ok(rows) => debug_println!("[INFO: PREPROCESSING] exported {} windows to {}", rows, path.display()),
+                        Err(e) => show_error_message(
+                            None::<&gtk::Widget>,
+                            Some("EXPORT ERROR"),
+                            Some(&format!("Unable to export the window features:\n{}", e)),
+                        ),
+                    }
+                }
+            }
+            dialog.close();
+        }));
+
+        dialog.show();
+    }));
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong read_config => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Unable to save the sensor settings, since no project is opened."),
+            );
+            return;
+        };
+
+        // keep the other sections of the file
+        let result = load_preprocessing(&dir).and_then(|mut saved| {
+            saved.sensor = read_config();
+            save_preprocessing(&dir, &saved)
+        });
+        match result {
+            Ok(()) => debug_println!("[INFO: PREPROCESSING] saved sensor settings to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the sensor settings:\n{}", e)),
+            ),
+        }
+    }));
+
+    vbox.connect_map(
+        gtk::glib::clone!(@strong project, @strong update_preview => move |_| {
+            let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+            if *loaded_from.borrow() != dir {
+                let saved = dir
+                    .as_deref()
+                    .and_then(|dir| load_preprocessing(dir).ok())
+                    .unwrap_or_default();
+                show_config(&saved.sensor);
+                loaded_from.replace(dir);
+            }
+
+            let paths = sample_items(&project, Modality::Sensor);
+            if *sample_paths.borrow() != paths {
+                let names: Vec<String> = paths
+                    .iter()
+                    .map(|p| {
+                        Path::new(p)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_else(|| p.clone())
+                    })
+                    .collect();
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                sample_paths.replace(paths);
+                sample_names.splice(0, sample_names.n_items(), &names);
+            }
+            update_preview();
+        }),
+    );
+
+    vbox.append(&sample_box);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+/// paths of all table files (`.csv` / `.tsv` items) of the opened project
+pub(crate) fn table_items(project: &SharedProject) -> Vec<String> {
+    project
+        .borrow()
+        .as_ref()
+        .map(|p| {
+            p.store
+                .items()
+                .filter(|item| matches!(item.modality, Modality::Tabular | Modality::Sensor))
+                .map(|item| item.path.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// training settings of the opened project (e.g. its label column), the defaults without one
+fn training_settings(project: &SharedProject) -> TrainingConfig {
+    project
+        .borrow()
+        .as_ref()
+        .and_then(|p| load_training(p.dir()).ok())
+        .unwrap_or_default()
+}
+
+/// # fit the tabular pipeline and save it with the fitted transformer to the project
+///
+/// the fitted transformer is what training and prediction apply, the steps keep it editable.
+/// It is fitted on the tables of the training split only, so validation and test rows do not
+/// leak into its statistics.
+///
+/// returns:
+///     Result with the project directory
+fn fit_and_save_tabular(
+    project: &SharedProject,
+    pipeline: &TabularPipeline,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let project = project.borrow();
+    let project = project.as_ref().ok_or("no project is opened")?;
+    let dir = project.dir().to_path_buf();
+    let training = load_training(&dir)?;
+    let source = DatasetSource::from_project(project, &training.label_column, training.seed)?;
+    let (fitted, _) = pipeline.fit(&source.training_table()?, &training.label_column)?;
+    save_fitted_tabular(&dir, &fitted)?;
+    let mut saved = load_preprocessing(&dir)?;
+    saved.tabular = pipeline.clone();
+    save_preprocessing(&dir, &saved)?;
+    Ok(dir)
+}
+
+/// Tabular preprocessing: imputation, encoding, scaling, clipping and derived columns
+fn tabular_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let pipeline: Rc<RefCell<TabularPipeline>> = Rc::default();
+    // project directory the pipeline was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let table: Rc<RefCell<Option<Table>>> = Rc::default();
+
+    // table file
+    // ---------------------------------------------------------------------------------------------
+    let table_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    let table_names = gtk::StringList::new(&[]);
+    let table_dd = gtk::DropDown::builder()
+        .model(&table_names)
+        .hexpand(true)
+        .build();
+    table_box.append(&Label::new(Some("table:")));
+    table_box.append(&table_dd);
+
+    // steps
+    // ---------------------------------------------------------------------------------------------
+    let steps_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .build();
+    let steps_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .width_request(320)
+        .vexpand(true)
+        .child(&steps_list)
+        .build();
+
+    let remove_btn = Button::with_label("remove");
+    let up_btn = Button::with_label("up");
+    let down_btn = Button::with_label("down");
+    let step_buttons = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    step_buttons.append(&remove_btn);
+    step_buttons.append(&up_btn);
+    step_buttons.append(&down_btn);
+
+    // form of a new step, only the widgets the chosen operation needs are shown
+    let op_dd = gtk::DropDown::from_strings(&[
+        "impute missing values",
+        "one-hot encode",
+        "ordinal encode",
+        "scale",
+        "clip outliers",
+        "derive column",
+    ]);
+    let column_names = gtk::StringList::new(&[]);
+    let column_dd = gtk::DropDown::builder().model(&column_names).build();
+    let option_names = gtk::StringList::new(&[]);
+    let option_dd = gtk::DropDown::builder().model(&option_names).build();
+    let right_column_dd = gtk::DropDown::builder().model(&column_names).build();
+    let text_entry = gtk::Entry::new();
+    let lower_spin = gtk::SpinButton::with_range(0.0, 1.0, 0.01);
+    lower_spin.set_digits(2);
+    lower_spin.set_value(0.01);
+    let upper_spin = gtk::SpinButton::with_range(0.0, 1.0, 0.01);
+    upper_spin.set_digits(2);
+    upper_spin.set_value(0.99);
+    let add_btn = Button::with_label("add step");
+
+    let form = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let option_label = Label::builder().halign(gtk::Align::Start).build();
+    let right_label = Label::builder()
+        .label("right column")
+        .halign(gtk::Align::Start)
+        .build();
+    let text_label = Label::builder().halign(gtk::Align::Start).build();
+    let lower_label = Label::builder()
+        .label("lower quantile")
+        .halign(gtk::Align::Start)
+        .build();
+    let upper_label = Label::builder()
+        .label("upper quantile")
+        .halign(gtk::Align::Start)
+        .build();
+    let column_label = Label::builder()
+        .label("column")
+        .halign(gtk::Align::Start)
+        .build();
+    let rows: [(&Label, &gtk::Widget); 7] = [
+        (&Label::new(Some("operation")), op_dd.upcast_ref()),
+        (&column_label, column_dd.upcast_ref()),
+        (&option_label, option_dd.upcast_ref()),
+        (&right_label, right_column_dd.upcast_ref()),
+        (&text_label, text_entry.upcast_ref()),
+        (&lower_label, lower_spin.upcast_ref()),
+        (&upper_label, upper_spin.upcast_ref()),
+    ];
+    for (row, (label, widget)) in rows.iter().enumerate() {
+        form.attach(*label, 0, row as i32, 1, 1);
+        form.attach(*widget, 1, row as i32, 1, 1);
+    }
+
+    let update_form = gtk::glib::clone!(@strong op_dd, @strong option_dd, @strong right_column_dd, @strong text_entry, @strong lower_spin, @strong upper_spin => move || {
+        let op = op_dd.selected();
+        let options: Vec<&str> = match op {
+            0 => ImputeStrategy::ALL.iter().map(ImputeStrategy::name).collect(),
+            3 => Scaling::ALL.iter().map(Scaling::name).collect(),
+            5 => DeriveOp::ALL.iter().map(DeriveOp::name).collect(),
+            _ => Vec::new(),
+        };
+        option_names.splice(0, option_names.n_items(), &options);
+        option_label.set_text(match op {
+            0 => "strategy",
+            3 => "method",
+            _ => "operation",
+        });
+        text_label.set_text(if op == 0 { "constant" } else { "new column name" });
+        column_label.set_text(if op == 5 { "left column" } else { "column" });
+
+        let derive_binary = op == 5
+            && DeriveOp::ALL
+                .get(option_dd.selected() as usize)
+                .is_some_and(DeriveOp::is_binary);
+        let impute_constant = op == 0 && option_dd.selected() == 3;
+        for (widget, visible) in [
+            (option_dd.upcast_ref::<gtk::Widget>(), !options.is_empty()),
+            (option_label.upcast_ref(), !options.is_empty()),
+            (right_column_dd.upcast_ref(), derive_binary),
+            (right_label.upcast_ref(), derive_binary),
+            (text_entry.upcast_ref(), op == 5 || impute_constant),
+            (text_label.upcast_ref(), op == 5 || impute_constant),
+            (lower_spin.upcast_ref(), op == 4),
+            (lower_label.upcast_ref(), op == 4),
+            (upper_spin.upcast_ref(), op == 4),
+            (upper_label.upcast_ref(), op == 4),
+        ] {
+            widget.set_visible(visible);
+        }
+    });
+    update_form();
+    op_dd.connect_selected_notify(gtk::glib::clone!(@strong update_form => move |_| update_form()));
+    option_dd
+        .connect_selected_notify(gtk::glib::clone!(@strong update_form => move |_| update_form()));
+
+    let fit_btn = Button::with_label("fit and preview");
+    let save_btn = Button::with_label("fit and save to project");
+
+    let editor = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    editor.append(&steps_window);
+    editor.append(&step_buttons);
+    editor.append(&form);
+    editor.append(&add_btn);
+    editor.append(&fit_btn);
+    editor.append(&save_btn);
+
+    // before / after preview
+    // ---------------------------------------------------------------------------------------------
+    let preview_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .valign(gtk::Align::Start)
+        .selectable(true)
+        .build();
+    preview_label.add_css_class("monospace");
+    let preview_window = gtk::ScrolledWindow::builder()
+        .hexpand(true)
+        .vexpand(true)
+        .child(&preview_label)
+        .build();
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .vexpand(true)
+        .build();
+    hbox.append(&editor);
+    hbox.append(&preview_window);
+
+    // fits the pipeline on the table, shows the result and offers the resulting columns
+    let update_preview = gtk::glib::clone!(@strong project, @strong pipeline, @strong table => move || {
+        let table = table.borrow();
+        let Some(table) = table.as_ref() else {
+            preview_label.set_text("no table");
+            column_names.splice(0, column_names.n_items(), &[]);
+            return;
+        };
+        let before = preview_text(table, TABLE_PREVIEW_ROWS);
+        let label_column = training_settings(&project).label_column;
+        let (after, columns) = match pipeline.borrow().fit(table, &label_column) {
+            Ok((fitted, transformed)) => (
+                preview_text(&transformed, TABLE_PREVIEW_ROWS),
+                fitted.output_columns,
+            ),
+            Err(e) => (format!("unable to fit the pipeline:\n{}", e), table.columns.clone()),
+        };
+        preview_label.set_text(&format!("original\n\n{}\n\npreprocessed\n\n{}", before, after));
+
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        column_names.splice(0, column_names.n_items(), &columns);
+    });
+
+    let refresh_steps = gtk::glib::clone!(@strong pipeline, @strong steps_list => move |selected: Option<usize>| {
+        while let Some(child) = steps_list.first_child() {
+            steps_list.remove(&child);
+        }
+        for step in &pipeline.borrow().steps {
+            steps_list.append(&Label::builder().label(step.describe()).halign(gtk::Align::Start).build());
+        }
+        if let Some(row) = selected.and_then(|i| steps_list.row_at_index(i as i32)) {
+            steps_list.select_row(Some(&row));
+        }
+    });
+
+    let selected_step = gtk::glib::clone!(@strong steps_list => move || {
+        steps_list.selected_row().map(|row| row.index() as usize)
+    });
+
+    let selected_column = |dd: &gtk::DropDown| {
+        dd.selected_item()
+            .and_downcast::<gtk::StringObject>()
+            .map(|s| s.string().to_string())
+    };
+
+    add_btn.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview => move |_| {
+        let Some(column) = selected_column(&column_dd) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Please choose a column first."),
+            );
+            return;
+        };
+        let option = option_dd.selected() as usize;
+        let step = match op_dd.selected() {
+            0 => TabularStep::Impute {
+                column,
+                strategy: ImputeStrategy::ALL[option.min(ImputeStrategy::ALL.len() - 1)],
+                constant: text_entry.text().to_string(),
+            },
+            1 => TabularStep::OneHot { column },
+            2 => TabularStep::Ordinal { column },
+            3 => TabularStep::Scale {
+                column,
+                method: Scaling::ALL[option.min(Scaling::ALL.len() - 1)],
+            },
+            4 => TabularStep::Clip {
+                column,
+                lower_quantile: lower_spin.value().min(upper_spin.value()),
+                upper_quantile: upper_spin.value().max(lower_spin.value()),
+            },
+            _ => {
+                let name = text_entry.text().trim().to_string();
+                if name.is_empty() {
+                    show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("PREPROCESSING ERROR"),
+                        Some("Please enter a name for the new column."),
+                    );
+                    return;
+                }
+                TabularStep::Derive {
+                    name,
+                    operation: DeriveOp::ALL[option.min(DeriveOp::ALL.len() - 1)],
+                    left: column,
+                    right: selected_column(&right_column_dd).unwrap_or_default(),
+                }
+            }
+        };
+
+        let count = {
+            let mut pipeline = pipeline.borrow_mut();
+            pipeline.steps.push(step);
+            pipeline.steps.len()
+        };
+        refresh_steps(Some(count - 1));
+        update_preview();
+    }));
+
+    remove_btn.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+        let Some(index) = selected_step() else {
+            return;
+        };
+        pipeline.borrow_mut().steps.remove(index);
+        refresh_steps(None);
+        update_preview();
+    }));
+
+    for (button, up) in [(&up_btn, true), (&down_btn, false)] {
+        button.connect_clicked(gtk::glib::clone!(@strong pipeline, @strong refresh_steps, @strong update_preview, @strong selected_step => move |_| {
+            let Some(index) = selected_step() else {
+                return;
+            };
+            let count = pipeline.borrow().steps.len();
+            let other = if up { index.checked_sub(1) } else { Some(index + 1).filter(|i| *i < count) };
+            let Some(other) = other else {
+                return;
+            };
+            pipeline.borrow_mut().steps.swap(index, other);
+            refresh_steps(Some(other));
+            update_preview();
+        }));
+    }
+
+    fit_btn.connect_clicked(gtk::glib::clone!(@strong update_preview => move |_| update_preview()));
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong pipeline => move |_| {
+        match fit_and_save_tabular(&project, &pipeline.borrow()) {
+            Ok(dir) => debug_println!("[INFO: PREPROCESSING] saved fitted tabular pipeline to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the tabular pipeline:\n{}", e)),
+            ),
+        }
+    }));
+
+    // loading the table and the saved pipeline
+    // ---------------------------------------------------------------------------------------------
+    let table_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    let load_table = gtk::glib::clone!(@strong table_paths, @strong table, @strong update_preview => move |index: u32| {
+        let path = table_paths.borrow().get(index as usize).cloned();
+        let loaded = path.and_then(|path| match read_table(Path::new(&path)) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                debug_println!("[ERROR: PREPROCESSING] unable to load {}: {}", path, e);
+                None
+            }
+        });
+        table.replace(loaded);
+        update_preview();
+    });
+
+    table_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong load_table => move |table_dd| load_table(table_dd.selected())),
+    );
+
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong pipeline, @strong refresh_steps, @strong update_preview, @strong table_dd => move |_| {
+        let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+        if *loaded_from.borrow() != dir {
+            let saved = dir
+                .as_deref()
+                .and_then(|dir| load_preprocessing(dir).ok())
+                .unwrap_or_default();
+            pipeline.replace(saved.tabular);
+            loaded_from.replace(dir);
+            refresh_steps(None);
+        }
+
+        let paths = table_items(&project);
+        if *table_paths.borrow() != paths {
+            let names: Vec<String> = paths
+                .iter()
+                .map(|p| {
+                    Path::new(p)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| p.clone())
+                })
+                .collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            table_paths.replace(paths);
+            table_names.splice(0, table_names.n_items(), &names);
+            load_table(table_dd.selected());
+        }
+        update_preview();
+    }));
+
+    vbox.append(&table_box);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+/// bar chart of the number of samples per class
+fn class_count_chart(
+    title: &str,
+    counts: &BTreeMap<String, usize>,
+    classes: &[LabelClass],
+) -> BarChart {
+    BarChart {
+        title: title.to_string(),
+        bars: counts
+            .iter()
+            .enumerate()
+            .map(|(i, (class, count))| Bar {
+                label: class.clone(),
+                value: *count as f64,
+                colour: match class_index(classes, class) {
+                    Some(index) => class_colour(classes, index),
+                    None => palette_colour(classes.len() + i),
+                },
+            })
+            .collect(),
+    }
+}
+
+/// # transformed table, resampled according to the config
+///
+/// the fitted tabular transformer of the project (if there is one) is applied first, so SMOTE
+/// interpolates between encoded and scaled features.
+fn balanced_table(
+    project_dir: &Path,
+    table: &Table,
+    label_column: &str,
+    config: &ImbalanceConfig,
+) -> Result<Table, Box<dyn Error>> {
+    let transformed = match load_fitted_tabular(project_dir)? {
+        Some(fitted) => fitted.transform(table)?,
+        None => table.clone(),
+    };
+    if !transformed.columns.iter().any(|c| c == label_column) {
+        return Err(format!(
+            "the label column {:?} is not part of the table",
+            label_column
+        )
+        .into());
+    }
+    resample_table(&transformed, label_column, config)
+}
+
+/// Class balance: resampling and class weights with a preview of the resulting distribution
+fn class_balance_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let config: Rc<RefCell<ImbalanceConfig>> = Rc::default();
+    // project directory the config was loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let classes: Rc<RefCell<Vec<LabelClass>>> = Rc::default();
+    let charts: Rc<RefCell<[BarChart; 2]>> = Rc::default();
+    let table: Rc<RefCell<Option<Table>>> = Rc::default();
+    let table_paths: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    // settings
+    // ---------------------------------------------------------------------------------------------
+    let resampling_names: Vec<&str> = Resampling::ALL.iter().map(Resampling::name).collect();
+    let resampling_dd = gtk::DropDown::from_strings(&resampling_names);
+    let ratio_spin = gtk::SpinButton::with_range(0.05, 1.0, 0.05);
+    ratio_spin.set_digits(2);
+    let neighbours_spin = gtk::SpinButton::with_range(1.0, 50.0, 1.0);
+    let weighting_names: Vec<&str> = ClassWeighting::ALL
+        .iter()
+        .map(ClassWeighting::name)
+        .collect();
+    let weighting_dd = gtk::DropDown::from_strings(&weighting_names);
+    let beta_spin = gtk::SpinButton::with_range(0.9, 0.9999, 0.0001);
+    beta_spin.set_digits(4);
+    let seed_spin = gtk::SpinButton::with_range(0.0, u32::MAX as f64, 1.0);
+
+    // the source of the labels: the items of the project or a column of one of its tables
+    let source_names = gtk::StringList::new(&["project items (training split)"]);
+    let source_dd = gtk::DropDown::builder().model(&source_names).build();
+    let label_column_names = gtk::StringList::new(&[]);
+    let label_column_dd = gtk::DropDown::builder().model(&label_column_names).build();
+    let label_column_label = Label::builder()
+        .label("label column")
+        .halign(gtk::Align::Start)
+        .build();
+
+    let form = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let rows: [(&str, &gtk::Widget); 7] = [
+        ("resampling", resampling_dd.upcast_ref()),
+        ("class size ratio", ratio_spin.upcast_ref()),
+        ("SMOTE neighbours", neighbours_spin.upcast_ref()),
+        ("class weights", weighting_dd.upcast_ref()),
+        ("beta (effective number)", beta_spin.upcast_ref()),
+        ("seed", seed_spin.upcast_ref()),
+        ("labels of", source_dd.upcast_ref()),
+    ];
+    for (row, (name, widget)) in rows.iter().enumerate() {
+        form.attach(
+            &Label::builder()
+                .label(*name)
+                .halign(gtk::Align::Start)
+                .build(),
+            0,
+            row as i32,
+            1,
+            1,
+        );
+        form.attach(*widget, 1, row as i32, 1, 1);
+    }
+    form.attach(&label_column_label, 0, rows.len() as i32, 1, 1);
+    form.attach(&label_column_dd, 1, rows.len() as i32, 1, 1);
+
+    let save_btn = Button::with_label("save to project");
+    let export_btn = Button::with_label("export balanced table ...");
+
+    let weights_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .valign(gtk::Align::Start)
+        .selectable(true)
+        .build();
+    weights_label.add_css_class("monospace");
+
+    let settings = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    settings.append(&form);
+    settings.append(&save_btn);
+    settings.append(&export_btn);
+    settings.append(&weights_label);
+
+    // before / after charts
+    // ---------------------------------------------------------------------------------------------
+    let area = gtk::DrawingArea::builder()
+        .hexpand(true)
+        .vexpand(true)
+        .content_height(300)
+        .build();
+    area.set_draw_func(gtk::glib::clone!(@strong charts => move |_, cr, width, height| {
+        let half = width as f64 / 2.0;
+        for (i, chart) in charts.borrow().iter().enumerate() {
+            cr.save().ok();
+            cr.translate(half * i as f64, 0.0);
+            if let Err(e) = draw_bar_chart(cr, half, height as f64, chart) {
+                debug_println!("[ERROR: PREPROCESSING] unable to draw the class distribution: {}", e);
+            }
+            cr.restore().ok();
+        }
+    }));
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .vexpand(true)
+        .build();
+    hbox.append(&settings);
+    hbox.append(&area);
+
+    // labels of the chosen source, `None` entries are skipped
+    let current_labels = gtk::glib::clone!(@strong project, @strong table, @strong source_dd, @strong label_column_dd => move || {
+        if source_dd.selected() == 0 {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                return Vec::new();
+            };
+            let splits = load_splits(project.dir()).ok();
+            return training_labels(&project.store, splits.as_ref())
+                .into_iter()
+                .map(|(_, class)| class)
+                .collect::<Vec<String>>();
+        }
+
+        let table = table.borrow();
+        let column = label_column_dd
+            .selected_item()
+            .and_downcast::<gtk::StringObject>()
+            .map(|s| s.string().to_string());
+        let (Some(table), Some(column)) = (table.as_ref(), column) else {
+            return Vec::new();
+        };
+        let Ok(index) = table.column_index(&column) else {
+            return Vec::new();
+        };
+        table
+            .rows
+            .iter()
+            .map(|row| row[index].trim())
+            .filter(|cell| !is_missing(cell))
+            .map(str::to_string)
+            .collect()
+    });
+
+    let update_preview = gtk::glib::clone!(@strong config, @strong charts, @strong classes, @strong area, @strong current_labels => move || {
+        let counts = label_counts(&current_labels());
+        let config = config.borrow();
+        let targets = target_counts(&counts, &config);
+        let weights = class_weights(&targets, &config);
+        charts.replace([
+            class_count_chart("samples per class", &counts, &classes.borrow()),
+            class_count_chart("after resampling", &targets, &classes.borrow()),
+        ]);
+        area.queue_draw();
+
+        let mut text = String::from("class weights (after resampling)\n\n");
+        for (class, weight) in &weights {
+            text.push_str(&format!("{:<24} {:>8.3}\n", class, weight));
+        }
+        weights_label.set_text(&text);
+    });
+
+    // shows the settings of the config in the widgets
+    let show_config = gtk::glib::clone!(@strong config, @strong resampling_dd, @strong ratio_spin, @strong neighbours_spin, @strong weighting_dd, @strong beta_spin, @strong seed_spin => move || {
+        let config = config.borrow().clone();
+        let position = |found: Option<usize>| found.unwrap_or(0) as u32;
+        resampling_dd.set_selected(position(Resampling::ALL.iter().position(|r| *r == config.resampling)));
+        ratio_spin.set_value(config.ratio);
+        neighbours_spin.set_value(config.neighbours as f64);
+        weighting_dd.set_selected(position(ClassWeighting::ALL.iter().position(|w| *w == config.weighting)));
+        beta_spin.set_value(config.beta);
+        seed_spin.set_value(config.seed as f64);
+    });
+
+    // takes the widgets into the config
+    let read_config = gtk::glib::clone!(@strong config, @strong resampling_dd, @strong ratio_spin, @strong neighbours_spin, @strong weighting_dd, @strong beta_spin, @strong seed_spin, @strong update_preview => move || {
+        {
+            let mut config = config.borrow_mut();
+            config.resampling = Resampling::ALL[(resampling_dd.selected() as usize).min(Resampling::ALL.len() - 1)];
+            config.ratio = ratio_spin.value();
+            config.neighbours = neighbours_spin.value() as usize;
+            config.weighting = ClassWeighting::ALL[(weighting_dd.selected() as usize).min(ClassWeighting::ALL.len() - 1)];
+            config.beta = beta_spin.value();
+            config.seed = seed_spin.value() as u64;
+        }
+        update_preview();
+    });
+
+    for dd in [&resampling_dd, &weighting_dd] {
+        dd.connect_selected_notify(
+            gtk::glib::clone!(@strong read_config => move |_| read_config()),
+        );
+    }
+    for spin in [&ratio_spin, &neighbours_spin, &beta_spin, &seed_spin] {
+        spin.connect_value_changed(
+            gtk::glib::clone!(@strong read_config => move |_| read_config()),
+        );
+    }
+
+    // the label column and the export only make sense for tables
+    let update_source = gtk::glib::clone!(@strong table_paths, @strong table, @strong source_dd, @strong label_column_dd, @strong export_btn, @strong update_preview => move || {
+        let index = source_dd.selected() as usize;
+        let path = index.checked_sub(1).and_then(|i| table_paths.borrow().get(i).cloned());
+        let loaded = path.and_then(|path| match read_table(Path::new(&path)) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                debug_println!("[ERROR: PREPROCESSING] unable to load {}: {}", path, e);
+                None
+            }
+        });
+
+        let columns: Vec<String> = loaded.as_ref().map(|t| t.columns.clone()).unwrap_or_default();
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        let is_table = loaded.is_some();
+        table.replace(loaded);
+        label_column_names.splice(0, label_column_names.n_items(), &columns);
+        // the label is usually the last column
+        label_column_dd.set_selected(columns.len().saturating_sub(1) as u32);
+
+        label_column_dd.set_visible(is_table);
+        label_column_label.set_visible(is_table);
+        export_btn.set_sensitive(is_table);
+        update_preview();
+    });
+
+    source_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong update_source => move |_| update_source()),
+    );
+    label_column_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong update_preview => move |_| update_preview()),
+    );
+
+    save_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some("Unable to save the class balance settings, since no project is opened."),
+            );
+            return;
+        };
+        let result = load_preprocessing(&dir).and_then(|mut saved| {
+            saved.imbalance = config.borrow().clone();
+            save_preprocessing(&dir, &saved)
+        });
+        match result {
+            Ok(()) => debug_println!("[INFO: PREPROCESSING] saved class balance settings to {}", dir.display()),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("PREPROCESSING ERROR"),
+                Some(&format!("Unable to save the class balance settings:\n{}", e)),
+            ),
+        }
+    }));
+
+    export_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong table, @strong label_column_dd => move |_| {
+        let Some(dir) = project.borrow().as_ref().map(|p| p.dir().to_path_buf()) else {
+            return;
+        };
+        let Some(column) = label_column_dd
+            .selected_item()
+            .and_downcast::<gtk::StringObject>()
+            .map(|s| s.string().to_string())
+        else {
+            return;
+        };
+        let balanced = match table.borrow().as_ref().map(|table| balanced_table(&dir, table, &column, &config.borrow())) {
+            Some(Ok(balanced)) => balanced,
+            Some(Err(e)) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("PREPROCESSING ERROR"),
+                    Some(&format!("Unable to balance the table:\n{}", e)),
+                );
+                return;
+            }
+            None => return,
+        };
+
+        let dialog = gtk::FileChooserDialog::builder()
+            .title("Export the balanced table")
+            .action(gtk::FileChooserAction::Save)
+            .build();
+        dialog.set_current_name("balanced.csv");
+        dialog.add_buttons(&[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Export", gtk::ResponseType::Accept),
+        ]);
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    match write_table(&path, &balanced) {
+                        Ok(()) => debug_println!("[INFO: PREPROCESSING] exported {} rows to {}", balanced.rows.len(), path.display()),
+                        Err(e) => show_error_message(
+                            None::<&gtk::Widget>,
+                            Some("EXPORT ERROR"),
+                            Some(&format!("Unable to export the table:\n{}", e)),
+                        ),
+                    }
+                }
+            }
+            dialog.close();
+        });
+        dialog.show();
+    }));
+
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong config, @strong show_config, @strong update_source, @strong source_dd => move |_| {
+        let dir = project.borrow().as_ref().map(|p| p.dir().to_path_buf());
+        if *loaded_from.borrow() != dir {
+            let saved = dir
+                .as_deref()
+                .and_then(|dir| load_preprocessing(dir).ok())
+                .unwrap_or_default();
+            config.replace(saved.imbalance);
+            loaded_from.replace(dir);
+            show_config();
+        }
+        classes.replace(
+            project
+                .borrow()
+                .as_ref()
+                .map(|p| p.config.classes.clone())
+                .unwrap_or_default(),
+        );
+
+        let paths = table_items(&project);
+        if *table_paths.borrow() != paths {
+            let mut names = vec!["project items (training split)".to_string()];
+            names.extend(paths.iter().map(|p| {
+                Path::new(p)
+                    .file_name()
+                    .map(|n| format!("table {}", n.to_string_lossy()))
+                    .unwrap_or_else(|| p.clone())
+            }));
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            table_paths.replace(paths);
+            source_names.splice(0, source_names.n_items(), &names);
+            source_dd.set_selected(0);
+        }
+        update_source();
+    }));
+
+    vbox.append(&hbox);
+
+    vbox
+}
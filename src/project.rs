@@ -0,0 +1,68 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! The currently opened project
+//!
+//! A project is its `.toml` config file plus everything in the directory next to it
+//! (annotation store, snapshots, splits, runs, ...).
+
+use crate::helper::{save_config, Config};
+use crate::store::{project_dir, AnnotationStore, Modality};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// the currently opened project, shared between all tabs
+///
+/// `None` as long as no project has been opened or created.
+pub(crate) type SharedProject = Rc<RefCell<Option<Project>>>;
+
+/// An opened project: its config and its annotation store
+#[derive(Debug)]
+pub(crate) struct Project {
+    pub(crate) config_path: PathBuf,
+    pub(crate) config: Config,
+    pub(crate) store: AnnotationStore,
+}
+
+impl Project {
+    /// open the annotation store next to an already loaded config file
+    pub(crate) fn open(config_path: &str, config: Config) -> Result<Project, Box<dyn Error>> {
+        Ok(Project {
+            config_path: PathBuf::from(config_path),
+            store: AnnotationStore::open(&project_dir(config_path))?,
+            config,
+        })
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        self.store.project_dir()
+    }
+
+    /// # kind of data of the project
+    ///
+    /// older configs do not store it, then it is the modality most of the items have.
+    ///
+    /// returns:
+    ///     the modality, `None` for an empty project without a stored modality
+    pub(crate) fn modality(&self) -> Option<Modality> {
+        self.config.modality.or_else(|| {
+            let mut counts: BTreeMap<Modality, usize> = BTreeMap::new();
+            for item in self.store.items() {
+                *counts.entry(item.modality).or_insert(0) += 1;
+            }
+            counts
+                .into_iter()
+                .max_by_key(|(_, count)| *count)
+                .map(|(modality, _)| modality)
+        })
+    }
+
+    /// write the (modified) config back to the project's `.toml` file
+    pub(crate) fn save_config(&self) -> Result<(), Box<dyn Error>> {
+        save_config(&self.config_path.display().to_string(), &self.config)
+    }
+}
@@ -0,0 +1,285 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Training runs
+//!
+//! Every run gets its own directory `<project>/runs/<run id>/` with a `run.toml` describing
+//! the run. The record names the dataset snapshot the run was trained on, so results can be
+//! traced back to the exact data. Iterative models also leave their checkpoints and the
+//! best model in the run directory (see [`crate::checkpoint`]).
+//!
+//! Next to the record, every run keeps the reports of its epochs (`epochs.jsonl`) and a copy
+//! of the preprocessing config it was trained with, so runs can be compared later on.
+
+use crate::checkpoint::{BestEpoch, CheckpointConfig};
+use crate::metrics::Metrics;
+use crate::model::{EpochReport, Params};
+use crate::store::unix_now;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// directory inside a project holding all training runs
+pub(crate) const RUNS_DIR: &str = "runs";
+
+/// file name of the run description inside a run directory
+pub(crate) const RUN_FILE_NAME: &str = "run.toml";
+
+/// file name of the epoch reports inside a run directory, one JSON object per line
+pub(crate) const EPOCHS_FILE_NAME: &str = "epochs.jsonl";
+
+/// error of runs the user stopped (the model trained so far is kept)
+pub(crate) const STOPPED_BY_USER: &str = "stopped by the user";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A note on a run, like `git notes` on a commit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct RunNote {
+    /// unix timestamp (seconds)
+    pub(crate) time: u64,
+    pub(crate) text: String,
+}
+
+/// Description of a single training run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RunRecord {
+    pub(crate) id: String,
+    /// name of the dataset snapshot the run was trained on
+    pub(crate) snapshot: Option<String>,
+    /// unix timestamp (seconds) of the start of the run
+    pub(crate) started: u64,
+    /// id of the model in the model registry
+    #[serde(default)]
+    pub(crate) model: String,
+    #[serde(default)]
+    pub(crate) seed: u64,
+    /// unix timestamp (seconds) of the end of the run, `None` while it is running
+    #[serde(default)]
+    pub(crate) finished: Option<u64>,
+    /// why the run failed or that it was stopped
+    #[serde(default)]
+    pub(crate) error: Option<String>,
+    /// class names in the order of the model outputs
+    #[serde(default)]
+    pub(crate) classes: Vec<String>,
+    /// hyperparameters of the model (all of them, including the defaults)
+    #[serde(default)]
+    pub(crate) params: Params,
+    /// metrics of the trained model on the validation split
+    #[serde(default)]
+    pub(crate) metrics: Metrics,
+    /// when the run writes checkpoints and by which metric it picks the best epoch
+    #[serde(default)]
+    pub(crate) checkpoints: CheckpointConfig,
+    #[serde(default)]
+    pub(crate) best: Option<BestEpoch>,
+    /// notes of the user, oldest first
+    #[serde(default)]
+    pub(crate) notes: Vec<RunNote>,
+    /// id of the search or cross-validation the run belongs to
+    #[serde(default)]
+    pub(crate) group: Option<String>,
+    /// fold the run is validated on, for runs of a cross-validation
+    #[serde(default)]
+    pub(crate) fold: Option<usize>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl RunRecord {
+    /// `running`, `finished`, `stopped` or `failed`
+    pub(crate) fn status(&self) -> &'static str {
+        match (&self.finished, &self.error) {
+            (None, _) => "running",
+            (Some(_), None) => "finished",
+            (Some(_), Some(e)) if e == STOPPED_BY_USER => "stopped",
+            (Some(_), Some(_)) => "failed",
+        }
+    }
+
+    /// seconds from the start to the end of the run, `None` while it is running
+    pub(crate) fn duration(&self) -> Option<u64> {
+        Some(self.finished?.saturating_sub(self.started))
+    }
+}
+
+pub(crate) fn run_dir(project_dir: &Path, run_id: &str) -> PathBuf {
+    project_dir.join(RUNS_DIR).join(run_id)
+}
+
+/// # create a new run
+///
+/// allocates a fresh run directory (named after the start time) and writes the run record.
+///
+/// returns:
+///     Result with the new record
+pub(crate) fn create_run(
+    project_dir: &Path,
+    snapshot: Option<&str>,
+) -> Result<RunRecord, Box<dyn Error>> {
+    let started = unix_now();
+    fs::create_dir_all(project_dir.join(RUNS_DIR))?;
+
+    // several runs may be started within the same second
+    let mut counter = 0;
+    let id = loop {
+        let id = if counter == 0 {
+            format!("run-{}", started)
+        } else {
+            format!("run-{}-{}", started, counter)
+        };
+        match fs::create_dir(run_dir(project_dir, &id)) {
+            Ok(()) => break id,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let record = RunRecord {
+        id,
+        snapshot: snapshot.map(str::to_string),
+        started,
+        model: String::new(),
+        seed: 0,
+        finished: None,
+        error: None,
+        classes: Vec::new(),
+        params: Params::new(),
+        metrics: Metrics::new(),
+        checkpoints: CheckpointConfig::default(),
+        best: None,
+        notes: Vec::new(),
+        group: None,
+        fold: None,
+    };
+    save_run(project_dir, &record)?;
+    Ok(record)
+}
+
+pub(crate) fn save_run(project_dir: &Path, record: &RunRecord) -> Result<(), Box<dyn Error>> {
+    let path = run_dir(project_dir, &record.id).join(RUN_FILE_NAME);
+    fs::write(path, toml::to_string(record)?)?;
+    Ok(())
+}
+
+pub(crate) fn load_run(project_dir: &Path, run_id: &str) -> Result<RunRecord, Box<dyn Error>> {
+    let contents = fs::read_to_string(run_dir(project_dir, run_id).join(RUN_FILE_NAME))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// # add a note to a run
+///
+/// returns:
+///     Result with the updated record
+pub(crate) fn add_note(
+    project_dir: &Path,
+    run_id: &str,
+    text: &str,
+) -> Result<RunRecord, Box<dyn Error>> {
+    let mut record = load_run(project_dir, run_id)?;
+    record.notes.push(RunNote {
+        time: unix_now(),
+        text: text.to_string(),
+    });
+    save_run(project_dir, &record)?;
+    Ok(record)
+}
+
+/// append the report of an epoch to the epoch log of a run
+pub(crate) fn append_epoch(
+    project_dir: &Path,
+    run_id: &str,
+    report: &EpochReport,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run_dir(project_dir, run_id).join(EPOCHS_FILE_NAME))?;
+    writeln!(file, "{}", serde_json::to_string(report)?)?;
+    Ok(())
+}
+
+/// replace the epoch log of a run, e.g. by the epochs of the checkpoint it continues from
+pub(crate) fn save_epochs(
+    project_dir: &Path,
+    run_id: &str,
+    reports: &[EpochReport],
+) -> Result<(), Box<dyn Error>> {
+    let mut contents = String::new();
+    for report in reports {
+        contents.push_str(&serde_json::to_string(report)?);
+        contents.push('\n');
+    }
+    fs::write(
+        run_dir(project_dir, run_id).join(EPOCHS_FILE_NAME),
+        contents,
+    )?;
+    Ok(())
+}
+
+/// # reports of all epochs of a run, oldest first
+///
+/// unreadable lines (e.g. the last one after a crash) are skipped, runs without an epoch log
+/// have no epochs.
+pub(crate) fn load_epochs(project_dir: &Path, run_id: &str) -> Vec<EpochReport> {
+    fs::read_to_string(run_dir(project_dir, run_id).join(EPOCHS_FILE_NAME))
+        .map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// # a unix timestamp as UTC date and time, e.g. `2024-06-01 14:03`
+///
+/// uses the days-to-civil conversion of Howard Hinnant's date algorithms.
+pub(crate) fn format_unix_time(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let minutes = seconds % 86400 / 60;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// all runs of a project, oldest first
+///
+/// run directories without a readable `run.toml` are skipped.
+pub(crate) fn load_runs(project_dir: &Path) -> Vec<RunRecord> {
+    let Ok(entries) = fs::read_dir(project_dir.join(RUNS_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut runs: Vec<RunRecord> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| fs::read_to_string(entry.path().join(RUN_FILE_NAME)).ok())
+        .filter_map(|contents| toml::from_str(&contents).ok())
+        .collect();
+
+    runs.sort_by(|a, b| (a.started, &a.id).cmp(&(b.started, &b.id)));
+    runs
+}
@@ -0,0 +1,843 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Hyperparameter search
+//!
+//! A search trains many runs of one model with hyperparameters taken from a search space:
+//! every combination of a grid, random samples, or successive halving / Hyperband, which train
+//! many random candidates with a small budget (e.g. few epochs) and only the best of them with
+//! more. Trials run in parallel, each one is a normal run of the project (see [`crate::runs`]).
+//!
+//! The search itself is kept in `<project>/searches/<search id>.toml`, with the hyperparameters
+//! and the score of every trial.
+
+use crate::engine::run_jobs;
+use crate::metrics::higher_is_better;
+use crate::model::{Hyperparameter, ParamKind, ParamValue, Params};
+use crate::rng::Rng;
+use crate::runs::RunRecord;
+use crate::store::unix_now;
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+/// directory inside a project holding all searches
+pub(crate) const SEARCHES_DIR: &str = "searches";
+
+/// values of a range in a grid if the range does not say otherwise
+const DEFAULT_GRID_STEPS: usize = 3;
+
+/// more trials than this are refused, a grid grows quickly
+const MAX_TRIALS: usize = 1000;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// How a search picks the hyperparameters of its trials
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Strategy {
+    /// every combination of the searched values
+    #[default]
+    Grid,
+    /// random samples from the search space
+    Random,
+    /// random samples, trained with a growing budget while only the best ones go on
+    Halving,
+    /// successive halving with several trade-offs between candidates and budget
+    Hyperband,
+}
+
+/// Values a hyperparameter takes in a search
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum ParamSpace {
+    /// exactly these values (choices, booleans or hand picked numbers)
+    Values { values: Vec<ParamValue> },
+    /// numbers between `min` and `max`, grids take `steps` evenly spaced ones
+    Range {
+        min: f64,
+        max: f64,
+        /// sampled and spaced on a logarithmic scale
+        log: bool,
+        steps: usize,
+    },
+}
+
+/// Settings of a search over the hyperparameters of a model
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct SearchConfig {
+    pub(crate) strategy: Strategy,
+    /// searched hyperparameters, all others keep the value of the model form
+    pub(crate) space: BTreeMap<String, ParamSpace>,
+    /// number of random candidates (random search and successive halving)
+    pub(crate) trials: usize,
+    /// validation metric ranking the trials, e.g. `val_accuracy`
+    pub(crate) metric: String,
+    /// integer hyperparameter used as budget by successive halving and Hyperband, its value in
+    /// the model form is the largest budget
+    pub(crate) resource: String,
+    /// smallest budget a trial is trained with
+    pub(crate) min_resource: i64,
+    /// factor between the budgets of two rungs, also the share of candidates going on
+    pub(crate) eta: usize,
+    /// trials trained at the same time, 0 for one per core
+    pub(crate) parallel: usize,
+}
+
+/// A round of successive halving: how many candidates train with which budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rung {
+    pub(crate) candidates: usize,
+    pub(crate) resource: i64,
+}
+
+/// A trained candidate of a search
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Trial {
+    /// empty if the run could not even be created
+    pub(crate) run_id: String,
+    /// all hyperparameters of the run
+    pub(crate) params: Params,
+    /// budget of the trial, `None` for grid and random search
+    pub(crate) resource: Option<i64>,
+    /// bracket of Hyperband (always 0 otherwise)
+    pub(crate) bracket: usize,
+    /// round of successive halving (always 0 for grid and random search)
+    pub(crate) rung: usize,
+    /// final value of the metric of the search, `None` for failed runs
+    pub(crate) score: Option<f64>,
+    pub(crate) error: Option<String>,
+}
+
+/// Description of a search and its trials so far
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SearchRecord {
+    pub(crate) id: String,
+    /// id of the model in the model registry
+    pub(crate) model: String,
+    /// unix timestamp (seconds) of the start of the search
+    pub(crate) started: u64,
+    /// unix timestamp (seconds) of the end of the search, `None` while it is running
+    #[serde(default)]
+    pub(crate) finished: Option<u64>,
+    #[serde(default)]
+    pub(crate) error: Option<String>,
+    pub(crate) seed: u64,
+    pub(crate) config: SearchConfig,
+    /// number of trials the search will train (if not stopped)
+    #[serde(default)]
+    pub(crate) planned: usize,
+    /// finished trials in the order they ended
+    #[serde(default)]
+    pub(crate) trials: Vec<Trial>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            strategy: Strategy::Grid,
+            space: BTreeMap::new(),
+            trials: 20,
+            metric: "val_accuracy".to_string(),
+            resource: "epochs".to_string(),
+            min_resource: 1,
+            eta: 3,
+            parallel: 0,
+        }
+    }
+}
+
+impl Strategy {
+    pub(crate) const ALL: [Strategy; 4] = [
+        Strategy::Grid,
+        Strategy::Random,
+        Strategy::Halving,
+        Strategy::Hyperband,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Strategy::Grid => "grid",
+            Strategy::Random => "random",
+            Strategy::Halving => "successive halving",
+            Strategy::Hyperband => "Hyperband",
+        }
+    }
+
+    /// whether the strategy trains with a growing budget
+    pub(crate) fn uses_resource(&self) -> bool {
+        matches!(self, Strategy::Halving | Strategy::Hyperband)
+    }
+}
+
+impl ParamSpace {
+    /// # values of a grid
+    ///
+    /// integer ranges may give fewer values than `steps` after rounding.
+    fn grid_values(
+        &self,
+        hyperparameter: &Hyperparameter,
+    ) -> Result<Vec<ParamValue>, Box<dyn Error>> {
+        let values = match self {
+            ParamSpace::Values { values } => values.clone(),
+            ParamSpace::Range {
+                min,
+                max,
+                log,
+                steps,
+            } => {
+                let steps = (*steps).max(1);
+                (0..steps)
+                    .map(|i| {
+                        let t = if steps == 1 {
+                            0.0
+                        } else {
+                            i as f64 / (steps - 1) as f64
+                        };
+                        let value = if *log {
+                            (min.ln() + (max.ln() - min.ln()) * t).exp()
+                        } else {
+                            min + (max - min) * t
+                        };
+                        ParamValue::Float(value.clamp(*min, *max))
+                    })
+                    .collect()
+            }
+        };
+        let mut checked: Vec<ParamValue> = Vec::new();
+        for value in values {
+            let value = hyperparameter.check(&value)?;
+            if !checked.contains(&value) {
+                checked.push(value);
+            }
+        }
+        Ok(checked)
+    }
+
+    /// a random value of the space
+    fn sample(
+        &self,
+        hyperparameter: &Hyperparameter,
+        rng: &mut Rng,
+    ) -> Result<ParamValue, Box<dyn Error>> {
+        let value = match self {
+            ParamSpace::Values { values } if values.is_empty() => {
+                return Err(format!("no values to search for {}", hyperparameter.name).into())
+            }
+            ParamSpace::Values { values } => values[rng.below(values.len())].clone(),
+            ParamSpace::Range { min, max, log, .. } => {
+                let value = if *log {
+                    rng.uniform(min.ln(), max.ln()).exp()
+                } else {
+                    rng.uniform(*min, *max)
+                };
+                // the logarithm may leave the range by a rounding error
+                ParamValue::Float(value.clamp(*min, *max))
+            }
+        };
+        hyperparameter.check(&value)
+    }
+
+    /// # position of a value within the space, for plotting
+    ///
+    /// returns:
+    ///     0 for the smallest (first) and 1 for the largest (last) value, `None` for values
+    ///     outside of the space
+    pub(crate) fn position(&self, value: &ParamValue) -> Option<f64> {
+        match self {
+            ParamSpace::Values { values } => {
+                let index = values.iter().position(|v| v == value)?;
+                Some(if values.len() > 1 {
+                    index as f64 / (values.len() - 1) as f64
+                } else {
+                    0.5
+                })
+            }
+            ParamSpace::Range { min, max, log, .. } => {
+                let v = value.as_f64();
+                let (v, min, max) = if *log {
+                    (v.ln(), min.ln(), max.ln())
+                } else {
+                    (v, *min, *max)
+                };
+                if max - min <= f64::EPSILON {
+                    return Some(0.5);
+                }
+                Some(((v - min) / (max - min)).clamp(0.0, 1.0))
+            }
+        }
+    }
+
+    /// labels along a plot axis, from the bottom (position 0) to the top (position 1)
+    pub(crate) fn ticks(&self) -> Vec<String> {
+        match self {
+            ParamSpace::Values { values } => values.iter().map(ParamValue::display).collect(),
+            ParamSpace::Range { min, max, .. } => vec![format!("{}", min), format!("{}", max)],
+        }
+    }
+}
+
+/// # parse the search space of a hyperparameter as typed into the search form
+///
+/// `min..max [log] [steps]` is a range of numbers, e.g. `1e-4..1e-1 log 4`, anything else a
+/// comma separated list of values, e.g. `32, 64, 128` or `adam, sgd`.
+///
+/// returns:
+///     Result with the space, `None` for an empty text (the hyperparameter is not searched)
+pub(crate) fn parse_space(
+    hyperparameter: &Hyperparameter,
+    text: &str,
+) -> Result<Option<ParamSpace>, Box<dyn Error>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let name = hyperparameter.name;
+    if let Some((min, rest)) = text.split_once("..") {
+        if !matches!(
+            hyperparameter.kind,
+            ParamKind::Int { .. } | ParamKind::Float { .. }
+        ) {
+            return Err(format!("{} is not a number, list its values instead", name).into());
+        }
+        let mut words = rest.split_whitespace();
+        let number = |word: Option<&str>| -> Result<f64, Box<dyn Error>> {
+            word.and_then(|w| w.parse::<f64>().ok())
+                .ok_or_else(|| format!("{}: expected min..max, got {:?}", name, text).into())
+        };
+        let min = number(Some(min.trim()))?;
+        let max = number(words.next())?;
+        let (mut log, mut steps) = (false, DEFAULT_GRID_STEPS);
+        for word in words {
+            match word {
+                "log" => log = true,
+                _ => {
+                    steps = word
+                        .parse()
+                        .ok()
+                        .filter(|s| *s > 0)
+                        .ok_or_else(|| format!("{}: unexpected {:?}", name, word))?
+                }
+            }
+        }
+        if min > max {
+            return Err(format!("{}: {} is larger than {}", name, min, max).into());
+        }
+        if log && min <= 0.0 {
+            return Err(format!("{}: a log range has to start above 0", name).into());
+        }
+        hyperparameter.check(&ParamValue::Float(min))?;
+        hyperparameter.check(&ParamValue::Float(max))?;
+        return Ok(Some(ParamSpace::Range {
+            min,
+            max,
+            log,
+            steps,
+        }));
+    }
+
+    let values = text
+        .split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let value = match &hyperparameter.kind {
+                ParamKind::Int { .. } | ParamKind::Float { .. } => ParamValue::Float(
+                    word.parse()
+                        .map_err(|_| format!("{}: {:?} is not a number", name, word))?,
+                ),
+                ParamKind::Bool => ParamValue::Bool(
+                    word.parse()
+                        .map_err(|_| format!("{}: {:?} is neither true nor false", name, word))?,
+                ),
+                ParamKind::Choice(_) => ParamValue::Text(word.to_string()),
+            };
+            hyperparameter.check(&value)
+        })
+        .collect::<Result<Vec<ParamValue>, Box<dyn Error>>>()?;
+    Ok(Some(ParamSpace::Values { values }))
+}
+
+/// a search space as typed into the search form, see [`parse_space`]
+pub(crate) fn format_space(space: &ParamSpace) -> String {
+    match space {
+        ParamSpace::Values { values } => values
+            .iter()
+            .map(ParamValue::display)
+            .collect::<Vec<String>>()
+            .join(", "),
+        ParamSpace::Range {
+            min,
+            max,
+            log,
+            steps,
+        } => {
+            let mut text = format!("{}..{}", min, max);
+            if *log {
+                text.push_str(" log");
+            }
+            if *steps != DEFAULT_GRID_STEPS {
+                text.push_str(&format!(" {}", steps));
+            }
+            text
+        }
+    }
+}
+
+/// # integer logarithm
+///
+/// returns:
+///     the largest `k` with `base^k <= value`
+fn log_floor(value: f64, base: usize) -> usize {
+    let mut k = 0;
+    let mut power = base as f64;
+    while power <= value + 1e-9 {
+        k += 1;
+        power *= base as f64;
+    }
+    k
+}
+
+/// # rungs of successive halving
+///
+/// `rounds` rungs start with `candidates` candidates and the budget `max_resource / eta^(rounds
+/// - 1)`; every rung keeps the best `1 / eta` of the candidates and multiplies the budget by
+/// `eta`, the last one trains with `max_resource`.
+fn rungs(candidates: usize, max_resource: i64, rounds: usize, eta: usize) -> Vec<Rung> {
+    (0..rounds)
+        .map(|i| Rung {
+            candidates: (candidates / eta.pow(i as u32)).max(1),
+            resource: ((max_resource as f64 / (eta as f64).powi((rounds - 1 - i) as i32)).round()
+                as i64)
+                .max(1),
+        })
+        .collect()
+}
+
+/// # rungs of successive halving with `candidates` random candidates
+///
+/// as many rungs as the budget range allows, but never so many that a rung would keep less
+/// than one candidate.
+pub(crate) fn halving_rungs(
+    candidates: usize,
+    max_resource: i64,
+    min_resource: i64,
+    eta: usize,
+) -> Vec<Rung> {
+    let halvings = log_floor(max_resource as f64 / min_resource.max(1) as f64, eta)
+        .min(log_floor(candidates as f64, eta));
+    rungs(candidates, max_resource, halvings + 1, eta)
+}
+
+/// # brackets of Hyperband
+///
+/// from the most aggressive bracket (many candidates, smallest budget) to plain random search
+/// with the full budget, every bracket using about the same total budget.
+pub(crate) fn hyperband_brackets(
+    max_resource: i64,
+    min_resource: i64,
+    eta: usize,
+) -> Vec<Vec<Rung>> {
+    let s_max = log_floor(max_resource as f64 / min_resource.max(1) as f64, eta);
+    (0..=s_max)
+        .rev()
+        .map(|s| {
+            let candidates =
+                ((s_max + 1) as f64 / (s + 1) as f64 * eta.pow(s as u32) as f64).ceil() as usize;
+            rungs(candidates, max_resource, s + 1, eta)
+        })
+        .collect()
+}
+
+impl SearchConfig {
+    /// hyperparameters of the model that are searched, in the order of the model
+    fn searched<'a>(
+        &'a self,
+        hyperparameters: &'a [Hyperparameter],
+    ) -> impl Iterator<Item = (&'a Hyperparameter, &'a ParamSpace)> + 'a {
+        hyperparameters
+            .iter()
+            .filter_map(|h| self.space.get(h.name).map(|space| (h, space)))
+    }
+
+    /// # check the search against the hyperparameters of the model
+    ///
+    /// returns:
+    ///     Result with the largest budget of successive halving (the value of the resource
+    ///     hyperparameter in `base`), `None` for the other strategies
+    fn check(
+        &self,
+        hyperparameters: &[Hyperparameter],
+        base: &Params,
+    ) -> Result<Option<i64>, Box<dyn Error>> {
+        if let Some(unknown) = self
+            .space
+            .keys()
+            .find(|name| !hyperparameters.iter().any(|h| h.name == name.as_str()))
+        {
+            return Err(format!("unknown hyperparameter {:?}", unknown).into());
+        }
+        if self.space.is_empty() {
+            return Err("choose at least one hyperparameter to search".into());
+        }
+        if self.metric.trim().is_empty() {
+            return Err("choose the metric ranking the trials".into());
+        }
+        if !self.strategy.uses_resource() {
+            return Ok(None);
+        }
+
+        let resource = hyperparameters
+            .iter()
+            .find(|h| h.name == self.resource)
+            .ok_or_else(|| format!("the model has no hyperparameter {:?}", self.resource))?;
+        if !matches!(resource.kind, ParamKind::Int { .. }) {
+            return Err(format!("the budget {} is not an integer", self.resource).into());
+        }
+        if self.space.contains_key(&self.resource) {
+            return Err(format!("the budget {} cannot be searched as well", self.resource).into());
+        }
+        if self.eta < 2 {
+            return Err("eta has to be at least 2".into());
+        }
+        let max_resource = base
+            .get(&self.resource)
+            .map_or(0, |v| v.as_f64().round() as i64);
+        if self.min_resource < 1 || self.min_resource > max_resource {
+            return Err(format!(
+                "the smallest budget has to be between 1 and {} = {}",
+                self.resource, max_resource
+            )
+            .into());
+        }
+        Ok(Some(max_resource))
+    }
+
+    /// # every combination of the grid, in a fixed order
+    ///
+    /// returns:
+    ///     Result with the complete hyperparameters of every candidate
+    fn grid(
+        &self,
+        hyperparameters: &[Hyperparameter],
+        base: &Params,
+    ) -> Result<Vec<Params>, Box<dyn Error>> {
+        let mut candidates = vec![base.clone()];
+        for (hyperparameter, space) in self.searched(hyperparameters) {
+            let values = space.grid_values(hyperparameter)?;
+            if candidates.len() * values.len() > MAX_TRIALS {
+                return Err(format!(
+                    "the grid has more than {} combinations, search fewer values",
+                    MAX_TRIALS
+                )
+                .into());
+            }
+            candidates = candidates
+                .into_iter()
+                .flat_map(|params| {
+                    values.iter().map(move |value| {
+                        let mut params = params.clone();
+                        params.insert(hyperparameter.name.to_string(), value.clone());
+                        params
+                    })
+                })
+                .collect();
+        }
+        Ok(candidates)
+    }
+
+    /// a random candidate, the searched hyperparameters drawn independently
+    fn sample(
+        &self,
+        hyperparameters: &[Hyperparameter],
+        base: &Params,
+        rng: &mut Rng,
+    ) -> Result<Params, Box<dyn Error>> {
+        let mut params = base.clone();
+        for (hyperparameter, space) in self.searched(hyperparameters) {
+            params.insert(
+                hyperparameter.name.to_string(),
+                space.sample(hyperparameter, rng)?,
+            );
+        }
+        Ok(params)
+    }
+
+    /// # number of trials a search trains if it is not stopped
+    ///
+    /// also checks the search, so mistakes show up before anything is trained.
+    pub(crate) fn planned_trials(
+        &self,
+        hyperparameters: &[Hyperparameter],
+        base: &Params,
+    ) -> Result<usize, Box<dyn Error>> {
+        let max_resource = self.check(hyperparameters, base)?;
+        let count = |rungs: &[Rung]| rungs.iter().map(|r| r.candidates).sum::<usize>();
+        let planned = match (self.strategy, max_resource) {
+            (Strategy::Grid, _) => self.grid(hyperparameters, base)?.len(),
+            (Strategy::Random, _) => self.trials,
+            (Strategy::Halving, Some(max)) => count(&halving_rungs(
+                self.trials,
+                max,
+                self.min_resource,
+                self.eta,
+            )),
+            (Strategy::Hyperband, Some(max)) => {
+                hyperband_brackets(max, self.min_resource, self.eta)
+                    .iter()
+                    .map(|bracket| count(bracket))
+                    .sum()
+            }
+            _ => 0,
+        };
+        if planned == 0 {
+            return Err("the search has no trials".into());
+        }
+        if planned > MAX_TRIALS {
+            return Err(format!(
+                "the search would train {} runs, more than {}",
+                planned, MAX_TRIALS
+            )
+            .into());
+        }
+        Ok(planned)
+    }
+}
+
+/// order of two scores of `metric`, better first and missing scores last
+fn compare_scores(metric: &str, a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if higher_is_better(metric) => b.total_cmp(&a),
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl SearchRecord {
+    /// a new search, not saved yet
+    pub(crate) fn new(
+        model: &str,
+        seed: u64,
+        config: SearchConfig,
+        planned: usize,
+    ) -> SearchRecord {
+        SearchRecord {
+            id: String::new(),
+            model: model.to_string(),
+            started: unix_now(),
+            finished: None,
+            error: None,
+            seed,
+            config,
+            planned,
+            trials: Vec::new(),
+        }
+    }
+
+    /// # indices of the trials from the best to the worst
+    ///
+    /// trials trained with a larger budget come first, failed ones last.
+    pub(crate) fn leaderboard(&self) -> Vec<usize> {
+        let mut ranked: Vec<usize> = (0..self.trials.len()).collect();
+        ranked.sort_by(|a, b| {
+            let (a, b) = (&self.trials[*a], &self.trials[*b]);
+            a.score
+                .is_none()
+                .cmp(&b.score.is_none())
+                .then(b.resource.cmp(&a.resource))
+                .then(compare_scores(&self.config.metric, a.score, b.score))
+        });
+        ranked
+    }
+
+    /// the best trial so far
+    pub(crate) fn best(&self) -> Option<&Trial> {
+        self.leaderboard()
+            .first()
+            .map(|i| &self.trials[*i])
+            .filter(|trial| trial.score.is_some())
+    }
+}
+
+/// # run a search
+///
+/// `train` trains a run with the given hyperparameters (from several threads at once) and
+/// returns its record, `on_trial` sees the search after every finished trial. Stops early once
+/// `cancel` is set.
+pub(crate) fn run_search(
+    search: &mut SearchRecord,
+    hyperparameters: &[Hyperparameter],
+    base: &Params,
+    threads: usize,
+    cancel: &AtomicBool,
+    train: &(dyn Fn(&Params) -> Result<RunRecord, String> + Sync),
+    on_trial: &mut dyn FnMut(&SearchRecord),
+) -> Result<(), Box<dyn Error>> {
+    let config = search.config.clone();
+    let max_resource = config.check(hyperparameters, base)?;
+    let mut rng = Rng::new(search.seed);
+
+    // trains a rung and returns the score of every candidate
+    let mut train_rung = |search: &mut SearchRecord,
+                          candidates: &[Params],
+                          resource: Option<i64>,
+                          bracket: usize,
+                          rung: usize| {
+        let mut scores = vec![None; candidates.len()];
+        run_jobs(candidates, threads, cancel, train, &mut |index, result| {
+            let trial = match result {
+                Ok(record) => Trial {
+                    run_id: record.id,
+                    params: record.params,
+                    resource,
+                    bracket,
+                    rung,
+                    score: record
+                        .metrics
+                        .get(&config.metric)
+                        .copied()
+                        .filter(|v| v.is_finite() && record.error.is_none()),
+                    error: record.error,
+                },
+                Err(error) => Trial {
+                    run_id: String::new(),
+                    params: candidates[index].clone(),
+                    resource,
+                    bracket,
+                    rung,
+                    score: None,
+                    error: Some(error),
+                },
+            };
+            scores[index] = trial.score;
+            search.trials.push(trial);
+            on_trial(search);
+        });
+        scores
+    };
+
+    let brackets = match (config.strategy, max_resource) {
+        (Strategy::Grid, _) => {
+            let candidates = config.grid(hyperparameters, base)?;
+            train_rung(search, &candidates, None, 0, 0);
+            return Ok(());
+        }
+        (Strategy::Random, _) => {
+            let candidates = (0..config.trials)
+                .map(|_| config.sample(hyperparameters, base, &mut rng))
+                .collect::<Result<Vec<Params>, Box<dyn Error>>>()?;
+            train_rung(search, &candidates, None, 0, 0);
+            return Ok(());
+        }
+        (Strategy::Halving, Some(max)) => vec![halving_rungs(
+            config.trials,
+            max,
+            config.min_resource,
+            config.eta,
+        )],
+        (Strategy::Hyperband, Some(max)) => {
+            hyperband_brackets(max, config.min_resource, config.eta)
+        }
+        _ => return Ok(()),
+    };
+
+    for (bracket, rungs) in brackets.iter().enumerate() {
+        let Some(first) = rungs.first() else {
+            continue;
+        };
+        let mut candidates = (0..first.candidates)
+            .map(|_| config.sample(hyperparameters, base, &mut rng))
+            .collect::<Result<Vec<Params>, Box<dyn Error>>>()?;
+        for (rung, round) in rungs.iter().enumerate() {
+            if cancel.load(AtomicOrdering::Relaxed) {
+                return Ok(());
+            }
+            for params in &mut candidates {
+                params.insert(config.resource.clone(), ParamValue::Int(round.resource));
+            }
+            let scores = train_rung(search, &candidates, Some(round.resource), bracket, rung);
+
+            // the best candidates go on to the next rung with a larger budget
+            let Some(next) = rungs.get(rung + 1) else {
+                break;
+            };
+            let mut ranked: Vec<usize> = (0..candidates.len()).collect();
+            ranked.sort_by(|a, b| compare_scores(&config.metric, scores[*a], scores[*b]));
+            ranked.truncate(next.candidates);
+            candidates = ranked.into_iter().map(|i| candidates[i].clone()).collect();
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn search_path(project_dir: &Path, search_id: &str) -> PathBuf {
+    project_dir
+        .join(SEARCHES_DIR)
+        .join(format!("{}.toml", search_id))
+}
+
+/// # create the file of a new search
+///
+/// the id is named after the start time of the search.
+///
+/// returns:
+///     Result with the search and its new id
+pub(crate) fn create_search(
+    project_dir: &Path,
+    mut search: SearchRecord,
+) -> Result<SearchRecord, Box<dyn Error>> {
+    fs::create_dir_all(project_dir.join(SEARCHES_DIR))?;
+    let mut counter = 0;
+    loop {
+        search.id = if counter == 0 {
+            format!("search-{}", search.started)
+        } else {
+            format!("search-{}-{}", search.started, counter)
+        };
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(search_path(project_dir, &search.id))
+        {
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    save_search(project_dir, &search)?;
+    Ok(search)
+}
+
+pub(crate) fn save_search(project_dir: &Path, search: &SearchRecord) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        search_path(project_dir, &search.id),
+        toml::to_string(search)?,
+    )?;
+    Ok(())
+}
+
+/// all readable searches of a project, oldest first
+pub(crate) fn load_searches(project_dir: &Path) -> Vec<SearchRecord> {
+    let Ok(entries) = fs::read_dir(project_dir.join(SEARCHES_DIR)) else {
+        return Vec::new();
+    };
+    let mut searches: Vec<SearchRecord> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|e| e == "toml"))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|contents| toml::from_str(&contents).ok())
+        .collect();
+    searches.sort_by(|a, b| (a.started, &a.id).cmp(&(b.started, &b.id)));
+    searches
+}
@@ -0,0 +1,601 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Sensor recordings: resampling, filtering and sliding windows
+//!
+//! A recording is a `.csv` / `.tsv` file with a header row, a time column (seconds) first and
+//! one column per channel. Empty or non numeric cells are missing values, so channels may be
+//! sampled at different rates. Times are relative to the first row, the same time axis the
+//! `Segment` annotations of the item use.
+
+use crate::audio::fft;
+use crate::store::{Annotation, Shape};
+use crate::tabular::quote;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// One channel of a recording as (time, value) pairs
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Channel {
+    pub(crate) name: String,
+    pub(crate) samples: Vec<(f64, f64)>,
+}
+
+/// Channels of a recording, regularly sampled after [`resample_channels`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Signals {
+    pub(crate) sample_rate: f64,
+    pub(crate) names: Vec<String>,
+    /// `[channel][sample]`
+    pub(crate) values: Vec<Vec<f64>>,
+}
+
+/// How missing samples are filled in when resampling
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GapFill {
+    /// interpolate between the samples around the gap
+    Linear,
+    /// repeat the last sample before the gap
+    Hold,
+    Zero,
+}
+
+/// Zero phase butterworth filter (2nd order, applied forwards and backwards)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum SignalFilter {
+    None,
+    LowPass { cutoff: f64 },
+    HighPass { cutoff: f64 },
+    BandPass { low: f64, high: f64 },
+}
+
+/// How the annotated intervals label a window
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WindowLabelling {
+    /// the class covering most of the window, if it covers at least half of it
+    Majority,
+    /// every class overlapping the window
+    AnyOverlap,
+}
+
+/// Hand-crafted features computed per window and channel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct WindowFeatures {
+    pub(crate) mean: bool,
+    pub(crate) std: bool,
+    pub(crate) rms: bool,
+    /// number of equally wide frequency bands whose energy is computed, 0 disables them
+    pub(crate) fft_bands: usize,
+}
+
+/// Sensor preprocessing settings of a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SensorConfig {
+    /// common sample rate of all channels [Hz]
+    pub(crate) sample_rate: f64,
+    pub(crate) gap_fill: GapFill,
+    /// gaps longer than this [s] are filled with [`SensorConfig::gap_fill`], shorter ones are
+    /// always interpolated
+    pub(crate) max_gap: f64,
+    pub(crate) filter: SignalFilter,
+    /// z-score normalisation per channel
+    pub(crate) normalise: bool,
+    /// window length [s]
+    pub(crate) window: f64,
+    /// overlap of consecutive windows, between 0 and 1
+    pub(crate) overlap: f64,
+    pub(crate) labelling: WindowLabelling,
+    pub(crate) features: WindowFeatures,
+}
+
+/// A window cut out of a recording
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Window {
+    /// sample range inside the signals
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    /// classes of the window, empty if it is not labelled
+    pub(crate) labels: Vec<String>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        SensorConfig {
+            sample_rate: 50.0,
+            gap_fill: GapFill::Linear,
+            max_gap: 0.1,
+            filter: SignalFilter::None,
+            normalise: true,
+            window: 2.0,
+            overlap: 0.5,
+            labelling: WindowLabelling::Majority,
+            features: WindowFeatures {
+                mean: true,
+                std: true,
+                rms: true,
+                fft_bands: 4,
+            },
+        }
+    }
+}
+
+impl GapFill {
+    pub(crate) const ALL: [GapFill; 3] = [GapFill::Linear, GapFill::Hold, GapFill::Zero];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            GapFill::Linear => "linear interpolation",
+            GapFill::Hold => "hold last value",
+            GapFill::Zero => "zero",
+        }
+    }
+}
+
+impl WindowLabelling {
+    pub(crate) const ALL: [WindowLabelling; 2] =
+        [WindowLabelling::Majority, WindowLabelling::AnyOverlap];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            WindowLabelling::Majority => "majority",
+            WindowLabelling::AnyOverlap => "any overlap",
+        }
+    }
+}
+
+impl SignalFilter {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SignalFilter::None => "no filter",
+            SignalFilter::LowPass { .. } => "low-pass",
+            SignalFilter::HighPass { .. } => "high-pass",
+            SignalFilter::BandPass { .. } => "band-pass",
+        }
+    }
+}
+
+/// # read a recording
+///
+/// the delimiter is a tab for `.tsv` files, otherwise a comma (or a semicolon, if the header
+/// contains one but no comma).
+pub(crate) fn read_recording(path: &Path) -> Result<Vec<Channel>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| format!("{}: empty file", path.display()))?;
+
+    let tsv = path
+        .extension()
+        .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("tsv"));
+    let delimiter = if tsv {
+        '\t'
+    } else if !header.contains(',') && header.contains(';') {
+        ';'
+    } else {
+        ','
+    };
+
+    let names: Vec<&str> = header.split(delimiter).map(str::trim).collect();
+    if names.len() < 2 {
+        return Err(format!(
+            "{}: expected a time column and at least one channel",
+            path.display()
+        )
+        .into());
+    }
+    let mut channels: Vec<Channel> = names[1..]
+        .iter()
+        .map(|name| Channel {
+            name: name.to_string(),
+            samples: Vec::new(),
+        })
+        .collect();
+
+    let mut origin = None;
+    for (row, line) in lines.enumerate() {
+        let mut cells = line.split(delimiter).map(str::trim);
+        let time: f64 = cells
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| format!("{}: invalid time in row {}", path.display(), row + 2))?;
+        let origin = *origin.get_or_insert(time);
+        for (channel, cell) in channels.iter_mut().zip(cells) {
+            if let Ok(value) = cell.parse::<f64>() {
+                if value.is_finite() {
+                    channel.samples.push((time - origin, value));
+                }
+            }
+        }
+    }
+    for channel in channels.iter_mut() {
+        channel.samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+    Ok(channels)
+}
+
+/// value of a channel at `time` (the samples are sorted by time)
+fn sample_at(samples: &[(f64, f64)], time: f64, gap_fill: GapFill, max_gap: f64) -> f64 {
+    let next = samples.partition_point(|(t, _)| *t < time);
+    match (
+        next.checked_sub(1).map(|i| samples[i]),
+        samples.get(next).copied(),
+    ) {
+        (_, Some((t, v))) if t == time => v,
+        (Some((t0, v0)), Some((t1, v1))) => {
+            if t1 - t0 <= max_gap || gap_fill == GapFill::Linear {
+                v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+            } else if gap_fill == GapFill::Hold {
+                v0
+            } else {
+                0.0
+            }
+        }
+        // before the first sample or after the last one
+        (Some((_, v)), None) | (None, Some((_, v))) => match gap_fill {
+            GapFill::Zero => 0.0,
+            GapFill::Linear | GapFill::Hold => v,
+        },
+        (None, None) => 0.0,
+    }
+}
+
+/// # sample all channels at a common rate
+///
+/// the signals span from the first to the last sample of any channel.
+pub(crate) fn resample_channels(
+    channels: &[Channel],
+    sample_rate: f64,
+    gap_fill: GapFill,
+    max_gap: f64,
+) -> Signals {
+    let end = channels
+        .iter()
+        .filter_map(|c| c.samples.last().map(|s| s.0))
+        .fold(0.0, f64::max);
+    let sample_rate = sample_rate.max(f64::EPSILON);
+    let count = (end * sample_rate).floor() as usize + 1;
+
+    Signals {
+        sample_rate,
+        names: channels.iter().map(|c| c.name.clone()).collect(),
+        values: channels
+            .iter()
+            .map(|c| {
+                (0..count)
+                    .map(|i| sample_at(&c.samples, i as f64 / sample_rate, gap_fill, max_gap))
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+/// biquad coefficients `(b0, b1, b2, a1, a2)` of a 2nd order butterworth filter
+fn butterworth(cutoff: f64, sample_rate: f64, high_pass: bool) -> [f64; 5] {
+    let nyquist = sample_rate / 2.0;
+    let k = (PI * (cutoff / nyquist).clamp(1e-6, 0.999) / 2.0).tan();
+    let q = std::f64::consts::FRAC_1_SQRT_2;
+    let norm = 1.0 / (1.0 + k / q + k * k);
+    let (b0, b1) = if high_pass {
+        (norm, -2.0 * norm)
+    } else {
+        (k * k * norm, 2.0 * k * k * norm)
+    };
+    [
+        b0,
+        b1,
+        b0,
+        2.0 * (k * k - 1.0) * norm,
+        (1.0 - k / q + k * k) * norm,
+    ]
+}
+
+fn biquad(values: &[f64], [b0, b1, b2, a1, a2]: [f64; 5]) -> Vec<f64> {
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+    // start in the steady state of the first value to avoid a jump at the border
+    if let Some(first) = values.first() {
+        let gain = (b0 + b1 + b2) / (1.0 + a1 + a2);
+        (x1, x2, y1, y2) = (*first, *first, first * gain, first * gain);
+    }
+    values
+        .iter()
+        .map(|x| {
+            let y = b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+            (x2, x1, y2, y1) = (x1, *x, y1, y);
+            y
+        })
+        .collect()
+}
+
+/// forwards and backwards, which cancels the phase shift
+fn filtfilt(values: &[f64], coefficients: [f64; 5]) -> Vec<f64> {
+    let mut forward = biquad(values, coefficients);
+    forward.reverse();
+    let mut backward = biquad(&forward, coefficients);
+    backward.reverse();
+    backward
+}
+
+pub(crate) fn apply_filter(values: &[f64], filter: SignalFilter, sample_rate: f64) -> Vec<f64> {
+    match filter {
+        SignalFilter::None => values.to_vec(),
+        SignalFilter::LowPass { cutoff } => {
+            filtfilt(values, butterworth(cutoff, sample_rate, false))
+        }
+        SignalFilter::HighPass { cutoff } => {
+            filtfilt(values, butterworth(cutoff, sample_rate, true))
+        }
+        SignalFilter::BandPass { low, high } => filtfilt(
+            &filtfilt(values, butterworth(low, sample_rate, true)),
+            butterworth(high, sample_rate, false),
+        ),
+    }
+}
+
+fn mean_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len().max(1) as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+/// `(v - mean) / std`, constant channels become 0
+pub(crate) fn z_score(values: &[f64]) -> Vec<f64> {
+    let (mean, std) = mean_std(values);
+    let std = if std == 0.0 { 1.0 } else { std };
+    values.iter().map(|v| (v - mean) / std).collect()
+}
+
+/// read, resample, filter and normalise a recording
+pub(crate) fn preprocess_recording(
+    path: &Path,
+    config: &SensorConfig,
+) -> Result<Signals, Box<dyn Error>> {
+    let channels = read_recording(path)?;
+    let mut signals = resample_channels(
+        &channels,
+        config.sample_rate,
+        config.gap_fill,
+        config.max_gap,
+    );
+    for values in signals.values.iter_mut() {
+        *values = apply_filter(values, config.filter, signals.sample_rate);
+        if config.normalise {
+            *values = z_score(values);
+        }
+    }
+    Ok(signals)
+}
+
+/// # cut the signals into windows and label them
+///
+/// `annotations` are the annotations of the recording, only `Segment` shapes count.
+/// Incomplete windows at the end are dropped.
+pub(crate) fn sliding_windows(
+    signals: &Signals,
+    annotations: &[&Annotation],
+    config: &SensorConfig,
+) -> Vec<Window> {
+    let length = ((config.window * signals.sample_rate).round() as usize).max(1);
+    let step = ((length as f64 * (1.0 - config.overlap.clamp(0.0, 0.99))).round() as usize).max(1);
+    let total = signals.values.first().map_or(0, Vec::len);
+    let segments: Vec<(&str, f64, f64)> = annotations
+        .iter()
+        .filter_map(|a| match a.shape {
+            Shape::Segment { start, end } => Some((a.class.as_str(), start as f64, end as f64)),
+            _ => None,
+        })
+        .collect();
+
+    (0..)
+        .map(|i| i * step)
+        .take_while(|start| start + length <= total)
+        .map(|start| {
+            let (t0, t1) = (
+                start as f64 / signals.sample_rate,
+                (start + length) as f64 / signals.sample_rate,
+            );
+            // covered time per class
+            let mut coverage: Vec<(&str, f64)> = Vec::new();
+            for (class, s, e) in &segments {
+                let overlap = e.min(t1) - s.max(t0);
+                if overlap <= 0.0 {
+                    continue;
+                }
+                match coverage.iter_mut().find(|(c, _)| c == class) {
+                    Some((_, covered)) => *covered += overlap,
+                    None => coverage.push((class, overlap)),
+                }
+            }
+
+            let labels = match config.labelling {
+                WindowLabelling::AnyOverlap => {
+                    coverage.iter().map(|(c, _)| c.to_string()).collect()
+                }
+                WindowLabelling::Majority => coverage
+                    .iter()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .filter(|(_, covered)| *covered >= (t1 - t0) / 2.0)
+                    .map(|(c, _)| vec![c.to_string()])
+                    .unwrap_or_default(),
+            };
+            Window {
+                start,
+                end: start + length,
+                labels,
+            }
+        })
+        .collect()
+}
+
+/// energy of `bands` equally wide frequency bands (0 Hz to half the sample rate)
+fn band_energies(values: &[f64], bands: usize) -> Vec<f64> {
+    let n = values.len().max(2).next_power_of_two();
+    let mut re = vec![0.0; n];
+    let mut im = vec![0.0; n];
+    re[..values.len()].copy_from_slice(values);
+    fft(&mut re, &mut im);
+
+    let bins = n / 2 + 1;
+    let mut energies = vec![0.0; bands];
+    for k in 0..bins {
+        let band = (k * bands / bins).min(bands - 1);
+        energies[band] += (re[k] * re[k] + im[k] * im[k]) / n as f64;
+    }
+    energies
+}
+
+/// names of the columns of [`window_features`]
+pub(crate) fn feature_names(signals: &Signals, features: &WindowFeatures) -> Vec<String> {
+    let mut names = Vec::new();
+    for channel in &signals.names {
+        if features.mean {
+            names.push(format!("{}_mean", channel));
+        }
+        if features.std {
+            names.push(format!("{}_std", channel));
+        }
+        if features.rms {
+            names.push(format!("{}_rms", channel));
+        }
+        for band in 0..features.fft_bands {
+            names.push(format!("{}_band{}", channel, band));
+        }
+    }
+    names
+}
+
+/// the enabled features of every channel of a window, in the order of [`feature_names`]
+pub(crate) fn window_features(
+    signals: &Signals,
+    window: &Window,
+    features: &WindowFeatures,
+) -> Vec<f64> {
+    let mut row = Vec::new();
+    for channel in &signals.values {
+        let values = &channel[window.start..window.end.min(channel.len())];
+        let (mean, std) = mean_std(values);
+        if features.mean {
+            row.push(mean);
+        }
+        if features.std {
+            row.push(std);
+        }
+        if features.rms {
+            let square = values.iter().map(|v| v * v).sum::<f64>() / values.len().max(1) as f64;
+            row.push(square.sqrt());
+        }
+        if features.fft_bands > 0 {
+            row.extend(band_energies(values, features.fft_bands));
+        }
+    }
+    row
+}
+
+/// # write the window features of recordings as one CSV table
+///
+/// every row is a window: item id, start and end time, the labels (joined with `|`) and the
+/// features. Names containing commas or quotes are quoted like RFC 4180 asks. Recordings whose
+/// channels differ from the first one are rejected, since their columns would not match.
+pub(crate) fn export_features(
+    path: &Path,
+    recordings: &[(u64, Signals, Vec<Window>)],
+    features: &WindowFeatures,
+) -> Result<usize, Box<dyn Error>> {
+    let mut file = std::io::BufWriter::new(fs::File::create(path)?);
+    let Some((_, first, _)) = recordings.first() else {
+        writeln!(file, "item_id,start,end,label")?;
+        return Ok(0);
+    };
+
+    let names: Vec<String> = feature_names(first, features)
+        .iter()
+        .map(|name| quote(name, ','))
+        .collect();
+    writeln!(file, "item_id,start,end,label,{}", names.join(","))?;
+    let mut rows = 0;
+    for (item_id, signals, windows) in recordings {
+        if signals.names != first.names {
+            return Err(format!(
+                "item {} has the channels {:?} instead of {:?}",
+                item_id, signals.names, first.names
+            )
+            .into());
+        }
+        for window in windows {
+            let values: Vec<String> = window_features(signals, window, features)
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                item_id,
+                window.start as f64 / signals.sample_rate,
+                window.end as f64 / signals.sample_rate,
+                quote(&window.labels.join("|"), ','),
+                values.join(",")
+            )?;
+            rows += 1;
+        }
+    }
+    file.flush()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tabular::read_table;
+
+    #[test]
+    fn exported_names_are_quoted() {
+        let signals = Signals {
+            sample_rate: 10.0,
+            names: vec!["acc, x".to_string(), "gyro \"y\"".to_string()],
+            values: vec![vec![1.0; 20], vec![2.0; 20]],
+        };
+        let windows = vec![Window {
+            start: 0,
+            end: 10,
+            labels: vec!["walk, fast".to_string(), "stairs".to_string()],
+        }];
+        let features = WindowFeatures {
+            mean: true,
+            std: false,
+            rms: false,
+            fft_bands: 0,
+        };
+
+        let path = std::env::temp_dir().join(format!("ai-lab-sensor-{}.csv", std::process::id()));
+        let rows = export_features(&path, &[(3, signals, windows)], &features).unwrap();
+        let table = read_table(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows, 1);
+        assert_eq!(
+            table.columns,
+            [
+                "item_id",
+                "start",
+                "end",
+                "label",
+                "acc, x_mean",
+                "gyro \"y\"_mean"
+            ]
+        );
+        assert_eq!(table.rows, [["3", "0", "1", "walk, fast|stairs", "1", "2"]]);
+    }
+}
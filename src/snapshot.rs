@@ -0,0 +1,254 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Dataset snapshots
+//!
+//! A snapshot freezes the current dataset of a project: every item together with the
+//! SHA-256 hash of its content and all annotations. Snapshots are written once to
+//! `<project>/snapshots/<name>.json` and never modified afterwards, so a training run that
+//! records the name of its snapshot can always be reproduced.
+
+use crate::store::{unix_now, Annotation, AnnotationStore, Item};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// directory inside a project holding all snapshots
+pub(crate) const SNAPSHOT_DIR: &str = "snapshots";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// An item as it was at the time of the snapshot
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SnapshotItem {
+    pub(crate) item: Item,
+    /// hex encoded SHA-256 hash of the file content
+    pub(crate) hash: String,
+}
+
+/// An immutable, named version of the dataset
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) name: String,
+    /// unix timestamp (seconds) of the moment the snapshot was taken
+    pub(crate) created: u64,
+    pub(crate) items: Vec<SnapshotItem>,
+    pub(crate) annotations: Vec<Annotation>,
+}
+
+/// Label change of a single item between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LabelChange {
+    pub(crate) path: String,
+    pub(crate) before: Vec<String>,
+    pub(crate) after: Vec<String>,
+}
+
+/// Difference between two snapshots, items are identified by their store id
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SnapshotDiff {
+    pub(crate) added: Vec<String>,
+    pub(crate) removed: Vec<String>,
+    /// items whose file content changed (different hash)
+    pub(crate) modified: Vec<String>,
+    pub(crate) relabelled: Vec<LabelChange>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// hex encoded SHA-256 hash of a file's content
+pub(crate) fn content_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn snapshot_path(project_dir: &Path, name: &str) -> PathBuf {
+    project_dir
+        .join(SNAPSHOT_DIR)
+        .join(format!("{}.json", name))
+}
+
+/// snapshot names end up as file names, so only allow a safe subset of characters
+fn validate_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        || name.starts_with('.')
+    {
+        return Err(format!(
+            "invalid snapshot name '{}', use only letters, digits, '-', '_' and '.'",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// # take a snapshot
+///
+/// hashes every item of the store and writes the snapshot to the project's snapshot
+/// directory. Fails if a snapshot with the same name already exists or if an item's file
+/// can not be read.
+///
+/// returns:
+///     Result with the written snapshot
+pub(crate) fn take_snapshot(
+    store: &AnnotationStore,
+    name: &str,
+) -> Result<Snapshot, Box<dyn Error>> {
+    validate_name(name)?;
+
+    let mut items = Vec::with_capacity(store.item_count());
+    for item in store.items() {
+        let hash = content_hash(Path::new(&item.path))
+            .map_err(|e| format!("unable to hash {}: {}", item.path, e))?;
+        items.push(SnapshotItem {
+            item: item.clone(),
+            hash,
+        });
+    }
+
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        created: unix_now(),
+        items,
+        annotations: store.annotations().cloned().collect(),
+    };
+
+    let path = snapshot_path(store.project_dir(), name);
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // `create_new` refuses to overwrite, snapshots are immutable
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("unable to create snapshot '{}': {}", name, e))?;
+    file.write_all(serde_json::to_string_pretty(&snapshot)?.as_bytes())?;
+
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&path, permissions)?;
+
+    Ok(snapshot)
+}
+
+pub(crate) fn load_snapshot(project_dir: &Path, name: &str) -> Result<Snapshot, Box<dyn Error>> {
+    validate_name(name)?;
+    let contents = fs::read_to_string(snapshot_path(project_dir, name))?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// names of all snapshots of a project, oldest first
+pub(crate) fn list_snapshots(project_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(project_dir.join(SNAPSHOT_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut snapshots: Vec<(std::time::SystemTime, String)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, path.file_stem()?.to_str()?.to_string()))
+        })
+        .collect();
+
+    snapshots.sort();
+    snapshots.into_iter().map(|(_, name)| name).collect()
+}
+
+/// classes of every item of a snapshot (sorted, without duplicates)
+fn labels_per_item(snapshot: &Snapshot) -> BTreeMap<u64, Vec<String>> {
+    let mut labels: BTreeMap<u64, BTreeSet<String>> = BTreeMap::new();
+    for annotation in &snapshot.annotations {
+        labels
+            .entry(annotation.item_id)
+            .or_default()
+            .insert(annotation.class.clone());
+    }
+    labels
+        .into_iter()
+        .map(|(id, classes)| (id, classes.into_iter().collect()))
+        .collect()
+}
+
+/// # diff two snapshots
+///
+/// lists items that were added, removed or whose content changed from `old` to `new` and
+/// items whose set of classes changed.
+pub(crate) fn diff_snapshots(old: &Snapshot, new: &Snapshot) -> SnapshotDiff {
+    let old_items: BTreeMap<u64, &SnapshotItem> =
+        old.items.iter().map(|i| (i.item.id, i)).collect();
+    let new_items: BTreeMap<u64, &SnapshotItem> =
+        new.items.iter().map(|i| (i.item.id, i)).collect();
+    let old_labels = labels_per_item(old);
+    let new_labels = labels_per_item(new);
+    let no_labels = Vec::new();
+
+    let mut diff = SnapshotDiff::default();
+
+    for (id, item) in &new_items {
+        match old_items.get(id) {
+            None => diff.added.push(item.item.path.clone()),
+            Some(old_item) if old_item.hash != item.hash => {
+                diff.modified.push(item.item.path.clone())
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (id, item) in &old_items {
+        if !new_items.contains_key(id) {
+            diff.removed.push(item.item.path.clone());
+            continue;
+        }
+        let before = old_labels.get(id).unwrap_or(&no_labels);
+        let after = new_labels.get(id).unwrap_or(&no_labels);
+        if before != after {
+            diff.relabelled.push(LabelChange {
+                path: item.item.path.clone(),
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    }
+
+    diff
+}
+
+impl SnapshotDiff {
+    /// human readable summary, one line per change
+    pub(crate) fn report(&self) -> String {
+        let mut lines = vec![format!(
+            "{} added, {} removed, {} modified, {} relabelled",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len(),
+            self.relabelled.len()
+        )];
+        lines.extend(self.added.iter().map(|p| format!("+ {}", p)));
+        lines.extend(self.removed.iter().map(|p| format!("- {}", p)));
+        lines.extend(self.modified.iter().map(|p| format!("~ {}", p)));
+        lines.extend(self.relabelled.iter().map(|c| {
+            format!(
+                "~ {}: [{}] -> [{}]",
+                c.path,
+                c.before.join(", "),
+                c.after.join(", ")
+            )
+        }));
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,430 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Train / validation / test split manager
+//!
+//! Every item of a project is assigned to exactly one split. The assignment is reproducible
+//! from the [`SplitConfig`] (ratios and seed) and is stored in `<project>/splits.toml`.
+//!
+//! - *stratified*: the ratios are applied per class (the primary class of an item), so
+//!   every class is represented in every split with the same proportions
+//! - *grouped*: items sharing the same value of a meta key (e.g. `patient`) form one unit
+//!   and always end up in the same split, so a subject never leaks across splits. Items of
+//!   the same duplicate cluster (see [`crate::dedup`]) are always grouped.
+//! - *k-fold*: additionally all non-test items get a fold index for cross-validation
+
+use crate::dedup::DUPLICATE_GROUP_KEY;
+use crate::helper::UnionFind;
+use crate::rng::Rng;
+use crate::store::{AnnotationStore, Item};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// file name of the split assignment inside a project directory
+pub(crate) const SPLITS_FILE_NAME: &str = "splits.toml";
+
+/// stratum / class name used for items without any annotation
+pub(crate) const UNLABELLED: &str = "(unlabelled)";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Split {
+    Train,
+    Val,
+    Test,
+}
+
+impl Split {
+    pub(crate) const ALL: [Split; 3] = [Split::Train, Split::Val, Split::Test];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Split::Train => "train",
+            Split::Val => "val",
+            Split::Test => "test",
+        }
+    }
+}
+
+/// Parameters of a split assignment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SplitConfig {
+    /// fractions of the three splits, they are normalised so they do not need to sum to one
+    pub(crate) train: f64,
+    pub(crate) val: f64,
+    pub(crate) test: f64,
+    pub(crate) seed: u64,
+    /// apply the ratios per class instead of over the whole dataset
+    pub(crate) stratify: bool,
+    /// meta key whose value groups items that must stay in the same split
+    pub(crate) group_key: Option<String>,
+    /// number of cross-validation folds over the non-test items (`0` = no folds)
+    #[serde(default)]
+    pub(crate) folds: usize,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        SplitConfig {
+            train: 0.7,
+            val: 0.15,
+            test: 0.15,
+            seed: 42,
+            stratify: true,
+            group_key: None,
+            folds: 0,
+        }
+    }
+}
+
+/// Split (and fold) of a single item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ItemSplit {
+    pub(crate) item_id: u64,
+    pub(crate) split: Split,
+    pub(crate) fold: Option<usize>,
+}
+
+/// The persisted split assignment of a project
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SplitAssignment {
+    pub(crate) config: SplitConfig,
+    pub(crate) items: Vec<ItemSplit>,
+}
+
+/// A set of items that has to be assigned as a whole
+struct Unit {
+    item_ids: Vec<u64>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// # assign every item of the store to a split
+///
+/// the result only depends on the items, their annotations and `config`, i.e. the same
+/// dataset and seed always gives the same assignment.
+///
+/// returns:
+///     Result with the assignment, an error if the ratios are invalid
+pub(crate) fn assign_splits(
+    store: &AnnotationStore,
+    config: &SplitConfig,
+) -> Result<SplitAssignment, Box<dyn Error>> {
+    let total = config.train + config.val + config.test;
+    if config.train < 0.0 || config.val < 0.0 || config.test < 0.0 || total <= 0.0 {
+        return Err("split ratios must be non-negative and must not all be zero".into());
+    }
+    if config.folds == 1 {
+        return Err("k-fold needs at least two folds".into());
+    }
+    let train_end = config.train / total;
+    let val_end = (config.train + config.val) / total;
+
+    // build the units: items sharing a group value or a duplicate cluster are merged
+    let all_items: Vec<&Item> = store.items().collect();
+    let mut union_find = UnionFind::new(all_items.len());
+    let mut first_of_value: HashMap<(&str, &str), usize> = HashMap::new();
+    let keys = config
+        .group_key
+        .iter()
+        .map(String::as_str)
+        .chain([DUPLICATE_GROUP_KEY]);
+    for key in keys {
+        for (i, item) in all_items.iter().enumerate() {
+            if let Some(value) = item.meta.get(key) {
+                let first = *first_of_value.entry((key, value.as_str())).or_insert(i);
+                union_find.union(first, i);
+            }
+        }
+    }
+
+    let mut units: BTreeMap<usize, Vec<u64>> = BTreeMap::new();
+    for (i, item) in all_items.iter().enumerate() {
+        units.entry(union_find.find(i)).or_default().push(item.id);
+    }
+
+    // stratum of a unit is the most common primary class of its items
+    let mut strata: BTreeMap<String, Vec<Unit>> = BTreeMap::new();
+    for item_ids in units.into_values() {
+        let stratum = if config.stratify {
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for id in &item_ids {
+                let class = store.primary_class(*id).unwrap_or(UNLABELLED);
+                *counts.entry(class).or_insert(0) += 1;
+            }
+            counts
+                .into_iter()
+                .rev()
+                .max_by_key(|(_, count)| *count)
+                .map(|(class, _)| class.to_string())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        strata.entry(stratum).or_default().push(Unit { item_ids });
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let mut items = Vec::with_capacity(store.item_count());
+    // continue the fold counter across strata so the folds stay balanced
+    let mut next_fold = 0;
+
+    for units in strata.values_mut() {
+        rng.shuffle(units);
+        let n = units.len() as f64;
+
+        for (i, unit) in units.iter().enumerate() {
+            // position of the unit's centre inside the stratum decides the split
+            let position = (i as f64 + 0.5) / n;
+            let split = if position < train_end {
+                Split::Train
+            } else if position < val_end {
+                Split::Val
+            } else {
+                Split::Test
+            };
+
+            let fold = if config.folds > 1 && split != Split::Test {
+                let fold = next_fold;
+                next_fold = (next_fold + 1) % config.folds;
+                Some(fold)
+            } else {
+                None
+            };
+
+            items.extend(unit.item_ids.iter().map(|id| ItemSplit {
+                item_id: *id,
+                split,
+                fold,
+            }));
+        }
+    }
+
+    items.sort_by_key(|s| s.item_id);
+    Ok(SplitAssignment {
+        config: config.clone(),
+        items,
+    })
+}
+
+pub(crate) fn save_splits(
+    project_dir: &Path,
+    assignment: &SplitAssignment,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        project_dir.join(SPLITS_FILE_NAME),
+        toml::to_string(assignment)?,
+    )?;
+    Ok(())
+}
+
+pub(crate) fn load_splits(project_dir: &Path) -> Result<SplitAssignment, Box<dyn Error>> {
+    let contents = fs::read_to_string(project_dir.join(SPLITS_FILE_NAME))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+impl SplitAssignment {
+    /// split of an item, `None` for items added after the assignment
+    pub(crate) fn split_of(&self, item_id: u64) -> Option<Split> {
+        self.items
+            .binary_search_by_key(&item_id, |s| s.item_id)
+            .ok()
+            .map(|i| self.items[i].split)
+    }
+
+    /// ids of all items of one split
+    pub(crate) fn items_of(&self, split: Split) -> Vec<u64> {
+        self.items
+            .iter()
+            .filter(|s| s.split == split)
+            .map(|s| s.item_id)
+            .collect()
+    }
+
+    /// # number of items per class and split
+    ///
+    /// an item is counted once for every class it is annotated with, items without
+    /// annotations are counted as [`UNLABELLED`].
+    ///
+    /// returns:
+    ///     map from class name to the counts of train, val and test
+    pub(crate) fn class_counts(&self, store: &AnnotationStore) -> BTreeMap<String, [usize; 3]> {
+        let mut counts: BTreeMap<String, [usize; 3]> = BTreeMap::new();
+        for item_split in &self.items {
+            if store.item(item_split.item_id).is_none() {
+                continue;
+            }
+            let index = Split::ALL
+                .iter()
+                .position(|s| *s == item_split.split)
+                .unwrap();
+
+            let mut classes: Vec<&str> = store
+                .annotations_of_item(item_split.item_id)
+                .iter()
+                .map(|a| a.class.as_str())
+                .collect();
+            classes.sort_unstable();
+            classes.dedup();
+            if classes.is_empty() {
+                classes.push(UNLABELLED);
+            }
+
+            for class in classes {
+                counts.entry(class.to_string()).or_insert([0; 3])[index] += 1;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{Modality, Shape};
+
+    use std::path::PathBuf;
+
+    /// a fresh store in a temporary directory, `classes[i]` is the label of item `i`
+    fn store_with(
+        name: &str,
+        classes: &[&str],
+        meta: &[(&str, String)],
+    ) -> (PathBuf, AnnotationStore) {
+        let dir =
+            std::env::temp_dir().join(format!("ai-lab-splits-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut store = AnnotationStore::open(&dir).unwrap();
+        for (i, class) in classes.iter().enumerate() {
+            let meta = meta
+                .get(i)
+                .map(|(key, value)| BTreeMap::from([(key.to_string(), value.clone())]))
+                .unwrap_or_default();
+            let id = store
+                .add_item(&format!("{}.png", i), Modality::Image, meta)
+                .unwrap();
+            store.annotate(id, class, Shape::Label, "test").unwrap();
+        }
+        (dir, store)
+    }
+
+    fn split_sizes(assignment: &SplitAssignment) -> [usize; 3] {
+        Split::ALL.map(|split| assignment.items_of(split).len())
+    }
+
+    #[test]
+    fn ratios_are_applied_exactly() {
+        let (dir, store) = store_with("ratios", &["cat"; 100], &[]);
+        let config = SplitConfig {
+            stratify: false,
+            ..SplitConfig::default()
+        };
+        let assignment = assign_splits(&store, &config).unwrap();
+        assert_eq!(split_sizes(&assignment), [70, 15, 15]);
+
+        // the ratios are normalised
+        let config = SplitConfig {
+            train: 2.0,
+            val: 1.0,
+            test: 1.0,
+            ..config
+        };
+        assert_eq!(
+            split_sizes(&assign_splits(&store, &config).unwrap()),
+            [50, 25, 25]
+        );
+
+        assert!(assign_splits(
+            &store,
+            &SplitConfig {
+                test: -0.1,
+                ..config.clone()
+            }
+        )
+        .is_err());
+        assert!(assign_splits(&store, &SplitConfig { folds: 1, ..config }).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn same_seed_gives_same_assignment() {
+        let (dir, store) = store_with("seed", &["cat"; 40], &[]);
+        let config = SplitConfig::default();
+        let first = assign_splits(&store, &config).unwrap();
+        assert_eq!(first.items, assign_splits(&store, &config).unwrap().items);
+
+        let other = assign_splits(&store, &SplitConfig { seed: 7, ..config }).unwrap();
+        assert_ne!(first.items, other.items);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stratification_keeps_class_proportions() {
+        let mut classes = vec!["cat"; 60];
+        classes.extend(["dog"; 40]);
+        let (dir, store) = store_with("stratify", &classes, &[]);
+        let config = SplitConfig {
+            train: 0.5,
+            val: 0.25,
+            test: 0.25,
+            ..SplitConfig::default()
+        };
+        let counts = assign_splits(&store, &config).unwrap().class_counts(&store);
+        assert_eq!(counts["cat"], [30, 15, 15]);
+        assert_eq!(counts["dog"], [20, 10, 10]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn groups_stay_in_one_split() {
+        let meta: Vec<(&str, String)> = (0..50)
+            .map(|i| ("patient", format!("P-{}", i / 5)))
+            .collect();
+        let (dir, store) = store_with("groups", &["cat"; 50], &meta);
+        let config = SplitConfig {
+            train: 0.6,
+            val: 0.2,
+            test: 0.2,
+            group_key: Some("patient".to_string()),
+            ..SplitConfig::default()
+        };
+        let assignment = assign_splits(&store, &config).unwrap();
+
+        let mut split_of_patient: HashMap<&str, Split> = HashMap::new();
+        for item in store.items() {
+            let split = assignment.split_of(item.id).unwrap();
+            let patient = item.meta["patient"].as_str();
+            assert_eq!(*split_of_patient.entry(patient).or_insert(split), split);
+        }
+        // the ratios apply to the 10 patients, not to the 50 items
+        assert_eq!(split_sizes(&assignment), [30, 10, 10]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn folds_cover_all_but_the_test_items() {
+        let (dir, store) = store_with("folds", &["cat"; 100], &[]);
+        let config = SplitConfig {
+            folds: 5,
+            ..SplitConfig::default()
+        };
+        let assignment = assign_splits(&store, &config).unwrap();
+
+        let mut per_fold = [0; 5];
+        for item in &assignment.items {
+            match item.fold {
+                Some(fold) => per_fold[fold] += 1,
+                None => assert_eq!(item.split, Split::Test),
+            }
+        }
+        assert_eq!(per_fold, [17; 5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Dataset statistics
+//!
+//! Aggregates of the annotation store shown in the statistics dashboard of the Projects tab.
+//! Image sizes and sound durations are read from the file headers (see [`crate::media`]).
+
+use crate::media::{image_dimensions, wav_info};
+use crate::store::{AnnotationStore, Modality, Shape};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// default number of bins of the value histograms
+pub(crate) const HISTOGRAM_BINS: usize = 10;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Histogram with equally wide bins
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Histogram {
+    /// `counts.len() + 1` bin edges
+    pub(crate) edges: Vec<f64>,
+    pub(crate) counts: Vec<usize>,
+}
+
+/// Summary of a project's dataset
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DatasetStats {
+    pub(crate) items: usize,
+    pub(crate) items_per_modality: BTreeMap<Modality, usize>,
+    /// number of annotations per class
+    pub(crate) labels_per_class: BTreeMap<String, usize>,
+    /// largest class count divided by the smallest one, `None` with less than two classes
+    pub(crate) imbalance_ratio: Option<f64>,
+    /// fraction of items with at least one annotation
+    pub(crate) coverage: f64,
+    pub(crate) image_widths: Histogram,
+    pub(crate) image_heights: Histogram,
+    /// durations of sound items in seconds
+    pub(crate) audio_durations: Histogram,
+    pub(crate) boxes_per_image: Histogram,
+    /// square root of the box area in pixels
+    pub(crate) box_sizes: Histogram,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Histogram {
+    /// histogram of arbitrary values with `bins` equally wide bins between min and max
+    pub(crate) fn from_values(values: &[f64], bins: usize) -> Histogram {
+        let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if values.is_empty() || bins == 0 {
+            return Histogram::default();
+        }
+
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if max <= min {
+            return Histogram {
+                edges: vec![min, min],
+                counts: vec![values.len()],
+            };
+        }
+
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0; bins];
+        for value in values {
+            let bin = (((value - min) / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+
+        Histogram {
+            edges: (0..=bins).map(|i| min + width * i as f64).collect(),
+            counts,
+        }
+    }
+
+    /// histogram of small integers with one bin per value from zero to the maximum
+    pub(crate) fn from_integers(values: &[usize]) -> Histogram {
+        let Some(max) = values.iter().copied().max() else {
+            return Histogram::default();
+        };
+        let mut counts = vec![0; max + 1];
+        for value in values {
+            counts[*value] += 1;
+        }
+        Histogram {
+            edges: (0..=max + 1).map(|v| v as f64 - 0.5).collect(),
+            counts,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// short label of a bin for chart axes (centre of the bin)
+    pub(crate) fn bin_label(&self, bin: usize) -> String {
+        let centre = (self.edges[bin] + self.edges[bin + 1]) / 2.0;
+        if centre.abs() >= 100.0 || centre.fract().abs() < 1e-9 {
+            format!("{:.0}", centre)
+        } else {
+            format!("{:.1}", centre)
+        }
+    }
+}
+
+/// # compute the statistics of a project's dataset
+///
+/// reads the headers of all image and sound files, so this should not be called on every
+/// redraw but only when the user asks for fresh numbers.
+pub(crate) fn compute_stats(store: &AnnotationStore) -> DatasetStats {
+    let mut stats = DatasetStats {
+        items: store.item_count(),
+        ..DatasetStats::default()
+    };
+
+    for item in store.items() {
+        *stats.items_per_modality.entry(item.modality).or_insert(0) += 1;
+    }
+
+    for annotation in store.annotations() {
+        *stats
+            .labels_per_class
+            .entry(annotation.class.clone())
+            .or_insert(0) += 1;
+    }
+
+    let min = stats.labels_per_class.values().copied().min();
+    let max = stats.labels_per_class.values().copied().max();
+    if let (Some(min), Some(max)) = (min, max) {
+        if stats.labels_per_class.len() > 1 && min > 0 {
+            stats.imbalance_ratio = Some(max as f64 / min as f64);
+        }
+    }
+
+    if stats.items > 0 {
+        let unlabelled = store.unlabelled_items().len();
+        stats.coverage = (stats.items - unlabelled) as f64 / stats.items as f64;
+    }
+
+    // media properties
+    let mut widths = Vec::new();
+    let mut heights = Vec::new();
+    let mut durations = Vec::new();
+    let mut boxes_per_image = Vec::new();
+    let mut box_sizes = Vec::new();
+
+    for item in store.items() {
+        match item.modality {
+            Modality::Image => {
+                if let Some((width, height)) = image_dimensions(Path::new(&item.path)) {
+                    widths.push(width as f64);
+                    heights.push(height as f64);
+                }
+
+                let mut boxes = 0;
+                for annotation in store.annotations_of_item(item.id) {
+                    if let Shape::BoundingBox { w, h, .. } = annotation.shape {
+                        boxes += 1;
+                        box_sizes.push(((w * h).abs() as f64).sqrt());
+                    }
+                }
+                boxes_per_image.push(boxes);
+            }
+            Modality::Sound => {
+                if let Ok(info) = wav_info(Path::new(&item.path)) {
+                    durations.push(info.duration());
+                }
+            }
+            Modality::Sensor | Modality::Tabular => {}
+        }
+    }
+
+    stats.image_widths = Histogram::from_values(&widths, HISTOGRAM_BINS);
+    stats.image_heights = Histogram::from_values(&heights, HISTOGRAM_BINS);
+    stats.audio_durations = Histogram::from_values(&durations, HISTOGRAM_BINS);
+    stats.boxes_per_image = Histogram::from_integers(&boxes_per_image);
+    stats.box_sizes = Histogram::from_values(&box_sizes, HISTOGRAM_BINS);
+
+    stats
+}
@@ -0,0 +1,639 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Annotation store
+//!
+//! All items (images, sound files, sensor recordings, ...) and their annotations of a project
+//! are kept in an append-only log (`annotations.jsonl`) next to the project `.toml` file.
+//! Every line is one JSON encoded [`Record`]. On open the log is replayed into memory and a few
+//! indices are built, so the typical questions of the other tabs ("all unlabelled images",
+//! "all boxes of class X", "items changed since date") do not need to touch the disk again.
+
+use crate::debug_println;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// file name of the annotation log inside a project directory
+pub(crate) const STORE_FILE_NAME: &str = "annotations.jsonl";
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Kind of data an item holds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Modality {
+    Image,
+    Sound,
+    Sensor,
+    Tabular,
+}
+
+impl Modality {
+    /// guess the modality of a file from its extension
+    pub(crate) fn from_path(path: &Path) -> Option<Modality> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "png" | "jpg" | "jpeg" | "bmp" | "gif" | "tif" | "tiff" | "webp" => {
+                Some(Modality::Image)
+            }
+            "wav" | "flac" | "mp3" | "ogg" => Some(Modality::Sound),
+            "csv" | "tsv" => Some(Modality::Sensor),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Modality::Image => "images",
+            Modality::Sound => "sound / speech",
+            Modality::Sensor => "sequential sensors",
+            Modality::Tabular => "tabular",
+        }
+    }
+}
+
+/// A single data item (one file) of a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Item {
+    pub(crate) id: u64,
+    pub(crate) path: String,
+    pub(crate) modality: Modality,
+    /// free form key / value pairs, e.g. `patient = "P-017"` or `recording = "r3"`
+    #[serde(default)]
+    pub(crate) meta: BTreeMap<String, String>,
+    /// unix timestamp (seconds) of the moment the item was added
+    pub(crate) added: u64,
+}
+
+/// Geometry of an annotation
+///
+/// Coordinates of images are in pixels, `Segment` bounds are in seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Shape {
+    /// the class applies to the whole item
+    Label,
+    BoundingBox {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+    },
+    Polygon {
+        points: Vec<(f32, f32)>,
+    },
+    Keypoint {
+        x: f32,
+        y: f32,
+    },
+    /// time interval of a sound file or sensor recording
+    Segment {
+        start: f32,
+        end: f32,
+    },
+}
+
+impl Shape {
+    pub(crate) fn is_box(&self) -> bool {
+        matches!(self, Shape::BoundingBox { .. })
+    }
+}
+
+/// One label / shape set by an annotator on an item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Annotation {
+    pub(crate) id: u64,
+    pub(crate) item_id: u64,
+    pub(crate) class: String,
+    pub(crate) shape: Shape,
+    pub(crate) annotator: String,
+    /// unix timestamp (seconds) of the last change
+    pub(crate) timestamp: u64,
+}
+
+/// One line of the annotation log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum Record {
+    AddItem {
+        item: Item,
+    },
+    RemoveItem {
+        item_id: u64,
+        at: u64,
+    },
+    /// set (`Some`) or remove (`None`) a meta value of an item
+    SetMeta {
+        item_id: u64,
+        key: String,
+        value: Option<String>,
+        at: u64,
+    },
+    PutAnnotation {
+        annotation: Annotation,
+    },
+    DeleteAnnotation {
+        annotation_id: u64,
+        at: u64,
+    },
+    /// lowest id never handed out, written by [`AnnotationStore::compact`] so the ids of deleted
+    /// items and annotations are not reused after the compaction
+    NextId {
+        next_id: u64,
+    },
+}
+
+/// In memory view of the annotation log with indices for the common queries
+#[derive(Debug)]
+pub(crate) struct AnnotationStore {
+    log_path: PathBuf,
+    items: BTreeMap<u64, Item>,
+    annotations: BTreeMap<u64, Annotation>,
+    by_item: HashMap<u64, BTreeSet<u64>>,
+    by_class: HashMap<String, BTreeSet<u64>>,
+    /// item id -> unix timestamp of the last change of the item or one of its annotations
+    changed: HashMap<u64, u64>,
+    next_id: u64,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+/// current unix timestamp in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// directory of a project, i.e. the directory containing the project `.toml` file
+pub(crate) fn project_dir(config_path: &str) -> PathBuf {
+    Path::new(config_path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+impl AnnotationStore {
+    /// # open the annotation store of a project
+    ///
+    /// creates an empty log if `project_dir` does not contain one yet and replays an existing
+    /// log otherwise. A truncated last line (e.g. after a crash while writing) is ignored,
+    /// any other malformed line is an error.
+    ///
+    /// returns:
+    ///     Result with the loaded store
+    pub(crate) fn open(project_dir: &Path) -> Result<AnnotationStore, Box<dyn Error>> {
+        let log_path = project_dir.join(STORE_FILE_NAME);
+        let mut store = AnnotationStore {
+            log_path: log_path.clone(),
+            items: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            by_item: HashMap::new(),
+            by_class: HashMap::new(),
+            changed: HashMap::new(),
+            next_id: 1,
+        };
+
+        if !log_path.exists() {
+            File::create(&log_path)?;
+            return Ok(store);
+        }
+
+        let content = fs::read_to_string(&log_path)?;
+        let lines: Vec<&str> = content.lines().collect();
+        let ends_with_newline = content.is_empty() || content.ends_with('\n');
+
+        let mut truncated = false;
+        for (nr, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(line) {
+                Ok(record) => store.apply(record),
+                Err(_) if nr + 1 == lines.len() && !ends_with_newline => {
+                    debug_println!(
+                        "[WARNING: ANNOTATION STORE] ignoring truncated last line of {}",
+                        log_path.display()
+                    );
+                    truncated = true;
+                }
+                Err(e) => {
+                    return Err(format!("{}:{}: {}", log_path.display(), nr + 1, e).into());
+                }
+            }
+        }
+
+        // new records must not be appended to the broken line
+        if truncated {
+            store.compact()?;
+        }
+
+        Ok(store)
+    }
+
+    pub(crate) fn log_path(&self) -> &Path {
+        &self.log_path
+    }
+
+    /// directory of the project this store belongs to
+    pub(crate) fn project_dir(&self) -> &Path {
+        self.log_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// replay a single record into the in memory state and indices
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::AddItem { item } => {
+                self.next_id = self.next_id.max(item.id + 1);
+                self.changed.insert(item.id, item.added);
+                self.items.insert(item.id, item);
+            }
+            Record::RemoveItem { item_id, .. } => {
+                self.items.remove(&item_id);
+                self.changed.remove(&item_id);
+                for annotation_id in self.by_item.remove(&item_id).unwrap_or_default() {
+                    if let Some(old) = self.annotations.remove(&annotation_id) {
+                        self.unindex_class(&old);
+                    }
+                }
+            }
+            Record::SetMeta {
+                item_id,
+                key,
+                value,
+                at,
+            } => {
+                if let Some(item) = self.items.get_mut(&item_id) {
+                    match value {
+                        Some(value) => item.meta.insert(key, value),
+                        None => item.meta.remove(&key),
+                    };
+                    let changed = self.changed.entry(item_id).or_insert(0);
+                    *changed = (*changed).max(at);
+                }
+            }
+            Record::PutAnnotation { annotation } => {
+                self.next_id = self.next_id.max(annotation.id + 1);
+                if let Some(old) = self.annotations.remove(&annotation.id) {
+                    self.unindex_class(&old);
+                }
+                self.by_item
+                    .entry(annotation.item_id)
+                    .or_default()
+                    .insert(annotation.id);
+                self.by_class
+                    .entry(annotation.class.clone())
+                    .or_default()
+                    .insert(annotation.id);
+                let changed = self.changed.entry(annotation.item_id).or_insert(0);
+                *changed = (*changed).max(annotation.timestamp);
+                self.annotations.insert(annotation.id, annotation);
+            }
+            Record::DeleteAnnotation { annotation_id, at } => {
+                if let Some(old) = self.annotations.remove(&annotation_id) {
+                    self.unindex_class(&old);
+                    if let Some(ids) = self.by_item.get_mut(&old.item_id) {
+                        ids.remove(&annotation_id);
+                    }
+                    let changed = self.changed.entry(old.item_id).or_insert(0);
+                    *changed = (*changed).max(at);
+                }
+            }
+            Record::NextId { next_id } => {
+                self.next_id = self.next_id.max(next_id);
+            }
+        }
+    }
+
+    fn unindex_class(&mut self, annotation: &Annotation) {
+        if let Some(ids) = self.by_class.get_mut(&annotation.class) {
+            ids.remove(&annotation.id);
+            if ids.is_empty() {
+                self.by_class.remove(&annotation.class);
+            }
+        }
+    }
+
+    /// append records to the log on disk and apply them to the in memory state
+    fn append(&mut self, records: Vec<Record>) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)?;
+        let mut buffer = String::new();
+        for record in &records {
+            buffer.push_str(&serde_json::to_string(record)?);
+            buffer.push('\n');
+        }
+        file.write_all(buffer.as_bytes())?;
+
+        for record in records {
+            self.apply(record);
+        }
+        Ok(())
+    }
+
+    // --- begin writing ---------------------------------------------------------------------------
+
+    /// add a new item, returns its id
+    pub(crate) fn add_item(
+        &mut self,
+        path: &str,
+        modality: Modality,
+        meta: BTreeMap<String, String>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let item = Item {
+            id: self.next_id,
+            path: path.to_string(),
+            modality,
+            meta,
+            added: unix_now(),
+        };
+        let id = item.id;
+        self.append(vec![Record::AddItem { item }])?;
+        Ok(id)
+    }
+
+    /// remove an item together with all of its annotations
+    pub(crate) fn remove_item(&mut self, item_id: u64) -> Result<(), Box<dyn Error>> {
+        if !self.items.contains_key(&item_id) {
+            return Err(format!("unknown item id: {}", item_id).into());
+        }
+        self.append(vec![Record::RemoveItem {
+            item_id,
+            at: unix_now(),
+        }])
+    }
+
+    /// set (`Some`) or remove (`None`) a meta value of several items at once
+    pub(crate) fn set_meta(
+        &mut self,
+        item_ids: &[u64],
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let at = unix_now();
+        let records = item_ids
+            .iter()
+            .filter(|id| self.items.contains_key(id))
+            .map(|id| Record::SetMeta {
+                item_id: *id,
+                key: key.to_string(),
+                value: value.map(str::to_string),
+                at,
+            })
+            .collect();
+        self.append(records)
+    }
+
+    /// add a new annotation to an existing item, returns the id of the annotation
+    pub(crate) fn annotate(
+        &mut self,
+        item_id: u64,
+        class: &str,
+        shape: Shape,
+        annotator: &str,
+    ) -> Result<u64, Box<dyn Error>> {
+        if !self.items.contains_key(&item_id) {
+            return Err(format!("unknown item id: {}", item_id).into());
+        }
+        let annotation = Annotation {
+            id: self.next_id,
+            item_id,
+            class: class.to_string(),
+            shape,
+            annotator: annotator.to_string(),
+            timestamp: unix_now(),
+        };
+        let id = annotation.id;
+        self.append(vec![Record::PutAnnotation { annotation }])?;
+        Ok(id)
+    }
+
+    /// overwrite existing annotations (e.g. after moving a box or changing its class)
+    ///
+    /// all given annotations are written in one go, so a bulk change is either stored
+    /// completely or not at all.
+    pub(crate) fn update_annotations(
+        &mut self,
+        annotations: Vec<Annotation>,
+    ) -> Result<(), Box<dyn Error>> {
+        let now = unix_now();
+        let records = annotations
+            .into_iter()
+            .map(|mut annotation| {
+                annotation.timestamp = now;
+                Record::PutAnnotation { annotation }
+            })
+            .collect();
+        self.append(records)
+    }
+
+    /// delete a single annotation, the item stays in the project
+    pub(crate) fn delete_annotation(&mut self, annotation_id: u64) -> Result<(), Box<dyn Error>> {
+        if !self.annotations.contains_key(&annotation_id) {
+            return Err(format!("unknown annotation id: {}", annotation_id).into());
+        }
+        self.append(vec![Record::DeleteAnnotation {
+            annotation_id,
+            at: unix_now(),
+        }])
+    }
+
+    /// rewrite the log so it only contains the current state
+    ///
+    /// the new log is written to a temporary file first and then renamed over the old one. It
+    /// starts with the next free id, the ids of deleted items and annotations stay used.
+    pub(crate) fn compact(&mut self) -> Result<(), Box<dyn Error>> {
+        let tmp_path = self.log_path.with_extension("jsonl.tmp");
+        let mut buffer = serde_json::to_string(&Record::NextId {
+            next_id: self.next_id,
+        })?;
+        buffer.push('\n');
+        for item in self.items.values() {
+            let record = Record::AddItem { item: item.clone() };
+            buffer.push_str(&serde_json::to_string(&record)?);
+            buffer.push('\n');
+        }
+        for annotation in self.annotations.values() {
+            let record = Record::PutAnnotation {
+                annotation: annotation.clone(),
+            };
+            buffer.push_str(&serde_json::to_string(&record)?);
+            buffer.push('\n');
+        }
+        fs::write(&tmp_path, buffer)?;
+        fs::rename(&tmp_path, &self.log_path)?;
+        Ok(())
+    }
+
+    // --- end writing -----------------------------------------------------------------------------
+
+    // --- begin queries ---------------------------------------------------------------------------
+
+    pub(crate) fn item(&self, item_id: u64) -> Option<&Item> {
+        self.items.get(&item_id)
+    }
+
+    pub(crate) fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.values()
+    }
+
+    pub(crate) fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
+    pub(crate) fn annotation(&self, annotation_id: u64) -> Option<&Annotation> {
+        self.annotations.get(&annotation_id)
+    }
+
+    pub(crate) fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        self.annotations.values()
+    }
+
+    /// all annotations of one item
+    pub(crate) fn annotations_of_item(&self, item_id: u64) -> Vec<&Annotation> {
+        self.by_item
+            .get(&item_id)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.annotations.get(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// all annotations (of any shape) of a class
+    pub(crate) fn annotations_of_class(&self, class: &str) -> Vec<&Annotation> {
+        self.by_class
+            .get(class)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.annotations.get(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// all bounding boxes of a class
+    pub(crate) fn boxes_of_class(&self, class: &str) -> Vec<&Annotation> {
+        self.annotations_of_class(class)
+            .into_iter()
+            .filter(|a| a.shape.is_box())
+            .collect()
+    }
+
+    /// names of all classes that are used by at least one annotation
+    pub(crate) fn used_classes(&self) -> Vec<&str> {
+        let mut classes: Vec<&str> = self.by_class.keys().map(String::as_str).collect();
+        classes.sort_unstable();
+        classes
+    }
+
+    /// the class used most often on an item (ties are broken alphabetically)
+    ///
+    /// this is the class of the item for anything that needs exactly one label per item,
+    /// e.g. stratification or single label classifiers.
+    pub(crate) fn primary_class(&self, item_id: u64) -> Option<&str> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for annotation in self.annotations_of_item(item_id) {
+            *counts.entry(annotation.class.as_str()).or_insert(0) += 1;
+        }
+        // `max_by_key` returns the last maximum, iterate in reverse to get the first name
+        counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(class, _)| class)
+    }
+
+    /// all items without a single annotation
+    pub(crate) fn unlabelled_items(&self) -> Vec<&Item> {
+        self.items
+            .values()
+            .filter(|item| self.by_item.get(&item.id).is_none_or(BTreeSet::is_empty))
+            .collect()
+    }
+
+    /// all items that were added or whose annotations changed at or after `since`
+    pub(crate) fn items_changed_since(&self, since: u64) -> Vec<&Item> {
+        self.items
+            .values()
+            .filter(|item| self.changed.get(&item.id).is_some_and(|t| *t >= since))
+            .collect()
+    }
+
+    /// all items of one modality
+    pub(crate) fn items_of_modality(&self, modality: Modality) -> Vec<&Item> {
+        self.items
+            .values()
+            .filter(|item| item.modality == modality)
+            .collect()
+    }
+
+    // --- end queries -----------------------------------------------------------------------------
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_keeps_ids_of_deleted_items() {
+        let dir = std::env::temp_dir().join(format!("ai-lab-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut store = AnnotationStore::open(&dir).unwrap();
+        let first = store
+            .add_item("a.png", Modality::Image, BTreeMap::new())
+            .unwrap();
+        let last = store
+            .add_item("b.png", Modality::Image, BTreeMap::new())
+            .unwrap();
+        store.remove_item(last).unwrap();
+        store.compact().unwrap();
+
+        let mut store = AnnotationStore::open(&dir).unwrap();
+        assert_eq!(store.item_count(), 1);
+        assert!(store.item(first).is_some());
+        let next = store
+            .add_item("c.png", Modality::Image, BTreeMap::new())
+            .unwrap();
+        assert!(next > last);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deleting_unknown_annotations_fails() {
+        let dir = std::env::temp_dir().join(format!("ai-lab-store-delete-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut store = AnnotationStore::open(&dir).unwrap();
+        let item = store
+            .add_item("a.png", Modality::Image, BTreeMap::new())
+            .unwrap();
+        let annotation = store.annotate(item, "cat", Shape::Label, "tester").unwrap();
+        assert!(store.delete_annotation(annotation + 1).is_err());
+        store.delete_annotation(annotation).unwrap();
+        assert!(store.annotation(annotation).is_none());
+        assert!(store.delete_annotation(annotation).is_err());
+
+        // the failed deletions left the log readable
+        let store = AnnotationStore::open(&dir).unwrap();
+        assert!(store.annotation(annotation).is_none());
+        assert!(store.item(item).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
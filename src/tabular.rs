@@ -0,0 +1,954 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Tabular preprocessing
+//!
+//! A [`TabularPipeline`] describes the steps, fitting it on a table learns their parameters
+//! (means, categories, quantiles, ...) and gives a [`FittedTabular`]. The fitted transformer
+//! is saved with the project, so prediction transforms new rows exactly like the training
+//! data. The label column is never transformed, it is passed through if a table has it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// file inside a project holding the fitted tabular transformer
+pub(crate) const FITTED_TABULAR_FILE_NAME: &str = "tabular_transform.toml";
+
+/// cells treated as missing values (compared case-insensitively, besides empty cells)
+const MISSING: [&str; 5] = ["na", "nan", "null", "none", "?"];
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// A table of text cells, the first row of the file is the header
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Table {
+    pub(crate) columns: Vec<String>,
+    pub(crate) rows: Vec<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ImputeStrategy {
+    Mean,
+    Median,
+    /// most frequent value, also for categorical columns
+    Mode,
+    Constant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Scaling {
+    /// `(x - mean) / std`
+    Standard,
+    /// to the range 0 .. 1
+    MinMax,
+    /// `(x - median) / IQR`, robust to outliers
+    Robust,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeriveOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// `ln(1 + x)` of the left column
+    Log1p,
+    /// `x^2` of the left column
+    Square,
+}
+
+/// A step of a tabular pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum TabularStep {
+    Impute {
+        column: String,
+        strategy: ImputeStrategy,
+        /// value of [`ImputeStrategy::Constant`]
+        #[serde(default)]
+        constant: String,
+    },
+    /// replace the column by one 0 / 1 column per category
+    OneHot {
+        column: String,
+    },
+    /// replace the categories by their index (sorted), unknown categories become -1
+    Ordinal {
+        column: String,
+    },
+    Scale {
+        column: String,
+        method: Scaling,
+    },
+    /// clip the values to the given quantiles of the fitted data
+    Clip {
+        column: String,
+        lower_quantile: f64,
+        upper_quantile: f64,
+    },
+    /// append a column computed from one or two columns
+    Derive {
+        name: String,
+        operation: DeriveOp,
+        left: String,
+        #[serde(default)]
+        right: String,
+    },
+}
+
+/// Tabular preprocessing of a project, the steps are fitted and applied in order
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct TabularPipeline {
+    #[serde(default)]
+    pub(crate) steps: Vec<TabularStep>,
+}
+
+/// A step with its fitted parameters
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum FittedStep {
+    Impute {
+        column: String,
+        value: String,
+    },
+    OneHot {
+        column: String,
+        categories: Vec<String>,
+    },
+    Ordinal {
+        column: String,
+        categories: Vec<String>,
+    },
+    /// `(x - offset) / scale`
+    Scale {
+        column: String,
+        offset: f64,
+        scale: f64,
+    },
+    Clip {
+        column: String,
+        low: f64,
+        high: f64,
+    },
+    Derive {
+        name: String,
+        operation: DeriveOp,
+        left: String,
+        right: String,
+    },
+}
+
+/// Fitted transformer, as stored in [`FITTED_TABULAR_FILE_NAME`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct FittedTabular {
+    /// columns the fitted table had besides the label, every table to transform needs them
+    pub(crate) input_columns: Vec<String>,
+    /// columns after the steps, without the label
+    pub(crate) output_columns: Vec<String>,
+    /// column holding the class, appended unchanged to the output of tables that have it
+    #[serde(default)]
+    pub(crate) label_column: Option<String>,
+    pub(crate) steps: Vec<FittedStep>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl ImputeStrategy {
+    pub(crate) const ALL: [ImputeStrategy; 4] = [
+        ImputeStrategy::Mean,
+        ImputeStrategy::Median,
+        ImputeStrategy::Mode,
+        ImputeStrategy::Constant,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ImputeStrategy::Mean => "mean",
+            ImputeStrategy::Median => "median",
+            ImputeStrategy::Mode => "mode",
+            ImputeStrategy::Constant => "constant",
+        }
+    }
+}
+
+impl Scaling {
+    pub(crate) const ALL: [Scaling; 3] = [Scaling::Standard, Scaling::MinMax, Scaling::Robust];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Scaling::Standard => "standard",
+            Scaling::MinMax => "min-max",
+            Scaling::Robust => "robust",
+        }
+    }
+}
+
+impl DeriveOp {
+    pub(crate) const ALL: [DeriveOp; 6] = [
+        DeriveOp::Add,
+        DeriveOp::Subtract,
+        DeriveOp::Multiply,
+        DeriveOp::Divide,
+        DeriveOp::Log1p,
+        DeriveOp::Square,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            DeriveOp::Add => "+",
+            DeriveOp::Subtract => "-",
+            DeriveOp::Multiply => "*",
+            DeriveOp::Divide => "/",
+            DeriveOp::Log1p => "log1p",
+            DeriveOp::Square => "square",
+        }
+    }
+
+    /// whether the operation needs a right column
+    pub(crate) fn is_binary(&self) -> bool {
+        !matches!(self, DeriveOp::Log1p | DeriveOp::Square)
+    }
+
+    fn apply(&self, left: Option<f64>, right: Option<f64>) -> Option<f64> {
+        let value = match self {
+            DeriveOp::Add => left? + right?,
+            DeriveOp::Subtract => left? - right?,
+            DeriveOp::Multiply => left? * right?,
+            DeriveOp::Divide => left? / right?,
+            DeriveOp::Log1p => left?.ln_1p(),
+            DeriveOp::Square => left?.powi(2),
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+impl TabularStep {
+    /// columns the step reads or writes
+    fn columns(&self) -> Vec<&str> {
+        match self {
+            TabularStep::Impute { column, .. }
+            | TabularStep::OneHot { column }
+            | TabularStep::Ordinal { column }
+            | TabularStep::Scale { column, .. }
+            | TabularStep::Clip { column, .. } => vec![column],
+            TabularStep::Derive {
+                name,
+                operation,
+                left,
+                right,
+            } => {
+                if operation.is_binary() {
+                    vec![name, left, right]
+                } else {
+                    vec![name, left]
+                }
+            }
+        }
+    }
+
+    /// short description, e.g. `impute age (median)`
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            TabularStep::Impute {
+                column,
+                strategy: ImputeStrategy::Constant,
+                constant,
+            } => format!("impute {} (constant {:?})", column, constant),
+            TabularStep::Impute {
+                column, strategy, ..
+            } => format!("impute {} ({})", column, strategy.name()),
+            TabularStep::OneHot { column } => format!("one-hot encode {}", column),
+            TabularStep::Ordinal { column } => format!("ordinal encode {}", column),
+            TabularStep::Scale { column, method } => {
+                format!("scale {} ({})", column, method.name())
+            }
+            TabularStep::Clip {
+                column,
+                lower_quantile,
+                upper_quantile,
+            } => format!(
+                "clip {} to quantiles {} .. {}",
+                column, lower_quantile, upper_quantile
+            ),
+            TabularStep::Derive {
+                name,
+                operation,
+                left,
+                right,
+            } => {
+                if operation.is_binary() {
+                    format!("{} = {} {} {}", name, left, operation.name(), right)
+                } else {
+                    format!("{} = {}({})", name, operation.name(), left)
+                }
+            }
+        }
+    }
+}
+
+/// whether a cell is a missing value
+pub(crate) fn is_missing(cell: &str) -> bool {
+    let cell = cell.trim();
+    cell.is_empty() || MISSING.iter().any(|m| cell.eq_ignore_ascii_case(m))
+}
+
+/// number in a cell, `None` for missing and non numeric cells
+pub(crate) fn parse_number(cell: &str) -> Option<f64> {
+    if is_missing(cell) {
+        return None;
+    }
+    cell.trim().parse().ok().filter(|v: &f64| v.is_finite())
+}
+
+/// split a file into records of cells, fields may be quoted with `"` (`""` is a literal quote)
+///
+/// line breaks inside quoted fields belong to the field, records that only contain whitespace
+/// are skipped.
+fn split_records(contents: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    // whether the record contains anything besides whitespace
+    let mut blank = true;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => {
+                quoted = !quoted;
+                blank = false;
+            }
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut cell));
+                if !blank {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+                blank = true;
+            }
+            c if c == delimiter && !quoted => {
+                record.push(std::mem::take(&mut cell));
+                blank = false;
+            }
+            c => {
+                blank &= c.is_whitespace();
+                cell.push(c);
+            }
+        }
+    }
+    record.push(cell);
+    if !blank {
+        records.push(record);
+    }
+    records
+}
+
+/// quote a cell if it contains the delimiter, a quote or a line break (RFC 4180)
+pub(crate) fn quote(cell: &str, delimiter: char) -> String {
+    if cell.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+fn delimiter_of(path: &Path) -> char {
+    let tsv = path
+        .extension()
+        .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("tsv"));
+    if tsv {
+        '\t'
+    } else {
+        ','
+    }
+}
+
+/// read a `.csv` or `.tsv` file with a header row, short rows are padded with missing values
+pub(crate) fn read_table(path: &Path) -> Result<Table, Box<dyn Error>> {
+    let delimiter = delimiter_of(path);
+    let contents = fs::read_to_string(path)?;
+    let mut records = split_records(&contents, delimiter).into_iter();
+    let columns: Vec<String> = records
+        .next()
+        .ok_or_else(|| format!("{}: empty file", path.display()))?
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .collect();
+
+    let rows = records
+        .map(|mut row| {
+            row.resize(columns.len(), String::new());
+            row
+        })
+        .collect();
+    Ok(Table { columns, rows })
+}
+
+pub(crate) fn write_table(path: &Path, table: &Table) -> Result<(), Box<dyn Error>> {
+    let delimiter = delimiter_of(path);
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .map(|c| quote(c, delimiter))
+            .collect::<Vec<_>>()
+            .join(&delimiter.to_string())
+    };
+    let mut contents = line(&table.columns);
+    contents.push('\n');
+    for row in &table.rows {
+        contents.push_str(&line(row));
+        contents.push('\n');
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+impl Table {
+    pub(crate) fn column_index(&self, column: &str) -> Result<usize, Box<dyn Error>> {
+        self.columns
+            .iter()
+            .position(|c| c == column)
+            .ok_or_else(|| format!("unknown column {:?}", column).into())
+    }
+
+    /// non missing values of a numeric column, an error if any of them is not a number
+    fn numbers(&self, column: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+        let index = self.column_index(column)?;
+        self.rows
+            .iter()
+            .filter(|row| !is_missing(&row[index]))
+            .map(|row| {
+                parse_number(&row[index]).ok_or_else(|| {
+                    format!("column {:?} is not numeric ({:?})", column, row[index]).into()
+                })
+            })
+            .collect()
+    }
+
+    /// # the given columns of the table, in the given order
+    ///
+    /// returns:
+    ///     Result with the table, an error if a column is missing
+    pub(crate) fn select(&self, columns: &[String]) -> Result<Table, Box<dyn Error>> {
+        let indices: Vec<usize> = columns
+            .iter()
+            .map(|c| self.column_index(c))
+            .collect::<Result<_, _>>()?;
+        Ok(Table {
+            columns: columns.to_vec(),
+            rows: self
+                .rows
+                .iter()
+                .map(|row| indices.iter().map(|i| row[*i].clone()).collect())
+                .collect(),
+        })
+    }
+
+    /// whether all non missing cells of the column are numbers
+    pub(crate) fn is_numeric(&self, column: &str) -> bool {
+        self.numbers(column).is_ok()
+    }
+
+    fn map_column(
+        &mut self,
+        column: &str,
+        f: impl Fn(&str) -> String,
+    ) -> Result<(), Box<dyn Error>> {
+        let index = self.column_index(column)?;
+        for row in self.rows.iter_mut() {
+            row[index] = f(&row[index]);
+        }
+        Ok(())
+    }
+}
+
+/// linear interpolation between the closest ranks, `values` must be sorted
+fn quantile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let position = q.clamp(0.0, 1.0) * (values.len() - 1) as f64;
+    let i = position.floor() as usize;
+    let j = (i + 1).min(values.len() - 1);
+    values[i] + (values[j] - values[i]) * (position - i as f64)
+}
+
+fn sorted(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+/// sorted distinct non missing values of a column
+fn categories(table: &Table, column: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let index = table.column_index(column)?;
+    let mut categories: Vec<String> = table
+        .rows
+        .iter()
+        .map(|row| row[index].trim().to_string())
+        .filter(|cell| !is_missing(cell))
+        .collect();
+    categories.sort();
+    categories.dedup();
+    Ok(categories)
+}
+
+pub(crate) fn format_number(value: f64) -> String {
+    value.to_string()
+}
+
+fn fit_step(step: &TabularStep, table: &Table) -> Result<FittedStep, Box<dyn Error>> {
+    Ok(match step {
+        TabularStep::Impute {
+            column,
+            strategy,
+            constant,
+        } => {
+            let value = match strategy {
+                ImputeStrategy::Mean => {
+                    let values = table.numbers(column)?;
+                    format_number(values.iter().sum::<f64>() / values.len().max(1) as f64)
+                }
+                ImputeStrategy::Median => {
+                    format_number(quantile(&sorted(table.numbers(column)?), 0.5))
+                }
+                ImputeStrategy::Mode => {
+                    let index = table.column_index(column)?;
+                    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+                    for row in &table.rows {
+                        if !is_missing(&row[index]) {
+                            *counts.entry(row[index].trim()).or_default() += 1;
+                        }
+                    }
+                    // the first (smallest) of equally frequent values
+                    counts
+                        .iter()
+                        .fold(
+                            None,
+                            |best: Option<(&str, usize)>, (value, count)| match best {
+                                Some((_, best_count)) if best_count >= *count => best,
+                                _ => Some((value, *count)),
+                            },
+                        )
+                        .map(|(value, _)| value.to_string())
+                        .unwrap_or_default()
+                }
+                ImputeStrategy::Constant => constant.clone(),
+            };
+            FittedStep::Impute {
+                column: column.clone(),
+                value,
+            }
+        }
+        TabularStep::OneHot { column } => FittedStep::OneHot {
+            column: column.clone(),
+            categories: categories(table, column)?,
+        },
+        TabularStep::Ordinal { column } => FittedStep::Ordinal {
+            column: column.clone(),
+            categories: categories(table, column)?,
+        },
+        TabularStep::Scale { column, method } => {
+            let values = sorted(table.numbers(column)?);
+            let (offset, scale) = match method {
+                Scaling::Standard => {
+                    let n = values.len().max(1) as f64;
+                    let mean = values.iter().sum::<f64>() / n;
+                    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+                    (mean, variance.sqrt())
+                }
+                Scaling::MinMax => {
+                    let min = values.first().copied().unwrap_or(0.0);
+                    (min, values.last().copied().unwrap_or(0.0) - min)
+                }
+                Scaling::Robust => (
+                    quantile(&values, 0.5),
+                    quantile(&values, 0.75) - quantile(&values, 0.25),
+                ),
+            };
+            FittedStep::Scale {
+                column: column.clone(),
+                offset,
+                scale: if scale == 0.0 { 1.0 } else { scale },
+            }
+        }
+        TabularStep::Clip {
+            column,
+            lower_quantile,
+            upper_quantile,
+        } => {
+            let values = sorted(table.numbers(column)?);
+            FittedStep::Clip {
+                column: column.clone(),
+                low: quantile(&values, *lower_quantile),
+                high: quantile(&values, *upper_quantile),
+            }
+        }
+        TabularStep::Derive {
+            name,
+            operation,
+            left,
+            right,
+        } => {
+            if table.columns.contains(name) {
+                return Err(format!("column {:?} exists already", name).into());
+            }
+            table.column_index(left)?;
+            if operation.is_binary() {
+                table.column_index(right)?;
+            }
+            FittedStep::Derive {
+                name: name.clone(),
+                operation: *operation,
+                left: left.clone(),
+                right: right.clone(),
+            }
+        }
+    })
+}
+
+impl FittedStep {
+    /// transform a table with the fitted parameters
+    pub(crate) fn apply(&self, table: &mut Table) -> Result<(), Box<dyn Error>> {
+        match self {
+            FittedStep::Impute { column, value } => table.map_column(column, |cell| {
+                if is_missing(cell) {
+                    value.clone()
+                } else {
+                    cell.to_string()
+                }
+            }),
+            FittedStep::OneHot { column, categories } => {
+                let index = table.column_index(column)?;
+                let names: Vec<String> = categories
+                    .iter()
+                    .map(|c| format!("{}={}", column, c))
+                    .collect();
+                table.columns.splice(index..=index, names);
+                for row in table.rows.iter_mut() {
+                    let cell = row[index].trim().to_string();
+                    let encoded = categories
+                        .iter()
+                        .map(|c| if *c == cell { "1" } else { "0" }.to_string());
+                    row.splice(index..=index, encoded);
+                }
+                Ok(())
+            }
+            FittedStep::Ordinal { column, categories } => table.map_column(column, |cell| {
+                let position = categories.iter().position(|c| c == cell.trim());
+                position.map_or(-1, |p| p as i64).to_string()
+            }),
+            FittedStep::Scale {
+                column,
+                offset,
+                scale,
+            } => table.map_column(column, |cell| {
+                parse_number(cell).map_or_else(String::new, |v| format_number((v - offset) / scale))
+            }),
+            FittedStep::Clip { column, low, high } => table.map_column(column, |cell| {
+                parse_number(cell).map_or_else(String::new, |v| format_number(v.clamp(*low, *high)))
+            }),
+            FittedStep::Derive {
+                name,
+                operation,
+                left,
+                right,
+            } => {
+                let left = table.column_index(left)?;
+                let right = if operation.is_binary() {
+                    Some(table.column_index(right)?)
+                } else {
+                    None
+                };
+                table.columns.push(name.clone());
+                for row in table.rows.iter_mut() {
+                    let value = operation.apply(
+                        parse_number(&row[left]),
+                        right.and_then(|r| parse_number(&row[r])),
+                    );
+                    row.push(value.map_or_else(String::new, format_number));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TabularPipeline {
+    /// # fit all steps on a table
+    ///
+    /// every step is fitted on the output of the steps before it. The label column is left
+    /// out of the fit, no step may use it.
+    ///
+    /// returns:
+    ///     Result with the fitted transformer and the transformed table (with the label column
+    ///     last, if the table has it)
+    pub(crate) fn fit(
+        &self,
+        table: &Table,
+        label_column: &str,
+    ) -> Result<(FittedTabular, Table), Box<dyn Error>> {
+        if let Some(step) = self
+            .steps
+            .iter()
+            .find(|step| step.columns().contains(&label_column))
+        {
+            return Err(format!(
+                "{}: the label column {:?} can not be transformed",
+                step.describe(),
+                label_column
+            )
+            .into());
+        }
+        let input_columns: Vec<String> = table
+            .columns
+            .iter()
+            .filter(|c| *c != label_column)
+            .cloned()
+            .collect();
+        let mut transformed = table.select(&input_columns)?;
+        let mut steps = Vec::with_capacity(self.steps.len());
+        for step in &self.steps {
+            let fitted =
+                fit_step(step, &transformed).map_err(|e| format!("{}: {}", step.describe(), e))?;
+            fitted.apply(&mut transformed)?;
+            steps.push(fitted);
+        }
+        let fitted = FittedTabular {
+            input_columns,
+            output_columns: transformed.columns.clone(),
+            label_column: Some(label_column.to_string()),
+            steps,
+        };
+        fitted.pass_label(table, &mut transformed);
+        Ok((fitted, transformed))
+    }
+}
+
+impl FittedTabular {
+    /// # transform a table like the one the transformer was fitted on
+    ///
+    /// the table needs all input columns (in any order, extra columns are dropped); it is
+    /// reordered to the fitted order first, so the output columns match exactly. The label
+    /// column is optional (e.g. for new data to predict), if the table has it, it is appended
+    /// as it is.
+    pub(crate) fn transform(&self, table: &Table) -> Result<Table, Box<dyn Error>> {
+        let mut transformed = table.select(&self.input_columns)?;
+        for step in &self.steps {
+            step.apply(&mut transformed)?;
+        }
+        self.pass_label(table, &mut transformed);
+        Ok(transformed)
+    }
+
+    /// append the label column of `table` (if it has one) to the transformed table
+    fn pass_label(&self, table: &Table, transformed: &mut Table) {
+        let Some(label) = &self.label_column else {
+            return;
+        };
+        let Ok(index) = table.column_index(label) else {
+            return;
+        };
+        transformed.columns.push(label.clone());
+        for (row, cells) in transformed.rows.iter_mut().zip(&table.rows) {
+            row.push(cells[index].clone());
+        }
+    }
+}
+
+/// # load the fitted tabular transformer of a project
+///
+/// returns:
+///     Result with the transformer, `None` if the project has none yet
+pub(crate) fn load_fitted_tabular(
+    project_dir: &Path,
+) -> Result<Option<FittedTabular>, Box<dyn Error>> {
+    let path = project_dir.join(FITTED_TABULAR_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(toml::from_str(&fs::read_to_string(path)?)?))
+}
+
+pub(crate) fn save_fitted_tabular(
+    project_dir: &Path,
+    fitted: &FittedTabular,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        project_dir.join(FITTED_TABULAR_FILE_NAME),
+        toml::to_string(fitted)?,
+    )?;
+    Ok(())
+}
+
+/// the first `rows` rows of a table as aligned text (e.g. for a monospace label)
+pub(crate) fn preview_text(table: &Table, rows: usize) -> String {
+    const MAX_WIDTH: usize = 16;
+    let clip = |cell: &str| -> String {
+        if cell.chars().count() > MAX_WIDTH {
+            let clipped: String = cell.chars().take(MAX_WIDTH - 1).collect();
+            format!("{}~", clipped)
+        } else {
+            cell.to_string()
+        }
+    };
+    let shown: Vec<Vec<String>> = std::iter::once(&table.columns)
+        .chain(table.rows.iter().take(rows))
+        .map(|row| row.iter().map(|c| clip(c)).collect())
+        .collect();
+    let widths: Vec<usize> = (0..table.columns.len())
+        .map(|c| {
+            shown
+                .iter()
+                .map(|row| row.get(c).map_or(0, |cell| cell.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut text = String::new();
+    for row in &shown {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        text.push_str(cells.join("  ").trim_end());
+        text.push('\n');
+    }
+    if table.rows.len() > rows {
+        text.push_str(&format!("... ({} rows)\n", table.rows.len()));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(columns: &[&str], rows: &[&[&str]]) -> Table {
+        Table {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|c| c.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    fn scale(column: &str) -> TabularStep {
+        TabularStep::Scale {
+            column: column.to_string(),
+            method: Scaling::MinMax,
+        }
+    }
+
+    #[test]
+    fn the_label_column_is_passed_through() {
+        let pipeline = TabularPipeline {
+            steps: vec![
+                scale("size"),
+                TabularStep::OneHot {
+                    column: "colour".to_string(),
+                },
+            ],
+        };
+        let training = table(
+            &["size", "label", "colour"],
+            &[
+                &["2", "cat", "red"],
+                &["4", "dog", "blue"],
+                &["6", "cat", "red"],
+            ],
+        );
+        let (fitted, transformed) = pipeline.fit(&training, "label").unwrap();
+        assert_eq!(fitted.input_columns, ["size", "colour"]);
+        assert_eq!(fitted.output_columns, ["size", "colour=blue", "colour=red"]);
+        assert_eq!(fitted.label_column.as_deref(), Some("label"));
+        assert_eq!(
+            transformed,
+            table(
+                &["size", "colour=blue", "colour=red", "label"],
+                &[
+                    &["0", "0", "1", "cat"],
+                    &["0.5", "1", "0", "dog"],
+                    &["1", "0", "1", "cat"]
+                ],
+            )
+        );
+
+        // new data has no label, the statistics of the fit are kept
+        let new = table(&["colour", "size"], &[&["blue", "8"]]);
+        assert_eq!(
+            fitted.transform(&new).unwrap(),
+            table(
+                &["size", "colour=blue", "colour=red"],
+                &[&["1.5", "1", "0"]]
+            )
+        );
+        assert!(fitted.transform(&table(&["size"], &[&["8"]])).is_err());
+    }
+
+    #[test]
+    fn steps_on_the_label_column_are_refused() {
+        let training = table(&["size", "label"], &[&["2", "1"], &["4", "0"]]);
+        let error = TabularPipeline {
+            steps: vec![scale("size"), scale("label")],
+        }
+        .fit(&training, "label")
+        .unwrap_err();
+        assert!(error.to_string().contains("label column"), "{}", error);
+
+        let derived = TabularStep::Derive {
+            name: "leak".to_string(),
+            operation: DeriveOp::Multiply,
+            left: "size".to_string(),
+            right: "label".to_string(),
+        };
+        let pipeline = TabularPipeline {
+            steps: vec![derived],
+        };
+        assert!(pipeline.fit(&training, "label").is_err());
+    }
+
+    #[test]
+    fn multi_line_fields_round_trip() {
+        let dir = std::env::temp_dir().join(format!("ai-lab-tabular-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let written = table(
+            &["note", "size"],
+            &[
+                &["first line\nsecond, \"quoted\" line", "2"],
+                &["", "4"],
+                &["plain", ""],
+            ],
+        );
+        for name in ["table.csv", "table.tsv"] {
+            let path = dir.join(name);
+            write_table(&path, &written).unwrap();
+            assert_eq!(read_table(&path).unwrap(), written);
+        }
+
+        // Windows line breaks, blank lines and short rows
+        let path = dir.join("windows.csv");
+        fs::write(&path, "note ,size\r\n\r\n\"a\r\nb\",1\r\nc\r\n").unwrap();
+        assert_eq!(
+            read_table(&path).unwrap(),
+            table(&["note", "size"], &[&["a\r\nb", "1"], &["c", ""]])
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
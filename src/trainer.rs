@@ -0,0 +1,1455 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Training runs in the background
+//!
+//! A training run builds the dataset of the project, applies the class imbalance handling of
+//! the preprocessing config, fits the model in a background thread and finally saves the
+//! model next to its run record. Like the preprocessing engine, the GTK side only polls the
+//! events of the run and never blocks. Runs that were stopped (or crashed) continue from their
+//! latest checkpoint.
+//!
+//! A hyperparameter search builds the dataset once and trains its trials on it, several at the
+//! same time (see [`crate::search`]). So does a cross-validation with its folds (see
+//! [`crate::crossval`]).
+//!
+//! Runs of the external trainer of a project do not build the dataset, the trainer gets the
+//! manifest of the items and reports its epochs back (see [`crate::external`]). The polling
+//! side sees the same events as for built-in models.
+
+use crate::checkpoint::{has_checkpoint, CheckpointConfig, Checkpointer};
+use crate::cluster::{cluster_of, elbow, standardised_points, ElbowPoint};
+use crate::clusters::{save_clusters, ClusterAssignment};
+use crate::crossval::{create_crossval, save_crossval, CrossValidation, FoldResult};
+use crate::dataset::{
+    build_dataset, describe_dataset, Dataset, DatasetSource, Representation, Sample,
+};
+use crate::debug_println;
+use crate::engine::{default_threads, run_jobs};
+use crate::external::{job, run_trainer, ExternalConfig, EXTERNAL_MODEL};
+use crate::helper::ProblemType;
+use crate::imbalance::{
+    class_weights, label_counts, resample_indices, smote, ImbalanceConfig, Resampling,
+};
+use crate::model::{
+    model_spec, resolve_params, EpochReport, FitContext, Model, ModelSpec, Params, TrainingControl,
+};
+use crate::pipeline::save_preprocessing;
+use crate::project::Project;
+use crate::runs::{
+    append_epoch, create_run, load_run, load_runs, run_dir, save_epochs, save_run, RunRecord,
+    STOPPED_BY_USER,
+};
+use crate::search::{create_search, run_search, save_search, SearchConfig, SearchRecord};
+use crate::snapshot::list_snapshots;
+use crate::splits::Split;
+use crate::store::{unix_now, Modality};
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// file name of the training settings inside a project
+pub(crate) const TRAINING_FILE_NAME: &str = "training.toml";
+
+/// file name of the trained model inside a run directory
+pub(crate) const MODEL_FILE_NAME: &str = "model.json";
+
+/// size of a memory page, for reading `/proc/self/statm`
+const PAGE_SIZE: u64 = 4096;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// Training settings of a project, as last used in the Training tab
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct TrainingConfig {
+    /// id of the selected model
+    pub(crate) model: String,
+    pub(crate) seed: u64,
+    /// column holding the class of tabular projects
+    pub(crate) label_column: String,
+    /// hyperparameters per model id, so switching between models keeps the edits
+    pub(crate) params: BTreeMap<String, Params>,
+    pub(crate) checkpoints: CheckpointConfig,
+    /// hyperparameter search settings per model id
+    pub(crate) searches: BTreeMap<String, SearchConfig>,
+    /// training script outside of AI Lab
+    pub(crate) external: ExternalConfig,
+}
+
+/// Messages from the training thread to the polling side
+#[derive(Debug, Clone)]
+pub(crate) enum TrainEvent {
+    /// what the run is doing right now, e.g. `building dataset 120 / 500`
+    Status(String),
+    Epoch(EpochReport),
+    /// the final run record (boxed, it dwarfs the other events), or why the run failed
+    Finished(Result<Box<RunRecord>, String>),
+}
+
+/// A started training run
+pub(crate) struct TrainingRun {
+    control: Arc<TrainingControl>,
+    events: Receiver<TrainEvent>,
+    pub(crate) run_id: String,
+    pub(crate) status: String,
+    /// reports of all finished epochs, oldest first
+    pub(crate) epochs: Vec<EpochReport>,
+    /// `Some` once the run is over
+    pub(crate) result: Option<Result<RunRecord, String>>,
+}
+
+/// Messages from a search to the polling side
+#[derive(Debug, Clone)]
+pub(crate) enum SearchEvent {
+    Status(String),
+    /// the search after another finished trial
+    Trial(SearchRecord),
+    /// the final search, or why it failed
+    Finished(Result<SearchRecord, String>),
+}
+
+/// A started hyperparameter search, see [`start_search`]
+pub(crate) struct SearchRun {
+    control: Arc<TrainingControl>,
+    events: Receiver<SearchEvent>,
+    pub(crate) status: String,
+    /// the search with all trials finished so far
+    pub(crate) search: SearchRecord,
+    /// `Some` once the search is over
+    pub(crate) result: Option<Result<(), String>>,
+}
+
+/// Messages from a cross-validation to the polling side
+#[derive(Debug, Clone)]
+pub(crate) enum CrossValidationEvent {
+    Status(String),
+    /// the cross-validation after another finished fold
+    Fold(CrossValidation),
+    /// the final cross-validation, or why it failed
+    Finished(Result<CrossValidation, String>),
+}
+
+/// A started cross-validation, see [`start_cross_validation`]
+pub(crate) struct CrossValidationRun {
+    control: Arc<TrainingControl>,
+    events: Receiver<CrossValidationEvent>,
+    pub(crate) status: String,
+    /// the cross-validation with all folds finished so far
+    pub(crate) crossval: CrossValidation,
+    /// `Some` once the cross-validation is over
+    pub(crate) result: Option<Result<(), String>>,
+}
+
+/// Which labelled samples train and which validate a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Holdout {
+    /// the training split trains, the validation split validates
+    Split,
+    /// the fold validates, all other folds train
+    Fold(usize),
+    /// the training and the validation split train together, nothing validates
+    All,
+}
+
+/// The dataset and settings shared by the runs of a search or a cross-validation
+struct RunGroup<'a> {
+    source: &'a DatasetSource,
+    data: &'a Dataset,
+    spec: &'a ModelSpec,
+    problem: ProblemType,
+    /// id of the search or cross-validation
+    id: &'a str,
+    seed: u64,
+    checkpoints: &'a CheckpointConfig,
+    control: &'a TrainingControl,
+}
+
+/// A running k-means sweep over a range of `k`, see [`start_elbow`]
+pub(crate) struct ElbowSweep {
+    cancel: Arc<AtomicBool>,
+    events: Receiver<TrainEvent>,
+    result: Receiver<Result<Vec<ElbowPoint>, String>>,
+    pub(crate) status: String,
+    /// `Some` once the sweep is over
+    pub(crate) points: Option<Result<Vec<ElbowPoint>, String>>,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            model: String::new(),
+            seed: 42,
+            label_column: "label".to_string(),
+            params: BTreeMap::new(),
+            checkpoints: CheckpointConfig::default(),
+            searches: BTreeMap::new(),
+            external: ExternalConfig::default(),
+        }
+    }
+}
+
+impl TrainingConfig {
+    /// hyperparameters of the selected model as edited so far (possibly incomplete)
+    pub(crate) fn model_params(&self) -> Params {
+        self.params.get(&self.model).cloned().unwrap_or_default()
+    }
+
+    /// search settings of the selected model
+    pub(crate) fn model_search(&self) -> SearchConfig {
+        self.searches.get(&self.model).cloned().unwrap_or_default()
+    }
+}
+
+/// # load the training settings of a project
+///
+/// returns:
+///     Result with the settings, the defaults if the project has none yet
+pub(crate) fn load_training(project_dir: &Path) -> Result<TrainingConfig, Box<dyn Error>> {
+    let path = project_dir.join(TRAINING_FILE_NAME);
+    if !path.exists() {
+        return Ok(TrainingConfig::default());
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub(crate) fn save_training(
+    project_dir: &Path,
+    config: &TrainingConfig,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        project_dir.join(TRAINING_FILE_NAME),
+        toml::to_string(config)?,
+    )?;
+    Ok(())
+}
+
+/// path of the trained model of a run
+pub(crate) fn model_path(project_dir: &Path, run_id: &str) -> PathBuf {
+    run_dir(project_dir, run_id).join(MODEL_FILE_NAME)
+}
+
+impl TrainingRun {
+    /// ask the model to stop after the current epoch, the model trained so far is kept
+    pub(crate) fn stop(&self) {
+        self.control.stop();
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.control.set_paused(paused);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.control.is_stopped()
+    }
+
+    /// samples per second of the last epoch
+    pub(crate) fn throughput(&self) -> Option<f64> {
+        let last = self.epochs.last()?;
+        (last.seconds > 0.0 && last.samples > 0).then(|| last.samples as f64 / last.seconds)
+    }
+
+    /// # time left until the last epoch is done
+    ///
+    /// estimated from the mean time of the epochs so far, `None` before the first epoch
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let last = self.epochs.last()?;
+        let mean = self.epochs.iter().map(|e| e.seconds).sum::<f64>() / self.epochs.len() as f64;
+        let left = last.epochs.saturating_sub(last.epoch) as f64;
+        Some(Duration::from_secs_f64(mean * left))
+    }
+
+    /// # take all events sent since the last call
+    ///
+    /// returns:
+    ///     true once the run is over
+    pub(crate) fn poll(&mut self) -> bool {
+        loop {
+            match self.events.try_recv() {
+                Ok(TrainEvent::Status(status)) => self.status = status,
+                Ok(TrainEvent::Epoch(report)) => {
+                    self.status = format!("epoch {} / {}", report.epoch, report.epochs);
+                    self.epochs.push(report);
+                }
+                Ok(TrainEvent::Finished(result)) => {
+                    self.status = match &result {
+                        Ok(record) => match &record.error {
+                            Some(error) => error.clone(),
+                            None => "finished".to_string(),
+                        },
+                        Err(e) => format!("failed: {}", e),
+                    };
+                    self.result = Some(result.map(|record| *record));
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.result.is_none() {
+                        self.status = "the training thread stopped unexpectedly".to_string();
+                        self.result = Some(Err(self.status.clone()));
+                    }
+                    break;
+                }
+            }
+        }
+        self.result.is_some()
+    }
+}
+
+/// # start training a model of the registry on a project
+///
+/// the hyperparameters are checked and the run is created before anything happens in the
+/// background, so configuration errors show up immediately.
+///
+/// returns:
+///     Result with the started run
+pub(crate) fn start_training(
+    project: &Project,
+    config: &TrainingConfig,
+) -> Result<TrainingRun, Box<dyn Error>> {
+    let spec =
+        model_spec(&config.model).ok_or_else(|| format!("unknown model {:?}", config.model))?;
+    let problem = project.config.problem;
+    let source = DatasetSource::from_project(project, &config.label_column, config.seed)?;
+    if !spec.supports(problem, source.modality) {
+        return Err(format!(
+            "{} does not support {} of {} data",
+            spec.name,
+            problem.name(),
+            source.modality.name()
+        )
+        .into());
+    }
+    let params = resolve_params(&(spec.hyperparameters)(), &config.model_params())?;
+    let model = spec.create(&params)?;
+
+    let record = new_run(
+        &source,
+        spec.id,
+        params,
+        config.seed,
+        &config.checkpoints,
+        None,
+        None,
+    )?;
+    debug_println!("[INFO: TRAINING] started {} ({})", record.id, spec.id);
+
+    let checkpoints = Checkpointer::new(
+        &run_dir(&source.project_dir, &record.id),
+        config.checkpoints.clone(),
+    );
+    Ok(spawn_run(source, spec, problem, model, record, checkpoints))
+}
+
+/// # create and save the record of a new run
+///
+/// returns:
+///     Result with the record, `group` is the search or cross-validation the run belongs to
+///     and `fold` the fold it is validated on
+fn new_run(
+    source: &DatasetSource,
+    model: &str,
+    params: Params,
+    seed: u64,
+    checkpoints: &CheckpointConfig,
+    group: Option<&str>,
+    fold: Option<usize>,
+) -> Result<RunRecord, Box<dyn Error>> {
+    let snapshot = list_snapshots(&source.project_dir).pop();
+    let mut record = create_run(&source.project_dir, snapshot.as_deref())?;
+    record.model = model.to_string();
+    record.seed = seed;
+    record.params = params;
+    record.checkpoints = checkpoints.clone();
+    record.group = group.map(str::to_string);
+    record.fold = fold;
+    save_run(&source.project_dir, &record)?;
+    // the pipeline may be edited while the run is going on
+    save_preprocessing(
+        &run_dir(&source.project_dir, &record.id),
+        &source.preprocessing,
+    )?;
+    Ok(record)
+}
+
+/// # continue a run from its latest checkpoint
+///
+/// the run keeps its id, model, hyperparameters and seed. The dataset is built again from the
+/// project, so the run only goes on exactly as it would have without the interruption as long
+/// as the items, splits and preprocessing did not change (the models refuse to continue on
+/// other training samples).
+///
+/// returns:
+///     Result with the continued run, its epochs start with the ones of the checkpoint
+pub(crate) fn resume_training(
+    project: &Project,
+    config: &TrainingConfig,
+    run_id: &str,
+) -> Result<TrainingRun, Box<dyn Error>> {
+    let mut record = load_run(project.dir(), run_id)?;
+    if record.finished.is_some() && record.error.is_none() {
+        return Err(format!("{} is finished already", run_id).into());
+    }
+    let spec =
+        model_spec(&record.model).ok_or_else(|| format!("unknown model {:?}", record.model))?;
+    let problem = project.config.problem;
+    let source = DatasetSource::from_project(project, &config.label_column, record.seed)?;
+    if !spec.supports(problem, source.modality) {
+        return Err(format!(
+            "{} does not support {} of {} data",
+            spec.name,
+            problem.name(),
+            source.modality.name()
+        )
+        .into());
+    }
+    let (checkpoints, model_path) = Checkpointer::resume(
+        &run_dir(&source.project_dir, &record.id),
+        record.checkpoints.clone(),
+    )?;
+    let model = spec.load(&model_path)?;
+
+    record.finished = None;
+    record.error = None;
+    save_run(&source.project_dir, &record)?;
+    // epochs after the checkpoint are trained again
+    save_epochs(&source.project_dir, &record.id, checkpoints.reports())?;
+    debug_println!(
+        "[INFO: TRAINING] resumed {} after epoch {}",
+        record.id,
+        checkpoints.reports().last().map_or(0, |r| r.epoch)
+    );
+    Ok(spawn_run(source, spec, problem, model, record, checkpoints))
+}
+
+/// runs that did not finish and can continue from a checkpoint, newest first
+pub(crate) fn resumable_runs(project_dir: &Path) -> Vec<RunRecord> {
+    let mut runs: Vec<RunRecord> = load_runs(project_dir)
+        .into_iter()
+        .filter(|r| r.finished.is_none() || r.error.is_some())
+        .filter(|r| has_checkpoint(&run_dir(project_dir, &r.id)))
+        .collect();
+    runs.reverse();
+    runs
+}
+
+/// train a model in a background thread, the record is saved once the run is over
+fn spawn_run(
+    source: DatasetSource,
+    spec: ModelSpec,
+    problem: ProblemType,
+    model: Box<dyn Model>,
+    mut record: RunRecord,
+    checkpoints: Checkpointer,
+) -> TrainingRun {
+    let control = Arc::new(TrainingControl::default());
+    let (sender, events) = mpsc::channel();
+    let run_id = record.id.clone();
+    let epochs = checkpoints.reports().to_vec();
+
+    let thread_control = control.clone();
+    thread::spawn(move || {
+        let result = train(
+            &source,
+            &spec,
+            problem,
+            model,
+            &mut record,
+            checkpoints,
+            &thread_control,
+            &sender,
+        );
+        finish_run(&source.project_dir, &mut record, &result);
+        let _ = sender.send(TrainEvent::Finished(
+            result.map(|_| Box::new(record)).map_err(|e| e.to_string()),
+        ));
+    });
+
+    TrainingRun {
+        control,
+        events,
+        run_id,
+        status: "starting".to_string(),
+        epochs,
+        result: None,
+    }
+}
+
+/// # start a run of the external trainer of the project
+///
+/// the run is created right away, the trainer is started in the background.
+///
+/// returns:
+///     Result with the started run, it reports like a run of a built-in model
+pub(crate) fn start_external(
+    project: &Project,
+    config: &TrainingConfig,
+) -> Result<TrainingRun, Box<dyn Error>> {
+    let external = config.external.clone();
+    if external.command.trim().is_empty() {
+        return Err("the project has no external trainer command".into());
+    }
+    let problem = project.config.problem;
+    let source = DatasetSource::from_project(project, &config.label_column, config.seed)?;
+    let mut record = new_run(
+        &source,
+        EXTERNAL_MODEL,
+        external.params.clone(),
+        config.seed,
+        &config.checkpoints,
+        None,
+        None,
+    )?;
+    record.classes = source.classes.clone();
+    debug_println!(
+        "[INFO: TRAINING] started {} ({:?})",
+        record.id,
+        external.command
+    );
+
+    let control = Arc::new(TrainingControl::default());
+    let (sender, events) = mpsc::channel();
+    let run_id = record.id.clone();
+    let thread_control = control.clone();
+    thread::spawn(move || {
+        let run_dir = run_dir(&source.project_dir, &record.id);
+        let job = job(
+            &source,
+            problem,
+            &record.id,
+            &run_dir,
+            record.seed,
+            &external,
+        );
+        let status_sender = sender.clone();
+        let mut status = |status: String| {
+            let _ = status_sender.send(TrainEvent::Status(status));
+        };
+        let epoch_log = record.id.clone();
+        let mut report = |epoch: EpochReport| {
+            if let Err(e) = append_epoch(&source.project_dir, &epoch_log, &epoch) {
+                debug_println!(
+                    "[ERROR: TRAINING] unable to log epoch {}: {}",
+                    epoch.epoch,
+                    e
+                );
+            }
+            let _ = sender.send(TrainEvent::Epoch(epoch));
+        };
+        let result = run_trainer(
+            &external,
+            &job,
+            &record.checkpoints.best_metric,
+            &thread_control,
+            &mut status,
+            &mut report,
+        )
+        .map(|report| {
+            record.metrics = report.metrics;
+            record.best = report.best;
+            if thread_control.is_stopped() {
+                record.error = Some(STOPPED_BY_USER.to_string());
+            }
+        });
+        finish_run(&source.project_dir, &mut record, &result);
+        let _ = sender.send(TrainEvent::Finished(
+            result.map(|_| Box::new(record)).map_err(|e| e.to_string()),
+        ));
+    });
+
+    Ok(TrainingRun {
+        control,
+        events,
+        run_id,
+        status: "starting".to_string(),
+        epochs: Vec::new(),
+        result: None,
+    })
+}
+
+/// save the record of a run that is over, with the notes added while it was going on
+fn finish_run(project_dir: &Path, record: &mut RunRecord, result: &Result<(), Box<dyn Error>>) {
+    record.finished = Some(unix_now());
+    if let Err(e) = result {
+        record.error = Some(e.to_string());
+    }
+    if let Ok(saved) = load_run(project_dir, &record.id) {
+        record.notes = saved.notes;
+    }
+    if let Err(e) = save_run(project_dir, record) {
+        debug_println!("[ERROR: TRAINING] unable to save run {}: {}", record.id, e);
+    }
+}
+
+/// # resident memory of the process (the GUI and all training threads) in bytes
+///
+/// returns:
+///     `None` where `/proc` is not available
+pub(crate) fn resident_memory() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * PAGE_SIZE)
+}
+
+impl ElbowSweep {
+    pub(crate) fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// # collect the pending events of the sweep
+    ///
+    /// returns:
+    ///     true once the sweep is over
+    pub(crate) fn poll(&mut self) -> bool {
+        while let Ok(TrainEvent::Status(status)) = self.events.try_recv() {
+            self.status = status;
+        }
+        match self.result.try_recv() {
+            Ok(result) => self.points = Some(result),
+            Err(TryRecvError::Disconnected) if self.points.is_none() => {
+                self.points = Some(Err("the sweep stopped unexpectedly".to_string()));
+            }
+            Err(_) => {}
+        }
+        self.points.is_some()
+    }
+}
+
+/// # inertia and silhouette of k-means for every `k` in `ks`, in the background
+///
+/// works on the standardised feature vectors of all samples of the project, the same input
+/// the clustering models of the registry see.
+///
+/// returns:
+///     Result with the started sweep
+pub(crate) fn start_elbow(
+    project: &Project,
+    config: &TrainingConfig,
+    ks: Vec<usize>,
+) -> Result<ElbowSweep, Box<dyn Error>> {
+    let source = DatasetSource::from_project(project, &config.label_column, config.seed)?;
+    let seed = config.seed;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender, events) = mpsc::channel();
+    let (result_sender, result) = mpsc::channel();
+
+    let thread_cancel = cancel.clone();
+    thread::spawn(move || {
+        let mut progress = |done: usize, total: usize| {
+            let _ = sender.send(TrainEvent::Status(format!(
+                "building dataset {} / {}",
+                done, total
+            )));
+        };
+        let points = build_dataset(
+            &source,
+            Representation::Features,
+            &thread_cancel,
+            &mut progress,
+        )
+        .map(|data| standardised_points(&data))
+        .map_err(|e| e.to_string())
+        .and_then(|points| {
+            let mut progress = |done: usize, total: usize| {
+                let _ = sender.send(TrainEvent::Status(format!("k-means {} / {}", done, total)));
+            };
+            let curve = elbow(&points, &ks, seed, &thread_cancel, &mut progress);
+            if curve.is_empty() {
+                return Err(format!(
+                    "{} samples are too few for the chosen k",
+                    points.len()
+                ));
+            }
+            Ok(curve)
+        });
+        let _ = result_sender.send(points);
+    });
+
+    Ok(ElbowSweep {
+        cancel,
+        events,
+        result,
+        status: "starting".to_string(),
+        points: None,
+    })
+}
+
+impl SearchRun {
+    /// ask the search to stop, running trials stop after their current epoch
+    pub(crate) fn stop(&self) {
+        self.control.stop();
+    }
+
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.control.is_stopped()
+    }
+
+    /// # take all events sent since the last call
+    ///
+    /// returns:
+    ///     true once the search is over
+    pub(crate) fn poll(&mut self) -> bool {
+        loop {
+            match self.events.try_recv() {
+                Ok(SearchEvent::Status(status)) => self.status = status,
+                Ok(SearchEvent::Trial(search)) => {
+                    self.status = format!("trial {} / {}", search.trials.len(), search.planned);
+                    self.search = search;
+                }
+                Ok(SearchEvent::Finished(result)) => {
+                    self.result = Some(match result {
+                        Ok(search) => {
+                            self.status = match &search.error {
+                                Some(error) => error.clone(),
+                                None => "finished".to_string(),
+                            };
+                            self.search = search;
+                            Ok(())
+                        }
+                        Err(e) => {
+                            self.status = format!("failed: {}", e);
+                            Err(e)
+                        }
+                    });
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.result.is_none() {
+                        self.status = "the search stopped unexpectedly".to_string();
+                        self.result = Some(Err(self.status.clone()));
+                    }
+                    break;
+                }
+            }
+        }
+        self.result.is_some()
+    }
+}
+
+/// # start a hyperparameter search of the selected model
+///
+/// hyperparameters that are not searched keep their values of the model form, the budget of
+/// successive halving is the value of its hyperparameter there. The search is checked before
+/// anything happens in the background.
+///
+/// returns:
+///     Result with the started search
+pub(crate) fn start_search(
+    project: &Project,
+    config: &TrainingConfig,
+) -> Result<SearchRun, Box<dyn Error>> {
+    let spec =
+        model_spec(&config.model).ok_or_else(|| format!("unknown model {:?}", config.model))?;
+    let problem = project.config.problem;
+    let source = DatasetSource::from_project(project, &config.label_column, config.seed)?;
+    if !spec.supports(problem, source.modality) {
+        return Err(format!(
+            "{} does not support {} of {} data",
+            spec.name,
+            problem.name(),
+            source.modality.name()
+        )
+        .into());
+    }
+    let hyperparameters = (spec.hyperparameters)();
+    let base = resolve_params(&hyperparameters, &config.model_params())?;
+    let search_config = config.model_search();
+    let planned = search_config.planned_trials(&hyperparameters, &base)?;
+    let threads = match search_config.parallel {
+        0 => default_threads(),
+        n => n,
+    };
+    // trials only keep the best model, they are not meant to be continued
+    let checkpoints = CheckpointConfig {
+        every: 0,
+        best_metric: config.checkpoints.best_metric.clone(),
+    };
+
+    let search = create_search(
+        &source.project_dir,
+        SearchRecord::new(spec.id, config.seed, search_config, planned),
+    )?;
+    debug_println!(
+        "[INFO: SEARCH] started {} ({}, {} trials on {} threads)",
+        search.id,
+        spec.id,
+        planned,
+        threads
+    );
+
+    let control = Arc::new(TrainingControl::default());
+    let (sender, events) = mpsc::channel();
+    let thread_control = control.clone();
+    let mut record = search.clone();
+    thread::spawn(move || {
+        let result = search_thread(
+            &source,
+            &spec,
+            problem,
+            &base,
+            &mut record,
+            threads,
+            &checkpoints,
+            &thread_control,
+            &sender,
+        );
+        record.finished = Some(unix_now());
+        match &result {
+            Err(e) => record.error = Some(e.to_string()),
+            Ok(()) if thread_control.is_stopped() => {
+                record.error = Some(STOPPED_BY_USER.to_string())
+            }
+            Ok(()) => {}
+        }
+        if let Err(e) = save_search(&source.project_dir, &record) {
+            debug_println!("[ERROR: SEARCH] unable to save {}: {}", record.id, e);
+        }
+        debug_println!("[INFO: SEARCH] finished {}", record.id);
+        let _ = sender.send(SearchEvent::Finished(
+            result.map(|_| record).map_err(|e| e.to_string()),
+        ));
+    });
+
+    Ok(SearchRun {
+        control,
+        events,
+        status: "starting".to_string(),
+        search,
+        result: None,
+    })
+}
+
+/// # the work of the search thread
+///
+/// builds the dataset once and trains all trials on it, the search is saved after every trial.
+#[allow(clippy::too_many_arguments)]
+fn search_thread(
+    source: &DatasetSource,
+    spec: &ModelSpec,
+    problem: ProblemType,
+    base: &Params,
+    search: &mut SearchRecord,
+    threads: usize,
+    checkpoints: &CheckpointConfig,
+    control: &TrainingControl,
+    sender: &Sender<SearchEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let mut progress = |done: usize, total: usize| {
+        let _ = sender.send(SearchEvent::Status(format!(
+            "building dataset {} / {}",
+            done, total
+        )));
+    };
+    let data = build_dataset(
+        source,
+        spec.representation,
+        control.stop_flag(),
+        &mut progress,
+    )?;
+    let _ = sender.send(SearchEvent::Status(describe_dataset(&data)));
+
+    let search_id = search.id.clone();
+    let group = RunGroup {
+        source,
+        data: &data,
+        spec,
+        problem,
+        id: &search_id,
+        seed: search.seed,
+        checkpoints,
+        control,
+    };
+    let train = |params: &Params| group.train(params, Holdout::Split);
+    run_search(
+        search,
+        &(spec.hyperparameters)(),
+        base,
+        threads,
+        control.stop_flag(),
+        &train,
+        &mut |search| {
+            if let Err(e) = save_search(&source.project_dir, search) {
+                debug_println!("[ERROR: SEARCH] unable to save {}: {}", search.id, e);
+            }
+            let _ = sender.send(SearchEvent::Trial(search.clone()));
+        },
+    )
+}
+
+impl CrossValidationRun {
+    /// ask the cross-validation to stop, running folds stop after their current epoch
+    pub(crate) fn stop(&self) {
+        self.control.stop();
+    }
+
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.control.is_stopped()
+    }
+
+    /// # take all events sent since the last call
+    ///
+    /// returns:
+    ///     true once the cross-validation is over
+    pub(crate) fn poll(&mut self) -> bool {
+        loop {
+            match self.events.try_recv() {
+                Ok(CrossValidationEvent::Status(status)) => self.status = status,
+                Ok(CrossValidationEvent::Fold(crossval)) => {
+                    self.status = format!("fold {} / {}", crossval.results.len(), crossval.folds);
+                    self.crossval = crossval;
+                }
+                Ok(CrossValidationEvent::Finished(result)) => {
+                    self.result = Some(match result {
+                        Ok(crossval) => {
+                            self.status = match &crossval.error {
+                                Some(error) => error.clone(),
+                                None => "finished".to_string(),
+                            };
+                            self.crossval = crossval;
+                            Ok(())
+                        }
+                        Err(e) => {
+                            self.status = format!("failed: {}", e);
+                            Err(e)
+                        }
+                    });
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.result.is_none() {
+                        self.status = "the cross-validation stopped unexpectedly".to_string();
+                        self.result = Some(Err(self.status.clone()));
+                    }
+                    break;
+                }
+            }
+        }
+        self.result.is_some()
+    }
+}
+
+/// # start a k-fold cross-validation of the selected model
+///
+/// uses the folds of the split assignment of the project, every fold trains a run with the
+/// hyperparameters of the model form. With `refit`, a last run trains on all folds together.
+///
+/// returns:
+///     Result with the started cross-validation
+pub(crate) fn start_cross_validation(
+    project: &Project,
+    config: &TrainingConfig,
+    refit: bool,
+) -> Result<CrossValidationRun, Box<dyn Error>> {
+    let spec =
+        model_spec(&config.model).ok_or_else(|| format!("unknown model {:?}", config.model))?;
+    let problem = project.config.problem;
+    if problem != ProblemType::Classification {
+        return Err("cross-validation needs labels, clustering has none to validate".into());
+    }
+    let source = DatasetSource::from_project(project, &config.label_column, config.seed)?;
+    if !spec.supports(problem, source.modality) {
+        return Err(format!(
+            "{} does not support {} of {} data",
+            spec.name,
+            problem.name(),
+            source.modality.name()
+        )
+        .into());
+    }
+    let folds = source.splits.as_ref().map_or(0, |s| s.config.folds);
+    if folds < 2 {
+        return Err(
+            "the splits of the project have no folds, assign them with k-folds in the Projects tab"
+                .into(),
+        );
+    }
+    let params = resolve_params(&(spec.hyperparameters)(), &config.model_params())?;
+    // like the trials of a search, the runs only keep their best model
+    let checkpoints = CheckpointConfig {
+        every: 0,
+        best_metric: config.checkpoints.best_metric.clone(),
+    };
+
+    let crossval = create_crossval(
+        &source.project_dir,
+        CrossValidation::new(spec.id, config.seed, params, folds, refit),
+    )?;
+    debug_println!(
+        "[INFO: CROSSVAL] started {} ({}, {} folds)",
+        crossval.id,
+        spec.id,
+        folds
+    );
+
+    let control = Arc::new(TrainingControl::default());
+    let (sender, events) = mpsc::channel();
+    let thread_control = control.clone();
+    let mut record = crossval.clone();
+    thread::spawn(move || {
+        let result = crossval_thread(
+            &source,
+            &spec,
+            problem,
+            &mut record,
+            &checkpoints,
+            &thread_control,
+            &sender,
+        );
+        record.finished = Some(unix_now());
+        match &result {
+            Err(e) => record.error = Some(e.to_string()),
+            Ok(()) if thread_control.is_stopped() => {
+                record.error = Some(STOPPED_BY_USER.to_string())
+            }
+            Ok(()) => {}
+        }
+        if let Err(e) = save_crossval(&source.project_dir, &record) {
+            debug_println!("[ERROR: CROSSVAL] unable to save {}: {}", record.id, e);
+        }
+        debug_println!("[INFO: CROSSVAL] finished {}", record.id);
+        let _ = sender.send(CrossValidationEvent::Finished(
+            result.map(|_| record).map_err(|e| e.to_string()),
+        ));
+    });
+
+    Ok(CrossValidationRun {
+        control,
+        events,
+        status: "starting".to_string(),
+        crossval,
+        result: None,
+    })
+}
+
+/// # the work of the cross-validation thread
+///
+/// builds the dataset once and trains the folds on it at the same time, the cross-validation
+/// is saved after every fold. The refit runs once all folds are done, if any of them
+/// succeeded.
+fn crossval_thread(
+    source: &DatasetSource,
+    spec: &ModelSpec,
+    problem: ProblemType,
+    crossval: &mut CrossValidation,
+    checkpoints: &CheckpointConfig,
+    control: &TrainingControl,
+    sender: &Sender<CrossValidationEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let mut progress = |done: usize, total: usize| {
+        let _ = sender.send(CrossValidationEvent::Status(format!(
+            "building dataset {} / {}",
+            done, total
+        )));
+    };
+    let data = build_dataset(
+        source,
+        spec.representation,
+        control.stop_flag(),
+        &mut progress,
+    )?;
+    let _ = sender.send(CrossValidationEvent::Status(describe_dataset(&data)));
+
+    let crossval_id = crossval.id.clone();
+    let params = crossval.params.clone();
+    let group = RunGroup {
+        source,
+        data: &data,
+        spec,
+        problem,
+        id: &crossval_id,
+        seed: crossval.seed,
+        checkpoints,
+        control,
+    };
+    let folds: Vec<usize> = (0..crossval.folds).collect();
+    let train = |fold: &usize| group.train(&params, Holdout::Fold(*fold));
+    run_jobs(
+        &folds,
+        default_threads(),
+        control.stop_flag(),
+        &train,
+        &mut |fold, result| {
+            let result = match result {
+                Ok(record) => FoldResult {
+                    fold,
+                    run_id: record.id,
+                    metrics: record.metrics,
+                    error: record.error,
+                },
+                Err(error) => FoldResult {
+                    fold,
+                    run_id: String::new(),
+                    metrics: Default::default(),
+                    error: Some(error),
+                },
+            };
+            crossval.add_fold(result);
+            if let Err(e) = save_crossval(&source.project_dir, crossval) {
+                debug_println!("[ERROR: CROSSVAL] unable to save {}: {}", crossval.id, e);
+            }
+            let _ = sender.send(CrossValidationEvent::Fold(crossval.clone()));
+        },
+    );
+
+    if control.is_stopped() {
+        return Ok(());
+    }
+    // a model failing on every fold would only fail once more on all of them
+    if crossval.results.iter().all(|result| result.error.is_some()) {
+        return Err("every fold failed, see the runs of the folds".into());
+    }
+    if crossval.refit {
+        let _ = sender.send(CrossValidationEvent::Status(
+            "refitting on all folds".to_string(),
+        ));
+        let record = group.train(&params, Holdout::All)?;
+        crossval.refit_run = Some(record.id);
+    }
+    Ok(())
+}
+
+impl RunGroup<'_> {
+    /// # train a run of the group on the already built dataset, in the calling thread
+    ///
+    /// returns:
+    ///     Result with the record of the run that is over (possibly with an error), `Err` if
+    ///     the run could not even be created
+    fn train(&self, params: &Params, holdout: Holdout) -> Result<RunRecord, String> {
+        let fold = match holdout {
+            Holdout::Fold(fold) => Some(fold),
+            _ => None,
+        };
+        let created = resolve_params(&(self.spec.hyperparameters)(), params).and_then(|params| {
+            let model = self.spec.create(&params)?;
+            let record = new_run(
+                self.source,
+                self.spec.id,
+                params,
+                self.seed,
+                self.checkpoints,
+                Some(self.id),
+                fold,
+            )?;
+            Ok((model, record))
+        });
+        let (model, mut record) = created.map_err(|e| e.to_string())?;
+        // the epochs of the runs of a group only go to their epoch logs
+        let (sender, _) = mpsc::channel();
+        let checkpointer = Checkpointer::new(
+            &run_dir(&self.source.project_dir, &record.id),
+            self.checkpoints.clone(),
+        );
+        let result = fit(
+            self.source,
+            self.spec,
+            self.problem,
+            Cow::Borrowed(self.data),
+            model,
+            &mut record,
+            checkpointer,
+            holdout,
+            self.control,
+            &sender,
+        );
+        if let Err(e) = &result {
+            debug_println!(
+                "[ERROR: TRAINING] {} of {} failed: {}",
+                record.id,
+                self.id,
+                e
+            );
+        }
+        finish_run(&self.source.project_dir, &mut record, &result);
+        Ok(record)
+    }
+}
+
+/// # the work of the training thread
+///
+/// builds the dataset and fits the model, see [`fit`].
+#[allow(clippy::too_many_arguments)]
+fn train(
+    source: &DatasetSource,
+    spec: &ModelSpec,
+    problem: ProblemType,
+    model: Box<dyn Model>,
+    record: &mut RunRecord,
+    checkpoints: Checkpointer,
+    control: &TrainingControl,
+    sender: &Sender<TrainEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let mut progress = |done: usize, total: usize| {
+        let _ = sender.send(TrainEvent::Status(format!(
+            "building dataset {} / {}",
+            done, total
+        )));
+    };
+    let data = build_dataset(
+        source,
+        spec.representation,
+        control.stop_flag(),
+        &mut progress,
+    )?;
+    let _ = sender.send(TrainEvent::Status(describe_dataset(&data)));
+    fit(
+        source,
+        spec,
+        problem,
+        Cow::Owned(data),
+        model,
+        record,
+        checkpoints,
+        Holdout::Split,
+        control,
+        sender,
+    )
+}
+
+/// # fit a model on a built dataset and save it
+///
+/// fills the classes, the final metrics, the best epoch and (if stopped early) the error of
+/// the record. `holdout` picks the training and validation samples of classification. The
+/// dataset is shared by the runs of a group, it is only copied if SMOTE adds samples to it.
+#[allow(clippy::too_many_arguments)]
+fn fit(
+    source: &DatasetSource,
+    spec: &ModelSpec,
+    problem: ProblemType,
+    mut data: Cow<'_, Dataset>,
+    mut model: Box<dyn Model>,
+    record: &mut RunRecord,
+    checkpoints: Checkpointer,
+    holdout: Holdout,
+    control: &TrainingControl,
+    sender: &Sender<TrainEvent>,
+) -> Result<(), Box<dyn Error>> {
+    record.classes = data.classes.clone();
+
+    // clustering has no labels to validate against, it uses every sample
+    let (train, val, weights) = match problem {
+        ProblemType::Classification => {
+            let (train, val) = match holdout {
+                Holdout::Split => (data.labelled(Split::Train), data.labelled(Split::Val)),
+                Holdout::Fold(fold) => data.fold(fold),
+                Holdout::All => {
+                    let mut train = data.labelled(Split::Train);
+                    train.extend(data.labelled(Split::Val));
+                    (train, Vec::new())
+                }
+            };
+            let (train, weights) = balance(&mut data, train, &source.preprocessing.imbalance);
+            (train, val, weights)
+        }
+        ProblemType::Clustering => ((0..data.samples.len()).collect(), Vec::new(), Vec::new()),
+    };
+    if train.is_empty() {
+        return Err("there are no training samples".into());
+    }
+
+    let mut last = checkpoints.reports().last().map(|r| r.metrics.clone());
+    let run_id = record.id.clone();
+    let mut report = |epoch: EpochReport| {
+        if let Err(e) = append_epoch(&source.project_dir, &run_id, &epoch) {
+            debug_println!(
+                "[ERROR: TRAINING] unable to log epoch {}: {}",
+                epoch.epoch,
+                e
+            );
+        }
+        last = Some(epoch.metrics.clone());
+        let _ = sender.send(TrainEvent::Epoch(epoch));
+    };
+    let mut context = FitContext {
+        train,
+        val,
+        class_weights: weights,
+        seed: record.seed,
+        augmentation: (data.modality == Modality::Image)
+            .then(|| source.preprocessing.augmentation.clone()),
+        control,
+        epoch_started: Instant::now(),
+        checkpoints: Some(checkpoints),
+        report: &mut report,
+    };
+    model.fit(&data, &mut context)?;
+    record.best = context.checkpoints.and_then(|c| c.best);
+
+    if control.is_stopped() {
+        record.error = Some(STOPPED_BY_USER.to_string());
+    }
+    record.metrics = last.unwrap_or_default();
+    model.save(&model_path(&source.project_dir, &record.id))?;
+
+    if problem == ProblemType::Clustering {
+        let assignment = ClusterAssignment::from_samples(
+            &record.id,
+            spec.id,
+            data.samples
+                .iter()
+                .map(|sample| (sample.item_id, cluster_of(&model.predict(&sample.x)))),
+        );
+        save_clusters(&source.project_dir, &assignment)?;
+        let _ = sender.send(TrainEvent::Status(format!(
+            "{} items in {} clusters",
+            assignment.items.len(),
+            assignment.clusters
+        )));
+    }
+    debug_println!("[INFO: TRAINING] finished {}", record.id);
+    Ok(())
+}
+
+/// # class imbalance handling of the training samples
+///
+/// SMOTE adds synthetic samples to (a copy of) the dataset (feature vectors only, tensors are
+/// resampled randomly instead). The class weights are computed from the resampled samples
+/// (like the preview of the Preprocessing tab shows them), so they only make up for the
+/// imbalance the resampling left.
+///
+/// returns:
+///     the (resampled) training indices and the loss weight per class
+fn balance(
+    data: &mut Cow<'_, Dataset>,
+    train: Vec<usize>,
+    config: &ImbalanceConfig,
+) -> (Vec<usize>, Vec<f64>) {
+    let label_of =
+        |data: &Dataset, i: usize| data.classes[data.samples[i].label.unwrap_or(0)].clone();
+    let labels: Vec<String> = train.iter().map(|i| label_of(data, *i)).collect();
+
+    let train = match config.resampling {
+        Resampling::None => train,
+        Resampling::Smote if data.representation == Representation::Features => {
+            let features: Vec<Vec<f64>> = train
+                .iter()
+                .map(|i| data.samples[*i].x.iter().map(|v| *v as f64).collect())
+                .collect();
+            let (synthetic, synthetic_labels) = smote(&features, &labels, config);
+            let data = data.to_mut();
+            let mut train = train;
+            for (x, label) in synthetic.into_iter().zip(synthetic_labels) {
+                train.push(data.samples.len());
+                data.samples.push(Sample {
+                    // synthetic samples do not belong to any item
+                    item_id: 0,
+                    x: x.into_iter().map(|v| v as f32).collect(),
+                    label: data.classes.iter().position(|c| *c == label),
+                    split: Split::Train,
+                    fold: None,
+                });
+            }
+            train
+        }
+        _ => resample_indices(&labels, config)
+            .into_iter()
+            .map(|j| train[j])
+            .collect(),
+    };
+
+    let labels: Vec<String> = train.iter().map(|i| label_of(data, *i)).collect();
+    let weights = class_weights(&label_counts(&labels), config);
+    let weights = data
+        .classes
+        .iter()
+        .map(|class| weights.get(class).copied().unwrap_or(1.0))
+        .collect();
+    (train, weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imbalance::ClassWeighting;
+
+    /// six samples of `a`, two of `b`
+    fn imbalanced() -> Dataset {
+        let labels = [0, 0, 0, 0, 0, 0, 1, 1];
+        Dataset {
+            modality: Modality::Tabular,
+            representation: Representation::Features,
+            shape: vec![2],
+            classes: vec!["a".to_string(), "b".to_string()],
+            samples: labels
+                .iter()
+                .enumerate()
+                .map(|(i, label)| Sample {
+                    item_id: 1,
+                    x: vec![i as f32, (i * i) as f32],
+                    label: Some(*label),
+                    split: Split::Train,
+                    fold: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// samples times weight per class
+    fn class_mass(data: &Dataset, train: &[usize], weights: &[f64]) -> Vec<f64> {
+        data.class_counts(train)
+            .iter()
+            .zip(weights)
+            .map(|(count, weight)| *count as f64 * weight)
+            .collect()
+    }
+
+    #[test]
+    fn resampling_and_weights_balance_once() {
+        let data = imbalanced();
+        for resampling in Resampling::ALL {
+            for ratio in [0.5, 1.0] {
+                let config = ImbalanceConfig {
+                    resampling,
+                    ratio,
+                    neighbours: 1,
+                    ..ImbalanceConfig::default()
+                };
+                let mut resampled = Cow::Borrowed(&data);
+                let (train, weights) = balance(&mut resampled, (0..8).collect(), &config);
+                let mass = class_mass(&resampled, &train, &weights);
+                let context = format!("{:?} {}: {:?} {:?}", resampling, ratio, train, weights);
+                // every class weighs the same in the loss, and the loss keeps its magnitude
+                assert!((mass[0] - mass[1]).abs() < 1e-9, "{}", context);
+                assert!(
+                    (mass.iter().sum::<f64>() - train.len() as f64).abs() < 1e-9,
+                    "{}",
+                    context
+                );
+                // fully resampled classes need no weights on top
+                if resampling != Resampling::None && ratio == 1.0 {
+                    assert!(
+                        weights.iter().all(|w| (w - 1.0).abs() < 1e-9),
+                        "{}",
+                        context
+                    );
+                }
+            }
+        }
+
+        // without weighting the resampling alone decides
+        let config = ImbalanceConfig {
+            resampling: Resampling::RandomOver,
+            ratio: 0.5,
+            weighting: ClassWeighting::None,
+            ..ImbalanceConfig::default()
+        };
+        let mut resampled = Cow::Borrowed(&data);
+        let (train, weights) = balance(&mut resampled, (0..8).collect(), &config);
+        assert_eq!(weights, [1.0, 1.0]);
+        assert_eq!(class_mass(&resampled, &train, &weights), [6.0, 3.0]);
+    }
+}
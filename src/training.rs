@@ -0,0 +1,1137 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Training tab
+//!
+//! Lists the models of the registry that fit the problem type and modality of the project,
+//! shows a form for the hyperparameters of the selected model and starts training runs.
+
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::charts::{draw_line_chart, palette_colour, LineChart, Series};
+use crate::cluster::ElbowPoint;
+use crate::debug_println;
+use crate::experiments::runs_ui;
+use crate::external::{format_params, parse_params, Transport};
+use crate::helper::{show_error_message, ProblemType};
+use crate::metrics::{format_metric, higher_is_better, Metrics};
+use crate::model::{
+    compatible_models, EpochReport, Hyperparameter, ModelSpec, ParamKind, ParamValue, Params,
+};
+use crate::project::SharedProject;
+use crate::store::Modality;
+use crate::trainer::{
+    load_training, resident_memory, resumable_runs, resume_training, save_training,
+    start_cross_validation, start_elbow, start_external, start_training, CrossValidationRun,
+    TrainingConfig, TrainingRun,
+};
+use crate::tuning::search_ui;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// interval of polling a running training for new events
+const PROGRESS_INTERVAL_MS: u64 = 100;
+
+const BYTE_UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+/// one line of metrics, e.g. `accuracy 0.9120  loss 0.2731`
+pub(crate) fn format_metrics(metrics: &Metrics) -> String {
+    metrics
+        .iter()
+        .map(|(name, value)| format!("{} {}", name, format_metric(*value)))
+        .collect::<Vec<String>>()
+        .join("  ")
+}
+
+fn clear_list(list: &gtk::ListBox) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+}
+
+/// e.g. `1h 05m`, `3m 20s` or `12s`
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// e.g. `312.4 MB`
+fn format_bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, BYTE_UNITS[unit])
+}
+
+/// draw charts next to each other, each getting the same share of the width
+pub(crate) fn draw_charts(cr: &gtk::cairo::Context, width: i32, height: i32, charts: &[LineChart]) {
+    let share = width as f64 / charts.len().max(1) as f64;
+    for (i, chart) in charts.iter().enumerate() {
+        cr.save().ok();
+        cr.translate(i as f64 * share, 0.0);
+        if let Err(e) = draw_line_chart(cr, share, height as f64, chart) {
+            debug_println!(
+                "[ERROR: TRAINING] unable to draw the chart {:?}: {}",
+                chart.title,
+                e
+            );
+        }
+        cr.restore().ok();
+    }
+}
+
+/// # names of the metrics reported so far, without the `val_` prefix
+///
+/// e.g. `loss` and `accuracy` for reports with `loss`, `val_loss` and `val_accuracy`
+pub(crate) fn curve_names(epochs: &[EpochReport]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in epochs.iter().flat_map(|e| e.metrics.keys()) {
+        let name = name.strip_prefix("val_").unwrap_or(name);
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// # training (solid) and validation (dashed) curve of a metric over the epochs
+///
+/// `label` is put in front of the series labels (e.g. the run id when comparing runs), epochs
+/// without the metric are left out.
+pub(crate) fn metric_series(
+    epochs: &[EpochReport],
+    name: &str,
+    label: &str,
+    colour: usize,
+) -> Vec<Series> {
+    [(name.to_string(), false), (format!("val_{}", name), true)]
+        .into_iter()
+        .filter_map(|(key, dashed)| {
+            let points: Vec<(f64, f64)> = epochs
+                .iter()
+                .filter_map(|e| Some((e.epoch as f64, *e.metrics.get(&key)?)))
+                .collect();
+            (!points.is_empty()).then(|| Series {
+                label: format!("{}{}", label, key),
+                points,
+                colour: palette_colour(colour),
+                dashed,
+            })
+        })
+        .collect()
+}
+
+/// # curves of a running (or finished) training
+///
+/// the first chart shows the loss (or whatever the model minimises), the second one the chosen
+/// metric, both for the training and the validation samples.
+fn dashboard_charts(epochs: &[EpochReport], metric: &str) -> Vec<LineChart> {
+    let names = curve_names(epochs);
+    let loss = names.iter().find(|n| !higher_is_better(n));
+    [loss.map(String::as_str), Some(metric)]
+        .into_iter()
+        .flatten()
+        .filter(|name| names.iter().any(|n| n == name))
+        .enumerate()
+        .map(|(i, name)| LineChart {
+            title: name.to_string(),
+            x_label: "epoch".to_string(),
+            series: metric_series(epochs, name, "", i),
+        })
+        .collect()
+}
+
+/// one line of progress, e.g. `epoch 3 / 20  lr 0.0010  820 samples/s  ETA 1m 20s`
+fn format_run_stats(run: &TrainingRun) -> String {
+    let mut stats = Vec::new();
+    if let Some(last) = run.epochs.last() {
+        stats.push(format!("epoch {} / {}", last.epoch, last.epochs));
+        if let Some(rate) = last.learning_rate {
+            stats.push(format!("lr {}", format_metric(rate)));
+        }
+    }
+    if let Some(throughput) = run.throughput() {
+        stats.push(format!("{:.0} samples/s", throughput));
+    }
+    if run.result.is_none() {
+        if let Some(remaining) = run.remaining() {
+            stats.push(format!("ETA {}", format_duration(remaining)));
+        }
+    }
+    if let Some(memory) = resident_memory() {
+        stats.push(format!("memory {}", format_bytes(memory)));
+    }
+    stats.join("  ")
+}
+
+/// inertia and silhouette curves over `k`, side by side
+fn elbow_charts(points: &[ElbowPoint]) -> [LineChart; 2] {
+    let curve = |label: &str, value: fn(&ElbowPoint) -> f64, colour: usize| LineChart {
+        title: label.to_string(),
+        x_label: "k".to_string(),
+        series: vec![Series {
+            label: label.to_string(),
+            points: points.iter().map(|p| (p.k as f64, value(p))).collect(),
+            colour: palette_colour(colour),
+            dashed: false,
+        }],
+    };
+    [
+        curve("inertia (look for the elbow)", |p| p.inertia, 0),
+        curve("silhouette (higher is better)", |p| p.silhouette, 1),
+    ]
+}
+
+/// # input widget of a hyperparameter
+///
+/// `on_change` gets every new value of the widget.
+///
+/// returns:
+///     the widget, its tooltip is the description of the hyperparameter
+pub(crate) fn param_widget(
+    hyperparameter: &Hyperparameter,
+    value: &ParamValue,
+    on_change: Rc<dyn Fn(ParamValue)>,
+) -> gtk::Widget {
+    let widget: gtk::Widget = match &hyperparameter.kind {
+        ParamKind::Int { min, max } => {
+            let spin = gtk::SpinButton::with_range(*min as f64, *max as f64, 1.0);
+            spin.set_digits(0);
+            spin.set_value(value.as_f64());
+            spin.connect_value_changed(move |spin| on_change(ParamValue::Int(spin.value() as i64)));
+            spin.upcast()
+        }
+        ParamKind::Float { min, max, log } => {
+            let step = if *log { *min } else { (max - min) / 100.0 };
+            let spin = gtk::SpinButton::with_range(*min, *max, step);
+            spin.set_digits(if *log { 6 } else { 3 });
+            spin.set_value(value.as_f64());
+            spin.connect_value_changed(move |spin| on_change(ParamValue::Float(spin.value())));
+            spin.upcast()
+        }
+        ParamKind::Choice(options) => {
+            let dropdown = gtk::DropDown::from_strings(options);
+            let current = value.display();
+            if let Some(index) = options.iter().position(|o| *o == current) {
+                dropdown.set_selected(index as u32);
+            }
+            let options = *options;
+            dropdown.connect_selected_notify(move |dropdown| {
+                if let Some(option) = options.get(dropdown.selected() as usize) {
+                    on_change(ParamValue::Text(option.to_string()));
+                }
+            });
+            dropdown.upcast()
+        }
+        ParamKind::Bool => {
+            let check = gtk::CheckButton::builder()
+                .active(matches!(value, ParamValue::Bool(true)))
+                .build();
+            check.connect_toggled(move |check| on_change(ParamValue::Bool(check.is_active())));
+            check.upcast()
+        }
+    };
+    widget.set_tooltip_text(Some(hyperparameter.description));
+    widget
+}
+
+/// # fill a grid with the hyperparameter form of a model
+///
+/// values missing in `params` are shown with their defaults. every edit is passed to
+/// `on_change` together with the name of the hyperparameter.
+pub(crate) fn fill_param_grid(
+    grid: &gtk::Grid,
+    spec: &ModelSpec,
+    params: &Params,
+    on_change: Rc<dyn Fn(&str, ParamValue)>,
+) {
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+    let hyperparameters = (spec.hyperparameters)();
+    if hyperparameters.is_empty() {
+        grid.attach(
+            &Label::new(Some("this model has no hyperparameters")),
+            0,
+            0,
+            2,
+            1,
+        );
+    }
+    for (row, hyperparameter) in hyperparameters.iter().enumerate() {
+        let value = params
+            .get(hyperparameter.name)
+            .unwrap_or(&hyperparameter.default);
+        let name = hyperparameter.name;
+        let on_change = on_change.clone();
+        let widget = param_widget(
+            hyperparameter,
+            value,
+            Rc::new(move |value| on_change(name, value)),
+        );
+        let label = Label::builder()
+            .label(name)
+            .halign(gtk::Align::Start)
+            .tooltip_text(hyperparameter.description)
+            .build();
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(&widget, 1, row as i32, 1, 1);
+    }
+}
+
+/// Training page: choose a compatible model, set its hyperparameters, train it
+pub fn training_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_top(15)
+        .margin_bottom(24)
+        .margin_start(50)
+        .margin_end(50)
+        .spacing(10)
+        .build();
+
+    let config: Rc<RefCell<TrainingConfig>> = Rc::default();
+    let models: Rc<RefCell<Vec<ModelSpec>>> = Rc::default();
+    // project directory the settings were loaded from, reloaded when another project is opened
+    let loaded_from: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+    let run: Rc<RefCell<Option<TrainingRun>>> = Rc::default();
+
+    let header_label = Label::builder()
+        .label("no project opened")
+        .halign(gtk::Align::Start)
+        .build();
+    header_label.add_css_class("title-4");
+
+    // compatible models
+    // ---------------------------------------------------------------------------------------------
+    let models_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .build();
+    let models_window = gtk::ScrolledWindow::builder()
+        .width_request(250)
+        .vexpand(true)
+        .child(&models_list)
+        .build();
+
+    // hyperparameters and settings of the run
+    // ---------------------------------------------------------------------------------------------
+    let description_label = Label::builder()
+        .wrap(true)
+        .halign(gtk::Align::Start)
+        .build();
+    let params_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+
+    let settings_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let seed_spin = gtk::SpinButton::with_range(0.0, u32::MAX as f64, 1.0);
+    let label_column_label = Label::builder()
+        .label("label column")
+        .halign(gtk::Align::Start)
+        .build();
+    let label_column_entry = gtk::Entry::new();
+    settings_grid.attach(
+        &Label::builder()
+            .label("seed")
+            .halign(gtk::Align::Start)
+            .build(),
+        0,
+        0,
+        1,
+        1,
+    );
+    settings_grid.attach(&seed_spin, 1, 0, 1, 1);
+    settings_grid.attach(&label_column_label, 0, 1, 1, 1);
+    settings_grid.attach(&label_column_entry, 1, 1, 1, 1);
+    let checkpoint_spin = gtk::SpinButton::with_range(0.0, 10000.0, 1.0);
+    checkpoint_spin.set_tooltip_text(Some(
+        "write a checkpoint after every this many epochs (0 for never), a stopped run continues from the latest one",
+    ));
+    let best_metric_entry = gtk::Entry::builder()
+        .tooltip_text("validation metric picking the best epoch, e.g. val_loss or val_accuracy")
+        .build();
+    for (row, (label, widget)) in [
+        (
+            "checkpoint every",
+            checkpoint_spin.upcast_ref::<gtk::Widget>(),
+        ),
+        ("best epoch by", best_metric_entry.upcast_ref()),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        settings_grid.attach(
+            &Label::builder()
+                .label(label)
+                .halign(gtk::Align::Start)
+                .build(),
+            0,
+            row as i32 + 2,
+            1,
+            1,
+        );
+        settings_grid.attach(widget, 1, row as i32 + 2, 1, 1);
+    }
+
+    let form = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(10)
+        .hexpand(true)
+        .build();
+    form.append(&description_label);
+    form.append(&params_grid);
+    form.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    form.append(&settings_grid);
+
+    let hbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(20)
+        .vexpand(true)
+        .build();
+    hbox.append(&models_window);
+    hbox.append(&form);
+
+    // run controls
+    // ---------------------------------------------------------------------------------------------
+    let start_btn = Button::with_label("start training");
+    let pause_btn = gtk::ToggleButton::with_label("pause");
+    pause_btn.set_sensitive(false);
+    let stop_btn = Button::with_label("stop");
+    stop_btn.set_sensitive(false);
+    // runs that were stopped or crashed, continued from their latest checkpoint
+    let resumable_ids: Rc<RefCell<Vec<String>>> = Rc::default();
+    let continue_dd = gtk::DropDown::from_strings(&[]);
+    continue_dd.set_tooltip_text(Some("interrupted runs with a checkpoint"));
+    let continue_btn = Button::with_label("continue");
+    continue_btn.set_tooltip_text(Some("continue the chosen run from its latest checkpoint"));
+    continue_btn.set_sensitive(false);
+    let progress_bar = gtk::ProgressBar::builder()
+        .show_text(true)
+        .text("not running")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let metrics_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .selectable(true)
+        .build();
+    metrics_label.add_css_class("monospace");
+
+    let run_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    run_box.append(&start_btn);
+    run_box.append(&pause_btn);
+    run_box.append(&stop_btn);
+    run_box.append(&progress_bar);
+    run_box.append(&continue_dd);
+    run_box.append(&continue_btn);
+
+    // live curves of the run, loss next to a metric of choice
+    // ---------------------------------------------------------------------------------------------
+    let stats_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .hexpand(true)
+        .build();
+    let curve_names_shown: Rc<RefCell<Vec<String>>> = Rc::default();
+    let curve_metric: Rc<RefCell<String>> = Rc::default();
+    let curve_dd = gtk::DropDown::from_strings(&[]);
+    curve_dd.set_tooltip_text(Some("metric drawn next to the loss"));
+    let stats_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    stats_box.append(&stats_label);
+    stats_box.append(&Label::new(Some("metric")));
+    stats_box.append(&curve_dd);
+
+    let curves_area = gtk::DrawingArea::builder()
+        .content_height(240)
+        .hexpand(true)
+        .build();
+    curves_area.set_draw_func(
+        gtk::glib::clone!(@strong run, @strong curve_metric => move |_, cr, width, height| {
+            // skipped while the timer updates the run, it draws again afterwards
+            let Ok(run) = run.try_borrow() else {
+                return;
+            };
+            if let Some(run) = run.as_ref() {
+                draw_charts(cr, width, height, &dashboard_charts(&run.epochs, &curve_metric.borrow()));
+            }
+        }),
+    );
+
+    curve_dd.connect_selected_notify(gtk::glib::clone!(@strong curve_names_shown, @strong curve_metric, @strong curves_area => move |dd| {
+        if let Some(name) = curve_names_shown.borrow().get(dd.selected() as usize) {
+            curve_metric.replace(name.clone());
+            curves_area.queue_draw();
+        }
+    }));
+
+    // number of clusters, inertia and silhouette of k-means over a range of k
+    // ---------------------------------------------------------------------------------------------
+    let elbow_points: Rc<RefCell<Vec<ElbowPoint>>> = Rc::default();
+    let k_from_spin = gtk::SpinButton::with_range(1.0, 100.0, 1.0);
+    k_from_spin.set_value(2.0);
+    let k_to_spin = gtk::SpinButton::with_range(1.0, 100.0, 1.0);
+    k_to_spin.set_value(10.0);
+    let elbow_btn = Button::with_label("compute");
+    let elbow_label = Label::builder()
+        .label("inertia and silhouette of k-means for every k")
+        .halign(gtk::Align::Start)
+        .hexpand(true)
+        .build();
+    let elbow_controls = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    elbow_controls.append(&Label::new(Some("k from")));
+    elbow_controls.append(&k_from_spin);
+    elbow_controls.append(&Label::new(Some("to")));
+    elbow_controls.append(&k_to_spin);
+    elbow_controls.append(&elbow_btn);
+    elbow_controls.append(&elbow_label);
+
+    let elbow_area = gtk::DrawingArea::builder()
+        .content_height(220)
+        .hexpand(true)
+        .build();
+    elbow_area.set_draw_func(
+        gtk::glib::clone!(@strong elbow_points => move |_, cr, width, height| {
+            let points = elbow_points.borrow();
+            if !points.is_empty() {
+                draw_charts(cr, width, height, &elbow_charts(&points));
+            }
+        }),
+    );
+
+    let elbow_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    elbow_box.append(&elbow_controls);
+    elbow_box.append(&elbow_area);
+    // history of all runs, compared side by side
+    // ---------------------------------------------------------------------------------------------
+    let (runs_box, reload_runs) = runs_ui(project);
+    let runs_expander = gtk::Expander::builder()
+        .label("runs")
+        .child(&runs_box)
+        .build();
+    runs_expander.connect_expanded_notify(
+        gtk::glib::clone!(@strong reload_runs => move |expander| {
+            if expander.is_expanded() {
+                reload_runs();
+            }
+        }),
+    );
+
+    let elbow_expander = gtk::Expander::builder()
+        .label("choose the number of clusters")
+        .child(&elbow_box)
+        .visible(false)
+        .build();
+
+    elbow_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong elbow_points, @strong elbow_area, @strong elbow_label, @strong k_from_spin, @strong k_to_spin, @strong seed_spin, @strong label_column_entry => move |elbow_btn| {
+        let (from, to) = (k_from_spin.value() as usize, k_to_spin.value() as usize);
+        let ks: Vec<usize> = (from.min(to)..=from.max(to)).collect();
+        let started = {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                return;
+            };
+            let mut config = config.borrow_mut();
+            config.seed = seed_spin.value() as u64;
+            config.label_column = label_column_entry.text().trim().to_string();
+            start_elbow(project, &config, ks)
+        };
+        let mut sweep = match started {
+            Ok(sweep) => sweep,
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("TRAINING ERROR"),
+                    Some(&format!("Unable to compute the elbow curve:\n{}", e)),
+                );
+                return;
+            }
+        };
+        elbow_btn.set_sensitive(false);
+        elbow_points.borrow_mut().clear();
+        elbow_area.queue_draw();
+
+        gtk::glib::timeout_add_local(
+            Duration::from_millis(PROGRESS_INTERVAL_MS),
+            gtk::glib::clone!(@strong elbow_points, @strong elbow_area, @strong elbow_label, @strong elbow_btn => move || {
+                // the sweep is cancelled when the tab is gone
+                if elbow_area.root().is_none() {
+                    sweep.cancel();
+                    return gtk::glib::ControlFlow::Break;
+                }
+                if !sweep.poll() {
+                    elbow_label.set_text(&sweep.status);
+                    return gtk::glib::ControlFlow::Continue;
+                }
+                match sweep.points.take() {
+                    Some(Ok(points)) => {
+                        let best = points.iter().max_by(|a, b| a.silhouette.total_cmp(&b.silhouette));
+                        elbow_label.set_text(&match best {
+                            Some(best) => format!("highest silhouette at k = {}", best.k),
+                            None => String::new(),
+                        });
+                        elbow_points.replace(points);
+                        elbow_area.queue_draw();
+                    }
+                    Some(Err(e)) => {
+                        debug_println!("[ERROR: TRAINING] elbow curve failed: {}", e);
+                        elbow_label.set_text(&format!("failed: {}", e));
+                    }
+                    None => {}
+                }
+                elbow_btn.set_sensitive(true);
+                gtk::glib::ControlFlow::Break
+            }),
+        );
+    }));
+
+    let selected_model = gtk::glib::clone!(@strong models, @strong models_list => move || {
+        let index = models_list.selected_row()?.index() as usize;
+        models.borrow().get(index).cloned()
+    });
+
+    // form of the selected model, filled again when a search changed its hyperparameters
+    let show_params: Rc<dyn Fn()> = Rc::new(
+        gtk::glib::clone!(@strong config, @strong selected_model, @strong params_grid, @strong description_label => move || {
+            let Some(spec) = selected_model() else {
+                description_label.set_text("");
+                while let Some(child) = params_grid.first_child() {
+                    params_grid.remove(&child);
+                }
+                return;
+            };
+            description_label.set_text(spec.description);
+            config.borrow_mut().model = spec.id.to_string();
+            let params = config.borrow().model_params();
+            let id = spec.id.to_string();
+            fill_param_grid(&params_grid, &spec, &params, Rc::new(gtk::glib::clone!(@strong config => move |name, value| {
+                config.borrow_mut().params.entry(id.clone()).or_default().insert(name.to_string(), value);
+            })));
+        }),
+    );
+
+    // search over the hyperparameters of the selected model
+    // ---------------------------------------------------------------------------------------------
+    let (search_box, refresh_search) =
+        search_ui(project, &config, reload_runs.clone(), show_params.clone());
+    let search_expander = gtk::Expander::builder()
+        .label("hyperparameter search")
+        .child(&search_box)
+        .build();
+
+    // k-fold cross-validation of the selected model on the folds of the splits
+    // ---------------------------------------------------------------------------------------------
+    let cv_btn = Button::with_label("cross-validate");
+    cv_btn.set_tooltip_text(Some(
+        "train one run per fold of the splits, each validated on its fold",
+    ));
+    let refit_check = gtk::CheckButton::with_label("refit on all data");
+    refit_check.set_tooltip_text(Some(
+        "train a last run on all folds together once the folds are done",
+    ));
+    let cv_stop_btn = Button::with_label("stop");
+    cv_stop_btn.set_sensitive(false);
+    let cv_progress_bar = gtk::ProgressBar::builder()
+        .show_text(true)
+        .text("not running")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let cv_controls = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    cv_controls.append(&cv_btn);
+    cv_controls.append(&refit_check);
+    cv_controls.append(&cv_stop_btn);
+    cv_controls.append(&cv_progress_bar);
+    let cv_label = Label::builder()
+        .label("uses the k-folds of the splits in the Projects tab")
+        .halign(gtk::Align::Start)
+        .selectable(true)
+        .build();
+    cv_label.add_css_class("monospace");
+
+    let cv_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    cv_box.append(&cv_controls);
+    cv_box.append(&cv_label);
+    let cv_expander = gtk::Expander::builder()
+        .label("cross-validation")
+        .child(&cv_box)
+        .visible(false)
+        .build();
+
+    let cv_run: Rc<RefCell<Option<CrossValidationRun>>> = Rc::default();
+    cv_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong cv_run, @strong refit_check, @strong cv_stop_btn, @strong cv_progress_bar, @strong cv_label, @strong seed_spin, @strong label_column_entry, @strong reload_runs => move |cv_btn| {
+        let started = {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                return;
+            };
+            let mut config = config.borrow_mut();
+            config.seed = seed_spin.value() as u64;
+            config.label_column = label_column_entry.text().trim().to_string();
+            if let Err(e) = save_training(project.dir(), &config) {
+                debug_println!("[ERROR: TRAINING] unable to save the training settings: {}", e);
+            }
+            start_cross_validation(project, &config, refit_check.is_active())
+        };
+        match started {
+            Ok(started) => {
+                cv_label.set_text(&started.crossval.describe().join("\n"));
+                cv_run.replace(Some(started));
+            }
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("TRAINING ERROR"),
+                    Some(&format!("Unable to start the cross-validation:\n{}", e)),
+                );
+                return;
+            }
+        }
+        cv_btn.set_sensitive(false);
+        cv_stop_btn.set_sensitive(true);
+        cv_progress_bar.set_fraction(0.0);
+
+        gtk::glib::timeout_add_local(
+            Duration::from_millis(PROGRESS_INTERVAL_MS),
+            gtk::glib::clone!(@strong cv_run, @strong cv_btn, @strong cv_stop_btn, @strong cv_progress_bar, @strong cv_label, @strong reload_runs => move || {
+                let mut guard = cv_run.borrow_mut();
+                let Some(current) = guard.as_mut() else {
+                    return gtk::glib::ControlFlow::Break;
+                };
+                // the cross-validation is stopped when the tab is gone
+                if cv_label.root().is_none() {
+                    current.stop();
+                    return gtk::glib::ControlFlow::Break;
+                }
+                let done = current.crossval.results.len();
+                let finished = current.poll();
+                let crossval = &current.crossval;
+                cv_progress_bar.set_fraction(crossval.results.len() as f64 / crossval.folds.max(1) as f64);
+                cv_progress_bar.set_text(Some(&format!("{}: {}", crossval.id, current.status)));
+                if crossval.results.len() != done || finished {
+                    cv_label.set_text(&crossval.describe().join("\n"));
+                }
+                if !finished {
+                    return gtk::glib::ControlFlow::Continue;
+                }
+
+                if let Some(Err(e)) = &current.result {
+                    debug_println!("[ERROR: TRAINING] {} failed: {}", crossval.id, e);
+                    show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("TRAINING ERROR"),
+                        Some(&format!("The cross-validation {} failed:\n{}", crossval.id, e)),
+                    );
+                }
+                drop(guard);
+                cv_btn.set_sensitive(true);
+                cv_stop_btn.set_sensitive(false);
+                reload_runs();
+                gtk::glib::ControlFlow::Break
+            }),
+        );
+    }));
+
+    cv_stop_btn.connect_clicked(gtk::glib::clone!(@strong cv_run => move |cv_stop_btn| {
+        if let Some(current) = cv_run.borrow().as_ref() {
+            current.stop();
+        }
+        cv_stop_btn.set_sensitive(false);
+    }));
+
+    // training script outside of AI Lab, reporting over JSON lines
+    // ---------------------------------------------------------------------------------------------
+    let external_command_entry = gtk::Entry::builder()
+        .placeholder_text("e.g. python3 train.py")
+        .tooltip_text("shell command run in the project directory, it finds the job in $AI_LAB_JOB")
+        .hexpand(true)
+        .build();
+    let transport_names: Vec<&str> = Transport::ALL.iter().map(Transport::name).collect();
+    let transport_dd = gtk::DropDown::from_strings(&transport_names);
+    transport_dd.set_tooltip_text(Some(
+        "where the trainer sends its events, the socket is named by $AI_LAB_SOCKET",
+    ));
+    let external_btn = Button::with_label("start external training");
+    let external_controls = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    external_controls.append(&Label::new(Some("command")));
+    external_controls.append(&external_command_entry);
+    external_controls.append(&Label::new(Some("events over")));
+    external_controls.append(&transport_dd);
+    external_controls.append(&external_btn);
+    let external_params_view = gtk::TextView::builder()
+        .monospace(true)
+        .height_request(80)
+        .build();
+    external_params_view.set_tooltip_text(Some(
+        "hyperparameters passed to the trainer, one `name = value` per line",
+    ));
+
+    let external_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+    external_box.append(&external_controls);
+    external_box.append(
+        &Label::builder()
+            .label("hyperparameters")
+            .halign(gtk::Align::Start)
+            .build(),
+    );
+    external_box.append(&external_params_view);
+    let external_expander = gtk::Expander::builder()
+        .label("external trainer")
+        .child(&external_box)
+        .build();
+
+    models_list.connect_selected_rows_changed(
+        gtk::glib::clone!(@strong show_params, @strong refresh_search => move |_| {
+            show_params();
+            refresh_search();
+        }),
+    );
+
+    let refresh_resumable = Rc::new(
+        gtk::glib::clone!(@strong project, @strong resumable_ids, @strong continue_dd, @strong continue_btn, @strong start_btn, @strong reload_runs => move || {
+            reload_runs();
+            let runs = match project.borrow().as_ref() {
+                Some(project) => resumable_runs(project.dir()),
+                None => Vec::new(),
+            };
+            let names: Vec<String> = runs
+                .iter()
+                .map(|r| format!("{} ({})", r.id, r.model))
+                .collect();
+            let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+            continue_dd.set_model(Some(&gtk::StringList::new(&strings)));
+            continue_dd.set_visible(!runs.is_empty());
+            continue_btn.set_visible(!runs.is_empty());
+            continue_btn.set_sensitive(start_btn.is_sensitive());
+            resumable_ids.replace(runs.into_iter().map(|r| r.id).collect());
+        }),
+    );
+
+    // the training thread only sends events, the widgets are updated from the main loop
+    let follow_run = Rc::new(
+        gtk::glib::clone!(@strong run, @strong start_btn, @strong continue_btn, @strong refresh_resumable, @strong pause_btn, @strong stop_btn, @strong progress_bar, @strong metrics_label, @strong stats_label, @strong curve_dd, @strong curve_names_shown, @strong curve_metric, @strong curves_area => move |started: TrainingRun| {
+            run.replace(Some(started));
+            start_btn.set_sensitive(false);
+            continue_btn.set_sensitive(false);
+            pause_btn.set_active(false);
+            pause_btn.set_sensitive(true);
+            stop_btn.set_sensitive(true);
+            progress_bar.set_fraction(0.0);
+            metrics_label.set_text("");
+            stats_label.set_text("");
+            curves_area.queue_draw();
+
+            gtk::glib::timeout_add_local(
+                Duration::from_millis(PROGRESS_INTERVAL_MS),
+                gtk::glib::clone!(@strong run, @strong start_btn, @strong refresh_resumable, @strong pause_btn, @strong stop_btn, @strong progress_bar, @strong metrics_label, @strong stats_label, @strong curve_dd, @strong curve_names_shown, @strong curve_metric, @strong curves_area => move || {
+                    let mut guard = run.borrow_mut();
+                    let Some(current) = guard.as_mut() else {
+                        return gtk::glib::ControlFlow::Break;
+                    };
+                    let epochs = current.epochs.len();
+                    let finished = current.poll();
+                    if let Some(last) = current.epochs.last() {
+                        progress_bar.set_fraction(last.epoch as f64 / last.epochs.max(1) as f64);
+                        metrics_label.set_text(&format_metrics(&last.metrics));
+                    } else {
+                        progress_bar.pulse();
+                    }
+                    let paused = if current.is_paused() && !finished { " (paused)" } else { "" };
+                    progress_bar.set_text(Some(&format!("{}: {}{}", current.run_id, current.status, paused)));
+                    stats_label.set_text(&format_run_stats(current));
+
+                    // the metric choice is offered again whenever the model reports new metrics
+                    let names: Vec<String> = curve_names(&current.epochs)
+                        .into_iter()
+                        .filter(|name| higher_is_better(name))
+                        .collect();
+                    let changed_epochs = current.epochs.len() != epochs;
+                    drop(guard);
+                    if names != *curve_names_shown.borrow() {
+                        let chosen = curve_metric.borrow().clone();
+                        let index = names.iter().position(|name| *name == chosen).unwrap_or(0);
+                        curve_names_shown.replace(names.clone());
+                        let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+                        curve_dd.set_model(Some(&gtk::StringList::new(&strings)));
+                        curve_dd.set_selected(index as u32);
+                        curve_metric.replace(names.get(index).cloned().unwrap_or_default());
+                    }
+                    if changed_epochs {
+                        curves_area.queue_draw();
+                    }
+                    if !finished {
+                        return gtk::glib::ControlFlow::Continue;
+                    }
+
+                    let guard = run.borrow();
+                    let Some(current) = guard.as_ref() else {
+                        return gtk::glib::ControlFlow::Break;
+                    };
+
+                    if let Some(Err(e)) = &current.result {
+                        debug_println!("[ERROR: TRAINING] {} failed: {}", current.run_id, e);
+                        show_error_message(
+                            None::<&gtk::Widget>,
+                            Some("TRAINING ERROR"),
+                            Some(&format!("The training run {} failed:\n{}", current.run_id, e)),
+                        );
+                    } else {
+                        progress_bar.set_fraction(1.0);
+                    }
+                    drop(guard);
+                    start_btn.set_sensitive(true);
+                    refresh_resumable();
+                    pause_btn.set_active(false);
+                    pause_btn.set_sensitive(false);
+                    stop_btn.set_sensitive(false);
+                    gtk::glib::ControlFlow::Break
+                }),
+            );
+        }),
+    );
+
+    start_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong follow_run, @strong seed_spin, @strong label_column_entry, @strong checkpoint_spin, @strong best_metric_entry => move |_| {
+        let started = {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("TRAINING ERROR"),
+                    Some("Please open a project first."),
+                );
+                return;
+            };
+            let mut config = config.borrow_mut();
+            config.seed = seed_spin.value() as u64;
+            config.label_column = label_column_entry.text().trim().to_string();
+            config.checkpoints.every = checkpoint_spin.value() as usize;
+            let best_metric = best_metric_entry.text().trim().to_string();
+            if !best_metric.is_empty() {
+                config.checkpoints.best_metric = best_metric;
+            }
+            if let Err(e) = save_training(project.dir(), &config) {
+                debug_println!("[ERROR: TRAINING] unable to save the training settings: {}", e);
+            }
+            start_training(project, &config)
+        };
+        match started {
+            Ok(started) => {
+                debug_println!("[INFO: TRAINING] started run {}", started.run_id);
+                follow_run(started);
+            }
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("TRAINING ERROR"),
+                    Some(&format!("Unable to start the training:\n{}", e)),
+                );
+            }
+        }
+    }));
+
+    continue_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong follow_run, @strong resumable_ids, @strong continue_dd => move |_| {
+        let Some(run_id) = resumable_ids.borrow().get(continue_dd.selected() as usize).cloned() else {
+            return;
+        };
+        let resumed = match project.borrow().as_ref() {
+            Some(project) => resume_training(project, &config.borrow(), &run_id),
+            None => return,
+        };
+        match resumed {
+            Ok(resumed) => follow_run(resumed),
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("TRAINING ERROR"),
+                Some(&format!("Unable to continue the run {}:\n{}", run_id, e)),
+            ),
+        }
+    }));
+
+    external_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong follow_run, @strong seed_spin, @strong label_column_entry, @strong best_metric_entry, @strong external_command_entry, @strong transport_dd, @strong external_params_view => move |_| {
+        let buffer = external_params_view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        let params = match parse_params(&text) {
+            Ok(params) => params,
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("TRAINING ERROR"),
+                    Some(&e.to_string()),
+                );
+                return;
+            }
+        };
+        let started = {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                return;
+            };
+            let mut config = config.borrow_mut();
+            config.seed = seed_spin.value() as u64;
+            config.label_column = label_column_entry.text().trim().to_string();
+            let best_metric = best_metric_entry.text().trim().to_string();
+            if !best_metric.is_empty() {
+                config.checkpoints.best_metric = best_metric;
+            }
+            config.external.command = external_command_entry.text().trim().to_string();
+            config.external.transport = Transport::ALL
+                .get(transport_dd.selected() as usize)
+                .copied()
+                .unwrap_or_default();
+            config.external.params = params;
+            if let Err(e) = save_training(project.dir(), &config) {
+                debug_println!("[ERROR: TRAINING] unable to save the training settings: {}", e);
+            }
+            start_external(project, &config)
+        };
+        match started {
+            Ok(started) => {
+                debug_println!("[INFO: TRAINING] started external run {}", started.run_id);
+                follow_run(started);
+            }
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("TRAINING ERROR"),
+                Some(&format!("Unable to start the external trainer:\n{}", e)),
+            ),
+        }
+    }));
+
+    // one run at a time, whether built-in or external
+    start_btn.connect_sensitive_notify(
+        gtk::glib::clone!(@strong external_btn => move |start_btn| {
+            external_btn.set_sensitive(start_btn.is_sensitive());
+        }),
+    );
+
+    // pausing only holds the training thread, the GUI keeps polling the run
+    pause_btn.connect_toggled(gtk::glib::clone!(@strong run => move |pause_btn| {
+        pause_btn.set_label(if pause_btn.is_active() { "resume" } else { "pause" });
+        if let Some(run) = run.borrow().as_ref() {
+            run.set_paused(pause_btn.is_active());
+        }
+    }));
+
+    stop_btn.connect_clicked(
+        gtk::glib::clone!(@strong run, @strong pause_btn => move |stop_btn| {
+            if let Some(run) = run.borrow().as_ref() {
+                run.stop();
+            }
+            stop_btn.set_sensitive(false);
+            pause_btn.set_sensitive(false);
+        }),
+    );
+
+    // models and settings of the opened project
+    // ---------------------------------------------------------------------------------------------
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong config, @strong models, @strong models_list, @strong header_label, @strong seed_spin, @strong label_column_entry, @strong checkpoint_spin, @strong best_metric_entry, @strong elbow_expander, @strong cv_expander, @strong external_command_entry, @strong transport_dd, @strong external_params_view, @strong refresh_resumable => move |_| {
+        refresh_resumable();
+        let project = project.borrow();
+        let Some(project) = project.as_ref() else {
+            header_label.set_text("no project opened");
+            elbow_expander.set_visible(false);
+            cv_expander.set_visible(false);
+            models.replace(Vec::new());
+            clear_list(&models_list);
+            return;
+        };
+
+        let dir = Some(project.dir().to_path_buf());
+        if *loaded_from.borrow() != dir {
+            config.replace(load_training(project.dir()).unwrap_or_default());
+            loaded_from.replace(dir);
+            seed_spin.set_value(config.borrow().seed as f64);
+            label_column_entry.set_text(&config.borrow().label_column);
+            checkpoint_spin.set_value(config.borrow().checkpoints.every as f64);
+            best_metric_entry.set_text(&config.borrow().checkpoints.best_metric);
+            let external = config.borrow().external.clone();
+            external_command_entry.set_text(&external.command);
+            let transport = Transport::ALL.iter().position(|t| *t == external.transport).unwrap_or(0);
+            transport_dd.set_selected(transport as u32);
+            external_params_view.buffer().set_text(&format_params(&external.params));
+        }
+
+        let problem = project.config.problem;
+        elbow_expander.set_visible(problem == ProblemType::Clustering);
+        cv_expander.set_visible(problem == ProblemType::Classification);
+        let modality = project.modality();
+        label_column_label.set_visible(modality == Some(Modality::Tabular));
+        label_column_entry.set_visible(modality == Some(Modality::Tabular));
+        let Some(modality) = modality else {
+            header_label.set_text(&format!("{} - the project has no items yet", problem.name()));
+            models.replace(Vec::new());
+            clear_list(&models_list);
+            return;
+        };
+        header_label.set_text(&format!("{} of {} data", problem.name(), modality.name()));
+
+        // the list is rebuilt every time, the selection is restored from the settings
+        let compatible = compatible_models(problem, modality);
+        let selected = config.borrow().model.clone();
+        clear_list(&models_list);
+        for spec in &compatible {
+            models_list.append(&Label::builder().label(spec.name).halign(gtk::Align::Start).build());
+        }
+        if compatible.is_empty() {
+            models_list.append(&Label::new(Some("no compatible models")));
+        }
+        let index = compatible.iter().position(|spec| spec.id == selected).unwrap_or(0);
+        models.replace(compatible);
+        if let Some(row) = models_list.row_at_index(index as i32) {
+            models_list.select_row(Some(&row));
+        }
+    }));
+
+    vbox.append(&header_label);
+    vbox.append(&hbox);
+    vbox.append(&run_box);
+    vbox.append(&metrics_label);
+    vbox.append(&stats_box);
+    vbox.append(&curves_area);
+    vbox.append(&elbow_expander);
+    vbox.append(&search_expander);
+    vbox.append(&cv_expander);
+    vbox.append(&external_expander);
+    vbox.append(&runs_expander);
+
+    vbox
+}
@@ -0,0 +1,793 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Tree based classifiers
+//!
+//! A single CART [`Tree`] implementation is shared by all models: the decision tree and the
+//! random forest grow classification trees on weighted class counts, gradient boosting grows
+//! regression trees on the gradients and hessians of the softmax loss. A tree only sees the
+//! summed statistics of its samples, the [`Criterion`] decides what they mean.
+
+use crate::dataset::Dataset;
+use crate::engine::default_threads;
+use crate::metrics::{softmax, Metrics};
+use crate::model::{
+    bool_param, evaluate, float_param, int_param, load_json, save_json, text_param, FitContext,
+    Hyperparameter, Model, Params,
+};
+use crate::rng::Rng;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+use std::thread;
+
+/// split criteria of classification trees
+const CRITERIA: &[&str] = &["gini", "entropy"];
+/// number of features tried at every split of a random forest
+const MAX_FEATURES: &[&str] = &["sqrt", "log2", "all"];
+
+/// splits have to improve the impurity by more than this
+const MIN_GAIN: f64 = 1e-12;
+/// probabilities are clipped to this before taking logarithms
+const PROBABILITY_FLOOR: f64 = 1e-12;
+
+// --- begin structs -------------------------------------------------------------------------------
+
+/// How the quality of a split is measured
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Criterion {
+    /// statistics are weighted class counts
+    Gini,
+    Entropy,
+    /// statistics are `[gradient, hessian]`, `lambda` is the L2 penalty on the leaf values
+    Newton {
+        lambda: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum Node {
+    /// class probabilities, or the single value of a regression tree
+    Leaf(Vec<f64>),
+    /// samples with `x[feature] <= threshold` go left
+    Split {
+        feature: usize,
+        threshold: f32,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// A binary tree stored as a list of nodes, the root is the first node
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct Tree {
+    nodes: Vec<Node>,
+}
+
+/// Limits of a growing tree
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TreeSettings {
+    pub(crate) max_depth: usize,
+    pub(crate) min_samples_split: usize,
+    pub(crate) min_samples_leaf: usize,
+    /// number of random features tried at every split, 0 for all
+    pub(crate) max_features: usize,
+    pub(crate) criterion: Criterion,
+}
+
+/// A single classification tree
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct DecisionTree {
+    settings: SavedSettings,
+    tree: Tree,
+}
+
+/// Bagged classification trees with random feature subsets
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RandomForest {
+    settings: SavedSettings,
+    n_trees: usize,
+    max_features: String,
+    bootstrap: bool,
+    trees: Vec<Tree>,
+}
+
+/// Softmax gradient boosting with second order (Newton) regression trees
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct GradientBoosting {
+    settings: SavedSettings,
+    rounds: usize,
+    learning_rate: f64,
+    subsample: f64,
+    /// raw score of every class before the first round (log priors)
+    init: Vec<f64>,
+    /// one tree per class and round
+    trees: Vec<Vec<Tree>>,
+}
+
+/// [`TreeSettings`] without the per fit values, as stored with a model
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SavedSettings {
+    max_depth: usize,
+    min_samples_split: usize,
+    min_samples_leaf: usize,
+    criterion: Criterion,
+}
+
+// --- end structs ---------------------------------------------------------------------------------
+
+impl Criterion {
+    fn from_name(name: &str) -> Criterion {
+        match name {
+            "entropy" => Criterion::Entropy,
+            _ => Criterion::Gini,
+        }
+    }
+
+    /// impurity of a node with the given summed statistics, lower is purer
+    fn impurity(&self, stats: &[f64]) -> f64 {
+        let total: f64 = stats.iter().sum();
+        match self {
+            Criterion::Gini if total > 0.0 => {
+                total - stats.iter().map(|s| s * s).sum::<f64>() / total
+            }
+            Criterion::Entropy if total > 0.0 => stats
+                .iter()
+                .filter(|s| **s > 0.0)
+                .map(|s| -s * (s / total).ln())
+                .sum(),
+            Criterion::Newton { lambda } => -0.5 * stats[0] * stats[0] / (stats[1] + lambda),
+            _ => 0.0,
+        }
+    }
+
+    fn leaf(&self, stats: &[f64]) -> Vec<f64> {
+        match self {
+            Criterion::Newton { lambda } => vec![-stats[0] / (stats[1] + lambda)],
+            _ => {
+                let total: f64 = stats.iter().sum();
+                if total > 0.0 {
+                    stats.iter().map(|s| s / total).collect()
+                } else {
+                    vec![1.0 / stats.len().max(1) as f64; stats.len()]
+                }
+            }
+        }
+    }
+}
+
+fn sum_stats(stats: &[Vec<f64>], indices: &[usize]) -> Vec<f64> {
+    let mut total = vec![0.0; stats.first().map_or(0, Vec::len)];
+    for i in indices {
+        for (t, s) in total.iter_mut().zip(&stats[*i]) {
+            *t += s;
+        }
+    }
+    total
+}
+
+/// # best split of a node
+///
+/// tries every threshold between two distinct values of the candidate features.
+///
+/// returns:
+///     feature and threshold, `None` if no split improves the impurity
+fn best_split(
+    inputs: &[&[f32]],
+    stats: &[Vec<f64>],
+    indices: &[usize],
+    total: &[f64],
+    settings: &TreeSettings,
+    rng: &mut Rng,
+) -> Option<(usize, f32)> {
+    let features = inputs[indices[0]].len();
+    let mut candidates: Vec<usize> = (0..features).collect();
+    if settings.max_features > 0 && settings.max_features < features {
+        rng.shuffle(&mut candidates);
+        candidates.truncate(settings.max_features);
+    }
+
+    let criterion = settings.criterion;
+    let parent = criterion.impurity(total);
+    let min_leaf = settings.min_samples_leaf.max(1);
+    let mut best: Option<(f64, usize, f32)> = None;
+    let mut order = indices.to_vec();
+    let mut right = vec![0.0; total.len()];
+    for feature in candidates {
+        order.sort_by(|a, b| inputs[*a][feature].total_cmp(&inputs[*b][feature]));
+        let mut left = vec![0.0; total.len()];
+        for position in 0..order.len() - 1 {
+            for (l, s) in left.iter_mut().zip(&stats[order[position]]) {
+                *l += s;
+            }
+            let (value, next) = (
+                inputs[order[position]][feature],
+                inputs[order[position + 1]][feature],
+            );
+            let left_count = position + 1;
+            if value == next || left_count < min_leaf || order.len() - left_count < min_leaf {
+                continue;
+            }
+            for ((r, t), l) in right.iter_mut().zip(total).zip(&left) {
+                *r = t - l;
+            }
+            let gain = parent - criterion.impurity(&left) - criterion.impurity(&right);
+            if gain > MIN_GAIN && best.is_none_or(|b| gain > b.0) {
+                let middle = value + (next - value) / 2.0;
+                let threshold = if middle < next { middle } else { value };
+                best = Some((gain, feature, threshold));
+            }
+        }
+    }
+    best.map(|(_, feature, threshold)| (feature, threshold))
+}
+
+impl Tree {
+    /// # grow a tree
+    ///
+    /// `inputs` and `stats` hold the features and statistics of all samples, `indices` the
+    /// samples of this tree (repeated for bootstrap samples).
+    pub(crate) fn grow(
+        inputs: &[&[f32]],
+        stats: &[Vec<f64>],
+        indices: Vec<usize>,
+        settings: &TreeSettings,
+        rng: &mut Rng,
+    ) -> Tree {
+        let mut tree = Tree::default();
+        if !indices.is_empty() {
+            tree.grow_node(inputs, stats, indices, 0, settings, rng);
+        }
+        tree
+    }
+
+    fn grow_node(
+        &mut self,
+        inputs: &[&[f32]],
+        stats: &[Vec<f64>],
+        indices: Vec<usize>,
+        depth: usize,
+        settings: &TreeSettings,
+        rng: &mut Rng,
+    ) -> usize {
+        let total = sum_stats(stats, &indices);
+        let index = self.nodes.len();
+        self.nodes.push(Node::Leaf(settings.criterion.leaf(&total)));
+        if depth >= settings.max_depth || indices.len() < settings.min_samples_split.max(2) {
+            return index;
+        }
+        let Some((feature, threshold)) = best_split(inputs, stats, &indices, &total, settings, rng)
+        else {
+            return index;
+        };
+
+        let (left, right): (Vec<usize>, Vec<usize>) = indices
+            .into_iter()
+            .partition(|i| inputs[*i][feature] <= threshold);
+        let left = self.grow_node(inputs, stats, left, depth + 1, settings, rng);
+        let right = self.grow_node(inputs, stats, right, depth + 1, settings, rng);
+        self.nodes[index] = Node::Split {
+            feature,
+            threshold,
+            left,
+            right,
+        };
+        index
+    }
+
+    /// value of the leaf the input ends up in (empty for an empty tree)
+    pub(crate) fn leaf(&self, x: &[f32]) -> &[f64] {
+        let mut node = 0;
+        loop {
+            match self.nodes.get(node) {
+                Some(Node::Leaf(value)) => return value,
+                Some(Node::Split {
+                    feature,
+                    threshold,
+                    left,
+                    right,
+                }) => {
+                    node = if x.get(*feature).is_none_or(|v| v <= threshold) {
+                        *left
+                    } else {
+                        *right
+                    };
+                }
+                None => return &[],
+            }
+        }
+    }
+}
+
+impl SavedSettings {
+    fn from_params(params: &Params) -> SavedSettings {
+        SavedSettings {
+            max_depth: int_param(params, "max_depth") as usize,
+            min_samples_split: int_param(params, "min_samples_split") as usize,
+            min_samples_leaf: int_param(params, "min_samples_leaf") as usize,
+            criterion: Criterion::from_name(&text_param(params, "criterion")),
+        }
+    }
+
+    fn tree_settings(&self, max_features: usize) -> TreeSettings {
+        TreeSettings {
+            max_depth: self.max_depth,
+            min_samples_split: self.min_samples_split,
+            min_samples_leaf: self.min_samples_leaf,
+            max_features,
+            criterion: self.criterion,
+        }
+    }
+}
+
+/// hyperparameters shared by all classification trees
+fn tree_hyperparameters(max_depth: i64) -> Vec<Hyperparameter> {
+    vec![
+        Hyperparameter::int("max_depth", "maximal depth of a tree", max_depth, 1, 64),
+        Hyperparameter::int(
+            "min_samples_split",
+            "nodes with fewer samples are not split",
+            2,
+            2,
+            10000,
+        ),
+        Hyperparameter::int(
+            "min_samples_leaf",
+            "minimal number of samples in a leaf",
+            1,
+            1,
+            10000,
+        ),
+        Hyperparameter::choice(
+            "criterion",
+            "impurity measure of the splits",
+            "gini",
+            CRITERIA,
+        ),
+    ]
+}
+
+/// inputs of all samples and the weighted one-hot labels of the training samples
+fn class_statistics<'a>(
+    data: &'a Dataset,
+    context: &FitContext,
+) -> (Vec<&'a [f32]>, Vec<Vec<f64>>) {
+    let inputs = data.samples.iter().map(|s| s.x.as_slice()).collect();
+    let mut stats = vec![vec![0.0; data.classes.len()]; data.samples.len()];
+    for i in &context.train {
+        if let Some(label) = data.samples[*i].label {
+            stats[*i][label] = context.weight(data, *i);
+        }
+    }
+    (inputs, stats)
+}
+
+/// the labelled training samples (with repetitions from resampling)
+fn labelled_train(data: &Dataset, context: &FitContext) -> Result<Vec<usize>, Box<dyn Error>> {
+    let train: Vec<usize> = context
+        .train
+        .iter()
+        .copied()
+        .filter(|i| data.samples[*i].label.is_some())
+        .collect();
+    if train.is_empty() {
+        return Err("no labelled training samples".into());
+    }
+    Ok(train)
+}
+
+impl Model for DecisionTree {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        tree_hyperparameters(10)
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(DecisionTree {
+            settings: SavedSettings::from_params(params),
+            tree: Tree::default(),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let train = labelled_train(data, context)?;
+        let (inputs, stats) = class_statistics(data, context);
+        let mut rng = Rng::new(context.seed);
+        let samples = train.len();
+        self.tree = Tree::grow(
+            &inputs,
+            &stats,
+            train,
+            &self.settings.tree_settings(0),
+            &mut rng,
+        );
+
+        let metrics = evaluate(self, data, &context.train);
+        context.end_epoch(data, self, 1, 1, metrics, None, samples);
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        self.tree.leaf(x).to_vec()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl Model for RandomForest {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        let mut hyperparameters = vec![Hyperparameter::int(
+            "n_trees",
+            "number of trees",
+            100,
+            1,
+            2000,
+        )];
+        hyperparameters.extend(tree_hyperparameters(16));
+        hyperparameters.push(Hyperparameter::choice(
+            "max_features",
+            "number of random features tried at every split",
+            "sqrt",
+            MAX_FEATURES,
+        ));
+        hyperparameters.push(Hyperparameter::bool(
+            "bootstrap",
+            "grow every tree on a bootstrap sample of the training data",
+            true,
+        ));
+        hyperparameters
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(RandomForest {
+            settings: SavedSettings::from_params(params),
+            n_trees: int_param(params, "n_trees") as usize,
+            max_features: text_param(params, "max_features"),
+            bootstrap: bool_param(params, "bootstrap"),
+            trees: Vec::new(),
+        })
+    }
+
+    /// trees are grown in parallel, one batch of trees (one per core) is one epoch
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let train = labelled_train(data, context)?;
+        let (inputs, stats) = class_statistics(data, context);
+        let features = data.input_size() as f64;
+        let max_features = match self.max_features.as_str() {
+            "sqrt" => features.sqrt().round() as usize,
+            "log2" => features.log2().round() as usize,
+            _ => 0,
+        }
+        .max(1);
+        let settings = self.settings.tree_settings(max_features);
+
+        self.trees.clear();
+        let threads = default_threads();
+        let epochs = self.n_trees.div_ceil(threads);
+        for epoch in 1..=epochs {
+            let first = self.trees.len();
+            let count = threads.min(self.n_trees - first);
+            let grown: Result<Vec<Tree>, _> = thread::scope(|scope| {
+                let handles: Vec<_> = (first..first + count)
+                    .map(|t| {
+                        let (inputs, stats, train, settings) = (&inputs, &stats, &train, &settings);
+                        let bootstrap = self.bootstrap;
+                        // every tree has its own generator, so the result does not depend on
+                        // the number of cores
+                        let mut rng = Rng::new(context.seed.wrapping_add(t as u64));
+                        scope.spawn(move || {
+                            let indices = if bootstrap {
+                                (0..train.len())
+                                    .map(|_| train[rng.below(train.len())])
+                                    .collect()
+                            } else {
+                                train.clone()
+                            };
+                            Tree::grow(inputs, stats, indices, settings, &mut rng)
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|handle| handle.join()).collect()
+            });
+            // a panicking tree would leave the forest short of a tree, fail the run instead
+            let grown = grown.map_err(|_| "growing a tree of the random forest panicked")?;
+            self.trees.extend(grown);
+
+            let samples = count * train.len();
+            if !context.end_epoch(data, self, epoch, epochs, Metrics::new(), None, samples) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        let mut probabilities: Vec<f64> = Vec::new();
+        for tree in &self.trees {
+            let leaf = tree.leaf(x);
+            probabilities.resize(leaf.len().max(probabilities.len()), 0.0);
+            for (p, v) in probabilities.iter_mut().zip(leaf) {
+                *p += v;
+            }
+        }
+        let n = self.trees.len().max(1) as f64;
+        probabilities.iter().map(|p| p / n).collect()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+impl GradientBoosting {
+    fn raw_scores(&self, x: &[f32]) -> Vec<f64> {
+        let mut scores = self.init.clone();
+        for round in &self.trees {
+            for (score, tree) in scores.iter_mut().zip(round) {
+                *score += self.learning_rate * tree.leaf(x).first().copied().unwrap_or(0.0);
+            }
+        }
+        scores
+    }
+}
+
+impl Model for GradientBoosting {
+    fn hyperparameters() -> Vec<Hyperparameter> {
+        vec![
+            Hyperparameter::int(
+                "rounds",
+                "number of boosting rounds (one tree per class each)",
+                100,
+                1,
+                5000,
+            ),
+            Hyperparameter::float(
+                "learning_rate",
+                "shrinkage of every tree",
+                0.1,
+                1e-3,
+                1.0,
+                true,
+            ),
+            Hyperparameter::int("max_depth", "maximal depth of a tree", 3, 1, 16),
+            Hyperparameter::int(
+                "min_samples_leaf",
+                "minimal number of samples in a leaf",
+                1,
+                1,
+                10000,
+            ),
+            Hyperparameter::float(
+                "subsample",
+                "fraction of the samples every round is fitted on",
+                1.0,
+                0.1,
+                1.0,
+                false,
+            ),
+            Hyperparameter::float(
+                "l2",
+                "L2 penalty on the leaf values",
+                1.0,
+                0.0,
+                100.0,
+                false,
+            ),
+        ]
+    }
+
+    fn new(params: &Params) -> Result<Self, Box<dyn Error>> {
+        Ok(GradientBoosting {
+            settings: SavedSettings {
+                max_depth: int_param(params, "max_depth") as usize,
+                min_samples_split: 2,
+                min_samples_leaf: int_param(params, "min_samples_leaf") as usize,
+                criterion: Criterion::Newton {
+                    lambda: float_param(params, "l2"),
+                },
+            },
+            rounds: int_param(params, "rounds") as usize,
+            learning_rate: float_param(params, "learning_rate"),
+            subsample: float_param(params, "subsample"),
+            init: Vec::new(),
+            trees: Vec::new(),
+        })
+    }
+
+    fn fit(&mut self, data: &Dataset, context: &mut FitContext) -> Result<(), Box<dyn Error>> {
+        let train = labelled_train(data, context)?;
+        let classes = data.classes.len();
+        let inputs: Vec<&[f32]> = train
+            .iter()
+            .map(|i| data.samples[*i].x.as_slice())
+            .collect();
+        let labels: Vec<usize> = train
+            .iter()
+            .filter_map(|i| data.samples[*i].label)
+            .collect();
+        let weights: Vec<f64> = train.iter().map(|i| context.weight(data, *i)).collect();
+
+        // start from the (weighted) log priors
+        let mut priors = vec![0.0; classes];
+        for (label, weight) in labels.iter().zip(&weights) {
+            priors[*label] += weight;
+        }
+        let total: f64 = priors.iter().sum();
+        self.init = priors
+            .iter()
+            .map(|p| (p / total).max(PROBABILITY_FLOOR).ln())
+            .collect();
+        self.trees.clear();
+
+        let settings = self.settings.tree_settings(0);
+        let mut rng = Rng::new(context.seed);
+        let mut scores = vec![self.init.clone(); train.len()];
+        for round in 1..=self.rounds {
+            let probabilities: Vec<Vec<f64>> = scores.iter().map(|s| softmax(s)).collect();
+            let rows: Vec<usize> = (0..train.len())
+                .filter(|_| self.subsample >= 1.0 || rng.chance(self.subsample))
+                .collect();
+            let mut round_trees = Vec::with_capacity(classes);
+            for k in 0..classes {
+                let stats: Vec<Vec<f64>> = probabilities
+                    .iter()
+                    .zip(labels.iter().zip(&weights))
+                    .map(|(p, (label, weight))| {
+                        let y = if *label == k { 1.0 } else { 0.0 };
+                        vec![
+                            weight * (p[k] - y),
+                            weight * (p[k] * (1.0 - p[k])).max(1e-6),
+                        ]
+                    })
+                    .collect();
+                let tree = Tree::grow(&inputs, &stats, rows.clone(), &settings, &mut rng);
+                for (score, x) in scores.iter_mut().zip(&inputs) {
+                    score[k] += self.learning_rate * tree.leaf(x).first().copied().unwrap_or(0.0);
+                }
+                round_trees.push(tree);
+            }
+            self.trees.push(round_trees);
+
+            let loss = scores
+                .iter()
+                .zip(labels.iter().zip(&weights))
+                .map(|(s, (label, weight))| {
+                    -weight * softmax(s)[*label].max(PROBABILITY_FLOOR).ln()
+                })
+                .sum::<f64>()
+                / total;
+
+            let metrics = Metrics::from([("loss".to_string(), loss)]);
+            let learning_rate = Some(self.learning_rate);
+            if !context.end_epoch(
+                data,
+                self,
+                round,
+                self.rounds,
+                metrics,
+                learning_rate,
+                rows.len(),
+            ) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &[f32]) -> Vec<f64> {
+        softmax(&self.raw_scores(x))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_json(path, self)
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        load_json(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(min_samples_leaf: usize) -> TreeSettings {
+        TreeSettings {
+            max_depth: 10,
+            min_samples_split: 2,
+            min_samples_leaf,
+            max_features: 0,
+            criterion: Criterion::Gini,
+        }
+    }
+
+    /// one-hot class counts as the statistics of classification trees
+    fn one_hot(labels: &[usize]) -> Vec<Vec<f64>> {
+        labels
+            .iter()
+            .map(|label| (0..2).map(|c| f64::from(u8::from(c == *label))).collect())
+            .collect()
+    }
+
+    fn split_of(
+        rows: &[[f32; 2]],
+        labels: &[usize],
+        min_samples_leaf: usize,
+    ) -> Option<(usize, f32)> {
+        let inputs: Vec<&[f32]> = rows.iter().map(|r| r.as_slice()).collect();
+        let stats = one_hot(labels);
+        let indices: Vec<usize> = (0..rows.len()).collect();
+        let total = sum_stats(&stats, &indices);
+        best_split(
+            &inputs,
+            &stats,
+            &indices,
+            &total,
+            &settings(min_samples_leaf),
+            &mut Rng::new(1),
+        )
+    }
+
+    #[test]
+    fn best_split_picks_the_separating_feature() {
+        // feature 0 is noise, feature 1 separates the classes between 3 and 10
+        let rows = [
+            [5.0, 1.0],
+            [1.0, 3.0],
+            [4.0, 2.0],
+            [2.0, 10.0],
+            [3.0, 12.0],
+            [6.0, 11.0],
+        ];
+        let labels = [0, 0, 0, 1, 1, 1];
+        assert_eq!(split_of(&rows, &labels, 1), Some((1, 6.5)));
+
+        for criterion in [Criterion::Entropy, Criterion::Gini] {
+            let inputs: Vec<&[f32]> = rows.iter().map(|r| r.as_slice()).collect();
+            let tree = Tree::grow(
+                &inputs,
+                &one_hot(&labels),
+                (0..rows.len()).collect(),
+                &TreeSettings {
+                    criterion,
+                    ..settings(1)
+                },
+                &mut Rng::new(1),
+            );
+            for (row, label) in rows.iter().zip(labels) {
+                assert_eq!(tree.leaf(row)[label], 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn best_split_needs_a_gain() {
+        // pure node
+        assert_eq!(split_of(&[[1.0, 1.0], [2.0, 2.0]], &[1, 1], 1), None);
+        // no two distinct values
+        assert_eq!(split_of(&[[1.0, 1.0], [1.0, 1.0]], &[0, 1], 1), None);
+        // both sides would be smaller than the minimum leaf
+        let rows = [[1.0, 0.0], [2.0, 0.0], [3.0, 0.0], [4.0, 0.0]];
+        assert_eq!(split_of(&rows, &[0, 0, 1, 1], 2), Some((0, 2.5)));
+        assert_eq!(split_of(&rows, &[0, 0, 1, 1], 3), None);
+    }
+
+    #[test]
+    fn best_split_respects_the_minimum_leaf() {
+        // the best split (after the single class 1 sample) would leave one sample in a leaf
+        let rows = [[1.0, 0.0], [2.0, 0.0], [3.0, 0.0], [4.0, 0.0], [5.0, 0.0]];
+        let labels = [1, 0, 0, 0, 0];
+        assert_eq!(split_of(&rows, &labels, 1), Some((0, 1.5)));
+        assert_eq!(split_of(&rows, &labels, 2), Some((0, 2.5)));
+    }
+}
@@ -0,0 +1,654 @@
+// ai lab - GUI for annotating, training, and evaluating AI models, simplifying workflows
+// Copyright (C) 2024 - Felix Drees - GNU General Public License v3.0
+
+//! Hyperparameter search of the Training tab
+//!
+//! A form for the search space of the selected model (one line per hyperparameter) and the
+//! strategy, the running search, a leaderboard of its trials and a parallel coordinates plot of
+//! the searched hyperparameters against the score. Earlier searches of the model can be shown
+//! again and the hyperparameters of the best trial taken over into the model form.
+
+use gtk::prelude::*;
+use gtk::{Button, Label};
+
+use crate::charts::{
+    draw_parallel_chart, heatmap_colour, palette_colour, ParallelAxis, ParallelChart, ParallelLine,
+};
+use crate::debug_println;
+use crate::engine::default_threads;
+use crate::helper::show_error_message;
+use crate::metrics::{format_metric, higher_is_better};
+use crate::model::{model_spec, Hyperparameter, ParamKind};
+use crate::project::SharedProject;
+use crate::runs::format_unix_time;
+use crate::search::{format_space, load_searches, parse_space, ParamSpace, SearchRecord, Strategy};
+use crate::trainer::{save_training, start_search, SearchRun, TrainingConfig};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// interval of polling a running search for new trials
+const SEARCH_INTERVAL_MS: u64 = 250;
+
+/// # placeholder of the search space entry of a hyperparameter
+///
+/// returns:
+///     an example of what can be typed, e.g. `a..b [log] [steps] or a, b, ...`
+fn space_hint(hyperparameter: &Hyperparameter) -> String {
+    match &hyperparameter.kind {
+        ParamKind::Int { min, max } => format!("{}..{} [steps] or a, b, ...", min, max),
+        ParamKind::Float { min, max, log } => format!(
+            "{}..{}{} [steps] or a, b, ...",
+            min,
+            max,
+            if *log { " log" } else { "" }
+        ),
+        ParamKind::Choice(options) => options.join(", "),
+        ParamKind::Bool => "true, false".to_string(),
+    }
+}
+
+/// one line of the leaderboard, e.g. `  1  0.9312  epochs 27  run-1718000000  lr=0.01 ...`
+fn leaderboard_line(rank: usize, search: &SearchRecord, trial_index: usize) -> String {
+    let trial = &search.trials[trial_index];
+    let score = match (trial.score, &trial.error) {
+        (Some(score), _) => format_metric(score),
+        (None, Some(_)) => "failed".to_string(),
+        (None, None) => "-".to_string(),
+    };
+    let budget = match trial.resource {
+        Some(resource) => format!("{} {}", search.config.resource, resource),
+        None => String::new(),
+    };
+    let params: Vec<String> = search
+        .config
+        .space
+        .keys()
+        .filter_map(|name| {
+            trial
+                .params
+                .get(name)
+                .map(|value| format!("{}={}", name, value.display()))
+        })
+        .collect();
+    let mut line = format!(
+        "{:>3}  {:>8}  {:<12}  {:<18}  {}",
+        rank,
+        score,
+        budget,
+        trial.run_id,
+        params.join("  ")
+    );
+    if let (None, Some(error)) = (trial.score, &trial.error) {
+        line.push_str(&format!("  ({})", error));
+    }
+    line
+}
+
+/// # parallel coordinates of a search
+///
+/// one axis per searched hyperparameter (and the budget of successive halving) and the score on
+/// the last axis, better scores on top. Lines are coloured by their score, the best trial
+/// stands out.
+fn search_chart(search: &SearchRecord) -> ParallelChart {
+    let config = &search.config;
+    let scored: Vec<(usize, f64)> = search
+        .trials
+        .iter()
+        .enumerate()
+        .filter_map(|(i, trial)| trial.score.map(|score| (i, score)))
+        .collect();
+    let (low, high) = scored
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), (_, s)| {
+            (lo.min(*s), hi.max(*s))
+        });
+    let higher = higher_is_better(&config.metric);
+    // 1 for the best score, 0 for the worst
+    let goodness = |score: f64| {
+        let t = if high - low > f64::EPSILON {
+            (score - low) / (high - low)
+        } else {
+            1.0
+        };
+        if higher {
+            t
+        } else {
+            1.0 - t
+        }
+    };
+
+    let mut axes: Vec<ParallelAxis> = config
+        .space
+        .iter()
+        .map(|(name, space)| ParallelAxis {
+            label: name.clone(),
+            ticks: space.ticks(),
+        })
+        .collect();
+    let mut budgets: Vec<i64> = search.trials.iter().filter_map(|t| t.resource).collect();
+    budgets.sort_unstable();
+    budgets.dedup();
+    if !budgets.is_empty() {
+        axes.push(ParallelAxis {
+            label: config.resource.clone(),
+            ticks: budgets.iter().map(i64::to_string).collect(),
+        });
+    }
+    let (worst, best) = if higher { (low, high) } else { (high, low) };
+    axes.push(ParallelAxis {
+        label: config.metric.clone(),
+        ticks: if scored.is_empty() {
+            Vec::new()
+        } else {
+            vec![format_metric(worst), format_metric(best)]
+        },
+    });
+
+    let best_run = search.best().map(|trial| trial.run_id.clone());
+    let lines = scored
+        .iter()
+        .map(|(i, score)| {
+            let trial = &search.trials[*i];
+            let mut values: Vec<f64> = config
+                .space
+                .iter()
+                .map(|(name, space)| {
+                    trial
+                        .params
+                        .get(name)
+                        .and_then(|value| space.position(value))
+                        .unwrap_or(0.5)
+                })
+                .collect();
+            if !budgets.is_empty() {
+                let index = trial
+                    .resource
+                    .and_then(|r| budgets.iter().position(|b| *b == r))
+                    .unwrap_or(0);
+                values.push(if budgets.len() > 1 {
+                    index as f64 / (budgets.len() - 1) as f64
+                } else {
+                    0.5
+                });
+            }
+            values.push(goodness(*score));
+            let highlighted = best_run.as_ref() == Some(&trial.run_id);
+            ParallelLine {
+                values,
+                colour: if highlighted {
+                    palette_colour(3)
+                } else {
+                    // dark lines for good trials, light ones for bad trials
+                    heatmap_colour(0.1 + 0.7 * (1.0 - goodness(*score)))
+                },
+                highlighted,
+            }
+        })
+        .collect();
+
+    ParallelChart {
+        title: format!(
+            "{} by hyperparameters ({} trials)",
+            config.metric,
+            scored.len()
+        ),
+        axes,
+        lines,
+    }
+}
+
+/// # search space as typed into the form
+///
+/// returns:
+///     Result with the searched hyperparameters, or the first entry that cannot be parsed
+fn read_space(
+    entries: &[(Hyperparameter, gtk::Entry)],
+) -> Result<BTreeMap<String, ParamSpace>, Box<dyn Error>> {
+    let mut space = BTreeMap::new();
+    for (hyperparameter, entry) in entries {
+        if let Some(values) = parse_space(hyperparameter, &entry.text())? {
+            space.insert(hyperparameter.name.to_string(), values);
+        }
+    }
+    Ok(space)
+}
+
+/// # hyperparameter search of the selected model
+///
+/// `reload_runs` is called once a search is over (its trials are runs), `show_params` after
+/// the hyperparameters of the best trial were taken over into the settings.
+///
+/// returns:
+///     the widget and a function showing the search settings of the selected model
+pub(crate) fn search_ui(
+    project: &SharedProject,
+    config: &Rc<RefCell<TrainingConfig>>,
+    reload_runs: Rc<dyn Fn()>,
+    show_params: Rc<dyn Fn()>,
+) -> (gtk::Box, Rc<dyn Fn()>) {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let running: Rc<RefCell<Option<SearchRun>>> = Rc::default();
+    // searches of the selected model, newest first, and the one shown below
+    let searches: Rc<RefCell<Vec<SearchRecord>>> = Rc::default();
+    let shown: Rc<RefCell<Option<SearchRecord>>> = Rc::default();
+    let entries: Rc<RefCell<Vec<(Hyperparameter, gtk::Entry)>>> = Rc::default();
+    let resources: Rc<RefCell<Vec<String>>> = Rc::default();
+
+    // strategy and budget
+    // ---------------------------------------------------------------------------------------------
+    let strategy_names: Vec<&str> = Strategy::ALL.iter().map(Strategy::name).collect();
+    let strategy_dd = gtk::DropDown::from_strings(&strategy_names);
+    let trials_spin = gtk::SpinButton::with_range(1.0, 1000.0, 1.0);
+    trials_spin.set_tooltip_text(Some(
+        "random candidates of random search and successive halving",
+    ));
+    let metric_entry = gtk::Entry::builder()
+        .tooltip_text("validation metric ranking the trials, e.g. val_accuracy or val_loss")
+        .width_chars(14)
+        .build();
+    let parallel_spin = gtk::SpinButton::with_range(1.0, 256.0, 1.0);
+    parallel_spin.set_tooltip_text(Some("trials trained at the same time"));
+    let strategy_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    strategy_box.append(&Label::new(Some("strategy")));
+    strategy_box.append(&strategy_dd);
+    strategy_box.append(&Label::new(Some("candidates")));
+    strategy_box.append(&trials_spin);
+    strategy_box.append(&Label::new(Some("ranked by")));
+    strategy_box.append(&metric_entry);
+    strategy_box.append(&Label::new(Some("in parallel")));
+    strategy_box.append(&parallel_spin);
+
+    let resource_dd = gtk::DropDown::from_strings(&[]);
+    resource_dd.set_tooltip_text(Some(
+        "integer hyperparameter used as budget, its value in the model form is the largest budget",
+    ));
+    let min_resource_spin = gtk::SpinButton::with_range(1.0, 100000.0, 1.0);
+    min_resource_spin.set_tooltip_text(Some("smallest budget a trial is trained with"));
+    let eta_spin = gtk::SpinButton::with_range(2.0, 10.0, 1.0);
+    eta_spin.set_tooltip_text(Some(
+        "budget factor between two rounds, only the best 1 / eta of the candidates go on",
+    ));
+    let resource_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    resource_box.append(&Label::new(Some("budget")));
+    resource_box.append(&resource_dd);
+    resource_box.append(&Label::new(Some("from")));
+    resource_box.append(&min_resource_spin);
+    resource_box.append(&Label::new(Some("eta")));
+    resource_box.append(&eta_spin);
+
+    strategy_dd.connect_selected_notify(
+        gtk::glib::clone!(@strong resource_box, @strong trials_spin => move |dd| {
+            let strategy = Strategy::ALL.get(dd.selected() as usize).copied().unwrap_or_default();
+            resource_box.set_visible(strategy.uses_resource());
+            trials_spin.set_sensitive(matches!(strategy, Strategy::Random | Strategy::Halving));
+        }),
+    );
+
+    // search space, one entry per hyperparameter
+    // ---------------------------------------------------------------------------------------------
+    let space_grid = gtk::Grid::builder()
+        .row_spacing(5)
+        .column_spacing(10)
+        .build();
+    let space_label = Label::builder()
+        .label("hyperparameters left empty keep their value of the model form")
+        .halign(gtk::Align::Start)
+        .build();
+
+    // running search
+    // ---------------------------------------------------------------------------------------------
+    let start_btn = Button::with_label("start search");
+    let stop_btn = Button::with_label("stop");
+    stop_btn.set_sensitive(false);
+    let progress_bar = gtk::ProgressBar::builder()
+        .show_text(true)
+        .text("not running")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let run_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    run_box.append(&start_btn);
+    run_box.append(&stop_btn);
+    run_box.append(&progress_bar);
+
+    // leaderboard and parallel coordinates of the shown search
+    // ---------------------------------------------------------------------------------------------
+    let searches_dd = gtk::DropDown::from_strings(&[]);
+    searches_dd.set_tooltip_text(Some("searches of the selected model"));
+    let best_btn = Button::with_label("use best");
+    best_btn.set_tooltip_text(Some(
+        "take the hyperparameters of the best trial over into the model form",
+    ));
+    best_btn.set_sensitive(false);
+    let summary_label = Label::builder()
+        .halign(gtk::Align::Start)
+        .hexpand(true)
+        .build();
+    let shown_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(10)
+        .build();
+    shown_box.append(&searches_dd);
+    shown_box.append(&summary_label);
+    shown_box.append(&best_btn);
+
+    let leaderboard = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    let leaderboard_window = gtk::ScrolledWindow::builder()
+        .height_request(180)
+        .child(&leaderboard)
+        .build();
+    let chart_area = gtk::DrawingArea::builder()
+        .content_height(260)
+        .hexpand(true)
+        .build();
+    chart_area.set_draw_func(
+        gtk::glib::clone!(@strong shown => move |_, cr, width, height| {
+            if let Some(search) = shown.borrow().as_ref() {
+                if let Err(e) = draw_parallel_chart(cr, width as f64, height as f64, &search_chart(search)) {
+                    debug_println!("[ERROR: SEARCH] unable to draw the search: {}", e);
+                }
+            }
+        }),
+    );
+
+    let show_search = gtk::glib::clone!(@strong shown, @strong leaderboard, @strong summary_label, @strong best_btn, @strong chart_area, @strong running => move || {
+        while let Some(child) = leaderboard.first_child() {
+            leaderboard.remove(&child);
+        }
+        let shown = shown.borrow();
+        let Some(search) = shown.as_ref() else {
+            summary_label.set_text("");
+            best_btn.set_sensitive(false);
+            chart_area.queue_draw();
+            return;
+        };
+        let header = Label::builder()
+            .label(format!("{:>3}  {:>8}  {:<12}  {:<18}  {}", "#", search.config.metric, "budget", "run", "hyperparameters"))
+            .xalign(0.0)
+            .build();
+        header.add_css_class("monospace");
+        leaderboard.append(&header);
+        for (rank, index) in search.leaderboard().into_iter().enumerate() {
+            let label = Label::builder()
+                .label(leaderboard_line(rank + 1, search, index))
+                .xalign(0.0)
+                .selectable(true)
+                .build();
+            label.add_css_class("monospace");
+            leaderboard.append(&label);
+        }
+        summary_label.set_text(&format!(
+            "{}, {}, {} / {} trials{}",
+            search.config.strategy.name(),
+            format_unix_time(search.started),
+            search.trials.len(),
+            search.planned,
+            match (&search.error, search.finished) {
+                (Some(error), _) => format!(" - {}", error),
+                (None, Some(_)) => String::new(),
+                (None, None) => " - running".to_string(),
+            }
+        ));
+        // the best trial of a running search may still change
+        let busy = running.borrow().is_some();
+        best_btn.set_sensitive(search.best().is_some() && !busy);
+        chart_area.queue_draw();
+    });
+
+    let reload_searches = gtk::glib::clone!(@strong project, @strong config, @strong searches, @strong searches_dd, @strong shown, @strong running, @strong show_search => move || {
+        let model = config.borrow().model.clone();
+        let mut loaded: Vec<SearchRecord> = match project.borrow().as_ref() {
+            Some(project) => load_searches(project.dir()),
+            None => Vec::new(),
+        };
+        loaded.retain(|search| search.model == model);
+        loaded.reverse();
+        let names: Vec<String> = loaded
+            .iter()
+            .map(|search| format!("{} ({})", search.id, search.config.strategy.name()))
+            .collect();
+        let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+        searches.replace(loaded);
+        searches_dd.set_model(Some(&gtk::StringList::new(&strings)));
+        if running.borrow().is_none() {
+            searches_dd.set_selected(0);
+            shown.replace(searches.borrow().first().cloned());
+            show_search();
+        }
+    });
+
+    searches_dd.connect_selected_notify(gtk::glib::clone!(@strong searches, @strong shown, @strong running, @strong show_search => move |dd| {
+        // the running search is shown until it is over
+        if running.borrow().is_some() {
+            return;
+        }
+        shown.replace(searches.borrow().get(dd.selected() as usize).cloned());
+        show_search();
+    }));
+
+    // settings of the selected model
+    // ---------------------------------------------------------------------------------------------
+    let refresh: Rc<dyn Fn()> = Rc::new(
+        gtk::glib::clone!(@strong config, @strong entries, @strong resources, @strong space_grid, @strong strategy_dd, @strong trials_spin, @strong metric_entry, @strong parallel_spin, @strong resource_dd, @strong min_resource_spin, @strong eta_spin, @strong reload_searches => move || {
+            while let Some(child) = space_grid.first_child() {
+                space_grid.remove(&child);
+            }
+            let (model, search) = {
+                let config = config.borrow();
+                (config.model.clone(), config.model_search())
+            };
+            let hyperparameters = model_spec(&model).map(|spec| (spec.hyperparameters)()).unwrap_or_default();
+
+            let mut rows = Vec::new();
+            for (row, hyperparameter) in hyperparameters.into_iter().enumerate() {
+                let entry = gtk::Entry::builder()
+                    .placeholder_text(space_hint(&hyperparameter))
+                    .tooltip_text(hyperparameter.description)
+                    .hexpand(true)
+                    .build();
+                if let Some(space) = search.space.get(hyperparameter.name) {
+                    entry.set_text(&format_space(space));
+                }
+                let label = Label::builder()
+                    .label(hyperparameter.name)
+                    .halign(gtk::Align::Start)
+                    .tooltip_text(hyperparameter.description)
+                    .build();
+                space_grid.attach(&label, 0, row as i32, 1, 1);
+                space_grid.attach(&entry, 1, row as i32, 1, 1);
+                rows.push((hyperparameter, entry));
+            }
+
+            let names: Vec<String> = rows
+                .iter()
+                .filter(|(h, _)| matches!(h.kind, ParamKind::Int { .. }))
+                .map(|(h, _)| h.name.to_string())
+                .collect();
+            let strings: Vec<&str> = names.iter().map(String::as_str).collect();
+            resource_dd.set_model(Some(&gtk::StringList::new(&strings)));
+            resource_dd.set_selected(names.iter().position(|n| *n == search.resource).unwrap_or(0) as u32);
+            resources.replace(names);
+            entries.replace(rows);
+
+            strategy_dd.set_selected(Strategy::ALL.iter().position(|s| *s == search.strategy).unwrap_or(0) as u32);
+            trials_spin.set_value(search.trials as f64);
+            metric_entry.set_text(&search.metric);
+            let threads = match search.parallel {
+                0 => default_threads(),
+                n => n,
+            };
+            parallel_spin.set_value(threads as f64);
+            min_resource_spin.set_value(search.min_resource as f64);
+            eta_spin.set_value(search.eta as f64);
+            reload_searches();
+        }),
+    );
+
+    // start, follow and stop a search
+    // ---------------------------------------------------------------------------------------------
+    start_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong running, @strong shown, @strong entries, @strong resources, @strong strategy_dd, @strong trials_spin, @strong metric_entry, @strong parallel_spin, @strong resource_dd, @strong min_resource_spin, @strong eta_spin, @strong stop_btn, @strong progress_bar, @strong show_search, @strong reload_searches, @strong reload_runs => move |start_btn| {
+        let started = {
+            let project = project.borrow();
+            let Some(project) = project.as_ref() else {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("SEARCH ERROR"),
+                    Some("Please open a project first."),
+                );
+                return;
+            };
+            let space = match read_space(&entries.borrow()) {
+                Ok(space) => space,
+                Err(e) => {
+                    show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("SEARCH ERROR"),
+                        Some(&format!("Invalid search space:\n{}", e)),
+                    );
+                    return;
+                }
+            };
+            let mut config = config.borrow_mut();
+            let mut search = config.model_search();
+            search.strategy = Strategy::ALL.get(strategy_dd.selected() as usize).copied().unwrap_or_default();
+            search.space = space;
+            search.trials = trials_spin.value() as usize;
+            search.metric = metric_entry.text().trim().to_string();
+            search.parallel = parallel_spin.value() as usize;
+            if let Some(resource) = resources.borrow().get(resource_dd.selected() as usize) {
+                search.resource = resource.clone();
+            }
+            search.min_resource = min_resource_spin.value() as i64;
+            search.eta = eta_spin.value() as usize;
+            let model = config.model.clone();
+            config.searches.insert(model, search);
+            if let Err(e) = save_training(project.dir(), &config) {
+                debug_println!("[ERROR: SEARCH] unable to save the training settings: {}", e);
+            }
+            start_search(project, &config)
+        };
+        let search = match started {
+            Ok(search) => search,
+            Err(e) => {
+                show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("SEARCH ERROR"),
+                    Some(&format!("Unable to start the search:\n{}", e)),
+                );
+                return;
+            }
+        };
+        shown.replace(Some(search.search.clone()));
+        running.replace(Some(search));
+        start_btn.set_sensitive(false);
+        stop_btn.set_sensitive(true);
+        progress_bar.set_fraction(0.0);
+        show_search();
+
+        gtk::glib::timeout_add_local(
+            Duration::from_millis(SEARCH_INTERVAL_MS),
+            gtk::glib::clone!(@strong running, @strong shown, @strong start_btn, @strong stop_btn, @strong progress_bar, @strong show_search, @strong reload_searches, @strong reload_runs => move || {
+                let mut guard = running.borrow_mut();
+                let Some(current) = guard.as_mut() else {
+                    return gtk::glib::ControlFlow::Break;
+                };
+                let trials = current.search.trials.len();
+                let finished = current.poll();
+                let search = &current.search;
+                progress_bar.set_fraction(search.trials.len() as f64 / search.planned.max(1) as f64);
+                let stopping = if current.is_stopping() && !finished { " (stopping)" } else { "" };
+                progress_bar.set_text(Some(&format!("{}: {}{}", search.id, current.status, stopping)));
+                let changed = search.trials.len() != trials || finished;
+                if changed {
+                    shown.replace(Some(search.clone()));
+                }
+                if !finished {
+                    drop(guard);
+                    if changed {
+                        show_search();
+                    }
+                    return gtk::glib::ControlFlow::Continue;
+                }
+
+                if let Some(Err(e)) = &current.result {
+                    debug_println!("[ERROR: SEARCH] {} failed: {}", current.search.id, e);
+                    show_error_message(
+                        None::<&gtk::Widget>,
+                        Some("SEARCH ERROR"),
+                        Some(&format!("The search {} failed:\n{}", current.search.id, e)),
+                    );
+                }
+                let last = current.search.clone();
+                guard.take();
+                drop(guard);
+                start_btn.set_sensitive(true);
+                stop_btn.set_sensitive(false);
+                reload_searches();
+                // keep showing the search that just ended
+                shown.replace(Some(last));
+                show_search();
+                reload_runs();
+                gtk::glib::ControlFlow::Break
+            }),
+        );
+    }));
+
+    stop_btn.connect_clicked(gtk::glib::clone!(@strong running => move |stop_btn| {
+        if let Some(search) = running.borrow().as_ref() {
+            search.stop();
+        }
+        stop_btn.set_sensitive(false);
+    }));
+
+    best_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong config, @strong shown, @strong show_params => move |_| {
+        let Some(best) = shown.borrow().as_ref().and_then(|search| search.best().cloned()) else {
+            return;
+        };
+        {
+            let mut config = config.borrow_mut();
+            let model = config.model.clone();
+            config.params.insert(model, best.params.clone());
+            if let Some(project) = project.borrow().as_ref() {
+                if let Err(e) = save_training(project.dir(), &config) {
+                    debug_println!("[ERROR: SEARCH] unable to save the training settings: {}", e);
+                }
+            }
+        }
+        debug_println!("[INFO: SEARCH] using the hyperparameters of {}", best.run_id);
+        show_params();
+    }));
+
+    vbox.append(&strategy_box);
+    vbox.append(&resource_box);
+    vbox.append(&space_label);
+    vbox.append(&space_grid);
+    vbox.append(&run_box);
+    vbox.append(&shown_box);
+    vbox.append(&leaderboard_window);
+    vbox.append(&chart_area);
+
+    // the strategy of a new search is a grid, successive halving settings stay hidden
+    resource_box.set_visible(false);
+    trials_spin.set_sensitive(false);
+
+    (vbox, refresh)
+}
@@ -8,19 +8,47 @@ use gtk::{Button, Dialog, Entry, Label, ResponseType};
 mod helper; */
 use crate::debug_println;
 
+use crate::annotation::JumpToItem;
+use crate::charts::{palette_colour, Rgb};
+use crate::class_editor::classes_ui;
+use crate::classes::{
+    class_colour, class_names, hex_colour, too_close_colour, validate_class_name, LabelClass,
+    MIN_COLOUR_DISTANCE,
+};
+use crate::clusters::{load_clusters, ClusterAssignment};
+use crate::dashboard::statistics_ui;
+use crate::dedup::{
+    drop_duplicates, find_duplicates, group_duplicates, image_files, DuplicateCluster,
+    DuplicateKind, HashKind,
+};
 use crate::helper::{
-    generate_config, load_config, save_config, show_error_message, update_dotfile,
+    generate_config, load_config, save_config, show_error_message, update_dotfile, Config,
+    ProblemType, BACKGROUND_CLASS,
 };
+use crate::lint::{lint, LintConfig, LintIssue};
+use crate::pixbuf::load_image_at_size;
+use crate::project::{Project, SharedProject};
+use crate::snapshot::{diff_snapshots, list_snapshots, load_snapshot, take_snapshot};
+use crate::splits::{assign_splits, load_splits, save_splits, SplitConfig};
+use crate::store::Modality;
 
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::rc::Rc;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// interval of checking whether a duplicate search is done
+const DUPLICATES_POLL_MS: u64 = 100;
 
 ///
 /// Workspace UI
 ///
 /// TODO(felix): add documentation
 ///
-pub fn projects_ui() -> gtk::Box {
+pub fn projects_ui(project: &SharedProject, jump_to_item: &JumpToItem) -> gtk::Box {
     let workspace_main_container = gtk::Box::builder()
         .orientation(gtk::Orientation::Horizontal)
         .margin_top(15)
@@ -35,14 +63,820 @@ pub fn projects_ui() -> gtk::Box {
     let separating_or_label = gtk::Label::new(Some("or"));
     separating_or_label.add_css_class("title-3");
 
-    workspace_main_container.append(&select_project_ui());
+    workspace_main_container.append(&select_project_ui(project));
     workspace_main_container.append(&separating_or_label);
-    workspace_main_container.append(&create_new_project_ui());
+    workspace_main_container.append(&create_new_project_ui(project));
+
+    let projects_page = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(15)
+        .build();
+
+    projects_page.append(&workspace_main_container);
+    projects_page.append(&project_tools_ui(project, jump_to_item));
+
+    projects_page
+}
+
+/// Tools working on the currently opened project (snapshots, ...), one stack page each
+fn project_tools_ui(project: &SharedProject, jump_to_item: &JumpToItem) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_start(50)
+        .margin_end(50)
+        .margin_bottom(24)
+        .spacing(10)
+        .vexpand(true)
+        .build();
+
+    let title = Label::builder()
+        .label("Current project")
+        .halign(gtk::Align::Start)
+        .build();
+
+    title.add_css_class("title-3");
+
+    let stack = gtk::Stack::builder().vexpand(true).build();
+    stack.add_titled(&classes_ui(project), Some("classes"), "Classes");
+    stack.add_titled(&snapshots_ui(project), Some("snapshots"), "Snapshots");
+    stack.add_titled(&splits_ui(project), Some("splits"), "Splits");
+    stack.add_titled(&statistics_ui(project), Some("statistics"), "Statistics");
+    stack.add_titled(&duplicates_ui(project), Some("duplicates"), "Duplicates");
+    stack.add_titled(&lint_ui(project, jump_to_item), Some("lint"), "Lint");
+    stack.add_titled(
+        &clusters_ui(project, jump_to_item),
+        Some("clusters"),
+        "Clusters",
+    );
+
+    let switcher = gtk::StackSwitcher::builder()
+        .stack(&stack)
+        .halign(gtk::Align::Start)
+        .build();
+
+    vbox.append(&title);
+    vbox.append(&switcher);
+    vbox.append(&stack);
+
+    vbox
+}
+
+/// Split page: assign items to train / val / test and show the per-class counts per split
+fn splits_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let defaults = SplitConfig::default();
+
+    // parameters
+    // ---------------------------------------------------------------------------------------------
+    let ratio_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let train_spin = gtk::SpinButton::with_range(0.0, 100.0, 1.0);
+    let val_spin = gtk::SpinButton::with_range(0.0, 100.0, 1.0);
+    let test_spin = gtk::SpinButton::with_range(0.0, 100.0, 1.0);
+    let seed_spin = gtk::SpinButton::with_range(0.0, u32::MAX as f64, 1.0);
+
+    ratio_box.append(&Label::new(Some("train %:")));
+    ratio_box.append(&train_spin);
+    ratio_box.append(&Label::new(Some("val %:")));
+    ratio_box.append(&val_spin);
+    ratio_box.append(&Label::new(Some("test %:")));
+    ratio_box.append(&test_spin);
+    ratio_box.append(&Label::new(Some("seed:")));
+    ratio_box.append(&seed_spin);
+
+    let option_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let stratify_check = gtk::CheckButton::with_label("stratify by class");
+    let group_entry = Entry::builder().placeholder_text("e.g. patient").build();
+    let folds_spin = gtk::SpinButton::with_range(0.0, 20.0, 1.0);
+    let assign_btn = Button::with_label("assign splits");
+
+    option_box.append(&stratify_check);
+    option_box.append(&Label::new(Some("group by meta key:")));
+    option_box.append(&group_entry);
+    option_box.append(&Label::new(Some("k-folds (0 = off):")));
+    option_box.append(&folds_spin);
+    option_box.append(&assign_btn);
+
+    // per-class counts per split
+    // ---------------------------------------------------------------------------------------------
+    let model = gtk::ListStore::new(&[
+        String::static_type(),
+        u32::static_type(),
+        u32::static_type(),
+        u32::static_type(),
+    ]);
+    let view = gtk::TreeView::with_model(&model);
+
+    for (column, title) in ["class", "train", "val", "test"].iter().enumerate() {
+        let renderer = gtk::CellRendererText::new();
+        let col = gtk::TreeViewColumn::new();
+        col.set_title(title);
+        col.pack_start(&renderer, true);
+        col.add_attribute(&renderer, "text", column as i32);
+        view.append_column(&col);
+    }
+
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(150)
+        .vexpand(true)
+        .child(&view)
+        .build();
+
+    // show the config of an assignment in the widgets and its counts in the table
+    let show_assignment = gtk::glib::clone!(
+        @strong train_spin, @strong val_spin, @strong test_spin, @strong seed_spin,
+        @strong stratify_check, @strong group_entry, @strong folds_spin, @strong model
+        => move |config: &SplitConfig, counts: &BTreeMap<String, [usize; 3]>| {
+        let total = (config.train + config.val + config.test).max(f64::EPSILON);
+        train_spin.set_value(100.0 * config.train / total);
+        val_spin.set_value(100.0 * config.val / total);
+        test_spin.set_value(100.0 * config.test / total);
+        seed_spin.set_value(config.seed as f64);
+        stratify_check.set_active(config.stratify);
+        group_entry.set_text(config.group_key.as_deref().unwrap_or(""));
+        folds_spin.set_value(config.folds as f64);
+
+        model.clear();
+        for (class, [train, val, test]) in counts {
+            model.insert_with_values(
+                None,
+                &[
+                    (0, class),
+                    (1, &(*train as u32)),
+                    (2, &(*val as u32)),
+                    (3, &(*test as u32)),
+                ],
+            );
+        }
+    });
+
+    show_assignment(&defaults, &BTreeMap::new());
+
+    // load the stored assignment whenever the page is shown
+    {
+        let project = project.clone();
+        let show_assignment = show_assignment.clone();
+        vbox.connect_map(move |_| {
+            if let Some(store) = project.borrow().as_ref().map(|p| &p.store) {
+                if let Ok(assignment) = load_splits(store.project_dir()) {
+                    show_assignment(&assignment.config, &assignment.class_counts(store));
+                }
+            }
+        });
+    }
+
+    assign_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
+        let group_key = group_entry.text().trim().to_string();
+        let config = SplitConfig {
+            train: train_spin.value() / 100.0,
+            val: val_spin.value() / 100.0,
+            test: test_spin.value() / 100.0,
+            seed: seed_spin.value() as u64,
+            stratify: stratify_check.is_active(),
+            group_key: (!group_key.is_empty()).then_some(group_key),
+            folds: folds_spin.value() as usize,
+        };
+
+        let project_ref = project.borrow();
+        let Some(store) = project_ref.as_ref().map(|p| &p.store) else {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("SPLIT ERROR"),
+                Some("Unable to assign splits, since no project is opened."),
+            );
+            return;
+        };
+
+        let result = assign_splits(store, &config).and_then(|assignment| {
+            save_splits(store.project_dir(), &assignment)?;
+            Ok(assignment)
+        });
+
+        match result {
+            Ok(assignment) => {
+                debug_println!("[INFO] assigned {} items to splits", assignment.items.len());
+                show_assignment(&assignment.config, &assignment.class_counts(store));
+            }
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("SPLIT ERROR"),
+                Some(&format!("Unable to assign splits:\n{}", e)),
+            ),
+        }
+    }));
 
-    workspace_main_container
+    vbox.append(&ratio_box);
+    vbox.append(&option_box);
+    vbox.append(&scrolled_window);
+
+    vbox
+}
+
+/// Duplicates page: find exact / near-duplicate images and review them side by side
+fn duplicates_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    // parameters
+    // ---------------------------------------------------------------------------------------------
+    let option_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let hash_names: Vec<&str> = HashKind::ALL.iter().map(HashKind::name).collect();
+    let hash_dd = gtk::DropDown::from_strings(&hash_names);
+    hash_dd.set_selected(2);
+    let threshold_spin = gtk::SpinButton::with_range(0.0, 32.0, 1.0);
+    threshold_spin.set_value(5.0);
+    let find_btn = Button::with_label("find duplicates");
+
+    option_box.append(&Label::new(Some("hash:")));
+    option_box.append(&hash_dd);
+    option_box.append(&Label::new(Some("max. Hamming distance:")));
+    option_box.append(&threshold_spin);
+    option_box.append(&find_btn);
+
+    // clusters, one row each with the images side by side
+    // ---------------------------------------------------------------------------------------------
+    let clusters: Rc<RefCell<Vec<DuplicateCluster>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let list_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Multiple)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .vexpand(true)
+        .child(&list_box)
+        .build();
+    let result_label = Label::builder().halign(gtk::Align::Start).build();
+
+    let show_clusters = gtk::glib::clone!(@strong project, @strong clusters, @strong list_box, @strong result_label => move || {
+        while let Some(child) = list_box.first_child() {
+            list_box.remove(&child);
+        }
+
+        let project_ref = project.borrow();
+        let Some(store) = project_ref.as_ref().map(|p| &p.store) else {
+            return;
+        };
+
+        for cluster in clusters.borrow().iter() {
+            let row = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(10)
+                .build();
+
+            row.append(&Label::new(Some(&match cluster.kind {
+                DuplicateKind::Exact => "exact".to_string(),
+                DuplicateKind::Near => format!("near (d <= {})", cluster.max_distance),
+            })));
+
+            for item in cluster.item_ids.iter().filter_map(|id| store.item(*id)) {
+                let cell = gtk::Box::builder()
+                    .orientation(gtk::Orientation::Vertical)
+                    .spacing(2)
+                    .build();
+                let picture = gtk::Picture::for_filename(&item.path);
+                picture.set_size_request(120, 120);
+                picture.set_can_shrink(true);
+                cell.append(&picture);
+
+                let name = std::path::Path::new(&item.path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                cell.append(&Label::new(Some(&name)));
+                row.append(&cell);
+            }
+
+            list_box.append(&row);
+        }
+
+        result_label.set_text(&format!(
+            "{} clusters (select rows to act on them, nothing selected = all)",
+            clusters.borrow().len()
+        ));
+    });
+
+    find_btn.connect_clicked(
+        gtk::glib::clone!(@strong project, @strong clusters, @strong show_clusters, @strong result_label => move |find_btn| {
+            let kind = HashKind::ALL[(hash_dd.selected() as usize).min(HashKind::ALL.len() - 1)];
+            let threshold = threshold_spin.value() as u32;
+            let Some((dir, images)) = project
+                .borrow()
+                .as_ref()
+                .map(|p| (p.dir().to_path_buf(), image_files(&p.store)))
+            else {
+                return;
+            };
+
+            // decoding every image takes a while, the search runs in the background and the
+            // main loop picks up the clusters
+            find_btn.set_sensitive(false);
+            result_label.set_text(&format!("searching {} images for duplicates ...", images.len()));
+            let (sender, found) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = sender.send(find_duplicates(&images, kind, threshold, |path| {
+                    load_image_at_size(path, 64, 64)
+                }));
+            });
+
+            gtk::glib::timeout_add_local(
+                Duration::from_millis(DUPLICATES_POLL_MS),
+                gtk::glib::clone!(@strong project, @strong clusters, @strong show_clusters, @strong result_label, @strong find_btn => move || {
+                    let found = match found.try_recv() {
+                        Ok(found) => found,
+                        Err(TryRecvError::Empty) => return gtk::glib::ControlFlow::Continue,
+                        Err(TryRecvError::Disconnected) => {
+                            result_label.set_text("the duplicate search stopped unexpectedly");
+                            find_btn.set_sensitive(true);
+                            return gtk::glib::ControlFlow::Break;
+                        }
+                    };
+                    find_btn.set_sensitive(true);
+                    // the clusters are item ids of the project the search started in
+                    if project.borrow().as_ref().map(|p| p.dir()) != Some(dir.as_path()) {
+                        result_label.set_text("another project was opened during the search");
+                        return gtk::glib::ControlFlow::Break;
+                    }
+                    debug_println!("[INFO] found {} duplicate clusters", found.len());
+                    clusters.replace(found);
+                    show_clusters();
+                    gtk::glib::ControlFlow::Break
+                }),
+            );
+        }),
+    );
+
+    // actions on the selected clusters
+    // ---------------------------------------------------------------------------------------------
+    let selected_clusters = gtk::glib::clone!(@strong clusters, @strong list_box => move || {
+        let clusters = clusters.borrow();
+        let rows = list_box.selected_rows();
+        if rows.is_empty() {
+            return clusters.clone();
+        }
+        rows.iter()
+            .filter_map(|row| clusters.get(row.index() as usize).cloned())
+            .collect::<Vec<_>>()
+    });
+
+    let action_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let drop_btn = Button::with_label("drop duplicates (keep first)");
+    let group_btn = Button::with_label("force into same split");
+
+    action_box.append(&drop_btn);
+    action_box.append(&group_btn);
+
+    drop_btn.connect_clicked(gtk::glib::clone!(@strong project, @strong clusters, @strong selected_clusters, @strong show_clusters, @strong list_box => move |drop_btn| {
+        let selected = selected_clusters();
+        let items: usize = selected.iter().map(|c| c.item_ids.len() - 1).sum();
+        if items == 0 {
+            return;
+        }
+
+        // removing items can not be undone, so the user confirms how many go
+        let which = if list_box.selected_rows().is_empty() {
+            format!("all {} clusters (no cluster is selected)", selected.len())
+        } else {
+            format!("the {} selected clusters", selected.len())
+        };
+        let dialog = gtk::MessageDialog::new(
+            drop_btn.root().and_downcast::<gtk::Window>().as_ref(),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::YesNo,
+            &format!(
+                "Remove {} items from the project, all but the first item of {}?",
+                items, which
+            ),
+        );
+        dialog.connect_response(gtk::glib::clone!(@strong project, @strong clusters, @strong show_clusters => move |dialog, response| {
+            dialog.destroy();
+            if response != ResponseType::Yes {
+                return;
+            }
+            let result = match project.borrow_mut().as_mut().map(|p| &mut p.store) {
+                Some(store) => drop_duplicates(store, &selected),
+                None => return,
+            };
+
+            match result {
+                Ok(removed) => {
+                    debug_println!("[INFO] dropped {} duplicate items", removed);
+                    clusters.borrow_mut().retain(|c| !selected.contains(c));
+                    show_clusters();
+                }
+                Err(e) => show_error_message(
+                    None::<&gtk::Widget>,
+                    Some("DUPLICATES ERROR"),
+                    Some(&format!("Unable to drop duplicates:\n{}", e)),
+                ),
+            }
+        }));
+        dialog.show();
+    }));
+
+    group_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
+        let selected = selected_clusters();
+        let result = match project.borrow_mut().as_mut().map(|p| &mut p.store) {
+            Some(store) => group_duplicates(store, &selected),
+            None => return,
+        };
+
+        if let Err(e) = result {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("DUPLICATES ERROR"),
+                Some(&format!("Unable to group duplicates:\n{}", e)),
+            );
+        } else {
+            debug_println!("[INFO] grouped {} duplicate clusters", selected.len());
+        }
+    }));
+
+    vbox.append(&option_box);
+    vbox.append(&result_label);
+    vbox.append(&scrolled_window);
+    vbox.append(&action_box);
+
+    vbox
 }
 
-fn select_project_ui() -> gtk::Box {
+/// Lint page: list annotation problems, activating a row opens the item in the annotator
+fn lint_ui(project: &SharedProject, jump_to_item: &JumpToItem) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let option_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let min_examples_spin = gtk::SpinButton::with_range(0.0, 10000.0, 1.0);
+    min_examples_spin.set_value(LintConfig::default().min_examples as f64);
+    let lint_btn = Button::with_label("run lint");
+
+    option_box.append(&Label::new(Some("min. examples per class:")));
+    option_box.append(&min_examples_spin);
+    option_box.append(&lint_btn);
+
+    let issues: Rc<RefCell<Vec<LintIssue>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let list_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .activate_on_single_click(false)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .vexpand(true)
+        .child(&list_box)
+        .build();
+    let result_label = Label::builder().halign(gtk::Align::Start).build();
+
+    lint_btn.connect_clicked(
+        gtk::glib::clone!(@strong project, @strong issues, @strong list_box, @strong result_label => move |_| {
+            let config = LintConfig {
+                min_examples: min_examples_spin.value() as usize,
+            };
+
+            let found = match project.borrow().as_ref() {
+                Some(project) => lint(
+                    &project.store,
+                    &class_names(&project.config.classes),
+                    &config,
+                ),
+                None => Vec::new(),
+            };
+
+            while let Some(child) = list_box.first_child() {
+                list_box.remove(&child);
+            }
+            for issue in &found {
+                let row = Label::builder()
+                    .label(format!("[{}] {}", issue.kind.name(), issue.message))
+                    .halign(gtk::Align::Start)
+                    .build();
+                list_box.append(&row);
+            }
+
+            result_label.set_text(&format!(
+                "{} issues (double click a row to open the item in the annotator)",
+                found.len()
+            ));
+            issues.replace(found);
+        }),
+    );
+
+    let jump_to_item = jump_to_item.clone();
+    list_box.connect_row_activated(gtk::glib::clone!(@strong issues => move |_, row| {
+        let item_id = issues
+            .borrow()
+            .get(row.index() as usize)
+            .and_then(|issue| issue.item_id);
+        if let Some(item_id) = item_id {
+            jump_to_item(item_id);
+        }
+    }));
+
+    vbox.append(&option_box);
+    vbox.append(&result_label);
+    vbox.append(&scrolled_window);
+
+    vbox
+}
+
+/// Clusters page: the items of every cluster of the latest clustering run
+fn clusters_ui(project: &SharedProject, jump_to_item: &JumpToItem) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    let assignment: Rc<RefCell<ClusterAssignment>> = Rc::default();
+    // item ids of the rows of the list
+    let shown_items: Rc<RefCell<Vec<u64>>> = Rc::default();
+
+    let info_label = Label::builder().halign(gtk::Align::Start).build();
+    let cluster_dropdown = gtk::DropDown::from_strings(&[]);
+    let option_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+    option_box.append(&Label::new(Some("cluster:")));
+    option_box.append(&cluster_dropdown);
+    option_box.append(&info_label);
+
+    let list_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .activate_on_single_click(false)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .height_request(200)
+        .vexpand(true)
+        .child(&list_box)
+        .build();
+
+    // the last entry of the dropdown is the noise
+    let show_cluster = gtk::glib::clone!(@strong project, @strong assignment, @strong shown_items, @strong list_box => move |index: u32| {
+        while let Some(child) = list_box.first_child() {
+            list_box.remove(&child);
+        }
+        let assignment = assignment.borrow();
+        let cluster = ((index as usize) < assignment.clusters).then_some(index as usize);
+        let project = project.borrow();
+
+        let mut items = Vec::new();
+        for item in assignment.items_of(cluster) {
+            let path = project
+                .as_ref()
+                .and_then(|p| p.store.item(item.item_id))
+                .map(|i| i.path.clone())
+                .unwrap_or_else(|| format!("item {} (removed)", item.item_id));
+            let row = Label::builder()
+                .label(format!("{}  ({:.0} % of its samples)", path, 100.0 * item.share))
+                .halign(gtk::Align::Start)
+                .build();
+            list_box.append(&row);
+            items.push(item.item_id);
+        }
+        shown_items.replace(items);
+    });
+
+    cluster_dropdown.connect_selected_notify(
+        gtk::glib::clone!(@strong show_cluster => move |dropdown| {
+            show_cluster(dropdown.selected());
+        }),
+    );
+
+    vbox.connect_map(gtk::glib::clone!(@strong project, @strong assignment, @strong cluster_dropdown, @strong info_label, @strong show_cluster => move |_| {
+        let loaded = match project.borrow().as_ref() {
+            Some(project) => load_clusters(project.dir()).ok(),
+            None => None,
+        };
+        let Some(loaded) = loaded else {
+            assignment.replace(ClusterAssignment::default());
+            cluster_dropdown.set_model(Some(&gtk::StringList::new(&[])));
+            info_label.set_text("no clustering run yet, train a clustering model first");
+            show_cluster(0);
+            return;
+        };
+
+        let sizes = loaded.sizes();
+        let mut names: Vec<String> = (0..loaded.clusters)
+            .map(|c| format!("cluster {} ({} items)", c, sizes[c]))
+            .collect();
+        names.push(format!("noise ({} items)", sizes[loaded.clusters]));
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        info_label.set_text(&format!(
+            "{} clusters of run {} ({}), double click an item to open it in the annotator",
+            loaded.clusters, loaded.run_id, loaded.model
+        ));
+        assignment.replace(loaded);
+        cluster_dropdown.set_model(Some(&gtk::StringList::new(&names)));
+        cluster_dropdown.set_selected(0);
+        show_cluster(0);
+    }));
+
+    let jump_to_item = jump_to_item.clone();
+    list_box.connect_row_activated(gtk::glib::clone!(@strong shown_items => move |_, row| {
+        let item_id = shown_items.borrow().get(row.index() as usize).copied();
+        if let Some(item_id) = item_id {
+            jump_to_item(item_id);
+        }
+    }));
+
+    vbox.append(&option_box);
+    vbox.append(&scrolled_window);
+
+    vbox
+}
+
+/// Snapshot page: freeze the dataset into a named version and diff two versions
+fn snapshots_ui(project: &SharedProject) -> gtk::Box {
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(5)
+        .build();
+
+    // take a new snapshot
+    // ---------------------------------------------------------------------------------------------
+    let snapshot_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(5)
+        .build();
+
+    let name_entry = Entry::builder().placeholder_text("v1.0").build();
+    let snapshot_btn = Button::with_label("take snapshot");
+
+    snapshot_box.append(&Label::new(Some("Snapshot name:")));
+    snapshot_box.append(&name_entry);
+    snapshot_box.append(&snapshot_btn);
+
+    // list of existing snapshots
+    // ---------------------------------------------------------------------------------------------
+    let model = gtk::ListStore::new(&[String::static_type()]);
+    let view = gtk::TreeView::with_model(&model);
+    view.selection().set_mode(gtk::SelectionMode::Multiple);
+
+    let read1 = gtk::CellRendererText::new();
+    let col1 = gtk::TreeViewColumn::new();
+
+    col1.set_title("snapshots (select two to diff):");
+    col1.pack_start(&read1, true);
+    col1.add_attribute(&read1, "text", 0);
+    view.append_column(&col1);
+
+    let scrolled_window = gtk::ScrolledWindow::builder().height_request(100).build();
+    scrolled_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    scrolled_window.set_child(Some(&view));
+
+    let refresh_list = gtk::glib::clone!(@strong project, @strong model => move || {
+        model.clear();
+        if let Some(store) = project.borrow().as_ref().map(|p| &p.store) {
+            for name in list_snapshots(store.project_dir()) {
+                model.insert_with_values(None, &[(0, &name)]);
+            }
+        }
+    });
+
+    refresh_list();
+    {
+        let refresh_list = refresh_list.clone();
+        vbox.connect_map(move |_| refresh_list());
+    }
+
+    snapshot_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
+        let name = name_entry.text().trim().to_string();
+        let result = match project.borrow().as_ref().map(|p| &p.store) {
+            Some(store) => take_snapshot(store, &name),
+            None => Err("no project opened".into()),
+        };
+
+        match result {
+            Ok(snapshot) => {
+                debug_println!(
+                    "[INFO] snapshot '{}' taken ({} items, {} annotations)",
+                    snapshot.name,
+                    snapshot.items.len(),
+                    snapshot.annotations.len()
+                );
+                name_entry.set_text("");
+                refresh_list();
+            }
+            Err(e) => show_error_message(
+                None::<&gtk::Widget>,
+                Some("SNAPSHOT ERROR"),
+                Some(&format!("Unable to take snapshot:\n{}", e)),
+            ),
+        }
+    }));
+
+    // diff of two snapshots
+    // ---------------------------------------------------------------------------------------------
+    let diff_btn = Button::with_label("diff selected snapshots");
+    let diff_view = gtk::TextView::builder()
+        .editable(false)
+        .monospace(true)
+        .build();
+    let diff_window = gtk::ScrolledWindow::builder()
+        .height_request(150)
+        .vexpand(true)
+        .child(&diff_view)
+        .build();
+
+    diff_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
+        let (paths, tree_model) = view.selection().selected_rows();
+        let names: Vec<String> = paths
+            .iter()
+            .filter_map(|path| tree_model.iter(path))
+            .filter_map(|iter| tree_model.get_value(&iter, 0).get::<String>().ok())
+            .collect();
+
+        if names.len() != 2 {
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("WARNING"),
+                Some("Please select exactly two snapshots to compare."),
+            );
+            return;
+        }
+
+        let Some(dir) = project.borrow().as_ref().map(|p| &p.store).map(|s| s.project_dir().to_path_buf()) else {
+            return;
+        };
+
+        // the list is sorted oldest first, so the first name is the older snapshot
+        let text = match (load_snapshot(&dir, &names[0]), load_snapshot(&dir, &names[1])) {
+            (Ok(old), Ok(new)) => format!(
+                "{} -> {}\n\n{}",
+                old.name,
+                new.name,
+                diff_snapshots(&old, &new).report()
+            ),
+            (Err(e), _) | (_, Err(e)) => format!("unable to load snapshot: {}", e),
+        };
+
+        diff_view.buffer().set_text(&text);
+    }));
+
+    vbox.append(&snapshot_box);
+    vbox.append(&scrolled_window);
+    vbox.append(&diff_btn);
+    vbox.append(&diff_window);
+
+    vbox
+}
+
+/// Opens the annotation store next to the given (already loaded) project config file and
+/// makes it the current project (shared with all other tabs).
+///
+/// Shows an error dialog if the store can not be opened, e.g. because the log is corrupt.
+fn open_project(project: &SharedProject, config_path: &str, config: Config) {
+    match Project::open(config_path, config) {
+        Ok(opened) => {
+            debug_println!(
+                "[INFO] opened annotation store {} ({} items)",
+                opened.store.log_path().display(),
+                opened.store.item_count()
+            );
+            project.replace(Some(opened));
+        }
+        Err(e) => {
+            debug_println!(
+                "[ERROR: OPEN PROJECT] failed to open annotation store: {}",
+                e
+            );
+            show_error_message(
+                None::<&gtk::Widget>,
+                Some("PROJECT ERROR"),
+                Some(&format!("Unable to open the annotation store:\n{}", e)),
+            );
+        }
+    }
+}
+
+fn select_project_ui(project: &SharedProject) -> gtk::Box {
     let vbox = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .spacing(24)
@@ -64,7 +898,7 @@ fn select_project_ui() -> gtk::Box {
         .label("open project via file explorer")
         .build();
 
-    select_workspace_btn.connect_clicked(move |_| {
+    select_workspace_btn.connect_clicked(gtk::glib::clone!(@strong project => move |_| {
         // Create a new file chooser dialog
         let dialog = gtk::FileChooserDialog::builder()
             .title("Select a workspace .toml file")
@@ -76,14 +910,16 @@ fn select_project_ui() -> gtk::Box {
             ("Select", gtk::ResponseType::Accept),
         ]);
 
-        dialog.connect_response(|dialog, response| {
+        dialog.connect_response(gtk::glib::clone!(@strong project => move |dialog, response| {
             if response == gtk::ResponseType::Accept {
                 if let Some(folder) = dialog.file() {
                     debug_println!("Selected directory: {}", folder.path().unwrap().display());
-                    let config = load_config(&folder.path().unwrap().display().to_string());
+                    let config_path = folder.path().unwrap().display().to_string();
+                    let config = load_config(&config_path);
 
                     if let Ok(x) = config {
                         debug_println!("owner of config: {:?}", x.owner);
+                        open_project(&project, &config_path, x);
                     } else {
                         // TODO gtk dialog popup error / info box
                         debug_println!("WTF, give me a correct .toml file!!! pls")
@@ -91,10 +927,10 @@ fn select_project_ui() -> gtk::Box {
                 }
             }
             dialog.close();
-        });
+        }));
 
         dialog.show();
-    });
+    }));
 
     // add tree view for recent projects
     // ---------------------------------------------------------------------------------------------
@@ -142,11 +978,15 @@ fn select_project_ui() -> gtk::Box {
     let open_recent_project = Button::with_label("open selected project");
 
     let view_clone = view.clone();
+    let project = project.clone();
     open_recent_project.connect_clicked(move |_| {
         let selection = view_clone.selection();
         if let Some((model, iter)) = selection.selected() {
             if let Ok(value) = model.get_value(&iter, 0).get::<String>() {
                 debug_println!("[OPEN RECENT PROJECTS] Open selected project: {}", value);
+                if let Ok(config) = load_config(&value) {
+                    open_project(&project, &value, config);
+                }
             } else {
                 panic!("[ERROR: OPEN RECENT PROJECTS] Failed to get the string value.");
             }
@@ -170,7 +1010,74 @@ fn select_project_ui() -> gtk::Box {
     vbox
 }
 
-fn create_new_project_ui() -> gtk::Box {
+/// columns of the class list of [`create_new_project_ui`], one row per [`LabelClass`]
+const CLASS_NAME_COLUMN: u32 = 0;
+/// `#rrggbb`, also used as background of the swatch cell
+const CLASS_COLOUR_COLUMN: u32 = 1;
+/// empty string for classes without hotkey
+const CLASS_HOTKEY_COLUMN: u32 = 2;
+
+fn insert_class_row(model: &gtk::ListStore, class: &LabelClass) {
+    model.insert_with_values(
+        None,
+        &[
+            (CLASS_NAME_COLUMN, &class.name),
+            (
+                CLASS_COLOUR_COLUMN,
+                &class.colour.clone().unwrap_or_default(),
+            ),
+            (
+                CLASS_HOTKEY_COLUMN,
+                &class.hotkey.map(String::from).unwrap_or_default(),
+            ),
+        ],
+    );
+}
+
+fn classes_from_model(model: &gtk::ListStore) -> Vec<LabelClass> {
+    let mut classes = Vec::new();
+    let Some(iter) = model.iter_first() else {
+        return classes;
+    };
+    loop {
+        let text = |column: u32| {
+            model
+                .get_value(&iter, column as i32)
+                .get::<String>()
+                .unwrap_or_default()
+        };
+        let colour = text(CLASS_COLOUR_COLUMN);
+        classes.push(LabelClass {
+            colour: (!colour.is_empty()).then_some(colour),
+            hotkey: text(CLASS_HOTKEY_COLUMN).chars().next(),
+            ..LabelClass::new(&text(CLASS_NAME_COLUMN))
+        });
+        if !model.iter_next(&iter) {
+            break;
+        }
+    }
+    classes
+}
+
+/// name and hotkey of a class added in the dialog must be unique
+fn check_new_class(existing: &[LabelClass], class: &LabelClass) -> Result<(), Box<dyn Error>> {
+    let names: Vec<&str> = existing.iter().map(|c| c.name.as_str()).collect();
+    validate_class_name(&names, &class.name)?;
+    if let Some(other) = existing
+        .iter()
+        .find(|c| c.hotkey.is_some() && c.hotkey == class.hotkey)
+    {
+        return Err(format!(
+            "hotkey '{}' is already used by '{}'",
+            class.hotkey.unwrap_or_default(),
+            other.name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn create_new_project_ui(project: &SharedProject) -> gtk::Box {
     let main_vbox = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .spacing(24)
@@ -209,11 +1116,14 @@ fn create_new_project_ui() -> gtk::Box {
 
     main_vbox.append(&class_cluster_tgls);
 
-    let data_types = vec![
-        "images",
-        /*"DICOM",*/ "sound / speech",
-        "sequential sensors", /*, etc. TODO */
+    // kinds of data a project can be created for, in the order of the drop down
+    let data_kinds = [
+        Modality::Image,
+        /*"DICOM",*/ Modality::Sound,
+        Modality::Sensor,
+        Modality::Tabular, /*, etc. TODO */
     ];
+    let data_types: Vec<&str> = data_kinds.iter().map(Modality::name).collect();
 
     let expression2 = gtk::PropertyExpression::new(
         gtk::StringObject::static_type(),
@@ -242,22 +1152,44 @@ fn create_new_project_ui() -> gtk::Box {
     main_vbox.append(&selection_box);
 
     // --- showing a list of all selected classes --------------------------------------------------
-    let model = gtk::ListStore::new(&[String::static_type()]);
-
-    model.insert_with_values(None, &[(0, &"default / background".to_value())]);
-    model.insert_with_values(None, &[(0, &"dog".to_value())]);
-    model.insert_with_values(None, &[(0, &"cat".to_value())]);
+    let model = gtk::ListStore::new(&[
+        String::static_type(),
+        String::static_type(),
+        String::static_type(),
+    ]);
+
+    for (i, name) in [BACKGROUND_CLASS, "dog", "cat"].iter().enumerate() {
+        insert_class_row(
+            &model,
+            &LabelClass {
+                colour: Some(hex_colour(palette_colour(i))),
+                ..LabelClass::new(name)
+            },
+        );
+    }
 
     let view = gtk::TreeView::with_model(&model.clone());
 
+    // colour swatch: an empty cell with the class colour as background
+    let swatch_renderer = gtk::CellRendererText::builder().width(24).build();
     let read1 = gtk::CellRendererText::new();
     let col1 = gtk::TreeViewColumn::new();
 
     col1.set_title("Labels / Classes");
+    col1.pack_start(&swatch_renderer, false);
+    col1.add_attribute(&swatch_renderer, "background", CLASS_COLOUR_COLUMN as i32);
     col1.pack_start(&read1, true);
-    col1.add_attribute(&read1, "text", 0);
+    col1.add_attribute(&read1, "text", CLASS_NAME_COLUMN as i32);
     view.append_column(&col1);
 
+    let read2 = gtk::CellRendererText::new();
+    let col2 = gtk::TreeViewColumn::new();
+
+    col2.set_title("Hotkey");
+    col2.pack_start(&read2, true);
+    col2.add_attribute(&read2, "text", CLASS_HOTKEY_COLUMN as i32);
+    view.append_column(&col2);
+
     let scrolled_window = gtk::ScrolledWindow::builder().height_request(150).build();
 
     scrolled_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
@@ -288,8 +1220,11 @@ fn create_new_project_ui() -> gtk::Box {
         move |_| {
         let selection = view_clone.selection();
         if let Some((tree_model, iter)) = selection.selected() {
-            if let Ok(value) = tree_model.get_value(&iter, 0).get::<String>() {
-                if value == "default / background" {
+            if let Ok(value) = tree_model
+                .get_value(&iter, CLASS_NAME_COLUMN as i32)
+                .get::<String>()
+            {
+                if value == BACKGROUND_CLASS {
                     debug_println!("[WARNING: DEL SELECTED CLASS] no you dont!!! why would anyone want to delete the background label?");
                 } else {
                     debug_println!(
@@ -402,11 +1337,17 @@ fn create_new_project_ui() -> gtk::Box {
                 let name_entry = Entry::new();
                 name_entry.set_placeholder_text(Some("Enter label class name"));
                 let color_button = gtk::ColorButton::new();
+                let hotkey_entry = Entry::builder()
+                    .placeholder_text("optional, e.g. d")
+                    .max_length(1)
+                    .build();
 
                 vbox.append(&Label::new(Some("Label Class Name:")));
                 vbox.append(&name_entry);
                 vbox.append(&Label::new(Some("Select Color:")));
                 vbox.append(&color_button);
+                vbox.append(&Label::new(Some("Hotkey:")));
+                vbox.append(&hotkey_entry);
 
                 content_area.append(&vbox);
 
@@ -417,19 +1358,67 @@ fn create_new_project_ui() -> gtk::Box {
                 dialog.connect_response(
                     gtk::glib::clone!(@strong model =>
                     move |dialog, response| {
-                    if response == ResponseType::Ok {
-                        let name = name_entry.text().to_string();
-                        let color = color_button.rgba();
+                    if response != ResponseType::Ok {
+                        dialog.close();
+                        return;
+                    }
+
+                    let color = color_button.rgba();
+                    let colour = (color.red() as f64, color.green() as f64, color.blue() as f64);
+                    let class = LabelClass {
+                        colour: Some(hex_colour(colour)),
+                        hotkey: hotkey_entry.text().chars().next(),
+                        ..LabelClass::new(name_entry.text().trim())
+                    };
+
+                    debug_println!("Label Class Name: {}", class.name);
+                    debug_println!("Selected Color: rgb({},{},{})", color.red(), color.green(), color.blue());
+
+                    // invalid input keeps the dialog open, so the user can fix it
+                    let existing = classes_from_model(&model);
+                    if let Err(e) = check_new_class(&existing, &class) {
+                        show_error_message(
+                            Some(dialog),
+                            Some("INVALID CLASS"),
+                            Some(&e.to_string()),
+                        );
+                        return;
+                    }
 
-                        debug_println!("Label Class Name: {}", name);
-                        debug_println!("Selected Color: rgb({},{},{})", color.red(), color.green(), color.blue());
+                    let existing_colours: Vec<(&str, Rgb)> = existing
+                        .iter()
+                        .enumerate()
+                        .map(|(i, c)| (c.name.as_str(), class_colour(&existing, i)))
+                        .collect();
 
-                        // TODO: check if name is already in the list
-                        if !name.is_empty() {
-                            model.insert_with_values(None, &[(0, &name)]);
+                    match too_close_colour(&existing_colours, colour) {
+                        None => {
+                            insert_class_row(&model, &class);
+                            dialog.close();
+                        }
+                        Some((other, distance)) => {
+                            let warning = gtk::MessageDialog::new(
+                                Some(dialog),
+                                gtk::DialogFlags::MODAL,
+                                gtk::MessageType::Warning,
+                                gtk::ButtonsType::YesNo,
+                                &format!(
+                                    "The colour is hard to tell apart from the colour of '{}' \
+                                    (distance {:.1}, recommended at least {:.0}).\n\
+                                    Add the class anyway?",
+                                    other, distance, MIN_COLOUR_DISTANCE
+                                ),
+                            );
+                            warning.connect_response(gtk::glib::clone!(@strong model, @strong dialog => move |warning, response| {
+                                if response == ResponseType::Yes {
+                                    insert_class_row(&model, &class);
+                                    dialog.close();
+                                }
+                                warning.destroy();
+                            }));
+                            warning.show();
                         }
                     }
-                    dialog.close();
                 }));
 
                 dialog.show();
@@ -454,12 +1443,25 @@ fn create_new_project_ui() -> gtk::Box {
     save_config_box.append(&save_btn);
     main_vbox.append(&save_config_box);
 
+    let project = project.clone();
+    let problem_tgl = clustering_tgl.clone();
     save_btn.connect_clicked(move |_| {
         // gtk::glib::clone!(@strong workspace_main_container => move |_| {
         let conf_name: Option<&str> = Option::from("name");
         let conf_dob: Option<&str> = Option::from("01.01.2024");
         let conf_title: Option<&str> = Option::from("ai lab config title");
-        let workspace_configs = generate_config(conf_name, conf_dob, conf_title);
+
+        let classes = classes_from_model(&model);
+
+        let problem = if problem_tgl.is_active() {
+            ProblemType::Clustering
+        } else {
+            ProblemType::Classification
+        };
+        let modality = data_kinds.get(data_kind_dd.selected() as usize).copied();
+
+        let workspace_configs =
+            generate_config(conf_name, conf_dob, conf_title, classes, problem, modality);
         let config_file_name = config_filename_entry.text().to_string();
 
         // if the filename is not empty and ends with .toml
@@ -483,6 +1485,9 @@ fn create_new_project_ui() -> gtk::Box {
             // save generated config to .toml file
             save_config(&config_file_name, &workspace_configs).unwrap();
             debug_println!("[INFO] saved config to file: {}", config_file_name);
+
+            // the new project starts with an empty annotation store next to its config
+            open_project(&project, &config_file_name, workspace_configs);
         }
     });
     // main_vbox.set_hexpand(true);